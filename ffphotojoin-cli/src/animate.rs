@@ -0,0 +1,162 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::template::OutputVars;
+use crate::{
+    auto_filter_arg, check_output, color_arg, decode_threads, dimensions_arg, filter_arg,
+    group_args, input_args, input_paths, is_stdout, layout_args, load_images, order_arg,
+    output_arg, output_args, output_file, output_name, parse_arg, pick_args, sharpen_arg,
+    write_output, Overwrite, DEFAULT_BACKGROUND,
+};
+use clap::{App, Arg, ArgMatches};
+use ffphotojoin::image::imageops::FilterType;
+use ffphotojoin::image::GenericImageView;
+use ffphotojoin::{Align, AnimationFormat, AnimationOptions, FitMode, PhotoJoinOptions, Sizing};
+
+const DEFAULT_FRAME_DELAY: u16 = 100;
+
+pub fn command() -> App<'static> {
+    clap_app!(animate =>
+        (about: "Makes an animated GIF or PNG with each image as a frame instead of joining them")
+        (@arg output: -o --output +required +takes_value "Set the animation output file (.gif, or .png/.apng for an animated PNG), - writes it to stdout")
+        (@arg format: --format +takes_value "Set the format of the animation instead of going by its extension (gif/apng, defaults to gif for stdout)")
+        (@arg delay: --delay +takes_value "Set how long each frame is shown in milliseconds (defaults to 100)")
+        (@arg loops: --loops +takes_value "Set how many times the animation plays (defaults to 0, which plays it forever)")
+    )
+    .args(frame_args())
+    .mut_arg("input", |arg| {
+        arg.help("Provides the images to use as frames (directories and glob patterns like photos/*.jpg work too, - reads a list of files from stdin)")
+    })
+}
+
+// The arguments of the subcommands that make the images into frames with `frame_options`, besides
+//  the output
+pub fn frame_args() -> Vec<Arg<'static>> {
+    let frame_args = App::new("frame_args")
+        .args(input_args())
+        .args(pick_args(
+            layout_args(),
+            &[
+                "filter",
+                "filter_up",
+                "filter_down",
+                "background",
+                "size_to_largest",
+                "size_to_smallest",
+                "size",
+                "max_output",
+                "sharpen",
+            ],
+        ))
+        .args(pick_args(output_args(), &["overwrite", "backup", "no_overwrite"]))
+        .mut_arg("input", |arg| arg.required_unless_present("files_from"))
+        .mut_arg("background", |arg| {
+            arg.help("Set the color to fill empty space in frames with (a name like black/white/transparent or hex like #ff8800)")
+        })
+        .mut_arg("size", |arg| {
+            arg.help("Resize all images (keeping the aspect ratio) to fit square frames this many pixels wide")
+        })
+        .mut_arg("max_output", |arg| {
+            arg.help("Shrink the frames to fit inside this size when they would be any larger (like 1920x1080)")
+        });
+    group_args(frame_args)
+}
+
+// Frames are padded to the size of the largest image unless a size argument asks for them to be
+//  resized
+pub fn make_animation(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let inputs = input_paths(arg_matcher)?;
+    if inputs.is_empty() {
+        return Err(CliError::Args("no input files provided".into()));
+    }
+    let output_path = output_arg(arg_matcher)?;
+    let format = match arg_matcher.value_of("format") {
+        Some(format) => format.to_lowercase(),
+        None if is_stdout(&output_path) => "gif".to_string(),
+        None => output_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .or_args("unknown output file extension")?
+            .to_lowercase(),
+    };
+    let animation_options = AnimationOptions {
+        format: match format.as_str() {
+            "gif" => AnimationFormat::Gif,
+            "png" | "apng" => AnimationFormat::Apng,
+            _ => {
+                return Err(CliError::Args(
+                    "animations can only be GIF or PNG files".into(),
+                ))
+            }
+        },
+        frame_delay: parse_arg(arg_matcher, "delay", "frame delay")?.unwrap_or(DEFAULT_FRAME_DELAY),
+        loop_count: parse_arg(arg_matcher, "loops", "loop count")?.unwrap_or(0),
+    };
+    let (options, exact_size) = frame_options(arg_matcher)?;
+    if !check_output(&output_path, Overwrite::from_args(arg_matcher))? {
+        return Ok(());
+    }
+
+    eprintln!("Making an animation of {} photos", inputs.len());
+    let frames = ffphotojoin::animation_frames(
+        load_images(inputs, decode_threads(arg_matcher)?, exact_size)?,
+        options,
+    )
+    .or_join("failed to make animation frames")?;
+    let (width, height) = frames[0].dimensions();
+    eprintln!("Generated {} frames of {}x{}", frames.len(), width, height);
+    let mut encoded = Vec::new();
+    ffphotojoin::encode_animation(&frames, &mut encoded, &animation_options)
+        .or_join("failed to encode animation")?;
+    let output_path = match output_file(
+        &output_path,
+        &OutputVars {
+            count: frames.len(),
+            width,
+            height,
+            index: None,
+            group: None,
+        },
+        Overwrite::from_args(arg_matcher),
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    write_output(&output_path, &encoded, Overwrite::from_args(arg_matcher))?;
+    eprintln!("Saved animation to {}", output_name(&output_path));
+    Ok(())
+}
+
+// The options that images are made into frames of an animation or video with, along with the
+//  exact size they're loaded at (if there is one)
+pub fn frame_options(arg_matcher: &ArgMatches) -> CliResult<(PhotoJoinOptions, Option<u32>)> {
+    let exact_size = parse_arg(arg_matcher, "size", "size")?;
+    let (sizing, fit) = match (
+        arg_matcher.is_present("size_to_largest"),
+        arg_matcher.is_present("size_to_smallest"),
+        exact_size,
+    ) {
+        (false, false, None) => (Sizing::ToLargest, FitMode::Pad(Align::Center)),
+        (true, false, None) => (Sizing::ToLargest, FitMode::Scale),
+        (false, true, None) => (Sizing::ToSmallest, FitMode::Scale),
+        (false, false, Some(size)) => (Sizing::Exact(size), FitMode::Scale),
+        _ => {
+            return Err(CliError::Args(
+                "only one size argument may be provided".into(),
+            ))
+        }
+    };
+    let options = PhotoJoinOptions {
+        sizing,
+        filter: filter_arg(arg_matcher, "filter", "filter")?.unwrap_or(FilterType::Gaussian),
+        auto_filter: auto_filter_arg(arg_matcher),
+        filter_up: filter_arg(arg_matcher, "filter_up", "filter for enlarged images")?,
+        filter_down: filter_arg(arg_matcher, "filter_down", "filter for shrunk images")?,
+        fit,
+        background: color_arg(arg_matcher, "background", "background color")?
+            .unwrap_or(DEFAULT_BACKGROUND),
+        max_output: dimensions_arg(arg_matcher, "max_output", "maximum output size")?,
+        order: order_arg(arg_matcher)?,
+        sharpen: sharpen_arg(arg_matcher)?,
+        ..Default::default()
+    };
+    Ok((options, exact_size))
+}
@@ -0,0 +1,149 @@
+use crate::error::CliResult;
+use crate::template::OutputVars;
+use crate::{
+    check_output, finish_report, output_file, output_format, output_name, progress, save_image,
+    OutputOptions,
+};
+use ffphotojoin::image::{GenericImageView, ImageFormat};
+use ffphotojoin::layout::{BatchSpec, JobSpec, LayoutSpec};
+use ffphotojoin::EncodeOptions;
+use std::panic;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+pub fn join_layout(
+    layout_path: &Path,
+    output_path: &Path,
+    output_format: ImageFormat,
+    encode_options: &EncodeOptions,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    eprintln!("Joining photos from {}", layout_path.display());
+    let spec = LayoutSpec::load(layout_path)?;
+    join_spec(
+        &spec,
+        output_path,
+        output_format,
+        encode_options,
+        output_options,
+        true,
+    )
+}
+
+// Runs every job of a batch file, on `threads` threads at once. Each job's output format comes
+//  from its output file's extension
+pub fn join_batch(
+    batch_path: &Path,
+    threads: usize,
+    encode_options: &EncodeOptions,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    eprintln!("Running the jobs in {}", batch_path.display());
+    let batch = BatchSpec::load(batch_path)?;
+    let run_job = |job: &JobSpec, show_progress: bool| {
+        eprintln!("Joining photos for {}", job.output.display());
+        join_spec(
+            &job.layout,
+            &job.output,
+            output_format(&job.output, None)?,
+            encode_options,
+            output_options,
+            show_progress,
+        )
+    };
+    if threads <= 1 {
+        for job in &batch.jobs {
+            run_job(job, true)?;
+        }
+        return Ok(());
+    }
+
+    // Progress messages from jobs running at the same time would be mixed together, so only the
+    //  start and end of each job is printed. A thread stops at its first failed job, and the other
+    //  threads finish theirs
+    let next_job = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let workers = (0..threads.min(batch.jobs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    while let Some(job) = batch.jobs.get(next_job.fetch_add(1, Ordering::Relaxed)) {
+                        run_job(job, false)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|err| panic::resume_unwind(err))
+        })
+    })
+}
+
+// Joins the images of a layout spec and saves the output
+fn join_spec(
+    spec: &LayoutSpec,
+    output_path: &Path,
+    output_format: ImageFormat,
+    encode_options: &EncodeOptions,
+    output_options: &OutputOptions,
+    show_progress: bool,
+) -> CliResult<()> {
+    if !check_output(output_path, output_options.overwrite)? {
+        return Ok(());
+    }
+
+    let mut report = output_options.report();
+    let output_image = spec.execute_with_progress(progress(show_progress, report.as_mut()))?;
+    eprintln!(
+        "Generated {}x{} image",
+        output_image.width(),
+        output_image.height(),
+    );
+    let metadata_source = if output_options.strip_metadata {
+        None
+    } else {
+        spec.images.first().map(|image| image.path.as_path())
+    };
+    let output_path = match output_file(
+        output_path,
+        &OutputVars {
+            count: spec.images.len(),
+            width: output_image.width(),
+            height: output_image.height(),
+            index: None,
+            group: None,
+        },
+        output_options.overwrite,
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    save_image(
+        &output_image,
+        &output_path,
+        output_format,
+        encode_options,
+        metadata_source,
+        output_options.overwrite,
+    )?;
+    eprintln!("Saved joined photo to {}", output_name(&output_path));
+    if let Some(report) = report {
+        let files = spec
+            .images
+            .iter()
+            .map(|image| image.path.clone())
+            .collect::<Vec<_>>();
+        finish_report(
+            report,
+            &output_path,
+            None,
+            output_image.dimensions(),
+            &files,
+            output_options,
+        )?;
+    }
+    Ok(())
+}
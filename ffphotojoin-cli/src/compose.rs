@@ -0,0 +1,136 @@
+use crate::error::{CliError, CliResult};
+use ffphotojoin::Node;
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::CharIndices;
+
+// A parsed compose expression, like `h(a.jpg, v(b.jpg, c.jpg))`. Groups are `h(...)` for
+//  horizontal, `v(...)` for vertical, and `g<cols>(...)` (like `g3(...)`) for grids. Paths with
+//  commas or parentheses in them can be quoted
+pub enum Expr {
+    Image(PathBuf),
+    Horizontal(Vec<Expr>),
+    Vertical(Vec<Expr>),
+    Grid(u32, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            src: expr,
+            chars: expr.char_indices().peekable(),
+        };
+        let parsed = parser.expr()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(parsed),
+            Some((pos, c)) => Err(format!("unexpected '{}' at {}", c, pos)),
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        match self {
+            Expr::Image(_) => 1,
+            Expr::Horizontal(exprs) | Expr::Vertical(exprs) | Expr::Grid(_, exprs) => {
+                exprs.iter().map(Expr::image_count).sum()
+            }
+        }
+    }
+
+    // Opens every image in the expression
+    pub fn into_node(self) -> CliResult<Node> {
+        let nodes = |exprs: Vec<Expr>| {
+            exprs
+                .into_iter()
+                .map(Expr::into_node)
+                .collect::<CliResult<Vec<_>>>()
+        };
+        Ok(match self {
+            Expr::Image(path) => {
+                eprintln!("Opening {}", path.display());
+                Node::Image(crate::open_image(&path).map_err(|err| CliError::image(&path, err))?)
+            }
+            Expr::Horizontal(exprs) => Node::Horizontal(nodes(exprs)?),
+            Expr::Vertical(exprs) => Node::Vertical(nodes(exprs)?),
+            Expr::Grid(cols, exprs) => Node::Grid {
+                rows: 0,
+                cols,
+                nodes: nodes(exprs)?,
+            },
+        })
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        let start = self.pos();
+        if let Some(&(_, quote)) = self.chars.peek().filter(|(_, c)| *c == '"' || *c == '\'') {
+            self.chars.next();
+            let start = self.pos();
+            while self.chars.next_if(|&(_, c)| c != quote).is_some() {}
+            let path = &self.src[start..self.pos()];
+            if self.chars.next().is_none() {
+                return Err(format!("unclosed quote at {}", start - 1));
+            }
+            return Ok(Expr::Image(expand(path)));
+        }
+
+        while self
+            .chars
+            .next_if(|&(_, c)| !matches!(c, ',' | '(' | ')'))
+            .is_some()
+        {}
+        let token = self.src[start..self.pos()].trim();
+        if self.chars.next_if(|&(_, c)| c == '(').is_none() {
+            return if token.is_empty() {
+                Err(format!("expected an image or group at {}", start))
+            } else {
+                Ok(Expr::Image(expand(token)))
+            };
+        }
+
+        // Everything followed by parentheses is a group
+        let exprs = self.list()?;
+        match token {
+            "h" => Ok(Expr::Horizontal(exprs)),
+            "v" => Ok(Expr::Vertical(exprs)),
+            grid if grid.starts_with('g') => match grid[1..].parse() {
+                Ok(cols) if cols > 0 => Ok(Expr::Grid(cols, exprs)),
+                _ => Err(format!("invalid number of grid columns at {}", start)),
+            },
+            _ => Err(format!("unknown group '{}' at {}", token, start)),
+        }
+    }
+
+    // The comma separated expressions inside of a group, through the closing parenthesis
+    fn list(&mut self) -> Result<Vec<Expr>, String> {
+        let mut exprs = vec![self.expr()?];
+        loop {
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => exprs.push(self.expr()?),
+                Some((_, ')')) => return Ok(exprs),
+                Some((pos, c)) => return Err(format!("unexpected '{}' at {}", c, pos)),
+                None => return Err("missing ')'".to_string()),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn pos(&mut self) -> usize {
+        self.chars.peek().map_or(self.src.len(), |&(pos, _)| pos)
+    }
+}
+
+fn expand(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).as_ref())
+}
@@ -0,0 +1,116 @@
+use ffphotojoin::image::ImageError;
+use ffphotojoin::layout::SpecError;
+use ffphotojoin::StreamError;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+// Why a run failed. Each kind exits with its own code, so scripts can tell them apart
+#[derive(Debug)]
+pub enum CliError {
+    // A missing, invalid, or conflicting argument (or layout spec)
+    Args(String),
+    // A file couldn't be read or written
+    Io(String),
+    // An input image couldn't be decoded
+    Decode(String),
+    // The images couldn't be joined, or the output couldn't be encoded
+    Join(String),
+}
+
+pub type CliResult<T> = Result<T, CliError>;
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Args(_) => 2,
+            CliError::Io(_) => 3,
+            CliError::Decode(_) => 4,
+            CliError::Join(_) => 5,
+        }
+    }
+
+    // An image that couldn't be opened, which is an IO error if the file couldn't be read and a
+    //  decode error otherwise
+    pub fn image(path: &Path, err: ImageError) -> Self {
+        let message = format!("failed to open image {}: {}", path.display(), err);
+        match err {
+            ImageError::IoError(_) => CliError::Io(message),
+            _ => CliError::Decode(message),
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Args(message)
+            | CliError::Io(message)
+            | CliError::Decode(message)
+            | CliError::Join(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl From<SpecError> for CliError {
+    fn from(err: SpecError) -> Self {
+        let message = err.to_string();
+        match err {
+            SpecError::Io(_) => CliError::Io(message),
+            SpecError::Json(_) | SpecError::Toml(_) | SpecError::UnknownFormat => {
+                CliError::Args(message)
+            }
+            SpecError::Image { path, err } => CliError::image(&path, err),
+            SpecError::Join(_) => CliError::Join(format!("failed to join photos: {}", message)),
+        }
+    }
+}
+
+impl From<StreamError> for CliError {
+    fn from(err: StreamError) -> Self {
+        let message = format!("failed to join photos: {}", err);
+        match err {
+            StreamError::Image(ImageError::IoError(_)) => CliError::Io(message),
+            StreamError::Image(ImageError::Decoding(_)) => CliError::Decode(message),
+            _ => CliError::Join(message),
+        }
+    }
+}
+
+// Turns a failure into a CLI error of the given kind, with `message` saying what failed (and the
+//  error itself after it), like `size.parse().or_args("invalid size")?`
+pub trait OrFail<T> {
+    fn or_args(self, message: &str) -> CliResult<T>;
+    fn or_io(self, message: &str) -> CliResult<T>;
+    fn or_join(self, message: &str) -> CliResult<T>;
+}
+
+impl<T, E: Display> OrFail<T> for Result<T, E> {
+    fn or_args(self, message: &str) -> CliResult<T> {
+        self.map_err(|err| CliError::Args(format!("{}: {}", message, err)))
+    }
+
+    fn or_io(self, message: &str) -> CliResult<T> {
+        self.map_err(|err| CliError::Io(format!("{}: {}", message, err)))
+    }
+
+    fn or_join(self, message: &str) -> CliResult<T> {
+        self.map_err(|err| CliError::Join(format!("{}: {}", message, err)))
+    }
+}
+
+impl<T> OrFail<T> for Option<T> {
+    fn or_args(self, message: &str) -> CliResult<T> {
+        self.ok_or_else(|| CliError::Args(message.to_string()))
+    }
+
+    fn or_io(self, message: &str) -> CliResult<T> {
+        self.ok_or_else(|| CliError::Io(message.to_string()))
+    }
+
+    fn or_join(self, message: &str) -> CliResult<T> {
+        self.ok_or_else(|| CliError::Join(message.to_string()))
+    }
+}
@@ -0,0 +1,57 @@
+use crate::error::{CliError, CliResult};
+use crate::join::{join_photos, watch_join};
+use crate::{input_args, layout_args, output_args, parse_arg};
+use clap::{App, ArgMatches};
+use ffphotojoin::Direction;
+
+pub fn command() -> App<'static> {
+    clap_app!(grid =>
+        (about: "Joins photos in a grid that's as square as possible, unless the number of rows or columns is given")
+        (@arg rows: --rows +takes_value "Set the number of rows (defaults to as many as needed)")
+        (@arg cols: --cols +takes_value "Set the number of columns (defaults to enough to make the grid about square)")
+    )
+    .args(input_args())
+    .args(layout_args())
+    .args(output_args())
+    .mut_arg("input", |arg| arg.required_unless_present("files_from"))
+    .mut_arg("output", |arg| arg.required(true))
+}
+
+// Joins the input images in a grid with the given number of rows or columns, or else one that's
+//  about as wide as it is tall
+pub fn make_grid(arg_matcher: &ArgMatches) -> CliResult<()> {
+    watch_join(arg_matcher, |inputs, output_options| {
+        if inputs.is_empty() {
+            return Err(CliError::Args("no input files provided".into()));
+        }
+        let direction = grid_direction(arg_matcher, inputs.len())?;
+        join_photos(arg_matcher, inputs, None, direction, output_options)
+    })
+}
+
+// The shape of a grid of `count` images
+fn grid_direction(arg_matcher: &ArgMatches, count: usize) -> CliResult<Direction> {
+    // Paginated grids are shaped for a full page
+    let per_output = parse_arg(arg_matcher, "max_per_output", "number of images per output")?
+        .map_or(count, |max: usize| max.clamp(1, count));
+    Ok(
+        match (
+            parse_arg(arg_matcher, "rows", "number of rows")?,
+            parse_arg(arg_matcher, "cols", "number of columns")?,
+        ) {
+            (rows, Some(cols)) => Direction::Grid {
+                rows: rows.unwrap_or(0),
+                cols,
+            },
+            (Some(0), None) => return Err(CliError::Args("invalid number of rows".into())),
+            (Some(rows), None) => Direction::Grid {
+                rows,
+                cols: (per_output as u32).div_ceil(rows),
+            },
+            (None, None) => Direction::Grid {
+                rows: 0,
+                cols: (per_output as f64).sqrt().ceil() as u32,
+            },
+        },
+    )
+}
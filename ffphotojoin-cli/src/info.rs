@@ -0,0 +1,35 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::{input_args, input_paths};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::io::Reader as ImageReader;
+
+pub fn command() -> App<'static> {
+    clap_app!(info =>
+        (about: "Prints the size, format, and date taken of images")
+    )
+    .args(input_args())
+    .mut_arg("input", |arg| arg.required_unless_present("files_from"))
+}
+
+// Prints the size (once it's turned upright), format, and date taken of each input image
+pub fn print_info(arg_matcher: &ArgMatches) -> CliResult<()> {
+    for input in input_paths(arg_matcher)? {
+        let (width, height) =
+            ffphotojoin::oriented_dimensions(&input).map_err(|err| CliError::image(&input, err))?;
+        let format = ImageReader::open(&input)
+            .and_then(ImageReader::with_guessed_format)
+            .or_io(&format!("failed to read {}", input.display()))?
+            .format()
+            .map_or("unknown".to_string(), |format| format!("{:?}", format));
+        let taken = ffphotojoin::date_taken(&input).map_err(|err| CliError::image(&input, err))?;
+        println!(
+            "{}: {}x{} {}, taken {}",
+            input.display(),
+            width,
+            height,
+            format.to_uppercase(),
+            taken.as_deref().unwrap_or("unknown")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use crate::error::{CliError, CliResult, OrFail};
+use chrono::{DateTime, Duration, Local, NaiveDateTime};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Extensions of the files used from directories and glob patterns, unless others are given
+#[cfg(not(feature = "heic"))]
+pub const DEFAULT_EXTENSIONS: &[&str] =
+    &["jpg", "jpeg", "png", "tif", "tiff", "webp", "bmp", "gif"];
+#[cfg(feature = "heic")]
+pub const DEFAULT_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "tif", "tiff", "webp", "bmp", "gif", "heic", "heif",
+];
+
+// Turns an input into the files it stands for. Directories become the images in them (and in
+//  their subdirectories if `recursive` is set) and glob patterns (like `photos/*.jpg`) become the
+//  images they match, both ordered by name and only with the given extensions. Anything else is
+//  a single file
+pub fn expand_input(
+    input: &str,
+    recursive: bool,
+    extensions: &[String],
+) -> CliResult<Vec<PathBuf>> {
+    let path = PathBuf::from(shellexpand::tilde(input).as_ref());
+    let mut files = if path.is_dir() {
+        let mut files = Vec::new();
+        read_directory(&path, recursive, &mut files)?;
+        files.sort();
+        files
+    } else if !path.exists() && input.contains(['*', '?', '['].as_ref()) {
+        glob::glob(&path.to_string_lossy())
+            .or_args(&format!("invalid glob pattern {}", input))?
+            .filter_map(Result::ok)
+            .filter(|file| file.is_file())
+            .collect()
+    } else {
+        return Ok(vec![path]);
+    };
+
+    files.retain(|file| has_extension(file, extensions));
+    if files.is_empty() {
+        return Err(CliError::Io(format!("no images found for {}", input)));
+    }
+    Ok(files)
+}
+
+// Reads a list of input files separated by newlines, or by NUL characters (like from
+//  `find -print0`) if there are any
+pub fn read_path_list(mut reader: impl Read) -> CliResult<Vec<String>> {
+    let mut list = String::new();
+    reader
+        .read_to_string(&mut list)
+        .or_io("failed to read list of input files")?;
+    let separator = if list.contains('\0') { '\0' } else { '\n' };
+    Ok(list
+        .split(separator)
+        .map(|path| path.strip_suffix('\r').unwrap_or(path))
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn read_directory(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> CliResult<()> {
+    let failed = format!("failed to read directory {}", dir.display());
+    for entry in fs::read_dir(dir).or_io(&failed)? {
+        let path = entry.or_io(&failed)?.path();
+        if path.is_dir() {
+            if recursive {
+                read_directory(&path, recursive, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn has_extension(file: &Path, extensions: &[String]) -> bool {
+    file.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(ext))
+        })
+}
+
+// The order input images are joined in
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum SortOrder {
+    // The order they were given in
+    None,
+    Name,
+    // Oldest to newest modification time
+    Mtime,
+    // Oldest to newest EXIF date, images without one go last
+    ExifDate,
+    // Fewest to most pixels
+    Dimensions,
+}
+
+impl SortOrder {
+    pub fn parse(order: &str) -> Option<Self> {
+        match order.to_lowercase().as_str() {
+            "none" => Some(SortOrder::None),
+            "name" => Some(SortOrder::Name),
+            "mtime" => Some(SortOrder::Mtime),
+            "exif_date" => Some(SortOrder::ExifDate),
+            "dimensions" => Some(SortOrder::Dimensions),
+            _ => None,
+        }
+    }
+}
+
+// Sorts the input files, images that tie stay in the order they were given in
+pub fn sort_inputs(inputs: &mut Vec<PathBuf>, order: SortOrder) -> CliResult<()> {
+    match order {
+        SortOrder::None => {}
+        SortOrder::Name => inputs.sort(),
+        SortOrder::Mtime => sort_by_key(inputs, modified)?,
+        SortOrder::ExifDate => sort_by_key(inputs, |input| {
+            let date = ffphotojoin::date_taken(input).map_err(|err| CliError::image(input, err))?;
+            Ok((date.is_none(), date))
+        })?,
+        SortOrder::Dimensions => sort_by_key(inputs, |input| {
+            let (width, height) = ffphotojoin::oriented_dimensions(input)
+                .map_err(|err| CliError::image(input, err))?;
+            Ok(width as u64 * height as u64)
+        })?,
+    }
+    Ok(())
+}
+
+// Sorts the files by a key that can fail to be read, which is only read once for each file
+fn sort_by_key<K: Ord>(
+    inputs: &mut Vec<PathBuf>,
+    key: impl Fn(&Path) -> CliResult<K>,
+) -> CliResult<()> {
+    let mut keyed = inputs
+        .drain(..)
+        .map(|input| Ok((key(&input)?, input)))
+        .collect::<CliResult<Vec<_>>>()?;
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    inputs.extend(keyed.into_iter().map(|(_, input)| input));
+    Ok(())
+}
+
+fn modified(input: &Path) -> CliResult<std::time::SystemTime> {
+    fs::metadata(input)
+        .and_then(|metadata| metadata.modified())
+        .or_io(&format!(
+            "failed to read modification time of {}",
+            input.display()
+        ))
+}
+
+// How photos are grouped into separate outputs by when they were taken
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum GroupBy {
+    Day,
+    Month,
+    Year,
+    // A new group starts whenever this much time passes between two photos
+    Gap(Duration),
+}
+
+impl GroupBy {
+    // `day`, `month`, `year`, or a gap like `90m`, `3h`, or `2d`
+    pub fn parse(group_by: &str) -> Option<Self> {
+        let group_by = group_by.to_lowercase();
+        match group_by.as_str() {
+            "day" => Some(GroupBy::Day),
+            "month" => Some(GroupBy::Month),
+            "year" => Some(GroupBy::Year),
+            gap => {
+                let split = gap.len().checked_sub(1)?;
+                let amount = gap[..split].parse().ok()?;
+                match &gap[split..] {
+                    "m" => Some(GroupBy::Gap(Duration::minutes(amount))),
+                    "h" => Some(GroupBy::Gap(Duration::hours(amount))),
+                    "d" => Some(GroupBy::Gap(Duration::days(amount))),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+// Orders the photos by when they were taken and splits them into groups, each with a label like
+//  2021-05-04 (the time the group starts is included for gaps, like 2021-05-04_1530)
+pub fn group_inputs(
+    inputs: Vec<PathBuf>,
+    group_by: GroupBy,
+) -> CliResult<Vec<(String, Vec<PathBuf>)>> {
+    let mut timed = inputs
+        .into_iter()
+        .map(|input| Ok((capture_time(&input)?, input)))
+        .collect::<CliResult<Vec<_>>>()?;
+    timed.sort_by_key(|(time, _)| *time);
+
+    let label_format = match group_by {
+        GroupBy::Day => "%Y-%m-%d",
+        GroupBy::Month => "%Y-%m",
+        GroupBy::Year => "%Y",
+        GroupBy::Gap(_) => "%Y-%m-%d_%H%M",
+    };
+    let mut groups: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    let mut last_time = None;
+    for (time, input) in timed {
+        let label = time.format(label_format).to_string();
+        let same_group = match (group_by, last_time, groups.last()) {
+            (GroupBy::Gap(gap), Some(last_time), Some(_)) => time - last_time <= gap,
+            (_, _, Some((last_label, _))) => *last_label == label,
+            _ => false,
+        };
+        match groups.last_mut() {
+            Some((_, group)) if same_group => group.push(input),
+            _ => groups.push((label, vec![input])),
+        }
+        last_time = Some(time);
+    }
+    Ok(groups)
+}
+
+// When a photo was taken going by its EXIF date, or else when the file was last modified
+fn capture_time(input: &Path) -> CliResult<NaiveDateTime> {
+    let taken = ffphotojoin::date_taken(input)
+        .map_err(|err| CliError::image(input, err))?
+        .and_then(|date| NaiveDateTime::parse_from_str(&date, "%Y:%m:%d %H:%M:%S").ok());
+    match taken {
+        Some(taken) => Ok(taken),
+        None => Ok(DateTime::<Local>::from(modified(input)?).naive_local()),
+    }
+}
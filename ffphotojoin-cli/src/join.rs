@@ -0,0 +1,811 @@
+use crate::batch;
+use crate::compose::Expr;
+use crate::error::{CliError, CliResult, OrFail};
+use crate::inputs::read_path_list;
+use crate::report::{TileIndex, TilePlacement};
+use crate::template::OutputVars;
+use crate::watch::InputWatcher;
+use crate::{
+    check_output, dimensions_arg, direction_arg, direction_args, encode_options, encode_output,
+    filter_name, finish_report, input_args, input_paths, is_stdout, join_inputs, join_options,
+    join_streaming, layout_args, output_arg, output_args, output_file, output_format, output_name,
+    over_memory_limit, page_format, page_path, paginate, parse_arg, save_image, write_output,
+    OutputOptions, Overwrite, STREAMING_CONFLICTS,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::imageops::FilterType;
+use ffphotojoin::image::io::Reader as ImageReader;
+use ffphotojoin::image::{DynamicImage, GenericImageView, ImageFormat};
+use ffphotojoin::{
+    Direction, EncodeOptions, PageFormat, PhotoJoinOptions, ZoomFormat, ZoomOptions,
+};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub fn command() -> App<'static> {
+    clap_app!(join =>
+        (about: "Joins photos into one image side by side, top to bottom, in a grid, or in justified rows")
+        (@arg layout: --layout +takes_value conflicts_with[input files_from sort max_per_output group_by mipmaps sizes zoom tile watch memory_limit weights autocrop match_colors brightness contrast saturate grayscale sepia] "Join the images described by a JSON or TOML layout spec instead (joining options come from the spec too)")
+        (@arg batch: --batch +takes_value conflicts_with[input files_from output layout compose low_memory memory_limit max_per_output group_by json mipmaps sizes zoom tile watch autocrop match_colors brightness contrast saturate grayscale sepia] "Run every join described in a JSON or TOML batch file, which lists layout specs with an output file each")
+        (@arg compose: --compose +takes_value conflicts_with[input files_from sort layout low_memory memory_limit max_per_output group_by caption_from_filename skip_errors manifest watch weights autocrop match_colors brightness contrast saturate grayscale sepia] "Join images in nested groups instead, like h(a.jpg, v(b.jpg, c.jpg)) (h/v/g<cols> groups)")
+        (@arg lossless: --lossless conflicts_with[layout batch compose low_memory memory_limit max_per_output group_by json manifest mipmaps sizes zoom tile skip_errors caption_from_filename title target_size weights autocrop match_colors brightness contrast saturate grayscale sepia] "Join JPEG images in a row or column without re-encoding them, so no quality is lost (they have to be the same height for a row or width for a column, and saved with the same quality settings)")
+    )
+    .args(input_args())
+    .args(direction_args())
+    .args(layout_args())
+    .args(output_args())
+    .mut_arg("input", |arg| {
+        arg.required_unless_present_any(["layout", "compose", "files_from", "batch"])
+    })
+    .mut_arg("direction", |arg| {
+        arg.required_unless_present_any(["layout", "compose", "batch"])
+    })
+    .mut_arg("output", |arg| arg.required_unless_present("batch"))
+    .mut_arg("threads", |arg| {
+        arg.help("Set how many images are decoded at the same time (defaults to the number of CPUs), or how many jobs run at the same time with --batch (defaults to 1)")
+    })
+}
+
+// Joins the input images, or runs a batch file or layout spec instead
+pub fn join(arg_matcher: &ArgMatches) -> CliResult<()> {
+    if let Some(batch) = arg_matcher.value_of("batch") {
+        return batch::join_batch(
+            Path::new(shellexpand::tilde(batch).as_ref()),
+            parse_arg(arg_matcher, "threads", "number of threads")?.unwrap_or(1),
+            &encode_options(arg_matcher)?,
+            &OutputOptions::from_args(arg_matcher)?,
+        );
+    }
+    if let Some(layout) = arg_matcher.value_of("layout") {
+        let output_path = output_arg(arg_matcher)?;
+        let output_options = OutputOptions::from_args(arg_matcher)?;
+        check_reports(&output_path, None, &output_options)?;
+        return batch::join_layout(
+            Path::new(shellexpand::tilde(layout).as_ref()),
+            &output_path,
+            output_format(&output_path, arg_matcher.value_of("format"))?,
+            &encode_options(arg_matcher)?,
+            &output_options,
+        );
+    }
+
+    let mut compose = arg_matcher
+        .value_of("compose")
+        .map(|expr| Expr::parse(expr).or_args("invalid compose expression"))
+        .transpose()?;
+    let direction = direction_arg(arg_matcher)?;
+    if arg_matcher.is_present("lossless") {
+        return watch_join(arg_matcher, |inputs, output_options| {
+            join_lossless(arg_matcher, inputs, direction, output_options)
+        });
+    }
+    watch_join(arg_matcher, |inputs, output_options| {
+        join_photos(
+            arg_matcher,
+            inputs,
+            compose.take(),
+            direction,
+            output_options,
+        )
+    })
+}
+
+// Runs a join of the input images, and with `--watch` runs it again whenever they change. A failed
+//  join doesn't stop the watch, and once a join works its output is replaced by the next ones
+pub fn watch_join(
+    arg_matcher: &ArgMatches,
+    mut join: impl FnMut(Vec<PathBuf>, &OutputOptions) -> CliResult<()>,
+) -> CliResult<()> {
+    let mut output_options = OutputOptions::from_args(arg_matcher)?;
+    if !arg_matcher.is_present("watch") {
+        return join(input_paths(arg_matcher)?, &output_options);
+    }
+
+    // Files listed in a file are watched along with the list
+    let mut watched = arg_matcher
+        .values_of("input")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    match arg_matcher.value_of("files_from") {
+        Some("-") | None => watched.extend(arg_matcher.value_of("files_from").map(String::from)),
+        Some(list) => {
+            watched.push(list.to_string());
+            watched.extend(read_path_list(
+                File::open(shellexpand::tilde(list).as_ref())
+                    .or_io("failed to open list of input files")?,
+            )?);
+        }
+    }
+    let watcher = InputWatcher::new(&watched, arg_matcher.is_present("recursive"))?;
+
+    // Changes that don't change which images there are or when they were modified (like the
+    //  output being written next to them) don't run the join again
+    let mut joined_inputs = None;
+    loop {
+        match input_paths(arg_matcher) {
+            Ok(inputs) => {
+                let modified = inputs
+                    .iter()
+                    .map(|input| {
+                        let modified = fs::metadata(input).and_then(|metadata| metadata.modified());
+                        (input.clone(), modified.ok())
+                    })
+                    .collect::<Vec<(PathBuf, Option<SystemTime>)>>();
+                if joined_inputs.as_ref() != Some(&modified) {
+                    joined_inputs = Some(modified);
+                    match join(inputs, &output_options) {
+                        // Outputs that are skipped stay skipped
+                        Ok(()) if output_options.overwrite != Overwrite::Skip => {
+                            output_options.overwrite = Overwrite::Replace
+                        }
+                        Ok(()) => {}
+                        Err(err) => eprintln!("Error: {}", err),
+                    }
+                    eprintln!("Watching for changes to the input images");
+                }
+            }
+            Err(err) => eprintln!("Error: {}", err),
+        }
+        watcher.wait()?;
+    }
+}
+
+// Joins the input images (or the compose expression) in the given direction, and writes each
+//  output the arguments ask for
+pub fn join_photos(
+    arg_matcher: &ArgMatches,
+    inputs: Vec<PathBuf>,
+    compose: Option<Expr>,
+    direction: Direction,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    let output_path = output_arg(arg_matcher)?;
+    let paginated = arg_matcher.is_present("max_per_output") || arg_matcher.is_present("group_by");
+    // Paginated joins can put every page into one PDF or TIFF file, PDF pages are JPEGs
+    let page_format = if paginated {
+        page_format(&output_path, arg_matcher.value_of("format"))
+    } else {
+        None
+    };
+    let zoom = zoom_arg(arg_matcher)?;
+    let output_format = match page_format {
+        Some(PageFormat::Pdf) => ImageFormat::Jpeg,
+        _ if zoom.is_some() => tile_format(arg_matcher.value_of("format"))?,
+        _ => output_format(&output_path, arg_matcher.value_of("format"))?,
+    };
+    let overwrite = output_options.overwrite;
+    check_reports(&output_path, page_format, output_options)?;
+    if zoom.is_some() && (is_stdout(&output_path) || page_format.is_some()) {
+        return Err(CliError::Args(
+            "zoomable images can only be saved as files of their own".into(),
+        ));
+    }
+    let tile_size = dimensions_arg(arg_matcher, "tile", "tile size")?;
+    if let Some((width, height)) = tile_size {
+        if width == 0 || height == 0 {
+            return Err(CliError::Args("invalid tile size".into()));
+        }
+        if is_stdout(&output_path) || page_format.is_some() {
+            return Err(CliError::Args(
+                "tiles can only be saved as files of their own".into(),
+            ));
+        }
+    }
+    let mipmaps = mipmaps_arg(arg_matcher)?;
+    if mipmaps.is_some() && (is_stdout(&output_path) || page_format.is_some()) {
+        return Err(CliError::Args(
+            "mipmaps can only be saved next to single image output files".into(),
+        ));
+    }
+    let sizes = sizes_arg(arg_matcher)?;
+    if !sizes.is_empty() && (is_stdout(&output_path) || page_format.is_some()) {
+        return Err(CliError::Args(
+            "other sizes can only be saved next to single image output files".into(),
+        ));
+    }
+
+    let options = join_options(arg_matcher, direction)?;
+    eprintln!(
+        "Joining photos {} with filter: {}",
+        match direction {
+            _ if compose.is_some() => "in nested groups",
+            Direction::Horizontal => "horizontally",
+            Direction::Vertical => "vertically",
+            Direction::Grid { .. } => "in a grid",
+            Direction::Justified { .. } => "in justified rows",
+            Direction::Aspect { .. } => "in rows to fit an aspect ratio",
+            Direction::Wrap { .. } => "in wrapped rows",
+            Direction::Atlas { .. } => "in an atlas",
+            Direction::Auto => "in whichever direction is the most square",
+        },
+        filter_name(&options)
+    );
+    let (labels, pages): (Vec<_>, Vec<_>) = paginate(inputs, arg_matcher)?.into_iter().unzip();
+    let page_paths = labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| match page_format {
+            None if paginated => page_path(&output_path, index, label.as_deref()),
+            _ => output_path.clone(),
+        })
+        .collect::<Vec<_>>();
+    // Pages of one file are all written or all skipped
+    let writes = page_paths
+        .iter()
+        .map(|page_path| check_output(page_path, overwrite))
+        .collect::<CliResult<Vec<_>>>()?;
+    if page_format.is_some() && !writes[0] {
+        return Ok(());
+    }
+    let streamable = page_format.is_none()
+        && compose.is_none()
+        && matches!(output_format, ImageFormat::Png | ImageFormat::Tiff)
+        && !is_stdout(&output_path)
+        && !STREAMING_CONFLICTS
+            .iter()
+            .any(|&arg| arg_matcher.is_present(arg));
+    if arg_matcher.is_present("low_memory")
+        || over_memory_limit(arg_matcher, &pages, options, streamable)?
+    {
+        if page_format.is_some() {
+            return Err(CliError::Args(
+                "low memory joins can't write multi-page files".into(),
+            ));
+        }
+        for (index, (page, page_path)) in pages.iter().zip(&page_paths).enumerate() {
+            if !writes[index] {
+                continue;
+            }
+            join_streaming(
+                page,
+                page_path,
+                output_format,
+                options,
+                paginated.then_some(index),
+                labels[index].clone(),
+                output_options,
+            )?;
+        }
+        return Ok(());
+    }
+    if paginated && page_format.is_none() && is_stdout(&output_path) {
+        return Err(CliError::Args(
+            "paginated joins can only write to stdout as a PDF or TIFF file".into(),
+        ));
+    }
+
+    let mut compose = compose;
+    let mut page_images = Vec::new();
+    for (index, (page, page_path)) in pages.iter().zip(&page_paths).enumerate() {
+        if !writes[index] {
+            continue;
+        }
+        if pages.len() > 1 {
+            eprintln!("Joining page {} of {}", index + 1, pages.len());
+        }
+        let compose = compose.take();
+        let count = compose.as_ref().map_or(page.len(), Expr::image_count);
+        let mut report = output_options.report();
+        let (output_image, joined) = join_inputs(
+            page,
+            compose,
+            options,
+            arg_matcher,
+            options.background,
+            report.as_mut(),
+        )?;
+        eprintln!(
+            "Generated {}x{} image",
+            output_image.width(),
+            output_image.height(),
+        );
+        // Pages of one file are reported once the file is written
+        let report = report.map(|report| (report, joined));
+        if page_format.is_some() {
+            page_images.push((output_image, count, report));
+            continue;
+        }
+        let page_path = match output_file(
+            page_path,
+            &OutputVars {
+                count,
+                width: output_image.width(),
+                height: output_image.height(),
+                index: paginated.then_some(index),
+                group: labels[index].clone(),
+            },
+            overwrite,
+        )? {
+            Some(page_path) => page_path,
+            None => continue,
+        };
+
+        // Each output gets the metadata of its first photo
+        let metadata_source = if output_options.strip_metadata {
+            None
+        } else {
+            page.first().map(PathBuf::as_path)
+        };
+        if let Some(zoom) = &zoom {
+            save_zoom(
+                &output_image,
+                &page_path,
+                zoom,
+                arg_matcher.value_of("iiif_url"),
+                output_format,
+                &encode_options(arg_matcher)?,
+                overwrite,
+            )?;
+        } else if let Some(tile_size) = tile_size {
+            save_tiles(
+                &output_image,
+                &page_path,
+                tile_size,
+                output_format,
+                &encode_options(arg_matcher)?,
+                metadata_source,
+                overwrite,
+            )?;
+        } else {
+            save_image(
+                &output_image,
+                &page_path,
+                output_format,
+                &encode_options(arg_matcher)?,
+                metadata_source,
+                overwrite,
+            )?;
+            eprintln!("Saved joined photo to {}", output_name(&page_path));
+        }
+        if let Some(mipmaps) = mipmaps {
+            save_mipmaps(
+                &output_image,
+                &page_path,
+                mipmaps,
+                output_format,
+                &encode_options(arg_matcher)?,
+                overwrite,
+            )?;
+        }
+        if !sizes.is_empty() {
+            save_sizes(
+                &output_image,
+                &page_path,
+                &sizes,
+                &options,
+                output_format,
+                &encode_options(arg_matcher)?,
+                overwrite,
+            )?;
+        }
+        if let Some((report, joined)) = report {
+            finish_report(
+                report,
+                &page_path,
+                None,
+                output_image.dimensions(),
+                &joined,
+                output_options,
+            )?;
+        }
+    }
+
+    if let Some(format) = page_format {
+        let mut counts = Vec::new();
+        let mut reports = Vec::new();
+        let page_images = page_images
+            .into_iter()
+            .map(|(image, count, report)| {
+                counts.push(count);
+                reports.push(report);
+                image
+            })
+            .collect::<Vec<_>>();
+        let mut encoded = Vec::new();
+        ffphotojoin::encode_pages(
+            &page_images,
+            &mut encoded,
+            format,
+            &encode_options(arg_matcher)?,
+        )
+        .or_join("failed to encode pages")?;
+        let output_path = match output_file(
+            &output_path,
+            &OutputVars {
+                count: counts.iter().sum(),
+                width: page_images[0].width(),
+                height: page_images[0].height(),
+                index: None,
+                group: None,
+            },
+            overwrite,
+        )? {
+            Some(output_path) => output_path,
+            None => return Ok(()),
+        };
+        write_output(&output_path, &encoded, overwrite)?;
+        eprintln!(
+            "Saved {} pages to {}",
+            page_images.len(),
+            output_name(&output_path)
+        );
+        for (page, (image, report)) in page_images.iter().zip(reports).enumerate() {
+            if let Some((report, joined)) = report {
+                finish_report(
+                    report,
+                    &output_path,
+                    Some(page),
+                    image.dimensions(),
+                    &joined,
+                    output_options,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Joins JPEG input images by stitching their compressed data together, for `--lossless`. Nothing
+//  is resized or drawn, so most of the joining options don't apply
+fn join_lossless(
+    arg_matcher: &ArgMatches,
+    inputs: Vec<PathBuf>,
+    direction: Direction,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    let output_path = output_arg(arg_matcher)?;
+    if output_format(&output_path, arg_matcher.value_of("format"))? != ImageFormat::Jpeg {
+        return Err(CliError::Args(
+            "lossless joins can only be saved as JPEG images".into(),
+        ));
+    }
+    let overwrite = output_options.overwrite;
+    if !check_output(&output_path, overwrite)? {
+        return Ok(());
+    }
+
+    eprintln!("Joining photos losslessly");
+    let jpegs = inputs
+        .iter()
+        .map(|path| fs::read(path).or_io(&format!("failed to read {}", path.display())))
+        .collect::<CliResult<Vec<_>>>()?;
+    let encoded = ffphotojoin::join_jpegs_lossless(&jpegs, direction)
+        .or_join("failed to join photos losslessly")?;
+    let (width, height) = ImageReader::with_format(io::Cursor::new(&encoded), ImageFormat::Jpeg)
+        .into_dimensions()
+        .or_join("failed to read the joined image")?;
+    eprintln!("Generated {}x{} image", width, height);
+    let output_path = match output_file(
+        &output_path,
+        &OutputVars {
+            count: inputs.len(),
+            width,
+            height,
+            index: None,
+            group: None,
+        },
+        overwrite,
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    write_output(&output_path, &encoded, overwrite)?;
+    eprintln!("Saved joined photo to {}", output_name(&output_path));
+    Ok(())
+}
+
+// How the mipmap chain of an output is saved, for `--mipmaps`
+#[derive(Copy, Clone)]
+enum Mipmaps {
+    // A file for each level after the first, which is the output itself
+    Files,
+    // One file of every level stacked top to bottom
+    Strip,
+}
+
+fn mipmaps_arg(arg_matcher: &ArgMatches) -> CliResult<Option<Mipmaps>> {
+    if !arg_matcher.is_present("mipmaps") {
+        return Ok(None);
+    }
+    match arg_matcher.value_of("mipmaps") {
+        None | Some("files") => Ok(Some(Mipmaps::Files)),
+        Some("strip") => Ok(Some(Mipmaps::Strip)),
+        Some(_) => Err(CliError::Args("invalid mipmaps layout".into())),
+    }
+}
+
+// Saves the mipmap chain of an output next to it, named like `joined_mip1.png` for each level or
+//  `joined_mips.png` for a strip
+fn save_mipmaps(
+    img: &DynamicImage,
+    output_path: &Path,
+    mipmaps: Mipmaps,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    overwrite: Overwrite,
+) -> CliResult<()> {
+    let levels = match mipmaps {
+        Mipmaps::Files => ffphotojoin::mipmaps(img, FilterType::Triangle)
+            .into_iter()
+            .enumerate()
+            .skip(1)
+            .map(|(level, img)| {
+                (
+                    page_path(output_path, 0, Some(&format!("mip{}", level))),
+                    img,
+                )
+            })
+            .collect(),
+        Mipmaps::Strip => vec![(
+            page_path(output_path, 0, Some("mips")),
+            ffphotojoin::mip_strip(img, FilterType::Triangle),
+        )],
+    };
+    for (path, level) in &levels {
+        if check_output(path, overwrite)? {
+            save_image(level, path, format, options, None, overwrite)?;
+        }
+    }
+    eprintln!("Saved mipmaps of {}", output_name(output_path));
+    Ok(())
+}
+
+// The sizes to also save each output at for `--sizes`, as percentages of its size
+fn sizes_arg(arg_matcher: &ArgMatches) -> CliResult<Vec<f64>> {
+    arg_matcher
+        .value_of("sizes")
+        .into_iter()
+        .flat_map(|sizes| sizes.split(','))
+        .map(|size| {
+            let size = size.trim();
+            match size.strip_suffix('%').unwrap_or(size).trim().parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+                _ => Err(CliError::Args(format!("invalid size {}", size))),
+            }
+        })
+        .collect()
+}
+
+// Saves an output at other sizes next to it, named after their percentages like `joined_50.png`.
+//  They're all shrunk from the joined image, and 100% is the output itself
+fn save_sizes(
+    img: &DynamicImage,
+    output_path: &Path,
+    sizes: &[f64],
+    options: &PhotoJoinOptions,
+    format: ImageFormat,
+    encode_options: &EncodeOptions,
+    overwrite: Overwrite,
+) -> CliResult<()> {
+    let sizes = sizes
+        .iter()
+        .copied()
+        .filter(|&percent| percent < 100.0)
+        .collect::<Vec<_>>();
+    let scales = sizes
+        .iter()
+        .map(|percent| percent / 100.0)
+        .collect::<Vec<_>>();
+    for (percent, scaled) in sizes
+        .iter()
+        .zip(ffphotojoin::output_scales(img, &scales, options))
+    {
+        let path = page_path(output_path, 0, Some(&percent.to_string()));
+        if check_output(&path, overwrite)? {
+            save_image(&scaled, &path, format, encode_options, None, overwrite)?;
+            eprintln!(
+                "Saved {}x{} copy to {}",
+                scaled.width(),
+                scaled.height(),
+                output_name(&path)
+            );
+        }
+    }
+    Ok(())
+}
+
+// How outputs are cut into the tiles of zoomable images for `--zoom`, if they are
+fn zoom_arg(arg_matcher: &ArgMatches) -> CliResult<Option<ZoomOptions>> {
+    let format = match arg_matcher
+        .value_of("zoom")
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None => return Ok(None),
+        Some("dzi") => ZoomFormat::DeepZoom,
+        Some("iiif") => ZoomFormat::Iiif,
+        Some(_) => return Err(CliError::Args("invalid zoomable image format".into())),
+    };
+    let mut options = ZoomOptions::new(format);
+    if let Some(tile_size) = parse_arg(arg_matcher, "zoom_tile_size", "zoom tile size")? {
+        if tile_size == 0 {
+            return Err(CliError::Args("invalid zoom tile size".into()));
+        }
+        options.tile_size = tile_size;
+    }
+    Ok(Some(options))
+}
+
+// The format of the tiles of zoomable images, which viewers can only show as JPEG or PNG images
+fn tile_format(format: Option<&str>) -> CliResult<ImageFormat> {
+    match format.map(str::to_lowercase).as_deref() {
+        None | Some("jpeg") | Some("jpg") => Ok(ImageFormat::Jpeg),
+        Some("png") => Ok(ImageFormat::Png),
+        Some(_) => Err(CliError::Args(
+            "zoomable image tiles can only be JPEG or PNG images".into(),
+        )),
+    }
+}
+
+// Saves an output as the tiles of a zoomable image, along with the file that describes it. The
+//  folder of tiles is replaced as a whole (or backed up), so no tiles of an older image are left
+//  in it
+fn save_zoom(
+    img: &DynamicImage,
+    output_path: &Path,
+    zoom: &ZoomOptions,
+    iiif_url: Option<&str>,
+    format: ImageFormat,
+    encode_options: &EncodeOptions,
+    overwrite: Overwrite,
+) -> CliResult<()> {
+    let (tiles_path, descriptor_path) = match zoom.format {
+        ZoomFormat::DeepZoom => {
+            let stem = output_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default();
+            (
+                output_path.with_file_name(format!("{}_files", stem)),
+                output_path.to_path_buf(),
+            )
+        }
+        ZoomFormat::Iiif => (output_path.to_path_buf(), output_path.join("info.json")),
+    };
+    if !check_output(&tiles_path, overwrite)? {
+        return Ok(());
+    }
+    if tiles_path.exists() {
+        // Only folders that look like older tiles are ever deleted
+        if zoom.format == ZoomFormat::Iiif && !descriptor_path.exists() {
+            return Err(CliError::Io(format!(
+                "{} already exists and isn't a folder of IIIF tiles",
+                tiles_path.display()
+            )));
+        }
+        if overwrite == Overwrite::Backup {
+            let mut backup_path = tiles_path.as_os_str().to_os_string();
+            backup_path.push(".bak");
+            let backup_path = PathBuf::from(backup_path);
+            if backup_path.is_dir() {
+                fs::remove_dir_all(&backup_path).or_io("failed to replace tiles backup")?;
+            }
+            fs::rename(&tiles_path, backup_path).or_io("failed to back up tiles")?;
+        } else {
+            fs::remove_dir_all(&tiles_path).or_io("failed to replace tiles")?;
+        }
+    }
+
+    let extension = match format {
+        ImageFormat::Png => "png",
+        _ => "jpg",
+    };
+    let mut count = 0;
+    ffphotojoin::zoom_tiles(img, zoom, extension, |path, tile| {
+        let path = tiles_path.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).or_io("failed to create tiles folder")?;
+        }
+        let sized = encode_output(tile, format, encode_options, None)?;
+        count += 1;
+        write_output(&path, &sized.encoded, Overwrite::Replace)
+    })?;
+    let id = match iiif_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => output_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let descriptor = ffphotojoin::zoom_descriptor(img.dimensions(), zoom, extension, &id);
+    write_output(&descriptor_path, descriptor.as_bytes(), overwrite)?;
+    eprintln!(
+        "Saved {} tiles of a zoomable image to {}",
+        count,
+        output_name(&descriptor_path)
+    );
+    Ok(())
+}
+
+// Cuts an output into tiles named by their row and column, like `joined_r0_c1.png`, and saves
+//  where each one goes in `joined_tiles.json`
+fn save_tiles(
+    img: &DynamicImage,
+    output_path: &Path,
+    tile_size: (u32, u32),
+    format: ImageFormat,
+    encode_options: &EncodeOptions,
+    metadata_source: Option<&Path>,
+    overwrite: Overwrite,
+) -> CliResult<()> {
+    let (width, height) = img.dimensions();
+    let columns = width.div_ceil(tile_size.0);
+    let mut tiles = Vec::new();
+    for (index, rect) in ffphotojoin::grid_tiles((width, height), tile_size)
+        .into_iter()
+        .enumerate()
+    {
+        let (row, column) = (index as u32 / columns, index as u32 % columns);
+        let path = page_path(output_path, 0, Some(&format!("r{}_c{}", row, column)));
+        if check_output(&path, overwrite)? {
+            let tile = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+            save_image(
+                &tile,
+                &path,
+                format,
+                encode_options,
+                metadata_source,
+                overwrite,
+            )?;
+        }
+        tiles.push(TilePlacement {
+            file: path.file_name().unwrap_or_default().into(),
+            row,
+            column,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        });
+    }
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let index_path = output_path.with_file_name(format!("{}_tiles.json", stem));
+    let index = TileIndex {
+        width,
+        height,
+        tile_width: tile_size.0,
+        tile_height: tile_size.1,
+        columns,
+        rows: height.div_ceil(tile_size.1),
+        tiles,
+    };
+    index.save(&index_path)?;
+    eprintln!(
+        "Saved {} tiles of the joined photo, listed in {}",
+        index.tiles.len(),
+        output_name(&index_path)
+    );
+    Ok(())
+}
+
+// JSON reports go to stdout, so the output can't, and manifests and coordinates are only written
+//  for single images saved to files
+fn check_reports(
+    output_path: &Path,
+    page_format: Option<PageFormat>,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    if output_options.json && is_stdout(output_path) {
+        return Err(CliError::Args(
+            "JSON reports can't be written to stdout along with the output image".into(),
+        ));
+    }
+    if output_options.manifest && (is_stdout(output_path) || page_format.is_some()) {
+        return Err(CliError::Args(
+            "manifests can only be saved next to single image output files".into(),
+        ));
+    }
+    if output_options.coordinates.is_some() && (is_stdout(output_path) || page_format.is_some()) {
+        return Err(CliError::Args(
+            "coordinates can only be saved next to single image output files".into(),
+        ));
+    }
+    Ok(())
+}
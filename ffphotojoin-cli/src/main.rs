@@ -1,81 +1,463 @@
 #[macro_use]
 extern crate clap;
 
+use clap::{App, Arg, ArgMatches};
+use clap_complete::Shell;
+use compose::Expr;
+use error::{CliError, CliResult, OrFail};
+use ffphotojoin::color;
 use ffphotojoin::image::imageops::FilterType;
-use ffphotojoin::image::io::Reader;
-use ffphotojoin::image::{DynamicImage, GenericImageView};
-use ffphotojoin::{Direction, Sizing};
-use std::path::PathBuf;
+use ffphotojoin::image::{
+    DynamicImage, GenericImageView, ImageBuffer, ImageFormat, ImageResult, Rgba,
+};
+use ffphotojoin::preprocess::{self, Adjustments};
+use ffphotojoin::text::{self, CaptionPosition, CaptionStyle, FontArc, TitleOptions};
+use ffphotojoin::{
+    Align, Anchor, AtlasFormat, Border, CompositeMode, Direction, EncodeOptions, Feather, FitMode,
+    JoinError, JoinEvent, JoinItem, Metadata, MetadataField, Order, PageFormat, PhotoJoinOptions,
+    PngCompression, Rect, ResizeBackend, Separator, Shadow, Sharpen, SizedEncoding, Sizing,
+    StreamFormat, TargetSize,
+};
+use inputs::{
+    expand_input, group_inputs, read_path_list, sort_inputs, GroupBy, SortOrder, DEFAULT_EXTENSIONS,
+};
+use progress::{counting_bar, join_progress, show_file};
+use report::{print_report, save_coordinates, JsonReport, Manifest};
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use template::OutputVars;
+
+mod animate;
+mod batch;
+mod compose;
+mod error;
+mod grid;
+mod info;
+mod inputs;
+mod join;
+mod pages;
+mod preview;
+mod progress;
+mod report;
+#[cfg(feature = "serve")]
+mod serve;
+mod sheet;
+mod split;
+mod template;
+mod terminal;
+#[cfg(feature = "video")]
+mod video;
+mod watch;
 
 const DEFAULT_SIZING: Sizing = Sizing::ToSmallest;
+const DEFAULT_CELL_SIZE: u32 = 256;
+const DEFAULT_CAPTION_SIZE: f32 = 24.0;
+const DEFAULT_TITLE_SIZE: f32 = 48.0;
+const DEFAULT_BORDER_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const DEFAULT_SHADOW_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const DEFAULT_SHADOW_OPACITY: f32 = 0.5;
+// Enough to bring back the edges a large downscale softens, without halos around them
+const DEFAULT_SHARPEN: Sharpen = Sharpen {
+    amount: 0.5,
+    radius: 0.8,
+    threshold: 2,
+};
+const DEFAULT_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+// Perceptual hashes come out the same for any size of image, so they're made from thumbnails
+const HASH_THUMBNAIL_SIZE: u32 = 64;
+// The colors of an image are measured on a copy about this big, so a reference image doesn't need
+//  to be decoded at full size
+const COLOR_REFERENCE_SIZE: u32 = 256;
+const PLACEHOLDER_COLOR: Rgba<u8> = Rgba([128, 128, 128, 255]);
 
 fn main() {
-    // Create argument parser
-    let arg_matcher = clap_app!(ffphotojoin_cli =>
+    let arg_matcher = cli().get_matches();
+
+    // Errors are printed without a backtrace, and the exit code says what kind of error it was
+    if let Err(err) = run(&arg_matcher) {
+        eprintln!("Error: {}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+// The argument parser, which shell completions are made from too
+fn cli() -> App<'static> {
+    clap_app!(ffphotojoin_cli =>
         (version: std::env!("CARGO_PKG_VERSION"))
         (author: std::env!("CARGO_PKG_AUTHORS"))
         (about: std::env!("CARGO_PKG_DESCRIPTION"))
-        (@arg input: -i --input +multiple +required +takes_value "Provides an input image or images to the joiner")
-        (@arg output: -o --output +required +takes_value "Set the image output file (PNG or JPEG formats only)")
-        (@arg direction: -d --direction +required +takes_value "Set the direction of the output image (vertical/horizontal)")
-        (@arg filter: --filter +takes_value "Set the filter to use when resizing images (nearest/triangle/catmull_rom/gaussian/lanczos3)")
-        (@arg override_output: -f --override_output "Overrides the output file if it exists when present")
+        (@setting SubcommandRequiredElseHelp)
+        (@subcommand completions =>
+            (about: "Prints a completion script for a shell, like `ffphotojoin-cli completions bash > ffphotojoin-cli.bash`")
+            (@setting Hidden)
+            (@arg shell: +required "Set the shell to complete in (bash/zsh/fish/powershell/elvish)")
+        )
+    )
+    .subcommand(join::command())
+    .subcommand(grid::command())
+    .subcommand(preview::command())
+    .subcommand(info::command())
+    .subcommand(sheet::command())
+    .subcommand(animate::command())
+    .subcommand(pages::command())
+    .subcommand(split::command())
+    .subcommands(optional_commands())
+}
+
+// Subcommands that are only built with some features
+fn optional_commands() -> Vec<App<'static>> {
+    vec![
+        #[cfg(feature = "serve")]
+        serve::command(),
+        #[cfg(feature = "video")]
+        video::command(),
+    ]
+}
+
+// The subcommands share most of their arguments, which come from these groups. Arguments in a
+//  group can only refer to others in the same group, the subcommands add the requirements and
+//  conflicts between groups. Subcommands that only take some of a group's arguments pick them
+//  with `pick_args`
+
+// Where the input images come from and the order they're joined in
+fn input_args() -> Vec<Arg<'static>> {
+    group_args(clap_app!(input_args =>
+        (@arg input: -i --input +multiple +takes_value "Provides an input image or images (directories and glob patterns like photos/*.jpg work too, - reads a list of files from stdin)")
+        (@arg files_from: --files_from +takes_value "Read a list of input images from a file (or stdin with -), one per line or separated by NUL characters")
+        (@arg recursive: --recursive "Include images in subdirectories of input directories")
+        (@arg extensions: --extensions +takes_value "Set which file extensions are used from input directories and glob patterns (defaults to jpg,jpeg,png,tif,tiff,webp,bmp,gif, and heic,heif with the heic feature)")
+        (@arg sort: --sort +takes_value "Set the order of the input images (none/name/mtime/exif_date/dimensions, defaults to none)")
+        (@arg reverse: --reverse conflicts_with[shuffle] "Lay the images out in the opposite order (within each output, when there are several)")
+        (@arg shuffle: --shuffle +takes_value min_values(0) max_values(1) "Lay the images out in a random order (within each output, when there are several), which is the same every time for the same seed (one is picked and printed if it's left out)")
+        (@arg dedupe: --dedupe +takes_value min_values(0) max_values(1) "Leave out images that look nearly the same as one before them (like burst shots), optionally with how many of the 64 bits of their perceptual hashes can differ for them to count (defaults to 6)")
+        (@arg threads: --threads +takes_value "Set how many images are decoded at the same time (defaults to the number of CPUs)")
+    ))
+}
+
+fn direction_args() -> Vec<Arg<'static>> {
+    group_args(clap_app!(direction_args =>
+        (@arg direction: -d --direction +takes_value "Set the direction of the output image (vertical/horizontal/grid/justified/wrap/aspect/atlas/auto, where atlas packs the images at their own sizes like a sprite sheet and auto picks whichever of a row, a column, or a grid makes the most square output)")
+        (@arg rows: --rows +takes_value "Set the number of rows when joining in a grid (defaults to as many as needed)")
+        (@arg cols: --cols +takes_value "Set the number of columns when joining in a grid")
+        (@arg width: --width +takes_value "Set the width of the rows when joining in justified or wrapped rows")
+        (@arg alternate: --alternate "Lay every other wrapped row out from right to left, so the images snake back and forth")
+        (@arg aspect: --aspect +takes_value "Set the aspect ratio to get the output closest to when joining with the aspect direction (like 16:9), by picking how many rows the images are packed into")
+        (@arg atlas_size: --atlas_size +takes_value "Pack the images into exactly this size when joining with the atlas direction (like 1024x1024), instead of the smallest size they fit in")
+    ))
+}
+
+// How the images are sized and arranged, and how they're split into several outputs
+fn layout_args() -> Vec<Arg<'static>> {
+    group_args(clap_app!(layout_args =>
+        (@arg filter: --filter +takes_value "Set the filter to use when resizing images (nearest/triangle/catmull_rom/gaussian/lanczos3, or auto to pick one for each image from how much it's resized)")
+        (@arg filter_up: --filter_up +takes_value "Set the filter to use for images that are enlarged, instead of --filter (nearest/triangle/catmull_rom/gaussian/lanczos3)")
+        (@arg filter_down: --filter_down +takes_value "Set the filter to use for images that are shrunk, instead of --filter (nearest/triangle/catmull_rom/gaussian/lanczos3)")
+        (@arg fit: --fit +takes_value "Set how images are fit into their space (scale/crop/pad)")
+        (@arg anchor: --anchor +takes_value "Set which part of cropped images is kept (center/top/bottom/left/right/top_left/top_right/bottom_left/bottom_right, or smart to keep the most detailed part)")
+        (@arg align: --align +takes_value "Set where padded images sit in their space (start/center/end)")
+        (@arg gap: --gap +takes_value "Set the number of pixels to leave between images")
+        (@arg background: --background +takes_value "Set the color to fill gaps and empty space with (a name like black/white/transparent or hex like #ff8800)")
+        (@arg border: --border +takes_value "Set the width of a frame drawn around each image")
+        (@arg border_color: --border_color +takes_value "Set the color of the frame around each image (defaults to white)")
+        (@arg margin: --margin +takes_value "Set the number of pixels of background to leave around the output image")
+        (@arg corner_radius: --corner_radius +takes_value "Round the corners of each image to this many pixels")
+        (@arg shadow: --shadow +takes_value "Draw a shadow under each image, given as x,y,blur (like 8,8,12)")
+        (@arg shadow_color: --shadow_color +takes_value "Set the color of the shadows (defaults to black)")
+        (@arg shadow_opacity: --shadow_opacity +takes_value "Set how solid the shadows are from 0 to 1 (defaults to 0.5)")
+        (@arg overlap: --overlap +takes_value conflicts_with[gap] "Overlap adjacent images by this many pixels, fading each one in over the last (strips only)")
+        (@arg feather: --feather +takes_value "Set how overlapping images fade into each other (linear/cosine)")
+        (@arg separator: --separator +takes_value "Draw a line between adjacent images, given as width:color (like 2:black)")
+        (@arg sharpen: --sharpen +takes_value min_values(0) max_values(1) "Sharpen each image after it's resized, which makes heavily shrunk images less soft, optionally given as amount,radius,threshold (defaults to 0.5,0.8,2)")
+        (@arg linear_light: --linear_light "Resize and blend images in linear light, which looks better for high contrast photos but is slower")
+        (@arg fast_resize: --fast_resize conflicts_with[linear_light] "Resize images with SIMD instructions, which is several times faster (needs the fast-resize feature)")
+        (@arg composite_mode: --composite_mode +takes_value "Set how see-through parts of images are combined with what's underneath (over/replace/premultiplied)")
+        (@arg autocrop: --autocrop +takes_value min_values(0) max_values(1) "Trim borders of one color (like scanner margins or letterboxing) off each image before joining, optionally with how far from the border's color each channel of a pixel can be, from 0 to 255 (defaults to 16)")
+        (@arg match_colors: --match_colors +takes_value min_values(0) max_values(1) "Even out the brightness and colors of the images (like photos taken under different light) by matching them to this image (defaults to the first input image)")
+        (@arg brightness: --brightness +takes_value "Multiply the brightness of each image by this much before joining (like 1.2 for 20% brighter)")
+        (@arg contrast: --contrast +takes_value "Multiply the contrast of each image by this much before joining (below 1 makes it flatter)")
+        (@arg saturate: --saturate +takes_value "Multiply how colorful each image is by this much before joining (0 makes it gray)")
+        (@arg grayscale: --grayscale "Turn each image gray before joining")
+        (@arg sepia: --sepia "Give each image the brown tint of an old photo before joining")
+        (@arg weights: --weights +takes_value "Give images more or less space along the join direction than the others, as a comma separated list in the order of each output's images (like 2,1,1 makes the first image twice as large, images without one get 1, strips and justified rows only)")
+        (@arg max_per_output: --max_per_output +takes_value "Split the join into several output images of at most this many images each, numbered like out_001.png (or pages of one file for PDF and TIFF outputs)")
+        (@arg group_by: --group_by +takes_value conflicts_with[max_per_output] "Join photos taken on the same day/month/year, or with less than a gap like 90m/3h/2d between them, into one output each, named like out_2021-05-04.png (photos without an EXIF date go by when they were modified)")
+        (@arg max_size: --max_size +takes_value "Fail instead of creating an output image larger than this (like 10000x10000)")
+        (@arg max_output: --max_output +takes_value "Shrink the output image (along with the gaps, borders, and everything else) to fit inside this size when it would be any larger (like 8000x8000)")
+        (@arg pot: --pot "Round the width and height of the output image up to powers of two (like 512 or 1024, which some GPUs need textures to be), padding it with the background color")
+        (@arg align_multiple: --align_multiple alias("align-multiple") +takes_value "Round the width and height of the output image up to a multiple of this (like 16 for video encoders), padding it with the background color")
         (@arg size_to_largest: -l --size_to_largest "Resize all images (keeping the aspect ratio) to fit the size of the largest image")
         (@arg size_to_smallest: -s --size_to_smallest "Resize all images (keeping the aspect ratio) to fit the size of the smallest image")
-    ).get_matches();
+        (@arg size: --size +takes_value "Resize all images (keeping the aspect ratio) so the shared edge is exactly this many pixels")
+    ))
+}
 
-    // Load arguments from parser
-    let inputs = arg_matcher
-        .values_of("input")
-        .expect("no input files/directories provided")
-        .into_iter()
-        .map(|input| PathBuf::from(shellexpand::tilde(input).as_ref()))
-        .collect::<Vec<_>>();
-    let output_path = PathBuf::from(
-        shellexpand::tilde(arg_matcher.value_of("output").expect("no output file")).as_ref(),
+// How the output is written, with the captions and title drawn on it
+fn output_args() -> Vec<Arg<'static>> {
+    group_args(clap_app!(output_args =>
+        (@arg output: -o --output +takes_value "Set the image output file, - writes it to stdout ({date}/{time}/{timestamp}/{count}/{width}/{height}/{index}/{group} in it are filled in, like joined_{date}_{width}x{height}.png)")
+        (@arg format: --format +takes_value "Set the format of the output image instead of going by its extension (png/jpeg/tiff/bmp/gif/webp/avif, defaults to png for stdout)")
+        (@arg quality: --quality +takes_value "Set the quality of JPEG, WebP, and AVIF output images from 1 to 100 (defaults to 75)")
+        (@arg png_compression: --png_compression +takes_value "Set how hard PNG output images are compressed (fast/default/best, defaults to fast)")
+        (@arg webp_lossless: --webp_lossless "Encode WebP output images losslessly (needs the webp feature)")
+        (@arg avif_speed: --avif_speed +takes_value "Set how fast AVIF output images are encoded from 1 to 10, slower makes smaller files (defaults to 4, needs the avif feature)")
+        (@arg target_size: --target_size +takes_value "Lower the quality of JPEG, WebP, and AVIF output images as little as it takes to make them at most this large (like 5MB, 800KB, or 2MiB)")
+        (@arg downscale_to_fit: --downscale_to_fit requires[target_size] "Shrink the output image when lowering the quality isn't enough to reach the target size (or it's in a lossless format)")
+        (@arg caption_from_filename: --caption_from_filename conflicts_with[low_memory] "Write each image's filename as a caption with it (needs --caption_font)")
+        (@arg caption_font: --caption_font +takes_value "Set the TrueType/OpenType font file captions and titles are written in")
+        (@arg title: --title +takes_value conflicts_with[low_memory] "Add a banner with this title to the output image (needs --caption_font)")
+        (@arg title_size: --title_size +takes_value "Set the height of the title text in pixels (defaults to 48)")
+        (@arg title_position: --title_position +takes_value "Set where the title banner goes (above/below)")
+        (@arg caption_size: --caption_size +takes_value "Set the height of caption text in pixels (defaults to 24)")
+        (@arg caption_color: --caption_color +takes_value "Set the color of caption text (defaults to black or white, whichever stands out from the background)")
+        (@arg caption_position: --caption_position +takes_value "Set where captions go (below/above/over)")
+        (@arg strip_metadata: --strip_metadata "Don't copy the date, camera, and copyright EXIF fields of the first image to the output (only JPEG and PNG outputs get them)")
+        (@arg low_memory: --low_memory conflicts_with[target_size weights autocrop match_colors brightness contrast saturate grayscale sepia] "Write the output while joining instead of keeping it all in memory (PNG or TIFF formats only)")
+        (@arg memory_limit: --memory_limit +takes_value conflicts_with[low_memory] "Join in low memory mode (see --low_memory) when joining in memory would take more than about this much memory (like 512MB or 2GiB), and stop instead if even that would")
+        (@arg gpu: --gpu conflicts_with[low_memory memory_limit] "Resize and blend the images on the GPU, which is much faster for large outputs (needs the gpu feature, joins with shadows, rounded corners, overlaps, separators, sharpening, or captions are done on the CPU, as are joins when there's no GPU)")
+        (@arg focus_faces: --focus_faces conflicts_with[low_memory memory_limit] "Keep the faces in images that are cropped to fill their space in frame (needs the faces feature)")
+        (@arg skip_errors: --skip_errors conflicts_with[low_memory] "Leave out input images that can't be opened (with a warning) instead of stopping")
+        (@arg placeholders: --placeholders requires[skip_errors] "Put a gray tile in place of each image that can't be opened, with its filename written on it if there's a --caption_font")
+        (@arg json: --json "Write progress events and a report of each output (its size and where each image went) to stdout as JSON lines")
+        (@arg manifest: --manifest "Save where each image went in each output to a JSON file next to it (like joined.png.json), which the split subcommand can cut the images back out with")
+        (@arg coordinates: --coordinates +takes_value "Save where each image went in each output for loading them as sprites, next to it with the format's extension (json/css/texture_packer, like sprites.css for sprites.png)")
+        (@arg mipmaps: --mipmaps +takes_value min_values(0) max_values(1) conflicts_with[low_memory] "Also save the mipmap chain of each output (each level half as large as the last, down to 1x1) next to it, as a file per level like joined_mip1.png, or with strip as one file of every level stacked top to bottom like joined_mips.png (files/strip, defaults to files)")
+        (@arg sizes: --sizes +takes_value conflicts_with[low_memory] "Also save each output at these sizes, as percentages of its size (like 100%,50%,25%), next to it like joined_50.png (100% is the output itself)")
+        (@arg zoom: --zoom +takes_value conflicts_with[low_memory target_size json manifest coordinates mipmaps sizes] "Save each output as the tiles of a zoomable image for viewers like OpenSeadragon instead of one image (dzi/iiif): dzi saves a Deep Zoom .dzi file with its tiles in a folder next to it (like joined_files for joined.dzi), and iiif saves IIIF tiles and their info.json into a folder (the tiles are JPEGs unless --format is png)")
+        (@arg tile: --tile +takes_value conflicts_with[low_memory json manifest coordinates mipmaps sizes zoom] "Cut each output into tiles of at most this size (like 4096x4096) instead of saving one image, named by their row and column like joined_r0_c1.png, with where each one goes saved in joined_tiles.json")
+        (@arg zoom_tile_size: --zoom_tile_size +takes_value requires[zoom] "Set the width and height of zoomable image tiles in pixels (defaults to 254 for dzi and 512 for iiif)")
+        (@arg iiif_url: --iiif_url +takes_value requires[zoom] "Set the URL the folder of IIIF tiles will be served at, which viewers load the tiles from (defaults to the folder's name, relative to the viewer's page)")
+        (@arg overwrite: -f --overwrite alias("override_output") conflicts_with[backup no_overwrite] "Replace the output file if it already exists")
+        (@arg backup: --backup conflicts_with[no_overwrite] "Replace the output file if it already exists, after renaming it with a .bak extension")
+        (@arg no_overwrite: --no_overwrite "Leave the output file as it is if it already exists and skip writing it, instead of stopping")
+        (@arg watch: --watch "Keep running and join the images again whenever one changes (or is added to an input directory), until stopped with Ctrl+C")
+    ))
+}
+
+// The arguments of a group, which is made as a command of its own to define them, without the help
+//  and version flags every command starts with
+fn group_args(group: App<'static>) -> Vec<Arg<'static>> {
+    group
+        .get_arguments()
+        .filter(|arg| !["help", "version"].contains(&arg.get_id()))
+        .cloned()
+        .collect()
+}
+
+// The arguments of a group with these names. None of them can refer to one that's left out
+fn pick_args(args: Vec<Arg<'static>>, names: &[&str]) -> Vec<Arg<'static>> {
+    args.into_iter()
+        .filter(|arg| names.contains(&arg.get_id()))
+        .collect()
+}
+
+fn run(arg_matcher: &ArgMatches) -> CliResult<()> {
+    match arg_matcher.subcommand() {
+        Some(("join", join_matches)) => join::join(join_matches),
+        Some(("grid", grid_matches)) => grid::make_grid(grid_matches),
+        Some(("preview", preview_matches)) => preview::preview(preview_matches),
+        Some(("info", info_matches)) => info::print_info(info_matches),
+        Some(("sheet", sheet_matches)) => sheet::make_sheet(sheet_matches),
+        Some(("animate", animate_matches)) => animate::make_animation(animate_matches),
+        Some(("pages", pages_matches)) => pages::make_pages(pages_matches),
+        Some(("split", split_matches)) => split::split_joined(split_matches),
+        Some(("completions", completions_matches)) => print_completions(completions_matches),
+        #[cfg(feature = "serve")]
+        Some(("serve", serve_matches)) => serve::serve(serve_matches),
+        #[cfg(feature = "video")]
+        Some(("video", video_matches)) => video::make_video(video_matches),
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+fn print_completions(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let shell: Shell = parse_arg(arg_matcher, "shell", "shell")?.or_args("no shell provided")?;
+    clap_complete::generate(
+        shell,
+        &mut cli(),
+        std::env!("CARGO_PKG_NAME"),
+        &mut io::stdout(),
     );
-    let direction = {
-        let d = arg_matcher
-            .value_of("direction")
-            .expect("no direction provided")
-            .to_lowercase();
-        match d.as_str() {
-            "vertical" => Direction::Vertical,
-            _ => Direction::Horizontal,
-        }
-    };
-    let filter = {
-        if let Some(filter) = arg_matcher.value_of("filter") {
-            match filter.to_lowercase().as_str() {
-                "nearest" => FilterType::Nearest,
-                "triangle" => FilterType::Triangle,
-                "catmull_rom" => FilterType::CatmullRom,
-                "gaussian" => FilterType::Gaussian,
-                "lanczos3" => FilterType::Lanczos3,
-                _ => FilterType::Gaussian,
+    Ok(())
+}
+
+// The direction of a join from the arguments
+fn direction_arg(arg_matcher: &ArgMatches) -> CliResult<Direction> {
+    let d = arg_matcher
+        .value_of("direction")
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    Ok(match d.as_str() {
+        "vertical" => Direction::Vertical,
+        "grid" => Direction::Grid {
+            rows: parse_arg(arg_matcher, "rows", "number of rows")?.unwrap_or(0),
+            cols: parse_arg(arg_matcher, "cols", "number of columns")?
+                .or_args("no number of columns provided for grid")?,
+        },
+        "justified" => Direction::Justified {
+            width: parse_arg(arg_matcher, "width", "width")?
+                .or_args("no width provided for justified rows")?,
+        },
+        "aspect" => {
+            let (width, height) = arg_matcher
+                .value_of("aspect")
+                .or_args("no aspect ratio provided")?
+                .split_once(':')
+                .or_args("invalid aspect ratio")?;
+            Direction::Aspect {
+                width: width.trim().parse().or_args("invalid aspect ratio")?,
+                height: height.trim().parse().or_args("invalid aspect ratio")?,
             }
-        } else {
-            FilterType::Gaussian
         }
+        "wrap" => Direction::Wrap {
+            width: parse_arg(arg_matcher, "width", "width")?
+                .or_args("no width provided for wrapped rows")?,
+            alternate: arg_matcher.is_present("alternate"),
+        },
+        "atlas" => Direction::Atlas {
+            size: dimensions_arg(arg_matcher, "atlas_size", "atlas size")?,
+        },
+        "auto" => Direction::Auto,
+        "" | "horizontal" => Direction::Horizontal,
+        _ => return Err(CliError::Args("invalid direction".into())),
+    })
+}
+
+// How the images are sized and arranged, from the arguments shared by the subcommands that join
+//  photos
+fn join_options(arg_matcher: &ArgMatches, direction: Direction) -> CliResult<PhotoJoinOptions> {
+    let filter = filter_arg(arg_matcher, "filter", "filter")?.unwrap_or(FilterType::Gaussian);
+    let fit = match arg_matcher
+        .value_of("fit")
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("crop") => match arg_matcher.value_of("anchor") {
+            Some(anchor) => FitMode::CropAnchor(match anchor.to_lowercase().as_str() {
+                "top_left" => Anchor::TopLeft,
+                "top" => Anchor::Top,
+                "top_right" => Anchor::TopRight,
+                "left" => Anchor::Left,
+                "right" => Anchor::Right,
+                "bottom_left" => Anchor::BottomLeft,
+                "bottom" => Anchor::Bottom,
+                "bottom_right" => Anchor::BottomRight,
+                "smart" => Anchor::Smart,
+                "center" => Anchor::Center,
+                _ => return Err(CliError::Args("invalid anchor".into())),
+            }),
+            None => FitMode::CropCenter,
+        },
+        Some("pad") => FitMode::Pad(
+            match arg_matcher
+                .value_of("align")
+                .map(str::to_lowercase)
+                .as_deref()
+            {
+                Some("start") => Align::Start,
+                Some("end") => Align::End,
+                None | Some("center") => Align::Center,
+                Some(_) => return Err(CliError::Args("invalid align".into())),
+            },
+        ),
+        None | Some("scale") => FitMode::Scale,
+        Some(_) => return Err(CliError::Args("invalid fit".into())),
     };
-    let override_output = arg_matcher.is_present("override_output");
+    let spacing = parse_arg(arg_matcher, "gap", "gap size")?.unwrap_or(0);
+    let background =
+        color_arg(arg_matcher, "background", "background color")?.unwrap_or(DEFAULT_BACKGROUND);
+    let border = match parse_arg(arg_matcher, "border", "border width")? {
+        Some(width) => Some(Border {
+            width,
+            color: color_arg(arg_matcher, "border_color", "border color")?
+                .unwrap_or(DEFAULT_BORDER_COLOR),
+        }),
+        None => None,
+    };
+    let margin = parse_arg(arg_matcher, "margin", "margin")?.unwrap_or(0);
+    let corner_radius = parse_arg(arg_matcher, "corner_radius", "corner radius")?.unwrap_or(0);
+    let shadow = match arg_matcher.value_of("shadow") {
+        Some(shadow) => {
+            let values = shadow
+                .split(',')
+                .map(|value| value.trim().parse::<i32>().or_args("invalid shadow"))
+                .collect::<CliResult<Vec<_>>>()?;
+            let (x, y, blur) = match values[..] {
+                [x, y, blur] if blur >= 0 => (x, y, blur as u32),
+                _ => return Err(CliError::Args("invalid shadow, expected x,y,blur".into())),
+            };
+            Some(Shadow {
+                offset: (x, y),
+                blur,
+                color: color_arg(arg_matcher, "shadow_color", "shadow color")?
+                    .unwrap_or(DEFAULT_SHADOW_COLOR),
+                opacity: parse_arg(arg_matcher, "shadow_opacity", "shadow opacity")?
+                    .unwrap_or(DEFAULT_SHADOW_OPACITY),
+            })
+        }
+        None => None,
+    };
+    let overlap = parse_arg(arg_matcher, "overlap", "overlap")?.unwrap_or(0);
+    let feather = match arg_matcher
+        .value_of("feather")
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("linear") => Feather::Linear,
+        Some("cosine") => Feather::Cosine,
+        Some(_) => return Err(CliError::Args("invalid feather".into())),
+    };
+    let composite_mode = match arg_matcher
+        .value_of("composite_mode")
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("over") => CompositeMode::Over,
+        Some("replace") => CompositeMode::Replace,
+        Some("premultiplied") => CompositeMode::Premultiplied,
+        Some(_) => return Err(CliError::Args("invalid composite mode".into())),
+    };
+    let separator = match arg_matcher.value_of("separator") {
+        Some(separator) => {
+            let (width, color) = separator
+                .split_once(':')
+                .or_args("invalid separator, expected width:color")?;
+            Some(Separator {
+                width: width.parse().or_args("invalid separator width")?,
+                color: parse_color(color).or_args("invalid separator color")?,
+            })
+        }
+        None => None,
+    };
+    let max_dimensions = dimensions_arg(arg_matcher, "max_size", "maximum size")?;
+    let max_output = dimensions_arg(arg_matcher, "max_output", "maximum output size")?;
     let size_to_largest = arg_matcher.is_present("size_to_largest");
     let size_to_smallest = arg_matcher.is_present("size_to_smallest");
-
-    println!(
-        "Joining photos {} with filter: {:?}",
-        match direction {
-            Direction::Horizontal => "horizontally",
-            Direction::Vertical => "vertically",
-        },
-        filter
-    );
+    let exact_size = parse_arg(arg_matcher, "size", "size")?;
 
     // Determine how to size the output image
-    if size_to_largest && size_to_smallest {
-        panic!("only one size argument may be provided");
+    if [size_to_largest, size_to_smallest, exact_size.is_some()]
+        .iter()
+        .filter(|&&present| present)
+        .count()
+        > 1
+    {
+        return Err(CliError::Args(
+            "only one size argument may be provided".into(),
+        ));
     }
-    let sizing = if size_to_smallest {
+    let sizing = if let Some(size) = exact_size {
+        Sizing::Exact(size)
+    } else if size_to_smallest {
         Sizing::ToSmallest
     } else if size_to_largest {
         Sizing::ToLargest
@@ -83,45 +465,1302 @@ fn main() {
         DEFAULT_SIZING
     };
     match sizing {
-        Sizing::ToSmallest => println!("Resizing to smallest image"),
-        Sizing::ToLargest => println!("Resizing to largest image"),
+        // Atlases keep every image at its own size
+        _ if matches!(direction, Direction::Atlas { .. }) => {}
+        Sizing::ToSmallest => eprintln!("Resizing to smallest image"),
+        Sizing::ToLargest => eprintln!("Resizing to largest image"),
+        Sizing::Exact(size) => eprintln!("Resizing to {} pixels", size),
     }
 
-    // Join the photos
-    let output_image = ffphotojoin::join_photos(
-        load_images(inputs),
-        ffphotojoin::PhotoJoinOptions {
-            direction,
-            sizing,
-            filter,
-        },
+    Ok(PhotoJoinOptions {
+        direction,
+        sizing,
+        filter,
+        auto_filter: auto_filter_arg(arg_matcher),
+        filter_up: filter_arg(arg_matcher, "filter_up", "filter for enlarged images")?,
+        filter_down: filter_arg(arg_matcher, "filter_down", "filter for shrunk images")?,
+        fit,
+        spacing,
+        background,
+        max_dimensions,
+        max_output,
+        border,
+        margin,
+        corner_radius,
+        shadow,
+        overlap,
+        feather,
+        separator,
+        linear_light: arg_matcher.is_present("linear_light"),
+        composite_mode,
+        order: order_arg(arg_matcher)?,
+        sharpen: sharpen_arg(arg_matcher)?,
+        power_of_two: arg_matcher.is_present("pot"),
+        align_multiple: parse_arg(arg_matcher, "align_multiple", "alignment multiple")?
+            .unwrap_or(0),
+        resize_backend: resize_backend_arg(arg_matcher)?,
+    })
+}
+
+// What the images are resized with, which can only be fast_image_resize when the CLI is built with
+//  it
+fn resize_backend_arg(arg_matcher: &ArgMatches) -> CliResult<ResizeBackend> {
+    if !arg_matcher.is_present("fast_resize") {
+        return Ok(ResizeBackend::Image);
+    }
+    if cfg!(feature = "fast-resize") {
+        Ok(ResizeBackend::Fast)
+    } else {
+        Err(CliError::Args(
+            "--fast_resize needs the CLI to be built with the fast-resize feature".into(),
+        ))
+    }
+}
+
+// How the images should be sharpened after they're resized, if they should be. Any of the amount,
+//  radius, and threshold that are left off the end are the defaults
+fn sharpen_arg(arg_matcher: &ArgMatches) -> CliResult<Option<Sharpen>> {
+    if !arg_matcher.is_present("sharpen") {
+        return Ok(None);
+    }
+    let mut sharpen = DEFAULT_SHARPEN;
+    if let Some(value) = arg_matcher.value_of("sharpen") {
+        let mut values = value.split(',').map(str::trim);
+        if let Some(amount) = values.next() {
+            sharpen.amount = amount.parse().or_args("invalid sharpen amount")?;
+        }
+        if let Some(radius) = values.next() {
+            sharpen.radius = radius.parse().or_args("invalid sharpen radius")?;
+        }
+        if let Some(threshold) = values.next() {
+            sharpen.threshold = threshold.parse().or_args("invalid sharpen threshold")?;
+        }
+        if values.next().is_some() {
+            return Err(CliError::Args(
+                "invalid sharpen, expected amount,radius,threshold".into(),
+            ));
+        }
+    }
+    Ok(Some(sharpen))
+}
+
+// How far from a border's color pixels can be for borders to be trimmed off the images, if they
+//  should be
+fn autocrop_arg(arg_matcher: &ArgMatches) -> CliResult<Option<u8>> {
+    if !arg_matcher.is_present("autocrop") {
+        return Ok(None);
+    }
+    Ok(Some(
+        parse_arg(arg_matcher, "autocrop", "autocrop tolerance")?
+            .unwrap_or(preprocess::DEFAULT_AUTOCROP_TOLERANCE),
+    ))
+}
+
+// Whether the colors of the images should be matched to a reference image, and the path of that
+//  image if one was given instead of the first input image
+fn match_colors_arg(arg_matcher: &ArgMatches) -> Option<Option<PathBuf>> {
+    if !arg_matcher.is_present("match_colors") {
+        return None;
+    }
+    Some(
+        arg_matcher
+            .value_of("match_colors")
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref())),
     )
-    .expect("failed to join photos");
+}
+
+// Matches the colors of every image to the image at `reference`, or to the first image if there
+//  isn't one. Only the colors are needed from the reference, so it's decoded at a small size
+fn match_colors_to(images: &mut [DynamicImage], reference: Option<&Path>) -> CliResult<()> {
+    let reference = match (reference, images.first()) {
+        (Some(path), _) => ffphotojoin::load_oriented_scaled(path, COLOR_REFERENCE_SIZE)
+            .map(|image| preprocess::ColorStats::of(&image))
+            .map_err(|err| CliError::image(path, err))?,
+        (None, Some(first)) => preprocess::ColorStats::of(first),
+        (None, None) => return Ok(()),
+    };
+    for image in images.iter_mut() {
+        *image = preprocess::match_colors(image, &reference);
+    }
+    Ok(())
+}
+
+// The touch-ups made to every image before they're joined
+fn adjustments_arg(arg_matcher: &ArgMatches) -> CliResult<Adjustments> {
+    let defaults = Adjustments::default();
+    Ok(Adjustments {
+        brightness: parse_arg(arg_matcher, "brightness", "brightness")?
+            .unwrap_or(defaults.brightness),
+        contrast: parse_arg(arg_matcher, "contrast", "contrast")?.unwrap_or(defaults.contrast),
+        saturation: parse_arg(arg_matcher, "saturate", "saturation")?
+            .unwrap_or(defaults.saturation),
+        grayscale: arg_matcher.is_present("grayscale"),
+        sepia: arg_matcher.is_present("sepia"),
+    })
+}
 
-    // Write the output image
-    if output_path.exists() && !override_output {
-        panic!("output file already exists");
+// How much space each image of an output gets relative to the others, in order
+fn weights_arg(arg_matcher: &ArgMatches) -> CliResult<Vec<f32>> {
+    arg_matcher
+        .value_of("weights")
+        .into_iter()
+        .flat_map(|weights| weights.split(','))
+        .map(|weight| match weight.trim().parse::<f32>() {
+            Ok(weight) if weight > 0.0 && weight.is_finite() => Ok(weight),
+            _ => Err(CliError::Args(format!("invalid weight {}", weight.trim()))),
+        })
+        .collect()
+}
+
+// The order images are laid out in. Shuffles without a seed get one picked the first time it's
+//  needed, so joins that run again (like with --watch) keep the same order
+fn order_arg(arg_matcher: &ArgMatches) -> CliResult<Order> {
+    static SEED: OnceLock<u64> = OnceLock::new();
+    if arg_matcher.is_present("reverse") {
+        return Ok(Order::Reversed);
+    }
+    if !arg_matcher.is_present("shuffle") {
+        return Ok(Order::AsGiven);
+    }
+    let seed = match parse_arg(arg_matcher, "shuffle", "shuffle seed")? {
+        Some(seed) => seed,
+        None => *SEED.get_or_init(|| {
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+                ^ process::id() as u64;
+            eprintln!("Shuffling with seed {}", seed);
+            seed
+        }),
+    };
+    Ok(Order::Shuffled(seed))
+}
+
+// Long joins are split into pages of at most `max_per_output` images, or photos are grouped by
+//  when they were taken, and each page is written to a numbered or labeled output file (or
+//  all into one file for PDF and TIFF outputs)
+fn paginate(
+    inputs: Vec<PathBuf>,
+    arg_matcher: &ArgMatches,
+) -> CliResult<Vec<(Option<String>, Vec<PathBuf>)>> {
+    let max_per_output = parse_arg(arg_matcher, "max_per_output", "number of images per output")?;
+    let group_by = arg_matcher
+        .value_of("group_by")
+        .map(|group_by| GroupBy::parse(group_by).or_args("invalid grouping"))
+        .transpose()?;
+    Ok(match (max_per_output, group_by) {
+        (Some(0), _) => return Err(CliError::Args("invalid number of images per output".into())),
+        (Some(count), _) => inputs
+            .chunks(count)
+            .map(|chunk| (None, chunk.to_vec()))
+            .collect(),
+        (None, Some(group_by)) => group_inputs(inputs, group_by)?
+            .into_iter()
+            .map(|(label, group)| (Some(label), group))
+            .collect(),
+        (None, None) => vec![(None, inputs)],
+    })
+}
+
+// Whether to join on the GPU, which can only be done when the CLI is built with the gpu feature
+fn gpu_arg(arg_matcher: &ArgMatches) -> CliResult<bool> {
+    let gpu = arg_matcher.is_present("gpu");
+    if gpu && !cfg!(feature = "gpu") {
+        return Err(CliError::Args(
+            "--gpu needs the CLI to be built with the gpu feature".into(),
+        ));
+    }
+    #[cfg(feature = "gpu")]
+    if gpu && log::set_logger(&LibraryWarnings).is_ok() {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    Ok(gpu)
+}
+
+// Whether to keep faces in frame, which can only be done when the CLI is built with the faces
+//  feature
+fn focus_faces_arg(arg_matcher: &ArgMatches) -> CliResult<bool> {
+    let focus_faces = arg_matcher.is_present("focus_faces");
+    if focus_faces && !cfg!(feature = "faces") {
+        return Err(CliError::Args(
+            "--focus_faces needs the CLI to be built with the faces feature".into(),
+        ));
+    }
+    Ok(focus_faces)
+}
+
+// Prints the warnings the library logs, like the GPU resizing with a different filter than the
+//  one asked for
+#[cfg(feature = "gpu")]
+struct LibraryWarnings;
+
+#[cfg(feature = "gpu")]
+impl log::Log for LibraryWarnings {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn && metadata.target().starts_with("ffphotojoin")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("Warning: {}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Joins the items on the GPU (if there is one) when `gpu` is set
+#[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+fn join_items(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    gpu: bool,
+    progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    #[cfg(feature = "gpu")]
+    if gpu {
+        return ffphotojoin::gpu::join_items_with_progress(items, options, progress);
     }
-    println!(
-        "Generated {}x{} image",
-        output_image.width(),
-        output_image.height(),
+    ffphotojoin::join_items_with_progress(items, options, progress)
+}
+
+// Joins the input images (or the compose expression) into one image, with the captions and title
+//  the arguments ask for. Also returns the files that were joined, in order, which leaves out any
+//  skipped with `--skip_errors`
+fn join_inputs(
+    inputs: &[PathBuf],
+    compose: Option<Expr>,
+    options: PhotoJoinOptions,
+    arg_matcher: &ArgMatches,
+    background: Rgba<u8>,
+    report: Option<&mut JsonReport>,
+) -> CliResult<(DynamicImage, Vec<PathBuf>)> {
+    let caption_style = if arg_matcher.is_present("caption_from_filename") {
+        Some(caption_style(arg_matcher, background)?)
+    } else {
+        None
+    };
+    let mut report = report;
+    let (output_image, joined) = match (compose, caption_style) {
+        (Some(expr), _) => (
+            ffphotojoin::join_tree(&expr.into_node()?, options),
+            inputs.to_vec(),
+        ),
+        (None, style) => {
+            let autocrop = autocrop_arg(arg_matcher)?;
+            let (joined, mut images): (Vec<_>, Vec<_>) =
+                load_inputs(inputs, arg_matcher, background)?
+                    .into_iter()
+                    .map(|(file, image)| match autocrop {
+                        Some(tolerance) => (file.clone(), preprocess::autocrop(&image, tolerance)),
+                        None => (file.clone(), image),
+                    })
+                    .unzip();
+            if let Some(reference) = match_colors_arg(arg_matcher) {
+                match_colors_to(&mut images, reference.as_deref())?;
+            }
+            let adjustments = adjustments_arg(arg_matcher)?;
+            if !adjustments.is_none() {
+                for image in images.iter_mut() {
+                    *image = preprocess::adjust(image, &adjustments);
+                }
+            }
+            let progress = progress(true, report.as_deref_mut());
+            let weights = weights_arg(arg_matcher)?;
+            let gpu = gpu_arg(arg_matcher)?;
+            let focus_faces = focus_faces_arg(arg_matcher)?;
+            let output_image = if style.is_none() && weights.is_empty() && !gpu && !focus_faces {
+                ffphotojoin::join_photos_with_progress(images, options, progress)
+            } else {
+                // Weights go by where the image is in the inputs, in case some were skipped
+                let weight = |file: &PathBuf| {
+                    let index = inputs.iter().position(|input| input == file);
+                    index.and_then(|index| weights.get(index)).copied()
+                };
+                #[cfg_attr(not(feature = "faces"), allow(unused_mut))]
+                let mut items = joined
+                    .iter()
+                    .zip(images)
+                    .map(|(file, image)| JoinItem {
+                        weight: weight(file).unwrap_or(1.0),
+                        caption: style
+                            .as_ref()
+                            .map(|_| file.file_name().unwrap().to_string_lossy().into_owned()),
+                        ..JoinItem::new(image)
+                    })
+                    .collect::<Vec<_>>();
+                #[cfg(feature = "faces")]
+                if focus_faces {
+                    ffphotojoin::faces::focus_on_faces(&mut items);
+                }
+                match style {
+                    Some(style) => {
+                        text::join_items_captioned_with_progress(items, options, &style, progress)
+                    }
+                    None => join_items(items, options, gpu, progress),
+                }
+            };
+            (output_image, joined)
+        }
+    };
+    let output_image = output_image.or_join("failed to join photos")?;
+    let output_image = match arg_matcher.value_of("title") {
+        Some(title) => {
+            let title_options = title_options(arg_matcher, title, background)?;
+            let titled =
+                text::add_title(&output_image, &title_options).or_join("failed to add title")?;
+            if let (Some(report), CaptionPosition::Above) = (report, title_options.style.position) {
+                report.shift_down(titled.height() - output_image.height());
+            }
+            titled
+        }
+        None => output_image,
+    };
+    Ok((output_image, joined))
+}
+
+// The progress callback of a join, which draws a progress bar unless `show_bar` is off and
+//  records each event for `--json`
+fn progress<'a>(
+    show_bar: bool,
+    mut report: Option<&'a mut JsonReport>,
+) -> impl FnMut(JoinEvent) + 'a {
+    let mut bar = show_bar.then(join_progress);
+    move |event| {
+        if let Some(bar) = bar.as_mut() {
+            bar(event);
+        }
+        if let Some(report) = report.as_mut() {
+            report.event(event);
+        }
+    }
+}
+
+fn caption_style(arg_matcher: &ArgMatches, background: Rgba<u8>) -> CliResult<CaptionStyle> {
+    Ok(CaptionStyle {
+        font: load_font(arg_matcher)?,
+        size: parse_arg(arg_matcher, "caption_size", "caption size")?
+            .unwrap_or(DEFAULT_CAPTION_SIZE),
+        color: color_arg(arg_matcher, "caption_color", "caption color")?
+            .unwrap_or_else(|| ffphotojoin::contrasting_color(background)),
+        position: match arg_matcher
+            .value_of("caption_position")
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("above") => CaptionPosition::Above,
+            Some("over") => CaptionPosition::Over(Anchor::Bottom),
+            None | Some("below") => CaptionPosition::Below,
+            Some(_) => return Err(CliError::Args("invalid caption position".into())),
+        },
+    })
+}
+
+fn title_options(
+    arg_matcher: &ArgMatches,
+    text: &str,
+    background: Rgba<u8>,
+) -> CliResult<TitleOptions> {
+    Ok(TitleOptions {
+        text: text.to_string(),
+        style: CaptionStyle {
+            font: load_font(arg_matcher)?,
+            size: parse_arg(arg_matcher, "title_size", "title size")?.unwrap_or(DEFAULT_TITLE_SIZE),
+            color: color_arg(arg_matcher, "caption_color", "caption color")?
+                .unwrap_or_else(|| ffphotojoin::contrasting_color(background)),
+            position: match arg_matcher
+                .value_of("title_position")
+                .map(str::to_lowercase)
+                .as_deref()
+            {
+                Some("below") => CaptionPosition::Below,
+                None | Some("above") => CaptionPosition::Above,
+                Some(_) => return Err(CliError::Args("invalid title position".into())),
+            },
+        },
+        background,
+    })
+}
+
+fn load_font(arg_matcher: &ArgMatches) -> CliResult<FontArc> {
+    let font_path = arg_matcher
+        .value_of("caption_font")
+        .or_args("captions and titles need a font file (--caption_font)")?;
+    let font_data =
+        std::fs::read(shellexpand::tilde(font_path).as_ref()).or_io("failed to read font file")?;
+    FontArc::try_from_vec(font_data).or_args("invalid font file")
+}
+
+// Writes the output image, encoded with `encode_output`
+fn save_image(
+    img: &DynamicImage,
+    output_path: &Path,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    metadata_source: Option<&Path>,
+    overwrite: Overwrite,
+) -> CliResult<()> {
+    let sized = encode_output(img, format, options, metadata_source)?;
+    if options.target_size.is_some() {
+        if let Some((width, height)) = sized.shrunk_to {
+            eprintln!("Shrunk to {}x{} to fit the target size", width, height);
+        }
+        eprintln!(
+            "Encoded in {} bytes at quality {}",
+            sized.encoded.len(),
+            sized.quality
+        );
+    }
+    write_output(output_path, &sized.encoded, overwrite)
+}
+
+// Encodes the output image, with the EXIF date, camera, and copyright fields of `metadata_source`
+//  copied into it, and marked as sRGB (since every image was converted to sRGB when it was opened)
+//  when the output format can hold them
+fn encode_output(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    metadata_source: Option<&Path>,
+) -> CliResult<SizedEncoding> {
+    let metadata = match format {
+        ImageFormat::Jpeg | ImageFormat::Png => {
+            metadata_source.and_then(|source| Metadata::read(source, &MetadataField::ALL).ok())
+        }
+        _ => None,
+    };
+    // The metadata is part of the encoded size, so the target size counts it too
+    ffphotojoin::encode_to_size(img, format, options, |img, options| {
+        Ok(match format {
+            ImageFormat::Jpeg | ImageFormat::Png => {
+                let encoded = match &metadata {
+                    Some(metadata) => {
+                        ffphotojoin::encode_with_metadata(img, format, metadata, options)?
+                    }
+                    None => ffphotojoin::encode(img, format, options)?,
+                };
+                color::embed_srgb_profile(encoded, format)
+            }
+            _ => ffphotojoin::encode(img, format, options)?,
+        })
+    })
+    .or_join("failed to encode output image")
+}
+
+// How the outputs of a run are written, besides how they're encoded
+#[derive(Copy, Clone)]
+struct OutputOptions {
+    overwrite: Overwrite,
+    strip_metadata: bool,
+    // Report each output (and the progress of joining it) as JSON lines on stdout
+    json: bool,
+    // Save where each image went in each output to a file next to it
+    manifest: bool,
+    // Save where each image went in each output as sprite coordinates in this format
+    coordinates: Option<AtlasFormat>,
+}
+
+impl OutputOptions {
+    fn from_args(arg_matcher: &ArgMatches) -> CliResult<Self> {
+        let coordinates = match arg_matcher.value_of("coordinates") {
+            None => None,
+            Some("json") => Some(AtlasFormat::Json),
+            Some("css") => Some(AtlasFormat::Css),
+            Some("texture_packer") => Some(AtlasFormat::TexturePacker),
+            Some(_) => return Err(CliError::Args("invalid coordinates format".into())),
+        };
+        Ok(Self {
+            overwrite: Overwrite::from_args(arg_matcher),
+            strip_metadata: arg_matcher.is_present("strip_metadata"),
+            json: arg_matcher.is_present("json"),
+            manifest: arg_matcher.is_present("manifest"),
+            coordinates,
+        })
+    }
+
+    // Keeps track of an output for its JSON report, manifest, or coordinates, if it needs any
+    fn report(&self) -> Option<JsonReport> {
+        (self.json || self.manifest || self.coordinates.is_some())
+            .then(|| JsonReport::new(self.json))
+    }
+}
+
+// What's done with an output file that's already there
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Overwrite {
+    // Stop before doing any work
+    Fail,
+    Replace,
+    // Rename it with a `.bak` extension first, replacing any older backup
+    Backup,
+    // Leave it as it is, and skip the work of making that output
+    Skip,
+}
+
+impl Overwrite {
+    fn from_args(arg_matcher: &ArgMatches) -> Self {
+        if arg_matcher.is_present("overwrite") {
+            Overwrite::Replace
+        } else if arg_matcher.is_present("backup") {
+            Overwrite::Backup
+        } else if arg_matcher.is_present("no_overwrite") {
+            Overwrite::Skip
+        } else {
+            Overwrite::Fail
+        }
+    }
+}
+
+// Prints the report of a saved output for `--json`, and saves its manifest for `--manifest` and its
+//  coordinates for `--coordinates`
+fn finish_report(
+    report: JsonReport,
+    output_path: &Path,
+    page: Option<usize>,
+    dimensions: (u32, u32),
+    files: &[PathBuf],
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    let report = report.report(output_path, page, dimensions, files);
+    if output_options.json {
+        print_report(&report);
+    }
+    if output_options.manifest {
+        Manifest::new(&report).save(&Manifest::sidecar_path(output_path))?;
+    }
+    if let Some(format) = output_options.coordinates {
+        save_coordinates(&report, format)?;
+    }
+    Ok(())
+}
+
+// The encoder settings from the arguments
+fn encode_options(arg_matcher: &ArgMatches) -> CliResult<EncodeOptions> {
+    let defaults = EncodeOptions::default();
+    Ok(EncodeOptions {
+        quality: parse_arg(arg_matcher, "quality", "quality")?.unwrap_or(defaults.quality),
+        png_compression: match arg_matcher
+            .value_of("png_compression")
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            None => defaults.png_compression,
+            Some("fast") => PngCompression::Fast,
+            Some("default") => PngCompression::Default,
+            Some("best") => PngCompression::Best,
+            Some(_) => return Err(CliError::Args("invalid PNG compression".into())),
+        },
+        webp_lossless: arg_matcher.is_present("webp_lossless"),
+        avif_speed: parse_arg(arg_matcher, "avif_speed", "AVIF speed")?
+            .unwrap_or(defaults.avif_speed),
+        target_size: match arg_matcher.value_of("target_size") {
+            Some(size) => Some(TargetSize {
+                max_bytes: parse_byte_size(size).or_args("invalid target size")?,
+                downscale: arg_matcher.is_present("downscale_to_fit"),
+            }),
+            None => None,
+        },
+    })
+}
+
+// A number of bytes like 5MB or 2.5MiB, where KB/MB/GB are powers of 1000 and KiB/MiB/GiB are
+//  powers of 1024
+fn parse_byte_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_lowercase();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let unit = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return None,
+    };
+    let bytes = number.parse::<f64>().ok()? * unit as f64;
+    (bytes >= 1.0).then_some(bytes as u64)
+}
+
+// The format of the output image, from the `--format` argument or else the output file's
+//  extension. Images written to stdout are PNGs unless a format is given
+fn output_format(output_path: &Path, format: Option<&str>) -> CliResult<ImageFormat> {
+    let format = match format {
+        Some(format) => ImageFormat::from_extension(format).or_args("invalid output format")?,
+        None if is_stdout(output_path) => ImageFormat::Png,
+        None => ImageFormat::from_path(output_path).or_args("unknown output file extension")?,
+    };
+    check_encodable(format)?;
+    Ok(format)
+}
+
+// Stops with an error for the formats whose encoders are behind features the CLI wasn't built
+//  with, before any images are joined
+fn check_encodable(format: ImageFormat) -> CliResult<()> {
+    match format {
+        #[cfg(not(feature = "webp"))]
+        ImageFormat::WebP => Err(CliError::Args(
+            "WebP output needs the CLI to be built with the webp feature".into(),
+        )),
+        #[cfg(not(feature = "avif"))]
+        ImageFormat::Avif => Err(CliError::Args(
+            "AVIF output needs the CLI to be built with the avif feature".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// The multi-page format of the output file, if it's one, from the `--format` argument or else the
+//  output file's extension. Pages written to stdout are a PDF unless a format is given
+fn page_format(output_path: &Path, format: Option<&str>) -> Option<PageFormat> {
+    let format = match format {
+        Some(format) => format.to_lowercase(),
+        None if is_stdout(output_path) => "pdf".to_string(),
+        None => output_path
+            .extension()
+            .and_then(|ext| ext.to_str())?
+            .to_lowercase(),
+    };
+    match format.as_str() {
+        "tif" | "tiff" => Some(PageFormat::Tiff),
+        "pdf" => Some(PageFormat::Pdf),
+        _ => None,
+    }
+}
+
+// The output file of one page of a paginated join, numbered from 1 like `out_001.png` (or labeled
+//  with its group like `out_2021-05-04.png`) unless the output path is a template that names them
+//  already
+fn page_path(output_path: &Path, index: usize, label: Option<&str>) -> PathBuf {
+    if template::names_pages(output_path) {
+        return output_path.to_path_buf();
+    }
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let suffix = match label {
+        Some(label) => label.to_string(),
+        None => format!("{:03}", index + 1),
+    };
+    let name = match output_path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    output_path.with_file_name(name)
+}
+
+// The file to write an output to, with the variables filled in if the output path is a template,
+//  or nothing if it's skipped. Templated paths can only be checked for an existing file once
+//  they're filled in
+fn output_file(
+    output_path: &Path,
+    vars: &OutputVars,
+    overwrite: Overwrite,
+) -> CliResult<Option<PathBuf>> {
+    if !template::is_template(output_path) {
+        return Ok(Some(output_path.to_path_buf()));
+    }
+    let path = template::fill_template(output_path, vars)?;
+    Ok(check_output(&path, overwrite)?.then_some(path))
+}
+
+// Whether an output should be made, checked before doing any work. Stops if the output file is
+//  already there and can't be replaced
+fn check_output(output_path: &Path, overwrite: Overwrite) -> CliResult<bool> {
+    if !output_path.exists() || is_stdout(output_path) {
+        return Ok(true);
+    }
+    match overwrite {
+        Overwrite::Fail => Err(CliError::Io(format!(
+            "output file {} already exists",
+            output_path.display()
+        ))),
+        Overwrite::Skip => {
+            eprintln!("Skipping {}, it already exists", output_path.display());
+            Ok(false)
+        }
+        Overwrite::Replace | Overwrite::Backup => Ok(true),
+    }
+}
+
+// Writes an encoded output file, or writes it to stdout. Files are written to a temporary file next
+//  to them and then renamed into place, so they're never seen (or left) half written
+fn write_output(output_path: &Path, encoded: &[u8], overwrite: Overwrite) -> CliResult<()> {
+    if is_stdout(output_path) {
+        io::stdout()
+            .lock()
+            .write_all(encoded)
+            .or_io("failed to write output to stdout")
+    } else {
+        let temp_path = temp_path(output_path);
+        fs::write(&temp_path, encoded)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&temp_path);
+            })
+            .or_io("failed to save output file")?;
+        finish_output(&temp_path, output_path, overwrite)
+    }
+}
+
+// The hidden file an output is written to before it's finished, like `.joined.png.tmp`
+fn temp_path(output_path: &Path) -> PathBuf {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(output_path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    output_path.with_file_name(temp_name)
+}
+
+// Renames a finished output into place, keeping the file it replaces as a backup with `--backup`
+fn finish_output(temp_path: &Path, output_path: &Path, overwrite: Overwrite) -> CliResult<()> {
+    if overwrite == Overwrite::Backup && output_path.exists() {
+        let mut backup_path = output_path.as_os_str().to_os_string();
+        backup_path.push(".bak");
+        fs::rename(output_path, backup_path).or_io("failed to back up output file")?;
+    }
+    fs::rename(temp_path, output_path).or_io("failed to save output file")
+}
+
+fn is_stdout(output_path: &Path) -> bool {
+    output_path == Path::new("-")
+}
+
+// How the output is described in messages
+fn output_name(output_path: &Path) -> Cow<'_, str> {
+    if is_stdout(output_path) {
+        Cow::Borrowed("stdout")
+    } else {
+        output_path.to_string_lossy()
+    }
+}
+
+// Opens an image, turned upright and converted to sRGB
+fn open_image(path: &Path) -> ImageResult<DynamicImage> {
+    to_srgb(path, ffphotojoin::load_oriented(path)?)
+}
+
+// Same as `open_image`, but JPEGs are decoded at a fraction of their size when that still leaves
+//  their longer side at least `min_size` pixels long
+fn open_image_reduced(path: &Path, min_size: u32) -> ImageResult<DynamicImage> {
+    to_srgb(path, ffphotojoin::load_oriented_reduced(path, min_size)?)
+}
+
+// Same as `open_image`, but only opens the part of the image inside `region`
+fn open_image_region(path: &Path, region: Rect) -> ImageResult<DynamicImage> {
+    to_srgb(path, ffphotojoin::load_oriented_region(path, region)?)
+}
+
+// Converts an image to sRGB from the color profile embedded in its file, if it has one
+fn to_srgb(path: &Path, img: DynamicImage) -> ImageResult<DynamicImage> {
+    Ok(match color::read_icc_profile(path)? {
+        Some(icc) => color::convert_to_srgb(&img, &icc).unwrap_or(img),
+        None => img,
+    })
+}
+
+// The options that low memory joins can't do
+const STREAMING_CONFLICTS: [&str; 16] = [
+    "weights",
+    "autocrop",
+    "match_colors",
+    "brightness",
+    "contrast",
+    "saturate",
+    "grayscale",
+    "sepia",
+    "target_size",
+    "caption_from_filename",
+    "title",
+    "skip_errors",
+    "mipmaps",
+    "sizes",
+    "zoom",
+    "tile",
+];
+
+// Whether joining any of the pages in memory would take more than --memory_limit, so they have to
+//  be joined in low memory mode. Stops with an error when even that would go over the limit, or
+//  the join can't be done in low memory mode
+fn over_memory_limit(
+    arg_matcher: &ArgMatches,
+    pages: &[Vec<PathBuf>],
+    options: PhotoJoinOptions,
+    streamable: bool,
+) -> CliResult<bool> {
+    let limit = match arg_matcher.value_of("memory_limit") {
+        Some(limit) => parse_byte_size(limit).or_args("invalid memory limit")?,
+        None => return Ok(false),
+    };
+    let mut over = false;
+    for page in pages {
+        let estimate = ffphotojoin::estimate_memory(&image_sizes(page)?, options)
+            .or_join("failed to lay out photos")?;
+        if estimate.in_memory <= limit {
+            continue;
+        }
+        let needed = |bytes: u64| format!("{:.1}MB", bytes as f64 / 1_000_000.0);
+        if !streamable {
+            return Err(CliError::Args(format!(
+                "joining the photos takes about {} of memory, more than the memory limit, and \
+                 these options can't be joined in low memory mode",
+                needed(estimate.in_memory)
+            )));
+        }
+        if estimate.streaming > limit {
+            return Err(CliError::Args(format!(
+                "joining the photos takes about {} of memory even in low memory mode, more than \
+                 the memory limit",
+                needed(estimate.streaming)
+            )));
+        }
+        over = true;
+    }
+    if over {
+        eprintln!("Joining in low memory mode to stay under the memory limit");
+    }
+    Ok(over)
+}
+
+fn join_streaming(
+    files: &[PathBuf],
+    output_path: &Path,
+    output_format: ImageFormat,
+    options: PhotoJoinOptions,
+    index: Option<usize>,
+    group: Option<String>,
+    output_options: &OutputOptions,
+) -> CliResult<()> {
+    if is_stdout(output_path) {
+        return Err(CliError::Args(
+            "low memory joins can't write to stdout".into(),
+        ));
+    }
+    let format = match output_format {
+        ImageFormat::Png => StreamFormat::Png,
+        ImageFormat::Tiff => StreamFormat::Tiff,
+        _ => {
+            return Err(CliError::Args(
+                "low memory joins can only write PNG or TIFF files".into(),
+            ))
+        }
+    };
+
+    // Only the sizes are needed up front, the images themselves are opened as they're needed
+    let sizes = image_sizes(files)?;
+    // Templated output paths need the size of the output before the file is created
+    let layout = ffphotojoin::compute_layout(&sizes, options).or_join("failed to join photos")?;
+    let mut report = output_options.report();
+    if let Some(report) = report.as_mut() {
+        report.set_placements(
+            layout
+                .placements
+                .iter()
+                .map(|placement| placement.target)
+                .collect(),
+        );
+    }
+    let output_path = match output_file(
+        output_path,
+        &OutputVars {
+            count: files.len(),
+            width: layout.width,
+            height: layout.height,
+            index,
+            group,
+        },
+        output_options.overwrite,
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    let temp_path = temp_path(&output_path);
+    let output = BufWriter::new(File::create(&temp_path).or_io("failed to create output file")?);
+    let bar = counting_bar("Joining", files.len());
+    // Only the parts of the images that are used are opened, which matters when they're cropped
+    let joined = ffphotojoin::join_photos_streaming_regions(
+        &sizes,
+        |index, region| -> ImageResult<_> {
+            show_file(&bar, &files[index]);
+            let image = open_image_region(&files[index], region);
+            bar.inc(1);
+            image
+        },
+        options,
+        format,
+        output,
     );
-    output_image
-        .save(&output_path)
-        .expect("failed to save image to output file");
-    println!("Saved joined photo to {}", output_path.to_str().unwrap());
+    bar.finish();
+    let (width, height) = match joined {
+        Ok(size) => size,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err.into());
+        }
+    };
+    finish_output(&temp_path, &output_path, output_options.overwrite)?;
+
+    eprintln!("Generated {}x{} image", width, height);
+    eprintln!("Saved joined photo to {}", output_path.display());
+    if let Some(report) = report {
+        finish_report(
+            report,
+            &output_path,
+            None,
+            (width, height),
+            files,
+            output_options,
+        )?;
+    }
+    Ok(())
 }
 
-fn load_images(files: Vec<PathBuf>) -> Vec<DynamicImage> {
+// The sizes of images (after they're turned upright) without opening them all the way
+fn image_sizes(files: &[PathBuf]) -> CliResult<Vec<(u32, u32)>> {
     files
-        .into_iter()
+        .iter()
         .map(|file| {
-            println!("Opening {}", file.to_str().unwrap());
-            Reader::open(file)
-                .expect("failed to open image file")
-                .decode()
-                .expect("failed to decode image")
+            ffphotojoin::oriented_dimensions(file).map_err(|err| CliError::image(file, err))
         })
         .collect()
 }
+
+// The input files with directories and glob patterns expanded, sorted the way the arguments ask
+//  for
+fn input_paths(arg_matcher: &ArgMatches) -> CliResult<Vec<PathBuf>> {
+    let extensions: Vec<String> = match arg_matcher.value_of("extensions") {
+        Some(extensions) => extensions
+            .split(',')
+            .map(|ext| ext.trim().trim_start_matches('.').to_string())
+            .collect(),
+        None => DEFAULT_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+    };
+    let recursive = arg_matcher.is_present("recursive");
+    let mut listed = Vec::new();
+    for input in arg_matcher.values_of("input").into_iter().flatten() {
+        match input {
+            "-" => listed.extend(read_path_list(io::stdin().lock())?),
+            _ => listed.push(input.to_string()),
+        }
+    }
+    match arg_matcher.value_of("files_from") {
+        Some("-") => listed.extend(read_path_list(io::stdin().lock())?),
+        Some(list) => listed.extend(read_path_list(
+            File::open(shellexpand::tilde(list).as_ref())
+                .or_io("failed to open list of input files")?,
+        )?),
+        None => {}
+    }
+    let mut inputs = Vec::new();
+    for input in &listed {
+        inputs.extend(expand_input(input, recursive, &extensions)?);
+    }
+    let order = arg_matcher
+        .value_of("sort")
+        .map(|order| SortOrder::parse(order).or_args("invalid sort order"))
+        .transpose()?
+        .unwrap_or(SortOrder::None);
+    sort_inputs(&mut inputs, order)?;
+    if arg_matcher.is_present("dedupe") {
+        inputs = dedupe_inputs(inputs, arg_matcher)?;
+    }
+    Ok(inputs)
+}
+
+// Leaves out images that look nearly the same as one before them, going by the perceptual hashes
+//  of thumbnails of them. Images that can't be opened are kept, so they fail (or get skipped) the
+//  same way they would without this
+fn dedupe_inputs(files: Vec<PathBuf>, arg_matcher: &ArgMatches) -> CliResult<Vec<PathBuf>> {
+    let max_distance = match arg_matcher.value_of("dedupe") {
+        Some(distance) => distance
+            .parse()
+            .ok()
+            .filter(|distance| *distance <= 64)
+            .or_args("invalid dedupe distance")?,
+        None => ffphotojoin::DEFAULT_DEDUPE_DISTANCE,
+    };
+    let hashes = map_files(&files, decode_threads(arg_matcher)?, "Comparing", |file| {
+        ffphotojoin::load_oriented_scaled(file, HASH_THUMBNAIL_SIZE)
+            .ok()
+            .map(|img| ffphotojoin::perceptual_hash(&img))
+    });
+    let kept = ffphotojoin::unique_indices(&hashes, max_distance);
+    if kept.len() < files.len() {
+        eprintln!(
+            "Left out {} near-duplicate images",
+            files.len() - kept.len()
+        );
+    }
+    Ok(kept.into_iter().map(|index| files[index].clone()).collect())
+}
+
+// Opens images that are only needed with their longer side `min_size` pixels long or more, if
+//  there's a limit, which lets JPEGs be decoded at a fraction of their size
+fn load_images(
+    files: Vec<PathBuf>,
+    threads: usize,
+    min_size: Option<u32>,
+) -> CliResult<Vec<DynamicImage>> {
+    let images = map_files(&files, threads, "Decoding", |file| match min_size {
+        Some(min_size) => open_image_reduced(file, min_size),
+        None => open_image(file),
+    });
+    files
+        .iter()
+        .zip(images)
+        .map(|(file, image)| image.map_err(|err| CliError::image(file, err)))
+        .collect()
+}
+
+// Opens the input images of a join. With `--skip_errors`, images that can't be opened are left out
+//  (or replaced by a placeholder tile with `--placeholders`) instead of stopping the whole join
+fn load_inputs<'a>(
+    inputs: &'a [PathBuf],
+    arg_matcher: &ArgMatches,
+    background: Rgba<u8>,
+) -> CliResult<Vec<(&'a PathBuf, DynamicImage)>> {
+    let images = decode_images(inputs, decode_threads(arg_matcher)?);
+    if !arg_matcher.is_present("skip_errors") {
+        return inputs
+            .iter()
+            .zip(images)
+            .map(|(file, image)| Ok((file, image.map_err(|err| CliError::image(file, err))?)))
+            .collect();
+    }
+
+    // Placeholders are the size of the first image that could be opened, so they fit in
+    let placeholder_size = images
+        .iter()
+        .find_map(|image| image.as_ref().ok())
+        .map_or((DEFAULT_CELL_SIZE, DEFAULT_CELL_SIZE), |image| {
+            image.dimensions()
+        });
+    let mut loaded = Vec::new();
+    for (file, image) in inputs.iter().zip(images) {
+        match image {
+            Ok(image) => loaded.push((file, image)),
+            Err(err) => {
+                eprintln!("Skipping {}: {}", file.display(), err);
+                if arg_matcher.is_present("placeholders") {
+                    let tile = placeholder(file, placeholder_size, arg_matcher, background)?;
+                    loaded.push((file, tile));
+                }
+            }
+        }
+    }
+    if loaded.is_empty() {
+        return Err(CliError::Decode(
+            "none of the input images could be opened".into(),
+        ));
+    }
+    Ok(loaded)
+}
+
+// A gray tile that stands in for an image that couldn't be opened, with the image's filename on it
+//  when there's a font to write it with
+fn placeholder(
+    file: &Path,
+    (width, height): (u32, u32),
+    arg_matcher: &ArgMatches,
+    background: Rgba<u8>,
+) -> CliResult<DynamicImage> {
+    let tile = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, height, PLACEHOLDER_COLOR));
+    if !arg_matcher.is_present("caption_font") {
+        return Ok(tile);
+    }
+    let title = TitleOptions {
+        text: file.file_name().unwrap().to_string_lossy().into_owned(),
+        style: CaptionStyle {
+            position: CaptionPosition::Over(Anchor::Center),
+            ..caption_style(arg_matcher, background)?
+        },
+        background: PLACEHOLDER_COLOR,
+    };
+    text::add_title(&tile, &title).or_join("failed to draw placeholder")
+}
+
+// Decodes the images on `threads` threads at once, keeping them in the same order as the files
+fn decode_images(files: &[PathBuf], threads: usize) -> Vec<ImageResult<DynamicImage>> {
+    map_files(files, threads, "Decoding", open_image)
+}
+
+// Does `work` to every file on `threads` threads at once, showing it on a bar labeled with
+//  `action`, and gives the results in the same order as the files
+fn map_files<T: Send>(
+    files: &[PathBuf],
+    threads: usize,
+    action: &str,
+    work: impl Fn(&Path) -> T + Sync,
+) -> Vec<T> {
+    let bar = counting_bar(action, files.len());
+    let load = |file: &PathBuf| {
+        show_file(&bar, file);
+        let result = work(file);
+        bar.inc(1);
+        result
+    };
+    if threads <= 1 {
+        let results = files.iter().map(load).collect();
+        bar.finish();
+        return results;
+    }
+
+    // Each thread takes the next file that hasn't been started yet, and the results are put back
+    //  in order once they're all done
+    let next_file = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..threads.min(files.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next_file.fetch_add(1, Ordering::Relaxed);
+                        match files.get(index) {
+                            Some(file) => results.push((index, load(file))),
+                            None => return results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect::<Vec<_>>()
+    });
+    bar.finish();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// How many images are decoded at the same time, which is one per CPU unless `--threads` says
+//  otherwise
+fn decode_threads(arg_matcher: &ArgMatches) -> CliResult<usize> {
+    match parse_arg(arg_matcher, "threads", "number of threads")? {
+        Some(threads) => Ok(threads),
+        None => Ok(thread::available_parallelism().map_or(1, usize::from)),
+    }
+}
+
+// The output file argument, with `~` expanded
+fn output_arg(arg_matcher: &ArgMatches) -> CliResult<PathBuf> {
+    let output = arg_matcher.value_of("output").or_args("no output file")?;
+    Ok(PathBuf::from(shellexpand::tilde(output).as_ref()))
+}
+
+// The value of an argument parsed as a `T`, if it's given. `what` names the value in the error
+//  message if it's invalid
+fn parse_arg<T: FromStr>(arg_matcher: &ArgMatches, name: &str, what: &str) -> CliResult<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    arg_matcher
+        .value_of(name)
+        .map(|value| value.parse().or_args(&format!("invalid {}", what)))
+        .transpose()
+}
+
+// The value of a size argument like 800x600, if it's given
+fn dimensions_arg(
+    arg_matcher: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> CliResult<Option<(u32, u32)>> {
+    arg_matcher
+        .value_of(name)
+        .map(|size| {
+            let size = size.to_lowercase();
+            let (width, height) = size.split_once('x').or_args(&format!("invalid {}", what))?;
+            Ok((
+                width.trim().parse().or_args(&format!("invalid {}", what))?,
+                height
+                    .trim()
+                    .parse()
+                    .or_args(&format!("invalid {}", what))?,
+            ))
+        })
+        .transpose()
+}
+
+// The value of a color argument, if it's given
+fn color_arg(arg_matcher: &ArgMatches, name: &str, what: &str) -> CliResult<Option<Rgba<u8>>> {
+    arg_matcher
+        .value_of(name)
+        .map(|color| parse_color(color).or_args(&format!("invalid {}", what)))
+        .transpose()
+}
+
+// How the filters the images are resized with are described in messages
+fn filter_name(options: &PhotoJoinOptions) -> String {
+    let mut name = match options.auto_filter {
+        true => "auto".to_string(),
+        false => format!("{:?}", options.filter),
+    };
+    if let Some(filter) = options.filter_up {
+        name += &format!(" ({:?} when enlarging)", filter);
+    }
+    if let Some(filter) = options.filter_down {
+        name += &format!(" ({:?} when shrinking)", filter);
+    }
+    name
+}
+
+// Whether the filter is picked for each image (`--filter auto`), rather than being the one given
+fn auto_filter_arg(arg_matcher: &ArgMatches) -> bool {
+    arg_matcher
+        .value_of("filter")
+        .is_some_and(|filter| filter.eq_ignore_ascii_case("auto"))
+}
+
+// The value of a filter argument, if it's given. `--filter auto` leaves the filter to be picked
+//  for each image (see `auto_filter_arg`)
+fn filter_arg(arg_matcher: &ArgMatches, name: &str, what: &str) -> CliResult<Option<FilterType>> {
+    match arg_matcher.value_of(name) {
+        Some(filter) if name == "filter" && filter.eq_ignore_ascii_case("auto") => Ok(None),
+        Some(filter) => parse_filter(filter)
+            .map(Some)
+            .or_args(&format!("invalid {}", what)),
+        None => Ok(None),
+    }
+}
+
+fn parse_filter(filter: &str) -> Option<FilterType> {
+    match filter.to_lowercase().as_str() {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" => Some(FilterType::Triangle),
+        "catmull_rom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" => Some(FilterType::Lanczos3),
+        _ => None,
+    }
+}
+
+fn parse_color(color: &str) -> Option<Rgba<u8>> {
+    match color.to_lowercase().as_str() {
+        "black" => Some(Rgba([0, 0, 0, 255])),
+        "white" => Some(Rgba([255, 255, 255, 255])),
+        "transparent" => Some(Rgba([0, 0, 0, 0])),
+        hex => {
+            let hex = hex.trim_start_matches('#');
+            if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+            Some(Rgba([
+                channel(0)?,
+                channel(1)?,
+                channel(2)?,
+                if hex.len() == 8 { channel(3)? } else { 255 },
+            ]))
+        }
+    }
+}
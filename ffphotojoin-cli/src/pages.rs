@@ -0,0 +1,92 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::template::OutputVars;
+use crate::{
+    check_output, decode_threads, input_args, input_paths, is_stdout, load_images, order_arg,
+    output_arg, output_args, output_file, output_name, parse_arg, pick_args, write_output,
+    Overwrite,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::GenericImageView;
+use ffphotojoin::{EncodeOptions, PageFormat};
+
+pub fn command() -> App<'static> {
+    clap_app!(pages =>
+        (about: "Writes each image as a page of a multi-page TIFF or PDF file instead of joining them")
+        (@arg output: -o --output +required +takes_value "Set the output file (.tif/.tiff or .pdf), - writes it to stdout")
+        (@arg format: --format +takes_value "Set the format of the output file instead of going by its extension (tiff/pdf, defaults to pdf for stdout)")
+        (@arg quality: --quality +takes_value "Set the JPEG quality of PDF pages from 1 to 100 (defaults to 75)")
+    )
+    .args(input_args())
+    .args(pick_args(output_args(), &["overwrite", "backup", "no_overwrite"]))
+    .mut_arg("input", |arg| {
+        arg.required_unless_present("files_from")
+            .help("Provides the images to use as pages (directories and glob patterns like photos/*.jpg work too, - reads a list of files from stdin)")
+    })
+}
+
+pub fn make_pages(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let inputs = input_paths(arg_matcher)?;
+    if inputs.is_empty() {
+        return Err(CliError::Args("no input files provided".into()));
+    }
+    let output_path = output_arg(arg_matcher)?;
+    let format = match arg_matcher.value_of("format") {
+        Some(format) => format.to_lowercase(),
+        None if is_stdout(&output_path) => "pdf".to_string(),
+        None => output_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .or_args("unknown output file extension")?
+            .to_lowercase(),
+    };
+    let format = match format.as_str() {
+        "tif" | "tiff" => PageFormat::Tiff,
+        "pdf" => PageFormat::Pdf,
+        _ => {
+            return Err(CliError::Args(
+                "pages can only be written to TIFF or PDF files".into(),
+            ))
+        }
+    };
+    if !check_output(&output_path, Overwrite::from_args(arg_matcher))? {
+        return Ok(());
+    }
+
+    let encode_options = EncodeOptions {
+        quality: parse_arg(arg_matcher, "quality", "quality")?
+            .unwrap_or(EncodeOptions::default().quality),
+        ..EncodeOptions::default()
+    };
+
+    eprintln!("Writing {} photos as pages", inputs.len());
+    let inputs = order_arg(arg_matcher)?
+        .indices(inputs.len())
+        .into_iter()
+        .map(|index| inputs[index].clone())
+        .collect();
+    let pages = load_images(inputs, decode_threads(arg_matcher)?, None)?;
+    let mut encoded = Vec::new();
+    ffphotojoin::encode_pages(&pages, &mut encoded, format, &encode_options)
+        .or_join("failed to encode pages")?;
+    let output_path = match output_file(
+        &output_path,
+        &OutputVars {
+            count: pages.len(),
+            width: pages[0].width(),
+            height: pages[0].height(),
+            index: None,
+            group: None,
+        },
+        Overwrite::from_args(arg_matcher),
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    write_output(&output_path, &encoded, Overwrite::from_args(arg_matcher))?;
+    eprintln!(
+        "Saved {} pages to {}",
+        pages.len(),
+        output_name(&output_path)
+    );
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::progress::{counting_bar, show_file};
+use crate::terminal::{show_image, Viewer};
+use crate::{
+    adjustments_arg, autocrop_arg, direction_arg, direction_args, image_sizes, input_args,
+    input_paths, join_options, layout_args, match_colors_arg, match_colors_to, paginate, parse_arg,
+    weights_arg,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::imageops::FilterType;
+use ffphotojoin::image::{DynamicImage, GenericImageView};
+use ffphotojoin::preprocess::{self, Adjustments};
+use ffphotojoin::{JoinItem, PhotoJoinOptions, Rect};
+use std::path::PathBuf;
+
+const DEFAULT_PREVIEW_SIZE: u32 = 1024;
+
+pub fn command() -> App<'static> {
+    clap_app!(preview =>
+        (about: "Shows a quick low resolution version of the output in the terminal (or the system's image viewer), without writing anything")
+        (@arg layout_only: --layout_only conflicts_with[autocrop match_colors brightness contrast saturate grayscale sepia] "Only print how big the output would be and where each image would go in it")
+        (@arg preview_size: --preview_size +takes_value conflicts_with[layout_only] "Largest width or height of the preview in pixels (1024 by default)")
+        (@arg viewer: --viewer +takes_value conflicts_with[layout_only] "Set how the preview is shown (kitty/sixel/system, defaults to what the terminal supports)")
+    )
+    .args(input_args())
+    .args(direction_args())
+    .args(layout_args())
+    .mut_arg("input", |arg| arg.required_unless_present("files_from"))
+    .mut_arg("direction", |arg| arg.required(true))
+}
+
+// Shows a small version of each output a join would write, or just prints their layouts
+pub fn preview(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let inputs = input_paths(arg_matcher)?;
+    let options = join_options(arg_matcher, direction_arg(arg_matcher)?)?;
+    let pages = paginate(inputs, arg_matcher)?;
+    if arg_matcher.is_present("layout_only") {
+        for (label, page) in pages {
+            if let Some(label) = label {
+                println!("{}:", label);
+            }
+            print_layout(&page, options, &weights_arg(arg_matcher)?)?;
+        }
+        return Ok(());
+    }
+
+    let preview_size = parse_arg(arg_matcher, "preview_size", "preview size")?
+        .unwrap_or(DEFAULT_PREVIEW_SIZE)
+        .max(1);
+    let viewer = match arg_matcher.value_of("viewer") {
+        Some(viewer) => Viewer::parse(viewer)
+            .ok_or_else(|| CliError::Args(format!("unknown viewer {}", viewer)))?,
+        None => Viewer::detect(),
+    };
+    for (index, (label, page)) in pages.into_iter().enumerate() {
+        if let Some(label) = &label {
+            eprintln!("{}:", label);
+        }
+        let preview = join_preview(
+            &page,
+            options,
+            &weights_arg(arg_matcher)?,
+            autocrop_arg(arg_matcher)?,
+            match_colors_arg(arg_matcher),
+            &adjustments_arg(arg_matcher)?,
+            preview_size,
+        )?;
+        show_image(&preview, viewer, &index.to_string())?;
+    }
+    Ok(())
+}
+
+// Joins the images at a fraction of their size so the output is at most `preview_size` pixels
+//  wide and tall. JPEGs are decoded straight to a smaller size, which is most of what makes this
+//  quicker than a real join
+fn join_preview(
+    files: &[PathBuf],
+    options: PhotoJoinOptions,
+    weights: &[f32],
+    autocrop: Option<u8>,
+    match_colors: Option<Option<PathBuf>>,
+    adjustments: &Adjustments,
+    preview_size: u32,
+) -> CliResult<DynamicImage> {
+    // Shrinking the output to fit `max_output` doesn't change how it looks, only its size
+    let options = PhotoJoinOptions {
+        max_output: None,
+        ..options
+    };
+    let sizes = image_sizes(files)?;
+    let layout = ffphotojoin::compute_layout_weighted(&sizes, weights, options)
+        .or_join("failed to lay out photos")?;
+    let scale = (preview_size as f64 / layout.width.max(layout.height).max(1) as f64).min(1.0);
+
+    let bar = counting_bar("Decoding", files.len());
+    let mut images = files
+        .iter()
+        .zip(&sizes)
+        .enumerate()
+        .map(|(index, (file, &(width, height)))| {
+            show_file(&bar, file);
+            // Heavier images can be drawn larger than the others
+            let weight = weights.get(index).map_or(1.0, |&weight| weight.max(1.0)) as f64;
+            let max_size = (width.max(height) as f64 * scale * weight).ceil().max(1.0) as u32;
+            let image = ffphotojoin::load_oriented_scaled(file, max_size)
+                .map(|image| match autocrop {
+                    Some(tolerance) => preprocess::autocrop(&image, tolerance),
+                    None => image,
+                })
+                .map_err(|err| CliError::image(file, err));
+            bar.inc(1);
+            image
+        })
+        .collect::<CliResult<Vec<_>>>()?;
+    bar.finish();
+    if let Some(reference) = match_colors {
+        match_colors_to(&mut images, reference.as_deref())?;
+    }
+    let images = images
+        .into_iter()
+        .enumerate()
+        .map(|(index, image)| JoinItem {
+            weight: weights.get(index).copied().unwrap_or(1.0),
+            adjustments: *adjustments,
+            ..JoinItem::new(image)
+        })
+        .collect::<Vec<_>>();
+
+    // The preview looks like a shrunk copy of the real output, so every size in pixels is scaled
+    //  along with the images
+    let options = PhotoJoinOptions {
+        filter: FilterType::Nearest,
+        auto_filter: false,
+        filter_up: None,
+        filter_down: None,
+        max_dimensions: None,
+        ..options.scaled(scale)
+    };
+    let preview = ffphotojoin::join_items(images, options).or_join("failed to join photos")?;
+    Ok(match preview.width().max(preview.height()) > preview_size {
+        true => preview.thumbnail(preview_size, preview_size),
+        false => preview,
+    })
+}
+
+fn print_layout(files: &[PathBuf], options: PhotoJoinOptions, weights: &[f32]) -> CliResult<()> {
+    let sizes = image_sizes(files)?;
+    let layout = ffphotojoin::compute_layout_weighted(&sizes, weights, options)
+        .or_join("failed to lay out photos")?;
+
+    println!("Output image would be {}x{}", layout.width, layout.height);
+    for (file, placement) in files.iter().zip(layout.placements) {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = placement.target;
+        println!("{} at {},{} as {}x{}", file.display(), x, y, width, height);
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use ffphotojoin::JoinEvent;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::Path;
+
+// Progress bars are drawn on stderr with an estimate of the time left, and aren't drawn at all
+//  when stderr isn't a terminal
+
+// A bar that counts `count` images (or files) as they're worked through, like "Decoding" them
+pub fn counting_bar(action: &str, count: usize) -> ProgressBar {
+    let style =
+        ProgressStyle::with_template("{prefix:>9} [{bar:30}] {pos}/{len} ({eta} left) {wide_msg}")
+            .expect("invalid progress bar template")
+            .progress_chars("=> ");
+    ProgressBar::new(count as u64)
+        .with_style(style)
+        .with_prefix(action.to_string())
+}
+
+// Shows which file a bar is on
+pub fn show_file(bar: &ProgressBar, file: &Path) {
+    let name = file.file_name().unwrap_or(file.as_os_str());
+    bar.set_message(name.to_string_lossy().into_owned());
+}
+
+// A progress callback for the library's joins that shows a bar of the images drawn so far
+pub fn join_progress() -> impl FnMut(JoinEvent) {
+    let mut bar: Option<ProgressBar> = None;
+    move |event| match event {
+        JoinEvent::LayoutComputed {
+            images,
+            width,
+            height,
+        } => {
+            eprintln!("Joining {} photos into {}x{}", images, width, height);
+            bar = Some(counting_bar("Joining", images));
+        }
+        JoinEvent::ImageResized { index, .. } => {
+            if let Some(bar) = &bar {
+                bar.set_message(format!("image {}", index + 1));
+            }
+        }
+        JoinEvent::ImageOverlaid { .. } => {
+            if let Some(bar) = &bar {
+                bar.inc(1);
+                if Some(bar.position()) == bar.length() {
+                    bar.finish_with_message("done");
+                }
+            }
+        }
+    }
+}
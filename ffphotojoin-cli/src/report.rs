@@ -0,0 +1,270 @@
+use crate::error::{CliResult, OrFail};
+use ffphotojoin::{AtlasFormat, JoinEvent, Rect};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// With `--json`, every progress event of a join and a report of each saved output are written to
+//  stdout as one JSON object per line, with an `event` field saying what the line is, like:
+//  {"event":"image_overlaid","index":0,"x":0,"y":0}
+//  {"event":"saved","output":"out.png","width":800,"height":300,"images":[...],"elapsed_ms":42}
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    LayoutComputed {
+        images: usize,
+        width: u32,
+        height: u32,
+    },
+    ImageResized {
+        index: usize,
+        width: u32,
+        height: u32,
+    },
+    ImageOverlaid {
+        index: usize,
+        x: u32,
+        y: u32,
+    },
+    Saved(&'a Report),
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub output: PathBuf,
+    // Counting from 0, if the output is one page of a multi-page file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    pub width: u32,
+    pub height: u32,
+    // Where each input image was drawn in the output, in the order they were joined
+    pub images: Vec<ImagePlacement>,
+    // How long the output took to make, from opening its images to saving it
+    pub elapsed_ms: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImagePlacement {
+    pub path: PathBuf,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Follows one output from start to finish, keeping track of where its images went from the
+//  progress events. The events are only printed if `print_events` is set, a report can be kept
+//  just for the manifest
+pub struct JsonReport {
+    started: Instant,
+    placements: Vec<Rect>,
+    print_events: bool,
+}
+
+impl JsonReport {
+    pub fn new(print_events: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            placements: Vec::new(),
+            print_events,
+        }
+    }
+
+    pub fn event(&mut self, event: JoinEvent) {
+        let line = match event {
+            JoinEvent::LayoutComputed {
+                images,
+                width,
+                height,
+            } => {
+                let empty = Rect {
+                    x: 0,
+                    y: 0,
+                    width: 0,
+                    height: 0,
+                };
+                self.placements = vec![empty; images];
+                JsonLine::LayoutComputed {
+                    images,
+                    width,
+                    height,
+                }
+            }
+            JoinEvent::ImageResized {
+                index,
+                width,
+                height,
+            } => {
+                if let Some(placement) = self.placements.get_mut(index) {
+                    placement.width = width;
+                    placement.height = height;
+                }
+                JsonLine::ImageResized {
+                    index,
+                    width,
+                    height,
+                }
+            }
+            JoinEvent::ImageOverlaid { index, x, y } => {
+                if let Some(placement) = self.placements.get_mut(index) {
+                    placement.x = x;
+                    placement.y = y;
+                }
+                JsonLine::ImageOverlaid { index, x, y }
+            }
+        };
+        if self.print_events {
+            print_line(&line);
+        }
+    }
+
+    // For joins that don't report progress events, like low memory joins, which know where their
+    //  images go ahead of time
+    pub fn set_placements(&mut self, placements: Vec<Rect>) {
+        self.placements = placements;
+    }
+
+    // Moves every image down, for when a title banner is added above them
+    pub fn shift_down(&mut self, offset: u32) {
+        for placement in &mut self.placements {
+            placement.y += offset;
+        }
+    }
+
+    // The report of the finished output. `files` are the input images in the order they were
+    //  joined
+    pub fn report(
+        &self,
+        output: &Path,
+        page: Option<usize>,
+        (width, height): (u32, u32),
+        files: &[PathBuf],
+    ) -> Report {
+        Report {
+            output: output.to_path_buf(),
+            page,
+            width,
+            height,
+            images: files
+                .iter()
+                .zip(&self.placements)
+                .map(|(path, placement)| ImagePlacement {
+                    path: path.clone(),
+                    x: placement.x,
+                    y: placement.y,
+                    width: placement.width,
+                    height: placement.height,
+                })
+                .collect(),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+// The layout of a joined image, saved next to it with `--manifest` so `split` can cut the images
+//  back out of it later
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub width: u32,
+    pub height: u32,
+    pub images: Vec<ImagePlacement>,
+}
+
+impl Manifest {
+    pub fn new(report: &Report) -> Self {
+        Self {
+            width: report.width,
+            height: report.height,
+            images: report.images.clone(),
+        }
+    }
+
+    // Where the manifest of an output goes, like `joined.png.json` for `joined.png`
+    pub fn sidecar_path(output: &Path) -> PathBuf {
+        let mut name = output.file_name().unwrap_or_default().to_os_string();
+        name.push(".json");
+        output.with_file_name(name)
+    }
+
+    pub fn load(path: &Path) -> CliResult<Self> {
+        let manifest = fs::read_to_string(path)
+            .or_io(&format!("failed to read manifest {}", path.display()))?;
+        serde_json::from_str(&manifest).or_args(&format!("invalid manifest {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> CliResult<()> {
+        let manifest = serde_json::to_string_pretty(self).expect("failed to write JSON");
+        fs::write(path, manifest).or_io(&format!("failed to save manifest {}", path.display()))
+    }
+}
+
+// The tiles an output was cut into with `--tile`, saved next to them like `joined_tiles.json`
+#[derive(Serialize)]
+pub struct TileIndex {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    // Row by row from the top left
+    pub tiles: Vec<TilePlacement>,
+}
+
+#[derive(Serialize)]
+pub struct TilePlacement {
+    // The file name of the tile, which is next to the index
+    pub file: PathBuf,
+    pub row: u32,
+    pub column: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileIndex {
+    pub fn save(&self, path: &Path) -> CliResult<()> {
+        let index = serde_json::to_string_pretty(self).expect("failed to write JSON");
+        fs::write(path, index).or_io(&format!("failed to save tile index {}", path.display()))
+    }
+}
+
+// Saves where each image went in an output for `--coordinates`, next to it with the extension of
+//  the format (like sprites.css for sprites.png). Images are named by their file names
+pub fn save_coordinates(report: &Report, format: AtlasFormat) -> CliResult<()> {
+    let path = report.output.with_extension(match format {
+        AtlasFormat::Css => "css",
+        AtlasFormat::Json | AtlasFormat::TexturePacker => "json",
+    });
+    let sprites = report.images.iter().map(|image| {
+        let name = image.path.file_name().unwrap_or_default().to_string_lossy();
+        let rect = Rect {
+            x: image.x,
+            y: image.y,
+            width: image.width,
+            height: image.height,
+        };
+        (name, rect)
+    });
+    let image = report
+        .output
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let coordinates =
+        ffphotojoin::atlas_coordinates((report.width, report.height), sprites, &image, format);
+    fs::write(&path, coordinates).or_io(&format!("failed to save coordinates {}", path.display()))
+}
+
+pub fn print_report(report: &Report) {
+    print_line(&JsonLine::Saved(report));
+}
+
+fn print_line(line: &JsonLine) {
+    println!(
+        "{}",
+        serde_json::to_string(line).expect("failed to write JSON")
+    );
+}
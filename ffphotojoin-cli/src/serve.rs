@@ -0,0 +1,693 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::{check_encodable, dimensions_arg, encode_output, parse_arg, parse_byte_size};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::{self, ImageFormat};
+use ffphotojoin::layout::LayoutSpec;
+use ffphotojoin::EncodeOptions;
+use serde_json::{json, Value};
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Where the server listens unless --address says otherwise
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+// The largest upload the server takes unless --max_upload says otherwise
+const DEFAULT_MAX_UPLOAD: u64 = 100_000_000;
+// The largest image the server joins (or takes as an input) unless --max_size says otherwise,
+//  whatever the spec allows
+const DEFAULT_MAX_SIZE: (u32, u32) = (20_000, 20_000);
+// The most bytes the request line and headers can take up together
+const MAX_HEAD: usize = 64 * 1024;
+// How long a client can take to send any part of its request
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// What a GET of / answers with
+const USAGE: &str = "POST a multipart/form-data upload to /join with:
+  image    an image file, once for each image (joined in the order they're uploaded)
+  spec     a JSON layout spec (like for --layout), where each of the images names an uploaded
+           file by its filename, or every uploaded image is joined in order if it has none
+  format   the format of the joined image (png/jpeg/tiff/bmp/gif, or webp with the webp feature,
+           defaults to png)
+  quality  the quality of JPEG and WebP images from 1 to 100 (defaults to 75)
+The joined image comes back in the response.
+";
+
+pub fn command() -> App<'static> {
+    clap_app!(serve =>
+        (about: "Runs an HTTP server that joins uploaded images (GET / for how to use it)")
+        (@arg address: --address +takes_value "Set the address and port to listen on (defaults to 127.0.0.1:8080)")
+        (@arg max_upload: --max_upload +takes_value "Set the largest upload the server takes (like 20MB, defaults to 100MB)")
+        (@arg max_size: --max_size +takes_value "Set the largest image the server joins or takes as an input, even if a spec's max_dimensions allows larger (like 10000x10000, defaults to 20000x20000)")
+        (@arg threads: --threads +takes_value "Set how many requests are answered at the same time (defaults to the number of CPUs)")
+    )
+}
+
+// What the server lets each request do
+#[derive(Copy, Clone)]
+struct Limits {
+    max_upload: u64,
+    max_size: (u32, u32),
+}
+
+// Answers requests until the server is stopped, a few at a time on a pool of threads
+pub fn serve(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let address = arg_matcher.value_of("address").unwrap_or(DEFAULT_ADDRESS);
+    let limits = Limits {
+        max_upload: match arg_matcher.value_of("max_upload") {
+            Some(size) => parse_byte_size(size).or_args("invalid upload size")?,
+            None => DEFAULT_MAX_UPLOAD,
+        },
+        max_size: dimensions_arg(arg_matcher, "max_size", "maximum size")?
+            .unwrap_or(DEFAULT_MAX_SIZE),
+    };
+    let threads = match parse_arg(arg_matcher, "threads", "number of threads")? {
+        Some(0) => return Err(CliError::Args("there must be at least one thread".into())),
+        Some(threads) => threads,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    };
+    let listener = TcpListener::bind(address).or_io(&format!("failed to listen on {}", address))?;
+    eprintln!(
+        "Listening on http://{}",
+        listener
+            .local_addr()
+            .map_or(address.to_string(), |address| address.to_string())
+    );
+
+    // Connections wait in a queue as long as the pool until a thread is free, and once that's
+    //  full too they wait to be accepted
+    let (sender, receiver) = mpsc::sync_channel(threads);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..threads {
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || answer_connections(&receiver, limits));
+    }
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if sender.send(stream).is_err() {
+                    break;
+                }
+            }
+            Err(err) => eprintln!("Failed to accept a connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+// Answers the connections the accepting thread hands over, one at a time
+fn answer_connections(receiver: &Mutex<Receiver<TcpStream>>, limits: Limits) {
+    loop {
+        // The lock is only held while waiting for a connection, not while answering it
+        let stream = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match stream {
+            Ok(stream) => respond(stream, limits),
+            Err(_) => return,
+        }
+    }
+}
+
+// An HTTP response, which is the only one sent over its connection
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, message: impl Display) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", message).into_bytes(),
+        }
+    }
+
+    fn write(&self, mut stream: &TcpStream) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+fn respond(stream: TcpStream, limits: Limits) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let peer = stream
+        .peer_addr()
+        .map_or("unknown".to_string(), |peer| peer.to_string());
+    let (request, response) = handle(&mut BufReader::new(&stream), &mut &stream, limits);
+    eprintln!("{} {} {}", peer, request, response.status);
+    if let Err(err) = response.write(&stream) {
+        eprintln!("Failed to answer {}: {}", peer, err);
+    }
+}
+
+// Reads a request and works out the response to it, along with the request line to log. `writer`
+//  is the connection back to the client, for telling it to go ahead with its upload
+fn handle(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    limits: Limits,
+) -> (String, Response) {
+    let mut head_left = MAX_HEAD;
+    let request = match read_line(reader, &mut head_left) {
+        Ok(request) => request,
+        Err(response) => return ("-".to_string(), response),
+    };
+    let response = match handle_request(&request, reader, writer, &mut head_left, limits) {
+        Ok(response) | Err(response) => response,
+    };
+    (request, response)
+}
+
+fn handle_request(
+    request: &str,
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    head_left: &mut usize,
+    limits: Limits,
+) -> Result<Response, Response> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader, head_left)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut parts = request.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .and_then(|target| target.split('?').next())
+        .unwrap_or_default();
+    match (method, path) {
+        ("GET", "/") => return Ok(Response::text(200, USAGE)),
+        ("POST", "/join") => {}
+        (_, "/") | (_, "/join") => return Err(Response::text(405, "method not allowed")),
+        _ => {
+            return Err(Response::text(
+                404,
+                "not found, GET / for how to use the server",
+            ))
+        }
+    }
+
+    let length = header("content-length")
+        .ok_or_else(|| Response::text(411, "the upload needs a Content-Length"))?
+        .parse::<u64>()
+        .map_err(|_| Response::text(400, "invalid Content-Length"))?;
+    if length > limits.max_upload {
+        return Err(Response::text(
+            413,
+            format!("uploads can be at most {} bytes", limits.max_upload),
+        ));
+    }
+    let boundary = header("content-type")
+        .and_then(multipart_boundary)
+        .ok_or_else(|| Response::text(400, "uploads have to be multipart/form-data"))?;
+    // Clients like curl wait to be told to go ahead before sending a large upload
+    if header("expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue")) {
+        writer
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .and_then(|()| writer.flush())
+            .map_err(|err| Response::text(500, err))?;
+    }
+    // The body is read as it arrives rather than into a buffer as large as the Content-Length,
+    //  so a client can't make the server set aside memory for an upload it never sends
+    let mut body = Vec::new();
+    let read = reader
+        .take(length)
+        .read_to_end(&mut body)
+        .map_err(|_| Response::text(400, "the upload was cut off"))?;
+    if (read as u64) < length {
+        return Err(Response::text(400, "the upload was cut off"));
+    }
+
+    let fields = parse_multipart(&body, &boundary)
+        .ok_or_else(|| Response::text(400, "invalid multipart/form-data upload"))?;
+    join_uploads(fields, limits).map_err(|err| match err {
+        CliError::Io(_) => Response::text(500, err),
+        _ => Response::text(400, err),
+    })
+}
+
+// Reads a line of the request line and headers without its line ending, taking its length from
+//  what's left of the most they can take up
+fn read_line(reader: &mut impl BufRead, left: &mut usize) -> Result<String, Response> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(*left as u64)
+        .read_until(b'\n', &mut line)
+        .map_err(|_| Response::text(400, "the request was cut off"))?;
+    if !line.ends_with(b"\n") {
+        return Err(match read < *left {
+            true => Response::text(400, "the request was cut off"),
+            false => Response::text(400, "the request headers are too long"),
+        });
+    }
+    *left -= read;
+    let line = String::from_utf8(line).map_err(|_| Response::text(400, "invalid request"))?;
+    Ok(line.trim_end().to_string())
+}
+
+// The boundary between the fields of a multipart/form-data upload, from its Content-Type
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    params.find_map(|param| header_param(param, "boundary"))
+}
+
+// The value of a `name=value` (or `name="value"`) parameter of a header, if it's the one named
+fn header_param(param: &str, name: &str) -> Option<String> {
+    let (key, value) = param.split_once('=')?;
+    key.trim()
+        .eq_ignore_ascii_case(name)
+        .then(|| value.trim().trim_matches('"').to_string())
+}
+
+// A field of a multipart/form-data upload
+struct Field<'a> {
+    name: String,
+    filename: Option<String>,
+    data: &'a [u8],
+}
+
+fn parse_multipart<'a>(body: &'a [u8], boundary: &str) -> Option<Vec<Field<'a>>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    // Each field ends with a line break before the next delimiter
+    let field_end = [b"\r\n", delimiter.as_slice()].concat();
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    let mut fields = Vec::new();
+    // The last delimiter has -- after it
+    while !rest.starts_with(b"--") {
+        let field = rest.strip_prefix(b"\r\n")?;
+        let head_end = find(field, b"\r\n\r\n")?;
+        let head = str::from_utf8(&field[..head_end]).ok()?;
+        let data = &field[head_end + 4..];
+        let data_end = find(data, &field_end)?;
+        let disposition = head.split("\r\n").find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("content-disposition")
+                .then_some(value)
+        })?;
+        let param = |name| {
+            disposition
+                .split(';')
+                .skip(1)
+                .find_map(|param| header_param(param, name))
+        };
+        fields.push(Field {
+            name: param("name")?,
+            filename: param("filename"),
+            data: &data[..data_end],
+        });
+        rest = &data[data_end + field_end.len()..];
+    }
+    Some(fields)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// A directory the images of one upload are saved in while they're joined, which is removed (with
+//  everything in it) once the upload has been answered
+struct UploadDir(PathBuf);
+
+impl UploadDir {
+    fn new() -> CliResult<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "ffphotojoin-serve-{}-{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).or_io("failed to save the uploaded images")?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for UploadDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Joins the uploaded images the way the uploaded spec says to, and encodes the joined image
+fn join_uploads(fields: Vec<Field>, limits: Limits) -> CliResult<Response> {
+    let mut spec = None;
+    let mut format = ImageFormat::Png;
+    let mut encode_options = EncodeOptions::default();
+    let mut images = Vec::new();
+    for field in fields {
+        let text = || str::from_utf8(field.data).or_args(&format!("invalid {}", field.name));
+        match field.name.as_str() {
+            "image" => images.push(field),
+            "spec" => spec = Some(text()?.to_string()),
+            "format" => {
+                format =
+                    ImageFormat::from_extension(text()?.trim()).or_args("invalid output format")?;
+                check_encodable(format)?;
+            }
+            "quality" => {
+                encode_options.quality = text()?.trim().parse().or_args("invalid quality")?
+            }
+            name => return Err(CliError::Args(format!("unknown field {}", name))),
+        }
+    }
+    if images.is_empty() {
+        return Err(CliError::Args("no images were uploaded".into()));
+    }
+
+    // Images are opened by their extension, so each one is saved with the one of its format
+    let dir = UploadDir::new()?;
+    let mut uploads = Vec::new();
+    for (index, image) in images.iter().enumerate() {
+        let name = image.filename.clone().unwrap_or_else(|| index.to_string());
+        let extension = match image::guess_format(image.data) {
+            Ok(format) => format.extensions_str().first().copied(),
+            Err(_) => Path::new(&name).extension().and_then(|ext| ext.to_str()),
+        };
+        let path = dir
+            .0
+            .join(format!("{}.{}", index, extension.unwrap_or_default()));
+        fs::write(&path, image.data).or_io("failed to save the uploaded images")?;
+        // Decoding takes memory for every pixel of the image however small it ends up in the
+        //  output, so images larger than the server joins are turned away before they're decoded
+        let (width, height) = ffphotojoin::oriented_dimensions(&path)
+            .or_args(&format!("failed to open image {}", name))?;
+        let (max_width, max_height) = limits.max_size;
+        if width > max_width || height > max_height {
+            return Ok(Response::text(
+                413,
+                format!(
+                    "image {} is {}x{}, larger than the maximum of {}x{}",
+                    name, width, height, max_width, max_height
+                ),
+            ));
+        }
+        uploads.push((name, path));
+    }
+
+    let mut spec = serde_json::from_str::<Value>(&spec.or_args("no spec was uploaded")?)
+        .or_args("invalid spec")?;
+    if let Some(spec) = spec.as_object_mut() {
+        spec.entry("images").or_insert_with(|| {
+            let images = uploads.iter().map(|(name, _)| json!({ "path": name }));
+            Value::Array(images.collect())
+        });
+    }
+    let mut spec = LayoutSpec::from_json(&spec.to_string())?;
+    for image in &mut spec.images {
+        image.path = uploads
+            .iter()
+            .find(|(name, _)| Path::new(name) == image.path)
+            .map(|(_, path)| path.clone())
+            .or_args(&format!(
+                "no uploaded image is named {}",
+                image.path.display()
+            ))?;
+    }
+    // Specs can make their own limit smaller, but not larger than the server's
+    let (max_width, max_height) = limits.max_size;
+    spec.options.max_dimensions = Some(match spec.options.max_dimensions {
+        Some((width, height)) => (width.min(max_width), height.min(max_height)),
+        None => limits.max_size,
+    });
+    let output = spec.execute()?;
+    let metadata_source = spec.images.first().map(|image| image.path.as_path());
+    let encoded = encode_output(&output, format, &encode_options, metadata_source)?.encoded;
+    Ok(Response {
+        status: 200,
+        content_type: content_type(format),
+        body: encoded,
+    })
+}
+
+fn content_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Tiff => "image/tiff",
+        ImageFormat::Bmp => "image/bmp",
+        ImageFormat::Avif => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffphotojoin::image::{DynamicImage, GenericImageView, RgbaImage};
+    use std::io::Cursor;
+
+    const LIMITS: Limits = Limits {
+        max_upload: 1_000_000,
+        max_size: (1000, 1000),
+    };
+
+    // The response to a request, and what was written back before it
+    fn answer(request: &[u8]) -> (Response, Vec<u8>) {
+        let mut written = Vec::new();
+        let (_, response) = handle(&mut Cursor::new(request), &mut written, LIMITS);
+        (response, written)
+    }
+
+    fn message(response: &Response) -> &str {
+        str::from_utf8(&response.body).unwrap().trim_end()
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
+        let mut png = Vec::new();
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        png
+    }
+
+    // A multipart/form-data body of fields with their names, filenames, and data
+    fn multipart(fields: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, filename, data) in fields {
+            body.extend(b"--xyz\r\nContent-Disposition: form-data; name=\"");
+            body.extend(name.as_bytes());
+            if let Some(filename) = filename {
+                body.extend(b"\"; filename=\"");
+                body.extend(filename.as_bytes());
+            }
+            body.extend(b"\"\r\n\r\n");
+            body.extend(*data);
+            body.extend(b"\r\n");
+        }
+        body.extend(b"--xyz--\r\n");
+        body
+    }
+
+    fn upload(content_type: &str, body: &[u8]) -> Vec<u8> {
+        let mut request = format!(
+            "POST /join HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        request.extend(body);
+        request
+    }
+
+    #[test]
+    fn routes_requests() {
+        let (response, _) = answer(b"GET /?help HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(
+            (response.status, message(&response)),
+            (200, USAGE.trim_end())
+        );
+        assert_eq!(answer(b"PUT /join HTTP/1.1\r\n\r\n").0.status, 405);
+        assert_eq!(answer(b"GET /other HTTP/1.1\r\n\r\n").0.status, 404);
+        assert_eq!(answer(b"POST /join HTTP/1.1\r\n\r\n").0.status, 411);
+    }
+
+    #[test]
+    fn refuses_broken_request_heads() {
+        let (response, _) = answer(b"GET / HTTP/1.1\r\nHost: local");
+        assert_eq!(message(&response), "the request was cut off");
+        let (response, _) = answer(b"");
+        assert_eq!(message(&response), "the request was cut off");
+
+        let mut long = b"GET / HTTP/1.1\r\nCookie: ".to_vec();
+        long.extend(vec![b'a'; MAX_HEAD]);
+        long.extend(b"\r\n\r\n");
+        let (response, _) = answer(&long);
+        assert_eq!(message(&response), "the request headers are too long");
+
+        let (response, _) = answer(b"GET / HTTP/1.1\r\nHost: \xFF\r\n\r\n");
+        assert_eq!(message(&response), "invalid request");
+    }
+
+    #[test]
+    fn checks_the_upload_before_reading_it() {
+        let request =
+            b"POST /join HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: 1000001\r\n\r\n";
+        assert_eq!(answer(request).0.status, 413);
+
+        let request = b"POST /join HTTP/1.1\r\nContent-Length: many\r\n\r\n";
+        assert_eq!(message(&answer(request).0), "invalid Content-Length");
+
+        for content_type in ["multipart/form-data", "text/plain; boundary=xyz"] {
+            let (response, _) = answer(&upload(content_type, &multipart(&[])));
+            assert_eq!(message(&response), "uploads have to be multipart/form-data");
+        }
+    }
+
+    #[test]
+    fn refuses_cut_off_uploads() {
+        let mut request = upload("multipart/form-data; boundary=xyz", &multipart(&[]));
+        request.truncate(request.len() - 3);
+        let (response, _) = answer(&request);
+        assert_eq!(message(&response), "the upload was cut off");
+    }
+
+    #[test]
+    fn answers_expect_continue() {
+        let body = multipart(&[("format", None, b"png")]);
+        let mut request = format!(
+            "POST /join HTTP/1.1\r\nExpect: 100-continue\r\nContent-Type: multipart/form-data; boundary=xyz\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        request.extend(&body);
+        let (response, written) = answer(&request);
+        assert_eq!(written, b"HTTP/1.1 100 Continue\r\n\r\n");
+        assert_eq!(message(&response), "no images were uploaded");
+    }
+
+    #[test]
+    fn parses_multipart_uploads() {
+        let body = multipart(&[
+            ("spec", None, b"{}"),
+            ("image", Some("a.png"), b"\r\n--xy\r\n"),
+        ]);
+        let fields = parse_multipart(&body, "xyz").unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(
+            (fields[0].name.as_str(), fields[0].filename.as_deref()),
+            ("spec", None)
+        );
+        assert_eq!(fields[0].data, b"{}");
+        assert_eq!(fields[1].filename.as_deref(), Some("a.png"));
+        assert_eq!(fields[1].data, b"\r\n--xy\r\n");
+
+        // A missing final boundary, a field without a name, and a different boundary
+        assert!(parse_multipart(&body[..body.len() - 9], "xyz").is_none());
+        assert!(parse_multipart(
+            b"--xyz\r\nContent-Disposition: form-data\r\n\r\na\r\n--xyz--",
+            "xyz"
+        )
+        .is_none());
+        assert!(parse_multipart(&body, "abc").is_none());
+    }
+
+    #[test]
+    fn joins_uploaded_images() {
+        let (a, b) = (png(4, 4), png(4, 4));
+        let body = multipart(&[
+            ("spec", None, br#"{"direction": "horizontal"}"#),
+            ("image", Some("a.png"), &a),
+            ("image", Some("b.png"), &b),
+        ]);
+        let (response, _) = answer(&upload("multipart/form-data; boundary=xyz", &body));
+        assert_eq!((response.status, response.content_type), (200, "image/png"));
+        let joined = image::load_from_memory(&response.body).unwrap();
+        assert_eq!(joined.dimensions(), (8, 4));
+    }
+
+    #[test]
+    fn limits_the_size_of_joined_images() {
+        let big = png(600, 10);
+        for spec in [
+            br#"{"direction": "horizontal"}"#.as_slice(),
+            br#"{"direction": "horizontal", "max_dimensions": [5000, 5000]}"#,
+        ] {
+            let body = multipart(&[
+                ("spec", None, spec),
+                ("image", Some("a.png"), &big),
+                ("image", Some("b.png"), &big),
+            ]);
+            let (response, _) = answer(&upload("multipart/form-data; boundary=xyz", &body));
+            assert_eq!(response.status, 400);
+            assert!(message(&response).ends_with("larger than the maximum of 1000x1000"));
+        }
+    }
+
+    #[test]
+    fn refuses_images_too_large_to_decode() {
+        let body = multipart(&[
+            ("spec", None, br#"{"direction": "horizontal"}"#),
+            ("image", Some("a.png"), &png(4, 4)),
+            ("image", Some("huge.png"), &png(1200, 10)),
+        ]);
+        let (response, _) = answer(&upload("multipart/form-data; boundary=xyz", &body));
+        assert_eq!(response.status, 413);
+        assert_eq!(
+            message(&response),
+            "image huge.png is 1200x10, larger than the maximum of 1000x1000"
+        );
+
+        let body = multipart(&[
+            ("spec", None, br#"{"direction": "horizontal"}"#),
+            ("image", Some("a.png"), b"not an image"),
+        ]);
+        let (response, _) = answer(&upload("multipart/form-data; boundary=xyz", &body));
+        assert_eq!(response.status, 400);
+    }
+}
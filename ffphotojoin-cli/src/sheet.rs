@@ -0,0 +1,134 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::template::OutputVars;
+use crate::{
+    auto_filter_arg, check_output, color_arg, decode_threads, encode_options, filter_arg,
+    input_args, input_paths, layout_args, load_images, order_arg, output_arg, output_args,
+    output_file, output_format, output_name, parse_arg, pick_args, save_image, sharpen_arg,
+    Overwrite, DEFAULT_BACKGROUND, DEFAULT_CELL_SIZE, DEFAULT_SIZING,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::imageops::FilterType;
+use ffphotojoin::image::GenericImageView;
+use ffphotojoin::PhotoJoinOptions;
+
+pub fn command() -> App<'static> {
+    clap_app!(sheet =>
+        (about: "Makes a contact sheet of thumbnails in a grid that's as square as possible")
+        (@arg cell_size: --cell_size +takes_value "Set the width and height of each thumbnail's cell in pixels (defaults to 256)")
+        (@arg labels: --labels "Write each image's filename under its thumbnail")
+    )
+    .args(input_args())
+    .args(pick_args(
+        layout_args(),
+        &["filter", "filter_up", "filter_down", "gap", "sharpen", "background"],
+    ))
+    .args(pick_args(
+        output_args(),
+        &[
+            "output",
+            "format",
+            "quality",
+            "png_compression",
+            "webp_lossless",
+            "avif_speed",
+            "target_size",
+            "downscale_to_fit",
+            "overwrite",
+            "backup",
+            "no_overwrite",
+        ],
+    ))
+    .mut_arg("input", |arg| {
+        arg.required_unless_present("files_from")
+            .help("Provides the images to make thumbnails of (directories and glob patterns like photos/*.jpg work too, - reads a list of files from stdin)")
+    })
+    .mut_arg("output", |arg| {
+        arg.required(true)
+            .help("Set the image output file, - writes it to stdout ({date}/{time}/{timestamp}/{count}/{width}/{height}/{index} in it are filled in, like joined_{date}_{width}x{height}.png)")
+    })
+    .mut_arg("gap", |arg| arg.help("Set the number of pixels to leave between thumbnails"))
+}
+
+pub fn make_sheet(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let inputs = input_paths(arg_matcher)?;
+    if inputs.is_empty() {
+        return Err(CliError::Args("no input files provided".into()));
+    }
+    let output_path = output_arg(arg_matcher)?;
+    let cell_size = parse_arg(arg_matcher, "cell_size", "cell size")?.unwrap_or(DEFAULT_CELL_SIZE);
+    let options = PhotoJoinOptions {
+        sizing: DEFAULT_SIZING,
+        filter: filter_arg(arg_matcher, "filter", "filter")?.unwrap_or(FilterType::Gaussian),
+        auto_filter: auto_filter_arg(arg_matcher),
+        filter_up: filter_arg(arg_matcher, "filter_up", "filter for enlarged images")?,
+        filter_down: filter_arg(arg_matcher, "filter_down", "filter for shrunk images")?,
+        spacing: parse_arg(arg_matcher, "gap", "gap size")?.unwrap_or(0),
+        background: color_arg(arg_matcher, "background", "background color")?
+            .unwrap_or(DEFAULT_BACKGROUND),
+        order: order_arg(arg_matcher)?,
+        sharpen: sharpen_arg(arg_matcher)?,
+        ..Default::default()
+    };
+    let output_format = output_format(&output_path, arg_matcher.value_of("format"))?;
+    if !check_output(&output_path, Overwrite::from_args(arg_matcher))? {
+        return Ok(());
+    }
+
+    eprintln!(
+        "Making a contact sheet of {} photos with {}x{} cells",
+        inputs.len(),
+        cell_size,
+        cell_size
+    );
+    let count = inputs.len();
+    let output_image = if arg_matcher.is_present("labels") {
+        let labels = inputs
+            .iter()
+            .map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        ffphotojoin::contact_sheet_labeled(
+            load_images(inputs, decode_threads(arg_matcher)?, Some(cell_size))?
+                .into_iter()
+                .zip(labels),
+            cell_size,
+            options,
+        )
+    } else {
+        ffphotojoin::contact_sheet(
+            load_images(inputs, decode_threads(arg_matcher)?, Some(cell_size))?,
+            cell_size,
+            options,
+        )
+    }
+    .or_join("failed to make contact sheet")?;
+
+    eprintln!(
+        "Generated {}x{} image",
+        output_image.width(),
+        output_image.height(),
+    );
+    let output_path = match output_file(
+        &output_path,
+        &OutputVars {
+            count,
+            width: output_image.width(),
+            height: output_image.height(),
+            index: None,
+            group: None,
+        },
+        Overwrite::from_args(arg_matcher),
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+    save_image(
+        &output_image,
+        &output_path,
+        output_format,
+        &encode_options(arg_matcher)?,
+        None,
+        Overwrite::from_args(arg_matcher),
+    )?;
+    eprintln!("Saved contact sheet to {}", output_name(&output_path));
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::error::{CliError, CliResult, OrFail};
+use crate::progress::{counting_bar, show_file};
+use crate::report::Manifest;
+use crate::{
+    check_output, encode_options, output_arg, output_args, output_format, pick_args, save_image,
+    Overwrite,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::GenericImageView;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+pub fn command() -> App<'static> {
+    clap_app!(split =>
+        (about: "Cuts the images of a joined image back out of it, going by the manifest saved with --manifest")
+        (@arg input: -i --input +required +takes_value "Provides the joined image")
+        (@arg manifest: --manifest +takes_value "Set the manifest file of the joined image (defaults to the one next to it, like joined.png.json)")
+        (@arg output: -o --output +required +takes_value "Set the directory the images are written to, each named after the image it came from")
+    )
+    .args(pick_args(
+        output_args(),
+        &[
+            "format",
+            "quality",
+            "png_compression",
+            "webp_lossless",
+            "avif_speed",
+            "target_size",
+            "downscale_to_fit",
+            "overwrite",
+            "backup",
+            "no_overwrite",
+        ],
+    ))
+    .mut_arg("format", |arg| {
+        arg.help("Set the format of the images instead of going by the extensions of the images they came from (png/jpeg/tiff/bmp/gif/webp/avif)")
+    })
+}
+
+// Cuts each image listed in the manifest of a joined image back out of it, into files named after
+//  the images they came from
+pub fn split_joined(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let input = PathBuf::from(
+        shellexpand::tilde(
+            arg_matcher
+                .value_of("input")
+                .or_args("no input file provided")?,
+        )
+        .as_ref(),
+    );
+    let manifest_path = match arg_matcher.value_of("manifest") {
+        Some(manifest) => PathBuf::from(shellexpand::tilde(manifest).as_ref()),
+        None => Manifest::sidecar_path(&input),
+    };
+    let manifest = Manifest::load(&manifest_path)?;
+    let joined = ffphotojoin::image::open(&input).map_err(|err| CliError::image(&input, err))?;
+    if joined.dimensions() != (manifest.width, manifest.height) {
+        return Err(CliError::Args(format!(
+            "{} is {}x{}, but its manifest is for a {}x{} image",
+            input.display(),
+            joined.width(),
+            joined.height(),
+            manifest.width,
+            manifest.height
+        )));
+    }
+
+    // Every file is checked before any are written, so a failed split doesn't leave half of them
+    let output_dir = output_arg(arg_matcher)?;
+    let format = arg_matcher.value_of("format");
+    let mut names = HashSet::new();
+    let mut tiles = Vec::new();
+    for image in &manifest.images {
+        let mut name = PathBuf::from(
+            image
+                .path
+                .file_name()
+                .or_args("invalid image in manifest")?,
+        );
+        if let Some(format) = format {
+            name.set_extension(format.to_lowercase());
+        }
+        if !names.insert(name.clone()) {
+            return Err(CliError::Args(format!(
+                "more than one image in the manifest is named {}",
+                name.display()
+            )));
+        }
+        if image.x.saturating_add(image.width) > manifest.width
+            || image.y.saturating_add(image.height) > manifest.height
+        {
+            return Err(CliError::Args(format!(
+                "{} is outside of the joined image in the manifest",
+                name.display()
+            )));
+        }
+        let path = output_dir.join(name);
+        let tile_format = output_format(&path, format)?;
+        if check_output(&path, Overwrite::from_args(arg_matcher))? {
+            tiles.push((image, path, tile_format));
+        }
+    }
+
+    fs::create_dir_all(&output_dir).or_io("failed to create output directory")?;
+    let encode_options = encode_options(arg_matcher)?;
+    let bar = counting_bar("Splitting", tiles.len());
+    for (image, path, tile_format) in &tiles {
+        show_file(&bar, path);
+        let tile = joined.crop_imm(image.x, image.y, image.width, image.height);
+        save_image(
+            &tile,
+            path,
+            *tile_format,
+            &encode_options,
+            None,
+            Overwrite::from_args(arg_matcher),
+        )?;
+        bar.inc(1);
+    }
+    bar.finish();
+    eprintln!("Saved {} images to {}", tiles.len(), output_dir.display());
+    Ok(())
+}
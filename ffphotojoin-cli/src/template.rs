@@ -0,0 +1,78 @@
+use crate::error::{CliError, CliResult};
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// What the variables of an output path template (like `joined_{date}_{width}x{height}.png`) are
+//  filled in with:
+//  - `{date}` and `{time}` are when the first output is named, like 2021-05-04 and 153000
+//  - `{timestamp}` is the same time in seconds since 1970
+//  - `{count}` is the number of images in the output
+//  - `{width}` and `{height}` are the size of the output (of its first page or frame if it has
+//    more than one)
+//  - `{index}` is the number of the page in a paginated join, like 001
+//  - `{group}` is the label of the group of photos in a join grouped by date, like 2021-05-04
+pub struct OutputVars {
+    pub count: usize,
+    pub width: u32,
+    pub height: u32,
+    // Counting from 0, if the output is one page of a paginated join
+    pub index: Option<usize>,
+    // If the output is one group of a join grouped by date
+    pub group: Option<String>,
+}
+
+// Every output of a run gets the same date and time, even if joining them takes a while
+fn started() -> DateTime<Local> {
+    static STARTED: OnceLock<DateTime<Local>> = OnceLock::new();
+    *STARTED.get_or_init(Local::now)
+}
+
+pub fn is_template(path: &Path) -> bool {
+    path.to_string_lossy().contains('{')
+}
+
+// Whether the template names the pages of paginated or grouped joins itself
+pub fn names_pages(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.contains("{index}") || path.contains("{group}")
+}
+
+pub fn fill_template(template: &Path, vars: &OutputVars) -> CliResult<PathBuf> {
+    let started = started();
+    let template = template.to_string_lossy();
+    let mut path = String::new();
+    let mut rest = template.as_ref();
+    while let Some(start) = rest.find('{') {
+        path.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => {
+                return Err(CliError::Args(format!(
+                    "unclosed {{ in output path {}",
+                    template
+                )))
+            }
+        };
+        let value = match &rest[start + 1..end] {
+            "date" => started.format("%Y-%m-%d").to_string(),
+            "time" => started.format("%H%M%S").to_string(),
+            "timestamp" => started.timestamp().to_string(),
+            "count" => vars.count.to_string(),
+            "width" => vars.width.to_string(),
+            "height" => vars.height.to_string(),
+            "index" => format!("{:03}", vars.index.unwrap_or(0) + 1),
+            "group" => vars.group.clone().unwrap_or_default(),
+            name => {
+                return Err(CliError::Args(format!(
+                    "unknown variable {{{}}} in output path",
+                    name
+                )))
+            }
+        };
+        path.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Ok(PathBuf::from(path))
+}
@@ -0,0 +1,171 @@
+use crate::error::{CliResult, OrFail};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ffphotojoin::image::{DynamicImage, ImageFormat, Rgb};
+use ffphotojoin::EncodeOptions;
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::process::{self, Command};
+
+// How previews are shown
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Viewer {
+    // Printed with the kitty graphics protocol (kitty, WezTerm, and Ghostty have it)
+    Kitty,
+    // Printed as sixels (foot, mlterm, and xterm with sixels turned on have them)
+    Sixel,
+    // Opened in the system's image viewer
+    System,
+}
+
+impl Viewer {
+    pub fn parse(viewer: &str) -> Option<Self> {
+        match viewer.to_lowercase().as_str() {
+            "kitty" => Some(Viewer::Kitty),
+            "sixel" => Some(Viewer::Sixel),
+            "system" => Some(Viewer::System),
+            _ => None,
+        }
+    }
+
+    // The graphics of the terminal that's running, going by its environment, or the system's
+    //  viewer if it doesn't have any (or stdout isn't a terminal)
+    pub fn detect() -> Self {
+        if !io::stdout().is_terminal() {
+            return Viewer::System;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || program == "WezTerm"
+        {
+            Viewer::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Viewer::Sixel
+        } else {
+            Viewer::System
+        }
+    }
+}
+
+// Shows an image the way the viewer does, `name` tells the files of several previews apart
+pub fn show_image(img: &DynamicImage, viewer: Viewer, name: &str) -> CliResult<()> {
+    match viewer {
+        Viewer::Kitty => print_kitty(img),
+        Viewer::Sixel => print_sixel(img),
+        Viewer::System => open_in_viewer(img, name),
+    }
+}
+
+// The kitty graphics protocol takes a PNG in base64, sent in chunks of at most 4096 bytes
+fn print_kitty(img: &DynamicImage) -> CliResult<()> {
+    let png = ffphotojoin::encode(img, ImageFormat::Png, &EncodeOptions::default())
+        .or_join("failed to encode preview")?;
+    let encoded = STANDARD.encode(png);
+    let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+    let mut out = io::stdout().lock();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        let result = if index == 0 {
+            write!(out, "\x1b_Gf=100,a=T,m={};", more)
+        } else {
+            write!(out, "\x1b_Gm={};", more)
+        };
+        result
+            .and_then(|_| out.write_all(chunk))
+            .and_then(|_| out.write_all(b"\x1b\\"))
+            .or_io("failed to print preview")?;
+    }
+    writeln!(out).or_io("failed to print preview")
+}
+
+// Sixels are columns of six pixels, so the image is drawn in bands six rows tall, going over each
+//  band once for each color in it. The colors are cut down to a 6x6x6 cube, which is plenty for
+//  a preview
+fn print_sixel(img: &DynamicImage) -> CliResult<()> {
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let color_index = |pixel: &Rgb<u8>| {
+        let level = |channel: u8| (channel as usize * 5 + 127) / 255;
+        level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+    };
+
+    let mut sixel = format!("\x1bPq\"1;1;{};{}", width, height);
+    for color in 0..216 {
+        let _ = write!(
+            sixel,
+            "#{};2;{};{};{}",
+            color,
+            color / 36 * 20,
+            color / 6 % 6 * 20,
+            color % 6 * 20
+        );
+    }
+    for band in (0..height).step_by(6) {
+        let mut colors: Vec<Option<Vec<u8>>> = vec![None; 216];
+        for x in 0..width {
+            for row in 0..(height - band).min(6) {
+                let color = color_index(rgb.get_pixel(x, band + row));
+                colors[color].get_or_insert_with(|| vec![0; width as usize])[x as usize] |=
+                    1 << row;
+            }
+        }
+        for (color, bits) in colors
+            .iter()
+            .enumerate()
+            .filter_map(|(color, bits)| Some((color, bits.as_ref()?)))
+        {
+            let _ = write!(sixel, "#{}", color);
+            // Runs of the same sixel are written once with how many there are
+            let mut x = 0;
+            while x < bits.len() {
+                let run = bits[x..].iter().take_while(|&&bit| bit == bits[x]).count();
+                let character = char::from(63 + bits[x]);
+                if run > 3 {
+                    let _ = write!(sixel, "!{}{}", run, character);
+                } else {
+                    sixel.extend(std::iter::repeat_n(character, run));
+                }
+                x += run;
+            }
+            sixel.push('$');
+        }
+        sixel.push('-');
+    }
+    sixel.push_str("\x1b\\");
+    let mut out = io::stdout().lock();
+    writeln!(out, "{}", sixel).or_io("failed to print preview")
+}
+
+// Saves the image as a temporary PNG and opens it with the system's image viewer, without waiting
+//  for the viewer to be closed
+fn open_in_viewer(img: &DynamicImage, name: &str) -> CliResult<()> {
+    let path = env::temp_dir().join(format!(
+        "ffphotojoin-preview-{}-{}.png",
+        process::id(),
+        name
+    ));
+    let png = ffphotojoin::encode(img, ImageFormat::Png, &EncodeOptions::default())
+        .or_join("failed to encode preview")?;
+    std::fs::write(&path, png).or_io("failed to save preview")?;
+
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = Command::new("xdg-open");
+    command.arg(&path).spawn().or_io(&format!(
+        "failed to open the preview (saved to {}) in an image viewer",
+        path.display()
+    ))?;
+    eprintln!("Opened preview {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::animate::{frame_args, frame_options};
+use crate::error::{CliError, CliResult, OrFail};
+use crate::progress::counting_bar;
+use crate::template::OutputVars;
+use crate::{
+    check_output, decode_threads, finish_output, input_paths, is_stdout, load_images, output_arg,
+    output_file, output_name, parse_arg, temp_path, Overwrite,
+};
+use clap::{App, ArgMatches};
+use ffphotojoin::image::RgbImage;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// How long each image is shown for unless --duration says otherwise, in seconds
+const DEFAULT_DURATION: f64 = 3.0;
+const DEFAULT_FPS: u32 = 30;
+
+// The containers a slideshow can be saved in, each with the codec ffmpeg encodes it with
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum VideoFormat {
+    // H.264 in MP4, which plays nearly everywhere
+    Mp4,
+    // VP9 in WebM, which is smaller for the same quality and plays in browsers
+    WebM,
+}
+
+impl VideoFormat {
+    // The arguments ffmpeg is given for the format, before the output file
+    fn ffmpeg_args(self, crf: Option<u32>) -> Vec<String> {
+        let mut args = match self {
+            VideoFormat::Mp4 => vec!["-c:v", "libx264", "-preset", "medium"],
+            VideoFormat::WebM => vec!["-c:v", "libvpx-vp9", "-b:v", "0"],
+        }
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+        let crf = crf.unwrap_or(match self {
+            VideoFormat::Mp4 => 23,
+            VideoFormat::WebM => 32,
+        });
+        args.extend(["-crf".to_string(), crf.to_string()]);
+        args.extend(
+            match self {
+                // Lets players start before the whole file is downloaded
+                VideoFormat::Mp4 => vec!["-movflags", "+faststart", "-f", "mp4"],
+                VideoFormat::WebM => vec!["-f", "webm"],
+            }
+            .into_iter()
+            .map(String::from),
+        );
+        args
+    }
+}
+
+pub fn command() -> App<'static> {
+    clap_app!(video =>
+        (about: "Makes a slideshow video (MP4 or WebM) showing each image in turn, encoded with ffmpeg (which has to be installed)")
+        (@arg output: -o --output +required +takes_value "Set the video output file (.mp4 or .webm, {date}/{time}/{timestamp}/{count}/{width}/{height} in it are filled in)")
+        (@arg format: --format +takes_value "Set the format of the video instead of going by its extension (mp4/webm)")
+        (@arg duration: --duration +takes_value "Set how long each image is shown in seconds, including its crossfade into the next (defaults to 3)")
+        (@arg crossfade: --crossfade +takes_value "Set how long each image fades into the next one in seconds (defaults to 0, which cuts straight to it)")
+        (@arg fps: --fps +takes_value "Set the frame rate of the video (defaults to 30)")
+        (@arg crf: --crf +takes_value "Set the constant rate factor ffmpeg encodes with, where lower is better quality and larger files (defaults to 23 for mp4 and 32 for webm)")
+        (@arg ffmpeg: --ffmpeg +takes_value "Set the ffmpeg program to run (defaults to the ffmpeg on the PATH)")
+    )
+    .args(frame_args())
+    .mut_arg("input", |arg| {
+        arg.help("Provides the images to show (directories and glob patterns like photos/*.jpg work too, - reads a list of files from stdin)")
+    })
+    .mut_arg("background", |arg| {
+        arg.help("Set the color to fill empty space in frames with (a name like black/white or hex like #ff8800, defaults to black)")
+    })
+}
+
+// Makes the images into frames the same way animations are, and pipes them to ffmpeg with each
+//  one repeated for as long as it's shown and blended into the next for crossfades
+pub fn make_video(arg_matcher: &ArgMatches) -> CliResult<()> {
+    let inputs = input_paths(arg_matcher)?;
+    if inputs.is_empty() {
+        return Err(CliError::Args("no input files provided".into()));
+    }
+    let output_path = output_arg(arg_matcher)?;
+    if is_stdout(&output_path) {
+        return Err(CliError::Args("videos can only be saved to files".into()));
+    }
+    let format = match arg_matcher.value_of("format") {
+        Some(format) => format.to_lowercase(),
+        None => output_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .or_args("unknown output file extension")?
+            .to_lowercase(),
+    };
+    let format = match format.as_str() {
+        "mp4" => VideoFormat::Mp4,
+        "webm" => VideoFormat::WebM,
+        _ => {
+            return Err(CliError::Args(
+                "videos can only be MP4 or WebM files".into(),
+            ))
+        }
+    };
+    let duration: f64 = parse_arg(arg_matcher, "duration", "duration")?.unwrap_or(DEFAULT_DURATION);
+    let crossfade: f64 = parse_arg(arg_matcher, "crossfade", "crossfade")?.unwrap_or(0.0);
+    let fps: u32 = parse_arg(arg_matcher, "fps", "frame rate")?.unwrap_or(DEFAULT_FPS);
+    if !(duration > 0.0 && duration.is_finite()) {
+        return Err(CliError::Args("invalid duration".into()));
+    }
+    if !(0.0..=duration).contains(&crossfade) {
+        return Err(CliError::Args(
+            "crossfades can't be longer than each image is shown".into(),
+        ));
+    }
+    if fps == 0 {
+        return Err(CliError::Args("invalid frame rate".into()));
+    }
+    let crf = parse_arg(arg_matcher, "crf", "constant rate factor")?;
+    let (mut options, exact_size) = frame_options(arg_matcher)?;
+    // Video is encoded with half as many color samples as pixels, which needs even sizes
+    options.align_multiple = 2;
+    let overwrite = Overwrite::from_args(arg_matcher);
+    if !check_output(&output_path, overwrite)? {
+        return Ok(());
+    }
+
+    eprintln!("Making a video of {} photos", inputs.len());
+    let frames = ffphotojoin::animation_frames(
+        load_images(inputs, decode_threads(arg_matcher)?, exact_size)?,
+        options,
+    )
+    .or_join("failed to make video frames")?
+    .into_iter()
+    .map(|frame| frame.to_rgb8())
+    .collect::<Vec<_>>();
+    let (width, height) = frames[0].dimensions();
+    let output_path = match output_file(
+        &output_path,
+        &OutputVars {
+            count: frames.len(),
+            width,
+            height,
+            index: None,
+            group: None,
+        },
+        overwrite,
+    )? {
+        Some(output_path) => output_path,
+        None => return Ok(()),
+    };
+
+    // Each image is shown for its whole duration, the last part of which it fades into the next
+    //  image over (except for the last image, which has nothing to fade into)
+    let shown = ((duration * fps as f64).round() as usize).max(1);
+    let fading = ((crossfade * fps as f64).round() as usize).min(shown);
+    let temp_path = temp_path(&output_path);
+    let encoded = encode_frames(
+        arg_matcher.value_of("ffmpeg").unwrap_or("ffmpeg"),
+        &frames,
+        (shown, fading),
+        fps,
+        format.ffmpeg_args(crf),
+        &temp_path,
+    );
+    if let Err(err) = encoded {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    finish_output(&temp_path, &output_path, overwrite)?;
+    eprintln!(
+        "Saved {:.1} second video to {}",
+        (shown * frames.len()) as f64 / fps as f64,
+        output_name(&output_path)
+    );
+    Ok(())
+}
+
+// Runs ffmpeg and writes every frame of the video to it as raw RGB pixels
+fn encode_frames(
+    ffmpeg: &str,
+    frames: &[RgbImage],
+    (shown, fading): (usize, usize),
+    fps: u32,
+    format_args: Vec<String>,
+    output_path: &Path,
+) -> CliResult<()> {
+    let (width, height) = frames[0].dimensions();
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-framerate", &fps.to_string(), "-i", "-"])
+        .args(format_args)
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(output_path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => CliError::Io(format!(
+                "{} wasn't found, videos need ffmpeg to be installed (or given with --ffmpeg)",
+                ffmpeg
+            )),
+            _ => CliError::Io(format!("failed to run {}: {}", ffmpeg, err)),
+        })?;
+
+    let bar = counting_bar("Encoding", frames.len());
+    // ffmpeg stops reading when it fails, so its exit status says why better than the write does
+    let written = (|| -> io::Result<()> {
+        let mut stdin = BufWriter::new(child.stdin.take().expect("ffmpeg stdin is piped"));
+        for (index, frame) in frames.iter().enumerate() {
+            let next = frames.get(index + 1).filter(|_| fading > 0);
+            let held = if next.is_some() {
+                shown - fading
+            } else {
+                shown
+            };
+            for _ in 0..held {
+                stdin.write_all(frame.as_raw())?;
+            }
+            if let Some(next) = next {
+                for step in 1..=fading {
+                    let blended = crossfade(frame, next, step as f32 / (fading + 1) as f32);
+                    stdin.write_all(blended.as_raw())?;
+                }
+            }
+            bar.inc(1);
+        }
+        stdin.flush()
+    })();
+    bar.finish_and_clear();
+    let status = child.wait().or_io(&format!("failed to run {}", ffmpeg))?;
+    if !status.success() {
+        return Err(CliError::Join(format!(
+            "{} failed to encode the video ({})",
+            ffmpeg, status
+        )));
+    }
+    written.or_io(&format!("failed to write frames to {}", ffmpeg))
+}
+
+// A frame part of the way through fading from one image into the next
+fn crossfade(from: &RgbImage, to: &RgbImage, amount: f32) -> RgbImage {
+    let mut blended = from.clone();
+    for (pixel, to) in blended.pixels_mut().zip(to.pixels()) {
+        for (channel, to) in pixel.0.iter_mut().zip(to.0) {
+            *channel = (*channel as f32 + (to as f32 - *channel as f32) * amount).round() as u8;
+        }
+    }
+    blended
+}
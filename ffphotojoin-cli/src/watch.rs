@@ -0,0 +1,103 @@
+use crate::error::{CliError, CliResult, OrFail};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+// How long the inputs have to stay the same after a change before the join runs again, so saving
+//  several photos at once (or an editor writing one in pieces) only runs it once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Watches the directories that input images come from. Directories are watched instead of the
+//  files themselves, since editors often save a file by replacing it, which ends a watch on it
+pub struct InputWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl InputWatcher {
+    // Watches what each input (a file, directory, or glob pattern) could be changed through, with
+    //  directories watched with their subdirectories if `recursive` is set
+    pub fn new(inputs: &[String], recursive: bool) -> CliResult<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).or_io("failed to watch inputs")?;
+        let mut watched = HashSet::new();
+        for input in inputs {
+            if input == "-" {
+                return Err(CliError::Args(
+                    "inputs read from stdin can't be watched".into(),
+                ));
+            }
+            let (path, mode) = watch_path(Path::new(shellexpand::tilde(input).as_ref()), recursive);
+            if watched.insert(path.clone()) {
+                watcher
+                    .watch(&path, mode)
+                    .or_io(&format!("failed to watch {}", path.display()))?;
+            }
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // Waits for something to change, and then for nothing to have changed for a moment
+    pub fn wait(&self) -> CliResult<()> {
+        self.events
+            .recv()
+            .or_io("stopped watching inputs")?
+            .or_io("failed to watch inputs")?;
+        loop {
+            match self.events.recv_timeout(DEBOUNCE) {
+                Ok(event) => {
+                    event.or_io("failed to watch inputs")?;
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(CliError::Io("stopped watching inputs".into()))
+                }
+            }
+        }
+    }
+}
+
+// The directory an input is watched through. Glob patterns are watched from the last directory
+//  before any wildcards, with its subdirectories if the pattern goes into them
+fn watch_path(input: &Path, recursive: bool) -> (PathBuf, RecursiveMode) {
+    let mode = |recursive| match recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    if input.is_dir() {
+        return (input.to_path_buf(), mode(recursive));
+    }
+    if input.exists() {
+        let parent = input.parent().filter(|parent| parent != &Path::new(""));
+        return (
+            parent.unwrap_or(Path::new(".")).to_path_buf(),
+            RecursiveMode::NonRecursive,
+        );
+    }
+
+    let is_wildcard = |component: &Component| {
+        component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '['])
+    };
+    let components = input.components().collect::<Vec<_>>();
+    let base = components
+        .iter()
+        .take_while(|component| !is_wildcard(component))
+        .collect::<PathBuf>();
+    // Only the last component is a file name, wildcards before it mean subdirectories are matched
+    let wildcard_dirs = components[..components.len().saturating_sub(1)]
+        .iter()
+        .any(is_wildcard);
+    let base = match base.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => base,
+    };
+    (base, mode(recursive || wildcard_dirs))
+}
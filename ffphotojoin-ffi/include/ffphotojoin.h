@@ -0,0 +1,184 @@
+/*
+ * C bindings for ffphotojoin, built by the ffphotojoin-ffi crate as libffphotojoin_ffi (a shared
+ * or static library). Images go in and come out encoded, like the contents of a JPEG or PNG file.
+ *
+ *     FfpjOptions options = ffpj_default_options();
+ *     options.direction = FFPJ_DIRECTION_GRID;
+ *     options.cols = 2;
+ *     FfpjBuffer joined;
+ *     if (ffpj_join(images, lengths, count, &options, &joined) != 0) {
+ *         fprintf(stderr, "%s\n", ffpj_last_error());
+ *     } else {
+ *         fwrite(joined.data, 1, joined.len, file);
+ *         ffpj_free_buffer(joined);
+ *     }
+ */
+
+#ifndef FFPHOTOJOIN_H
+#define FFPHOTOJOIN_H
+
+/* Generated by cbindgen from src/lib.rs, don't edit by hand (see cbindgen.toml) */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define FFPJ_DIRECTION_HORIZONTAL 0
+
+#define FFPJ_DIRECTION_VERTICAL 1
+
+/**
+ * Uses `rows` and `cols`, either of which can be 0 for as many as needed
+ */
+#define FFPJ_DIRECTION_GRID 2
+
+/**
+ * Uses `width`, the width of every row
+ */
+#define FFPJ_DIRECTION_JUSTIFIED 3
+
+/**
+ * Whichever of a row, a column, or a grid comes out the most square
+ */
+#define FFPJ_DIRECTION_AUTO 4
+
+#define FFPJ_SIZING_TO_SMALLEST 0
+
+#define FFPJ_SIZING_TO_LARGEST 1
+
+/**
+ * Uses `size`, the length of the shared edge in pixels
+ */
+#define FFPJ_SIZING_EXACT 2
+
+#define FFPJ_FIT_SCALE 0
+
+#define FFPJ_FIT_CROP 1
+
+#define FFPJ_FIT_PAD 2
+
+#define FFPJ_FORMAT_PNG 0
+
+#define FFPJ_FORMAT_JPEG 1
+
+#define FFPJ_FORMAT_TIFF 2
+
+#define FFPJ_FORMAT_BMP 3
+
+#define FFPJ_FORMAT_GIF 4
+
+/**
+ * Resizes images with the `image` crate
+ */
+#define FFPJ_RESIZE_IMAGE 0
+
+/**
+ * Resizes images with SIMD instructions, which needs the library to be built with the fast-resize
+ * feature (without it they're resized like `FFPJ_RESIZE_IMAGE`)
+ */
+#define FFPJ_RESIZE_FAST 1
+
+/**
+ * How images are joined and how the output is encoded, best started from
+ * `ffpj_default_options()`
+ */
+typedef struct FfpjOptions {
+  /**
+   * One of the `FFPJ_DIRECTION_` constants
+   */
+  uint32_t direction;
+  uint32_t rows;
+  uint32_t cols;
+  uint32_t width;
+  /**
+   * One of the `FFPJ_SIZING_` constants
+   */
+  uint32_t sizing;
+  uint32_t size;
+  /**
+   * One of the `FFPJ_FIT_` constants
+   */
+  uint32_t fit;
+  /**
+   * Pixels between the images
+   */
+  uint32_t spacing;
+  /**
+   * Pixels around the outside of the output
+   */
+  uint32_t margin;
+  /**
+   * Radius of the rounded corners of each image in pixels
+   */
+  uint32_t corner_radius;
+  /**
+   * RGBA color behind and between the images
+   */
+  uint8_t background[4];
+  /**
+   * One of the `FFPJ_FORMAT_` constants
+   */
+  uint32_t format;
+  /**
+   * JPEG quality from 1 to 100
+   */
+  uint8_t quality;
+  /**
+   * One of the `FFPJ_RESIZE_` constants
+   */
+  uint32_t resize_backend;
+} FfpjOptions;
+
+/**
+ * An encoded image made by `ffpj_join`, which has to be freed with `ffpj_free_buffer`
+ */
+typedef struct FfpjBuffer {
+  uint8_t *data;
+  size_t len;
+} FfpjBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The options a join starts out with: a horizontal strip of images all shrunk to the smallest
+ * one, on a black background, encoded as a PNG
+ */
+struct FfpjOptions ffpj_default_options(void);
+
+/**
+ * Joins `count` encoded images (`images[i]` is `lengths[i]` bytes long), turned upright by their
+ * EXIF orientation, and puts the encoded output in `output`. Returns 0 when it works, or -1 with
+ * `output` left alone when it doesn't (`ffpj_last_error` says why)
+ *
+ * # Safety
+ *
+ * `images` and `lengths` have to point to `count` elements each, every image has to point to as
+ * many bytes as its length, and `options` and `output` have to be valid pointers
+ */
+int ffpj_join(const uint8_t *const *images,
+              const size_t *lengths,
+              size_t count,
+              const struct FfpjOptions *options,
+              struct FfpjBuffer *output);
+
+/**
+ * Frees an image made by `ffpj_join`. Freeing an empty buffer does nothing
+ *
+ * # Safety
+ *
+ * `buffer` has to be one `ffpj_join` made, and can't be used (or freed again) afterwards
+ */
+void ffpj_free_buffer(struct FfpjBuffer buffer);
+
+/**
+ * Why the last call that failed on this thread failed, which stays valid until the next call
+ * that fails on this thread. It's empty if nothing has failed
+ */
+const char *ffpj_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FFPHOTOJOIN_H */
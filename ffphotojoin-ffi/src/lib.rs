@@ -0,0 +1,256 @@
+//! C bindings for ffphotojoin, declared in `include/ffphotojoin.h` (which cbindgen generates from
+//! this file, see `cbindgen.toml`). Images go in and come out encoded (like the contents of a JPEG
+//! or PNG file), so callers don't need to agree with Rust on how pixels are laid out in memory
+
+use ffphotojoin::image::{DynamicImage, ImageFormat, Rgba};
+use ffphotojoin::{
+    Align, Direction, EncodeOptions, FitMode, PhotoJoinOptions, ResizeBackend, Sizing,
+};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+pub const FFPJ_DIRECTION_HORIZONTAL: u32 = 0;
+pub const FFPJ_DIRECTION_VERTICAL: u32 = 1;
+/// Uses `rows` and `cols`, either of which can be 0 for as many as needed
+pub const FFPJ_DIRECTION_GRID: u32 = 2;
+/// Uses `width`, the width of every row
+pub const FFPJ_DIRECTION_JUSTIFIED: u32 = 3;
+/// Whichever of a row, a column, or a grid comes out the most square
+pub const FFPJ_DIRECTION_AUTO: u32 = 4;
+
+pub const FFPJ_SIZING_TO_SMALLEST: u32 = 0;
+pub const FFPJ_SIZING_TO_LARGEST: u32 = 1;
+/// Uses `size`, the length of the shared edge in pixels
+pub const FFPJ_SIZING_EXACT: u32 = 2;
+
+pub const FFPJ_FIT_SCALE: u32 = 0;
+pub const FFPJ_FIT_CROP: u32 = 1;
+pub const FFPJ_FIT_PAD: u32 = 2;
+
+pub const FFPJ_FORMAT_PNG: u32 = 0;
+pub const FFPJ_FORMAT_JPEG: u32 = 1;
+pub const FFPJ_FORMAT_TIFF: u32 = 2;
+pub const FFPJ_FORMAT_BMP: u32 = 3;
+pub const FFPJ_FORMAT_GIF: u32 = 4;
+
+/// Resizes images with the `image` crate
+pub const FFPJ_RESIZE_IMAGE: u32 = 0;
+/// Resizes images with SIMD instructions, which needs the library to be built with the fast-resize
+/// feature (without it they're resized like `FFPJ_RESIZE_IMAGE`)
+pub const FFPJ_RESIZE_FAST: u32 = 1;
+
+/// How images are joined and how the output is encoded, best started from
+/// `ffpj_default_options()`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FfpjOptions {
+    /// One of the `FFPJ_DIRECTION_` constants
+    pub direction: u32,
+    pub rows: u32,
+    pub cols: u32,
+    pub width: u32,
+    /// One of the `FFPJ_SIZING_` constants
+    pub sizing: u32,
+    pub size: u32,
+    /// One of the `FFPJ_FIT_` constants
+    pub fit: u32,
+    /// Pixels between the images
+    pub spacing: u32,
+    /// Pixels around the outside of the output
+    pub margin: u32,
+    /// Radius of the rounded corners of each image in pixels
+    pub corner_radius: u32,
+    /// RGBA color behind and between the images
+    pub background: [u8; 4],
+    /// One of the `FFPJ_FORMAT_` constants
+    pub format: u32,
+    /// JPEG quality from 1 to 100
+    pub quality: u8,
+    /// One of the `FFPJ_RESIZE_` constants
+    pub resize_backend: u32,
+}
+
+/// An encoded image made by `ffpj_join`, which has to be freed with `ffpj_free_buffer`
+#[repr(C)]
+pub struct FfpjBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+thread_local! {
+    // Why the last call on this thread failed
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+}
+
+/// The options a join starts out with: a horizontal strip of images all shrunk to the smallest
+/// one, on a black background, encoded as a PNG
+#[no_mangle]
+pub extern "C" fn ffpj_default_options() -> FfpjOptions {
+    FfpjOptions {
+        direction: FFPJ_DIRECTION_HORIZONTAL,
+        rows: 0,
+        cols: 0,
+        width: 0,
+        sizing: FFPJ_SIZING_TO_SMALLEST,
+        size: 0,
+        fit: FFPJ_FIT_SCALE,
+        spacing: 0,
+        margin: 0,
+        corner_radius: 0,
+        background: [0, 0, 0, 255],
+        format: FFPJ_FORMAT_PNG,
+        quality: 75,
+        resize_backend: FFPJ_RESIZE_IMAGE,
+    }
+}
+
+/// Joins `count` encoded images (`images[i]` is `lengths[i]` bytes long), turned upright by their
+/// EXIF orientation, and puts the encoded output in `output`. Returns 0 when it works, or -1 with
+/// `output` left alone when it doesn't (`ffpj_last_error` says why)
+///
+/// # Safety
+///
+/// `images` and `lengths` have to point to `count` elements each, every image has to point to as
+/// many bytes as its length, and `options` and `output` have to be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn ffpj_join(
+    images: *const *const u8,
+    lengths: *const usize,
+    count: usize,
+    options: *const FfpjOptions,
+    output: *mut FfpjBuffer,
+) -> c_int {
+    if images.is_null() || lengths.is_null() || options.is_null() || output.is_null() {
+        set_error("a pointer passed to ffpj_join is null".into());
+        return -1;
+    }
+    let images = slice::from_raw_parts(images, count);
+    let lengths = slice::from_raw_parts(lengths, count);
+    let encoded = images
+        .iter()
+        .zip(lengths)
+        .map(|(&image, &length)| match image.is_null() {
+            true => &[][..],
+            false => slice::from_raw_parts(image, length),
+        })
+        .collect::<Vec<_>>();
+    let options = *options;
+
+    // Panics can't unwind into C, so they fail the call like any other error
+    let joined = panic::catch_unwind(AssertUnwindSafe(|| join(&encoded, &options)))
+        .unwrap_or_else(|_| Err("ffphotojoin panicked".into()));
+    match joined {
+        Ok(joined) => {
+            let joined = Box::into_raw(joined.into_boxed_slice());
+            *output = FfpjBuffer {
+                data: joined as *mut u8,
+                len: joined.len(),
+            };
+            0
+        }
+        Err(message) => {
+            set_error(message);
+            -1
+        }
+    }
+}
+
+fn join(encoded: &[&[u8]], options: &FfpjOptions) -> Result<Vec<u8>, String> {
+    let join_options = join_options(options)?;
+    let format = match options.format {
+        FFPJ_FORMAT_PNG => ImageFormat::Png,
+        FFPJ_FORMAT_JPEG => ImageFormat::Jpeg,
+        FFPJ_FORMAT_TIFF => ImageFormat::Tiff,
+        FFPJ_FORMAT_BMP => ImageFormat::Bmp,
+        FFPJ_FORMAT_GIF => ImageFormat::Gif,
+        _ => return Err("invalid output format".into()),
+    };
+    let images = encoded
+        .iter()
+        .enumerate()
+        .map(|(index, encoded)| {
+            ffphotojoin::load_oriented_from_memory(encoded)
+                .map_err(|err| format!("failed to open image {}: {}", index, err))
+        })
+        .collect::<Result<Vec<DynamicImage>, _>>()?;
+    let joined = ffphotojoin::join_photos(&images, join_options)
+        .map_err(|err| format!("failed to join images: {}", err))?;
+
+    let encode_options = EncodeOptions {
+        quality: options.quality.clamp(1, 100),
+        ..EncodeOptions::default()
+    };
+    ffphotojoin::encode(&joined, format, &encode_options)
+        .map_err(|err| format!("failed to encode the output image: {}", err))
+}
+
+fn join_options(options: &FfpjOptions) -> Result<PhotoJoinOptions, String> {
+    Ok(PhotoJoinOptions {
+        direction: match options.direction {
+            FFPJ_DIRECTION_HORIZONTAL => Direction::Horizontal,
+            FFPJ_DIRECTION_VERTICAL => Direction::Vertical,
+            FFPJ_DIRECTION_GRID => Direction::Grid {
+                rows: options.rows,
+                cols: options.cols,
+            },
+            FFPJ_DIRECTION_JUSTIFIED => Direction::Justified {
+                width: options.width,
+            },
+            FFPJ_DIRECTION_AUTO => Direction::Auto,
+            _ => return Err("invalid direction".into()),
+        },
+        sizing: match options.sizing {
+            FFPJ_SIZING_TO_SMALLEST => Sizing::ToSmallest,
+            FFPJ_SIZING_TO_LARGEST => Sizing::ToLargest,
+            FFPJ_SIZING_EXACT => Sizing::Exact(options.size),
+            _ => return Err("invalid sizing".into()),
+        },
+        fit: match options.fit {
+            FFPJ_FIT_SCALE => FitMode::Scale,
+            FFPJ_FIT_CROP => FitMode::CropCenter,
+            FFPJ_FIT_PAD => FitMode::Pad(Align::Center),
+            _ => return Err("invalid fit".into()),
+        },
+        spacing: options.spacing,
+        background: Rgba(options.background),
+        margin: options.margin,
+        corner_radius: options.corner_radius,
+        resize_backend: match options.resize_backend {
+            FFPJ_RESIZE_IMAGE => ResizeBackend::Image,
+            FFPJ_RESIZE_FAST => ResizeBackend::Fast,
+            _ => return Err("invalid resize backend".into()),
+        },
+        ..Default::default()
+    })
+}
+
+/// Frees an image made by `ffpj_join`. Freeing an empty buffer does nothing
+///
+/// # Safety
+///
+/// `buffer` has to be one `ffpj_join` made, and can't be used (or freed again) afterwards
+#[no_mangle]
+pub unsafe extern "C" fn ffpj_free_buffer(buffer: FfpjBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// Why the last call that failed on this thread failed, which stays valid until the next call
+/// that fails on this thread. It's empty if nothing has failed
+#[no_mangle]
+pub extern "C" fn ffpj_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
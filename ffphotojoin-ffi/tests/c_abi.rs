@@ -0,0 +1,125 @@
+use ffphotojoin::image::{DynamicImage, GenericImageView, ImageFormat, RgbImage};
+use ffphotojoin_ffi::{
+    FfpjBuffer, FfpjOptions, FFPJ_DIRECTION_VERTICAL, FFPJ_FORMAT_GIF, FFPJ_RESIZE_FAST,
+};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+// The bindings as C sees them in include/ffphotojoin.h, found by their symbols rather than
+//  through Rust
+extern "C" {
+    fn ffpj_default_options() -> FfpjOptions;
+    fn ffpj_join(
+        images: *const *const u8,
+        lengths: *const usize,
+        count: usize,
+        options: *const FfpjOptions,
+        output: *mut FfpjBuffer,
+    ) -> c_int;
+    fn ffpj_free_buffer(buffer: FfpjBuffer);
+    fn ffpj_last_error() -> *const c_char;
+}
+
+// The symbols are only linked in if something in the crate is used
+use ffphotojoin_ffi as _;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let img = DynamicImage::ImageRgb8(RgbImage::new(width, height));
+    let mut png = Vec::new();
+    img.write_to(&mut png, ImageFormat::Png).unwrap();
+    png
+}
+
+// Joins the images with the options, giving back the encoded output or the error message
+fn join(images: &[Vec<u8>], options: &FfpjOptions) -> Result<Vec<u8>, String> {
+    let pointers = images.iter().map(|img| img.as_ptr()).collect::<Vec<_>>();
+    let lengths = images.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut output = FfpjBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+    unsafe {
+        let result = ffpj_join(
+            pointers.as_ptr(),
+            lengths.as_ptr(),
+            images.len(),
+            options,
+            &mut output,
+        );
+        if result != 0 {
+            assert_eq!(result, -1);
+            assert!(output.data.is_null());
+            let error = CStr::from_ptr(ffpj_last_error());
+            return Err(error.to_string_lossy().into_owned());
+        }
+        let encoded = slice::from_raw_parts(output.data, output.len).to_vec();
+        ffpj_free_buffer(output);
+        Ok(encoded)
+    }
+}
+
+#[test]
+fn joins_through_the_c_abi() {
+    let images = [png(20, 10), png(40, 10)];
+    let joined = join(&images, &unsafe { ffpj_default_options() }).unwrap();
+    let joined = ffphotojoin::image::load_from_memory_with_format(&joined, ImageFormat::Png);
+    assert_eq!(joined.unwrap().dimensions(), (60, 10));
+
+    let options = FfpjOptions {
+        direction: FFPJ_DIRECTION_VERTICAL,
+        spacing: 4,
+        format: FFPJ_FORMAT_GIF,
+        ..unsafe { ffpj_default_options() }
+    };
+    let joined = join(&images, &options).unwrap();
+    let joined = ffphotojoin::image::load_from_memory_with_format(&joined, ImageFormat::Gif);
+    assert_eq!(joined.unwrap().dimensions(), (20, 19));
+
+    let options = FfpjOptions {
+        resize_backend: FFPJ_RESIZE_FAST,
+        ..unsafe { ffpj_default_options() }
+    };
+    let joined = join(&images, &options).unwrap();
+    let joined = ffphotojoin::image::load_from_memory_with_format(&joined, ImageFormat::Png);
+    assert_eq!(joined.unwrap().dimensions(), (60, 10));
+}
+
+#[test]
+fn reports_errors_through_the_c_abi() {
+    let options = FfpjOptions {
+        direction: 99,
+        ..unsafe { ffpj_default_options() }
+    };
+    assert_eq!(
+        join(&[png(4, 4)], &options),
+        Err("invalid direction".into())
+    );
+    let options = FfpjOptions {
+        resize_backend: 99,
+        ..unsafe { ffpj_default_options() }
+    };
+    assert_eq!(
+        join(&[png(4, 4)], &options),
+        Err("invalid resize backend".into())
+    );
+    assert_eq!(
+        join(&[b"not an image".to_vec()], &unsafe {
+            ffpj_default_options()
+        }),
+        Err("failed to open image 0: The image format could not be determined".into())
+    );
+
+    let options = unsafe { ffpj_default_options() };
+    let mut output = FfpjBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+    let result = unsafe { ffpj_join(ptr::null(), ptr::null(), 0, &options, &mut output) };
+    assert_eq!(result, -1);
+    let error = unsafe { CStr::from_ptr(ffpj_last_error()) };
+    assert_eq!(error.to_str(), Ok("a pointer passed to ffpj_join is null"));
+    // Freeing an empty buffer does nothing
+    unsafe { ffpj_free_buffer(output) };
+}
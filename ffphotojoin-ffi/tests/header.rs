@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// include/ffphotojoin.h is generated by cbindgen, and has to be regenerated whenever the bindings
+//  change. Running this with FFPJ_UPDATE_HEADER=1 set writes the new header instead of checking it
+#[test]
+fn header_is_up_to_date() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/lib.rs"))
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+
+    let path = dir.join("include/ffphotojoin.h");
+    if env::var_os("FFPJ_UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).unwrap();
+    }
+    assert!(
+        fs::read(&path).unwrap() == generated,
+        "include/ffphotojoin.h is out of date, regenerate it with \
+         `cbindgen --config cbindgen.toml --output include/ffphotojoin.h`"
+    );
+}
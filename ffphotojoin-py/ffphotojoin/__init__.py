@@ -0,0 +1,91 @@
+"""Python bindings for ffphotojoin, a native extension built from the Rust library with maturin
+(`maturin develop` or `pip install .` in ffphotojoin-py).
+
+    import ffphotojoin
+    png = ffphotojoin.join(["a.jpg", "b.jpg"], direction="grid", cols=2)
+    grid = ffphotojoin.join(arrays, direction="vertical", sizing=256, as_array=True)
+"""
+
+import io
+import os
+
+from ._ffphotojoin import JoinError
+from ._ffphotojoin import join as _join
+
+__all__ = ["join", "JoinError"]
+
+
+# The encoded bytes of an image given as a path, encoded bytes, a PIL image, or a NumPy array
+def _encoded(image):
+    if isinstance(image, (str, os.PathLike)):
+        with open(image, "rb") as file:
+            return file.read()
+    if isinstance(image, (bytes, bytearray, memoryview)):
+        return bytes(image)
+    if hasattr(image, "__array_interface__") and not hasattr(image, "save"):
+        from PIL import Image
+
+        image = Image.fromarray(image)
+    if hasattr(image, "save"):
+        # PNG keeps every pixel as it is
+        encoded = io.BytesIO()
+        image.save(encoded, format="PNG")
+        return encoded.getvalue()
+    raise TypeError(
+        "images have to be paths, encoded bytes, PIL images, or NumPy arrays, not %s"
+        % type(image).__name__
+    )
+
+
+def join(
+    images,
+    direction="horizontal",
+    sizing="smallest",
+    *,
+    rows=0,
+    cols=0,
+    width=0,
+    fit="scale",
+    spacing=0,
+    margin=0,
+    corner_radius=0,
+    background=(0, 0, 0, 255),
+    format="png",
+    quality=75,
+    resize_backend="image",
+    as_array=False,
+):
+    """Joins images into one and returns it encoded (PNG by default), which PIL can open with
+    `Image.open(io.BytesIO(joined))`, or as a NumPy array with `as_array=True`.
+
+    `images` can be paths, encoded bytes, PIL images, or NumPy arrays (the last two need Pillow).
+    `direction` is horizontal, vertical, grid (with `rows` and `cols`, either of which can be 0
+    for as many as needed), justified (with `width`, the width of every row), or auto (whichever
+    is the most square). `sizing` is smallest, largest, or the length of the shared edge in
+    pixels. `fit` is scale, crop, or pad, and `background` is an RGB or RGBA color.
+    `resize_backend` is image, or fast to resize with SIMD instructions when the module is built
+    with the fast-resize feature.
+    """
+    joined = _join(
+        [_encoded(image) for image in images],
+        direction,
+        sizing,
+        rows=rows,
+        cols=cols,
+        width=width,
+        fit=fit,
+        spacing=spacing,
+        margin=margin,
+        corner_radius=corner_radius,
+        background=background,
+        format=format,
+        quality=quality,
+        resize_backend=resize_backend,
+    )
+
+    if as_array:
+        import numpy
+        from PIL import Image
+
+        return numpy.asarray(Image.open(io.BytesIO(joined)))
+    return joined
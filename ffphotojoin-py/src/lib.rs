@@ -0,0 +1,162 @@
+//! Python bindings for ffphotojoin, the native module of the `ffphotojoin` Python package (which
+//! turns paths, PIL images, and NumPy arrays into encoded images before they get here). Built
+//! with maturin, like `maturin develop` or `pip install .` in this directory
+
+use ffphotojoin::image::{DynamicImage, ImageFormat, Rgba};
+use ffphotojoin::{
+    Align, Direction, EncodeOptions, FitMode, PhotoJoinOptions, ResizeBackend, Sizing,
+};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::pybacked::PyBackedBytes;
+use pyo3::types::{PyBool, PyBytes};
+
+create_exception!(
+    ffphotojoin,
+    JoinError,
+    PyException,
+    "Raised when the images can't be opened, joined, or encoded"
+);
+
+/// Joins images given as the bytes of encoded image files, turned upright by their EXIF
+/// orientation, and returns the output encoded. The options are the ones `ffphotojoin.join`
+/// describes. The join runs without holding the GIL, so other Python threads keep running
+#[pyfunction]
+#[pyo3(signature = (
+    images,
+    direction = "horizontal",
+    sizing = None,
+    *,
+    rows = 0,
+    cols = 0,
+    width = 0,
+    fit = "scale",
+    spacing = 0,
+    margin = 0,
+    corner_radius = 0,
+    background = vec![0, 0, 0, 255],
+    format = "png",
+    quality = 75,
+    resize_backend = "image",
+))]
+#[allow(clippy::too_many_arguments)]
+fn join<'py>(
+    py: Python<'py>,
+    images: Vec<PyBackedBytes>,
+    direction: &str,
+    sizing: Option<&Bound<'py, PyAny>>,
+    rows: u32,
+    cols: u32,
+    width: u32,
+    fit: &str,
+    spacing: u32,
+    margin: u32,
+    corner_radius: u32,
+    background: Vec<u8>,
+    format: &str,
+    quality: u8,
+    resize_backend: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let join_options = PhotoJoinOptions {
+        direction: match direction {
+            "horizontal" => Direction::Horizontal,
+            "vertical" => Direction::Vertical,
+            "grid" => Direction::Grid { rows, cols },
+            "justified" => Direction::Justified { width },
+            "auto" => Direction::Auto,
+            direction => return Err(invalid("direction", direction)),
+        },
+        sizing: sizing_arg(sizing)?,
+        fit: match fit {
+            "scale" => FitMode::Scale,
+            "crop" => FitMode::CropCenter,
+            "pad" => FitMode::Pad(Align::Center),
+            fit => return Err(invalid("fit", fit)),
+        },
+        spacing,
+        background: match background[..] {
+            [r, g, b] => Rgba([r, g, b, 255]),
+            [r, g, b, a] => Rgba([r, g, b, a]),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "background has to be an (r, g, b) or (r, g, b, a) color",
+                ))
+            }
+        },
+        margin,
+        corner_radius,
+        resize_backend: match resize_backend {
+            "image" => ResizeBackend::Image,
+            "fast" => ResizeBackend::Fast,
+            backend => return Err(invalid("resize_backend", backend)),
+        },
+        ..Default::default()
+    };
+    let format = match format.to_lowercase().as_str() {
+        "png" => ImageFormat::Png,
+        "jpeg" | "jpg" => ImageFormat::Jpeg,
+        "tiff" => ImageFormat::Tiff,
+        "bmp" => ImageFormat::Bmp,
+        "gif" => ImageFormat::Gif,
+        _ => return Err(invalid("format", format)),
+    };
+    let encode_options = EncodeOptions {
+        quality: quality.clamp(1, 100),
+        ..EncodeOptions::default()
+    };
+
+    let joined = py.allow_threads(|| {
+        let images = images
+            .iter()
+            .enumerate()
+            .map(|(index, encoded)| {
+                ffphotojoin::load_oriented_from_memory(encoded)
+                    .map_err(|err| format!("failed to open image {}: {}", index, err))
+            })
+            .collect::<Result<Vec<DynamicImage>, _>>()?;
+        let joined = ffphotojoin::join_photos(&images, join_options)
+            .map_err(|err| format!("failed to join images: {}", err))?;
+        ffphotojoin::encode(&joined, format, &encode_options)
+            .map_err(|err| format!("failed to encode the output image: {}", err))
+    });
+    match joined {
+        Ok(joined) => Ok(PyBytes::new(py, &joined)),
+        Err(message) => Err(JoinError::new_err(message)),
+    }
+}
+
+// The sizing option, which is "smallest" (the default), "largest", or a number of pixels
+fn sizing_arg(sizing: Option<&Bound<PyAny>>) -> PyResult<Sizing> {
+    let sizing = match sizing {
+        Some(sizing) => sizing,
+        None => return Ok(Sizing::ToSmallest),
+    };
+    // Python's bools are ints too, but `sizing=True` is surely a mistake
+    if !sizing.is_instance_of::<PyBool>() {
+        if let Ok(size) = sizing.extract::<u32>() {
+            if size > 0 {
+                return Ok(Sizing::Exact(size));
+            }
+        }
+        match sizing.extract::<String>().as_deref() {
+            Ok("smallest") => return Ok(Sizing::ToSmallest),
+            Ok("largest") => return Ok(Sizing::ToLargest),
+            _ => {}
+        }
+    }
+    Err(PyValueError::new_err(
+        "sizing has to be smallest, largest, or a number of pixels",
+    ))
+}
+
+fn invalid(option: &str, value: &str) -> PyErr {
+    PyValueError::new_err(format!("invalid {} {:?}", option, value))
+}
+
+#[pymodule]
+fn _ffphotojoin(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(join, m)?)?;
+    m.add("JoinError", m.py().get_type::<JoinError>())?;
+    Ok(())
+}
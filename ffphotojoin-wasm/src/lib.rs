@@ -0,0 +1,180 @@
+//! WebAssembly bindings for ffphotojoin, built with `wasm-pack build --target web` (or any other
+//! wasm-bindgen target). Images go in and come out encoded, like the contents of a JPEG or PNG
+//! file, so a collage can be made from files the user picks without uploading them anywhere
+//!
+//! ```js
+//! import init, { join } from "./pkg/ffphotojoin_wasm.js";
+//!
+//! await init();
+//! const images = await Promise.all(
+//!     [...input.files].map(async (file) => new Uint8Array(await file.arrayBuffer())),
+//! );
+//! const png = join(images, { direction: "grid", cols: 2, spacing: 8 });
+//! const url = URL.createObjectURL(new Blob([png], { type: "image/png" }));
+//! ```
+
+use ffphotojoin::image::{DynamicImage, ImageFormat, Rgba};
+use ffphotojoin::{
+    Align, Direction, EncodeOptions, FitMode, PhotoJoinOptions, ResizeBackend, Sizing,
+};
+use js_sys::{Array, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Joins images given as `Uint8Array`s of their encoded bytes, turned upright by their EXIF
+/// orientation, and returns the output encoded as a `Uint8Array`. Every option can be left out:
+///
+/// - `direction`: `"horizontal"` (the default), `"vertical"`, `"grid"` (with `rows` and `cols`,
+///   either of which can be 0 for as many as needed), `"justified"` (with `width`, the width of
+///   every row), or `"auto"` (whichever is the most square)
+/// - `sizing`: `"smallest"` (the default), `"largest"`, or the length of the shared edge in pixels
+/// - `fit`: `"scale"` (the default), `"crop"`, or `"pad"`
+/// - `spacing`, `margin`, and `cornerRadius`: in pixels
+/// - `background`: an `[r, g, b]` or `[r, g, b, a]` array, black by default
+/// - `format`: `"png"` (the default), `"jpeg"`, `"tiff"`, `"bmp"`, or `"gif"`
+/// - `quality`: JPEG quality from 1 to 100
+/// - `resizeBackend`: `"image"` (the default), or `"fast"` to resize with SIMD instructions when
+///   built with the fast-resize feature
+#[wasm_bindgen]
+pub fn join(images: Array, options: JsValue) -> Result<Vec<u8>, JsError> {
+    let options = Options(options);
+    let join_options = join_options(&options)?;
+    let format = match options.string("format")?.as_deref() {
+        None | Some("png") => ImageFormat::Png,
+        Some("jpeg") | Some("jpg") => ImageFormat::Jpeg,
+        Some("tiff") => ImageFormat::Tiff,
+        Some("bmp") => ImageFormat::Bmp,
+        Some("gif") => ImageFormat::Gif,
+        Some(format) => return Err(invalid("format", format)),
+    };
+    let encode_options = EncodeOptions {
+        quality: options.number("quality")?.unwrap_or(75).clamp(1, 100) as u8,
+        ..EncodeOptions::default()
+    };
+
+    let images = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| {
+            let encoded = image
+                .dyn_into::<Uint8Array>()
+                .map_err(|_| JsError::new(&format!("image {} isn't a Uint8Array", index)))?
+                .to_vec();
+            ffphotojoin::load_oriented_from_memory(&encoded)
+                .map_err(|err| JsError::new(&format!("failed to open image {}: {}", index, err)))
+        })
+        .collect::<Result<Vec<DynamicImage>, _>>()?;
+    let joined = ffphotojoin::join_photos(&images, join_options)
+        .map_err(|err| JsError::new(&format!("failed to join images: {}", err)))?;
+
+    ffphotojoin::encode(&joined, format, &encode_options)
+        .map_err(|err| JsError::new(&format!("failed to encode the output image: {}", err)))
+}
+
+fn join_options(options: &Options) -> Result<PhotoJoinOptions, JsError> {
+    let number = |name| options.number(name).map(Option::unwrap_or_default);
+    Ok(PhotoJoinOptions {
+        direction: match options.string("direction")?.as_deref() {
+            None | Some("horizontal") => Direction::Horizontal,
+            Some("vertical") => Direction::Vertical,
+            Some("grid") => Direction::Grid {
+                rows: number("rows")?,
+                cols: number("cols")?,
+            },
+            Some("justified") => Direction::Justified {
+                width: number("width")?,
+            },
+            Some("auto") => Direction::Auto,
+            Some(direction) => return Err(invalid("direction", direction)),
+        },
+        sizing: match options.get("sizing")? {
+            None => Sizing::ToSmallest,
+            Some(sizing) => match (sizing.as_string().as_deref(), sizing.as_f64()) {
+                (Some("smallest"), _) => Sizing::ToSmallest,
+                (Some("largest"), _) => Sizing::ToLargest,
+                (_, Some(size)) if size >= 1.0 => Sizing::Exact(size as u32),
+                _ => {
+                    return Err(JsError::new(
+                        "sizing has to be \"smallest\", \"largest\", or a number of pixels",
+                    ))
+                }
+            },
+        },
+        fit: match options.string("fit")?.as_deref() {
+            None | Some("scale") => FitMode::Scale,
+            Some("crop") => FitMode::CropCenter,
+            Some("pad") => FitMode::Pad(Align::Center),
+            Some(fit) => return Err(invalid("fit", fit)),
+        },
+        spacing: number("spacing")?,
+        background: options.color("background")?.unwrap_or(Rgba([0, 0, 0, 255])),
+        margin: number("margin")?,
+        corner_radius: number("cornerRadius")?,
+        resize_backend: match options.string("resizeBackend")?.as_deref() {
+            None | Some("image") => ResizeBackend::Image,
+            Some("fast") => ResizeBackend::Fast,
+            Some(backend) => return Err(invalid("resizeBackend", backend)),
+        },
+        ..Default::default()
+    })
+}
+
+fn invalid(option: &str, value: &str) -> JsError {
+    JsError::new(&format!("invalid {} \"{}\"", option, value))
+}
+
+// The options object passed to `join`, which can be undefined or null for all the defaults
+struct Options(JsValue);
+
+impl Options {
+    // An option that's set (not undefined or null)
+    fn get(&self, name: &str) -> Result<Option<JsValue>, JsError> {
+        if self.0.is_undefined() || self.0.is_null() {
+            return Ok(None);
+        }
+        let value = Reflect::get(&self.0, &JsValue::from_str(name))
+            .map_err(|_| JsError::new("the options have to be an object"))?;
+        Ok((!value.is_undefined() && !value.is_null()).then_some(value))
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, JsError> {
+        self.get(name)?
+            .map(|value| {
+                value
+                    .as_string()
+                    .ok_or_else(|| JsError::new(&format!("{} has to be a string", name)))
+            })
+            .transpose()
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u32>, JsError> {
+        self.get(name)?
+            .map(|value| match value.as_f64() {
+                Some(number) if number >= 0.0 => Ok(number as u32),
+                _ => Err(JsError::new(&format!(
+                    "{} has to be a number that isn't negative",
+                    name
+                ))),
+            })
+            .transpose()
+    }
+
+    fn color(&self, name: &str) -> Result<Option<Rgba<u8>>, JsError> {
+        self.get(name)?
+            .map(|value| {
+                let channels = Array::from(&value)
+                    .iter()
+                    .map(|channel| channel.as_f64().map(|channel| channel as u8))
+                    .collect::<Option<Vec<_>>>();
+                match channels.as_deref() {
+                    Some(&[r, g, b]) => Ok(Rgba([r, g, b, 255])),
+                    Some(&[r, g, b, a]) => Ok(Rgba([r, g, b, a])),
+                    _ => Err(JsError::new(&format!(
+                        "{} has to be an [r, g, b] or [r, g, b, a] array",
+                        name
+                    ))),
+                }
+            })
+            .transpose()
+    }
+}
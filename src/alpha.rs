@@ -0,0 +1,42 @@
+use image::imageops::{self, FilterType};
+use image::{GenericImageView, ImageBuffer, Primitive, Rgba};
+
+// Resizing with premultiplied alpha, where the color of each pixel is multiplied by its alpha
+//  before the pixels are averaged. Otherwise the color of see-through pixels (which is often
+//  black or white, but could be anything) bleeds into the edges of the solid parts of the image
+
+// Same as `imageops::resize`, with premultiplied alpha if `premultiplied` is set
+pub(crate) fn resize<T: Primitive + 'static>(
+    img: &impl GenericImageView<Pixel = Rgba<T>>,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    premultiplied: bool,
+) -> ImageBuffer<Rgba<T>, Vec<T>> {
+    if !premultiplied {
+        return imageops::resize(img, width, height, filter);
+    }
+
+    // Work with floats from 0 to 1 so multiplying doesn't lose the darkest colors
+    let max = T::max_value().to_f32().unwrap_or(1.0);
+    let (w, h) = img.dimensions();
+    let multiplied: ImageBuffer<Rgba<f32>, Vec<f32>> = ImageBuffer::from_fn(w, h, |x, y| {
+        let [r, g, b, a] = img
+            .get_pixel(x, y)
+            .0
+            .map(|channel| channel.to_f32().unwrap_or_default() / max);
+        Rgba([r * a, g * a, b * a, a])
+    });
+    let resized = imageops::resize(&multiplied, width, height, filter);
+
+    let to_channel = |value: f32| T::from((value.clamp(0.0, 1.0) * max).round());
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = resized.get_pixel(x, y).0;
+        // Fully see-through pixels have no color left to divide back out
+        let divide = |value: f32| if a > 0.0 { value / a } else { 0.0 };
+        Rgba(
+            [divide(r), divide(g), divide(b), a]
+                .map(|value| to_channel(value).unwrap_or_else(T::zero)),
+        )
+    })
+}
@@ -0,0 +1,167 @@
+use crate::streaming::png_error;
+use crate::{
+    fit, render, round_up_length, size_by, with_focus, JoinError, Layout, LayoutItem,
+    PhotoJoinOptions, Rect, Sizing,
+};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, GenericImageView, ImageResult};
+use std::borrow::Borrow;
+use std::io::Write;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AnimationFormat {
+    Gif,
+    /// Animated PNG, which keeps every color (GIFs only have 256 per frame) but isn't shown as
+    /// animated everywhere
+    Apng,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// How long each frame is shown for in milliseconds
+    pub frame_delay: u16,
+    /// How many times the animation plays, or 0 to play it forever
+    pub loop_count: u16,
+}
+
+/// Makes every image into a frame of the same size, so they can be played one after another as
+/// an animation. The frame size comes from the widths and heights of the images picked by the
+/// sizing (like the cells of a grid), and each image is fit into the frame with the fit mode of
+/// `options`, and the frames are in the order of the options. Frames are padded with the
+/// background to the sizes `power_of_two` and `align_multiple` round them up to (like the even
+/// sizes video encoders need). The direction, spacing, margin, and other options between images
+/// are ignored
+pub fn animation_frames<I: Borrow<DynamicImage>>(
+    photos: impl IntoIterator<Item = I>,
+    options: PhotoJoinOptions,
+) -> Result<Vec<DynamicImage>, JoinError> {
+    let photos = photos.into_iter().collect::<Vec<_>>();
+    let photos = photos
+        .iter()
+        .map(Borrow::borrow)
+        .collect::<Vec<&DynamicImage>>();
+    if photos.is_empty() {
+        return Err(JoinError::NoImagesProvided);
+    }
+    if options.sizing == Sizing::Exact(0) {
+        return Err(JoinError::InvalidSizing);
+    }
+    let items = photos
+        .iter()
+        .map(|img| LayoutItem {
+            border: options.border,
+            ..LayoutItem::new(img.dimensions())
+        })
+        .collect::<Vec<_>>();
+    let items = with_focus(&photos, &items, &options);
+    if let Some(index) = items
+        .iter()
+        .position(|item| item.size.0 == 0 || item.size.1 == 0)
+    {
+        return Err(JoinError::ZeroSizedImage { index });
+    }
+
+    let frame = Rect {
+        x: 0,
+        y: 0,
+        width: size_by(options.sizing, items.iter().map(|item| item.size.0)),
+        height: size_by(options.sizing, items.iter().map(|item| item.size.1)),
+    };
+    // Frames larger than `max_output` are shrunk to fit inside of it
+    let frame = match options.max_output {
+        Some((max_width, max_height)) if frame.width > max_width || frame.height > max_height => {
+            let scale = (max_width as f64 / frame.width as f64)
+                .min(max_height as f64 / frame.height as f64);
+            Rect {
+                width: ((frame.width as f64 * scale).round() as u32).clamp(1, max_width.max(1)),
+                height: ((frame.height as f64 * scale).round() as u32).clamp(1, max_height.max(1)),
+                ..frame
+            }
+        }
+        _ => frame,
+    };
+    if let Some((max_width, max_height)) = options.max_dimensions {
+        if frame.width > max_width || frame.height > max_height {
+            return Err(JoinError::TooLarge {
+                width: frame.width,
+                height: frame.height,
+                max_width,
+                max_height,
+            });
+        }
+    }
+
+    let width = round_up_length(frame.width, &options)?;
+    let height = round_up_length(frame.height, &options)?;
+
+    Ok(options
+        .order
+        .indices(photos.len())
+        .into_iter()
+        .map(|index| {
+            let (img, item) = (&photos[index], &items[index]);
+            let layout = Layout {
+                width,
+                height,
+                placements: vec![fit(item, frame, options.fit)],
+                separators: Vec::new(),
+            };
+            render(&[*img], &[*item], layout, &options, &mut |_| {})
+        })
+        .collect())
+}
+
+/// Encodes frames of the same size (like from [`animation_frames`]) as an animated GIF or PNG.
+/// Frames are always written with 8 bits per channel
+pub fn encode_animation(
+    frames: &[DynamicImage],
+    writer: impl Write,
+    options: &AnimationOptions,
+) -> ImageResult<()> {
+    match options.format {
+        AnimationFormat::Gif => {
+            let mut encoder = GifEncoder::new(writer);
+            encoder.set_repeat(match options.loop_count {
+                0 => Repeat::Infinite,
+                count => Repeat::Finite(count),
+            })?;
+            let delay = Delay::from_numer_denom_ms(options.frame_delay as u32, 1);
+            encoder.encode_frames(
+                frames
+                    .iter()
+                    .map(|frame| Frame::from_parts(frame.to_rgba8(), 0, 0, delay)),
+            )
+        }
+        AnimationFormat::Apng => {
+            let (width, height) = frames.first().map_or((0, 0), |frame| frame.dimensions());
+            let has_alpha = frames.iter().any(|frame| frame.color().has_alpha());
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(if has_alpha {
+                png::ColorType::Rgba
+            } else {
+                png::ColorType::Rgb
+            });
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .set_animated(frames.len() as u32, options.loop_count as u32)
+                .map_err(png_error)?;
+            encoder
+                .set_frame_delay(options.frame_delay, 1000)
+                .map_err(png_error)?;
+            let mut writer = encoder.write_header().map_err(png_error)?;
+            for frame in frames {
+                let pixels = if has_alpha {
+                    frame.to_rgba8().into_raw()
+                } else {
+                    frame.to_rgb8().into_raw()
+                };
+                writer.write_image_data(&pixels).map_err(png_error)?;
+            }
+            writer.finish().map_err(png_error)
+        }
+    }
+}
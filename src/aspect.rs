@@ -0,0 +1,102 @@
+use crate::justified::{aspects, lay_out_rows, Row};
+use crate::{size_by, JoinError, Layout, LayoutItem, PhotoJoinOptions};
+use std::ops::Range;
+
+// Lays images out in rows like justified rows, but picks how many rows there are (and which images
+//  go in each) so the output comes as close as it can to the aspect ratio `width:height`. Every
+//  row is scaled to the same width, and the rows are as tall as the sizing picks on average
+pub(crate) fn layout_aspect(
+    items: &[LayoutItem],
+    ratio: (u32, u32),
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    if ratio.0 == 0 || ratio.1 == 0 {
+        return Err(JoinError::InvalidAspectRatio);
+    }
+    let target = ratio.0 as f64 / ratio.1 as f64;
+    let target_height = size_by(options.sizing, items.iter().map(|item| item.size.1)) as f64;
+    let spacing = options.spacing as f64;
+    let aspects = aspects(items);
+
+    // Try every number of rows and keep the closest, comparing ratios on a log scale so being
+    //  twice too wide is as bad as being twice too tall
+    let mut best: Option<(f64, Vec<Row>, f64)> = None;
+    for count in 1..=items.len() {
+        let ranges = match split_rows(&aspects, count) {
+            Some(ranges) => ranges,
+            None => continue,
+        };
+        let (rows, width, height) = size_rows(ranges, &aspects, target_height, spacing);
+        let error = (width / height / target).ln().abs();
+        if best
+            .as_ref()
+            .is_none_or(|(best_error, _, _)| error < *best_error)
+        {
+            best = Some((error, rows, width));
+        }
+    }
+    let (_, rows, width) = best.ok_or(JoinError::NoImagesProvided)?;
+    let width = width.round().max(1.0);
+    if width > u32::MAX as f64 {
+        return Err(JoinError::DimensionOverflow);
+    }
+    lay_out_rows(items, &aspects, &rows, width as u32, options)
+}
+
+// Splits the images into `count` rows that are about as wide as each other. Each image goes in
+//  the row its middle lands in when the images are lined up end to end and cut into `count`
+//  equal pieces, which doesn't work out when an image is wide enough to leave a row empty
+fn split_rows(aspects: &[f64], count: usize) -> Option<Vec<Range<usize>>> {
+    let total = aspects.iter().sum::<f64>();
+    let mut ranges: Vec<Range<usize>> = Vec::with_capacity(count);
+    let mut before = 0.0;
+    for (index, aspect) in aspects.iter().enumerate() {
+        let row = (((before + aspect / 2.0) / total * count as f64) as usize).min(count - 1);
+        before += aspect;
+        if row == ranges.len() {
+            ranges.push(index..index + 1);
+        } else if let Some(range) = ranges.get_mut(row) {
+            range.end = index + 1;
+        } else {
+            return None;
+        }
+    }
+    (ranges.len() == count).then_some(ranges)
+}
+
+// Picks the width every row is scaled to so the rows are `target_height` tall on average, and
+//  returns the rows at their heights along with the width and height of all of them together
+fn size_rows(
+    ranges: Vec<Range<usize>>,
+    aspects: &[f64],
+    target_height: f64,
+    spacing: f64,
+) -> (Vec<Row>, f64, f64) {
+    // A row of images with a total aspect of `aspect` and `gaps` of spacing between them is
+    //  (width - gaps) / aspect tall at any width
+    let rows = ranges
+        .into_iter()
+        .map(|range| {
+            let aspect = aspects[range.clone()].iter().sum::<f64>();
+            let gaps = spacing * (range.len() - 1) as f64;
+            (range, aspect, gaps)
+        })
+        .collect::<Vec<_>>();
+    let per_width = rows.iter().map(|(_, aspect, _)| 1.0 / aspect).sum::<f64>();
+    let gap_heights = rows
+        .iter()
+        .map(|(_, aspect, gaps)| gaps / aspect)
+        .sum::<f64>();
+    let width = (target_height * rows.len() as f64 + gap_heights) / per_width;
+
+    let rows = rows
+        .into_iter()
+        .map(|(items, aspect, gaps)| Row {
+            items,
+            height: ((width - gaps) / aspect).max(1.0),
+            reversed: false,
+        })
+        .collect::<Vec<_>>();
+    let height = rows.iter().map(|row| row.height).sum::<f64>() + spacing * (rows.len() - 1) as f64;
+    (rows, width, height)
+}
@@ -0,0 +1,344 @@
+use crate::{fit, Align, FitMode, JoinError, Layout, LayoutItem, PhotoJoinOptions, Rect};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+// How many widths between the widest image and a single row are tried when looking for the
+//  smallest atlas, on top of the ones around a square
+const WIDTH_STEPS: u64 = 16;
+// Packings of about the same area can be any shape from square to a long strip, so the most
+//  square one that's at most this much larger than the smallest is used
+const AREA_TOLERANCE: f64 = 0.05;
+
+// Packs images at their own sizes into the smallest rectangle they fit in (or into exactly
+//  `size`, margins included), like a sprite sheet. Each image is put as high up and then as far
+//  left as it fits, tallest first, which leaves little empty space between images of any shape.
+//  Every width the atlas could reasonably be is tried, and the most square of the ones with
+//  about the smallest area wins
+pub(crate) fn layout_atlas(
+    items: &[LayoutItem],
+    size: Option<(u32, u32)>,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    // Each image takes up its own size plus its border and caption, and the spacing after it
+    //  (which the atlas is made that much wider and taller to fit)
+    let spacing = options.spacing as u64;
+    let cells = items
+        .iter()
+        .map(|item| {
+            let border = item.border.map_or(0, |border| border.width as u64 * 2);
+            let caption = item.caption.map_or(0, |caption| caption.height as u64);
+            (
+                item.size.0 as u64 + border,
+                item.size.1 as u64 + border + caption,
+            )
+        })
+        .collect::<Vec<_>>();
+    let padded = cells
+        .iter()
+        .map(|&(width, height)| (width + spacing, height + spacing))
+        .collect::<Vec<_>>();
+    let mut order = (0..cells.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&index| {
+        let (width, height) = cells[index];
+        (std::cmp::Reverse(height), std::cmp::Reverse(width))
+    });
+    let widest = cells.iter().map(|&(width, _)| width).max().unwrap_or(0);
+
+    let (width, height, positions) = match size {
+        Some((width, height)) => {
+            // The margin goes around the packed images, inside of the requested size
+            let margins = options.margin as u64 * 2;
+            let inner = (
+                (width as u64).saturating_sub(margins),
+                (height as u64).saturating_sub(margins),
+            );
+            let too_large = |packed_width: u64, packed_height: u64| JoinError::TooLarge {
+                width: (packed_width + margins).min(u32::MAX as u64) as u32,
+                height: (packed_height + margins).min(u32::MAX as u64) as u32,
+                max_width: width,
+                max_height: height,
+            };
+            if widest > inner.0 {
+                return Err(too_large(widest, cells[order[0]].1));
+            }
+            let (positions, packed_height) = pack(&padded, &order, inner.0 + spacing);
+            let packed_height = packed_height - spacing;
+            if packed_height > inner.1 {
+                return Err(too_large(inner.0, packed_height));
+            }
+            (inner.0, inner.1, positions)
+        }
+        None => {
+            let area = padded
+                .iter()
+                .map(|&(width, height)| width * height)
+                .sum::<u64>();
+            let row = padded.iter().map(|&(width, _)| width).sum::<u64>() - spacing;
+            let square = (area as f64).sqrt();
+            let mut widths = (0..=WIDTH_STEPS)
+                .map(|step| widest + (row - widest) * step / WIDTH_STEPS)
+                .chain((8..=32).map(|sixteenths| (square * sixteenths as f64 / 16.0) as u64))
+                .map(|width| width.clamp(widest, row))
+                .collect::<Vec<_>>();
+            widths.sort_unstable();
+            widths.dedup();
+
+            let packings = widths
+                .into_iter()
+                .map(|width| {
+                    let (positions, height) = pack(&padded, &order, width + spacing);
+                    // The packed images might not reach all the way to the edge that was allowed
+                    let width = positions
+                        .iter()
+                        .zip(&cells)
+                        .map(|(&(x, _), &(cell_width, _))| x + cell_width)
+                        .max()
+                        .unwrap_or(0);
+                    (width, height - spacing, positions)
+                })
+                .collect::<Vec<_>>();
+            let smallest = packings
+                .iter()
+                .map(|&(width, height, _)| width * height)
+                .min()
+                .ok_or(JoinError::NoImagesProvided)?;
+            let squareness =
+                |&(width, height, _): &(u64, u64, _)| (width as f64 / height as f64).ln().abs();
+            packings
+                .into_iter()
+                .filter(|&(width, height, _)| {
+                    (width * height) as f64 <= smallest as f64 * (1.0 + AREA_TOLERANCE)
+                })
+                .min_by(|a, b| squareness(a).total_cmp(&squareness(b)))
+                .ok_or(JoinError::NoImagesProvided)?
+        }
+    };
+
+    let length = |length: u64| u32::try_from(length).map_err(|_| JoinError::DimensionOverflow);
+    let placements = items
+        .iter()
+        .zip(&cells)
+        .zip(positions)
+        .map(|((item, &(cell_width, cell_height)), (x, y))| {
+            let cell = Rect {
+                x: length(x)?,
+                y: length(y)?,
+                width: length(cell_width)?,
+                height: length(cell_height)?,
+            };
+            // The cell is exactly as large as the image and its border, so it isn't resized
+            Ok(fit(item, cell, FitMode::Pad(Align::Center)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Layout {
+        width: length(width)?,
+        height: length(height)?,
+        placements,
+        separators: Vec::new(),
+    })
+}
+
+// Packs rectangles into a strip `width` wide (which has to fit the widest one) in the given order,
+//  each as high up as it fits along the bottom edge of the ones before it (the skyline).
+//  Returns where each rectangle goes, in the order they were given in, and how tall the strip is
+fn pack(sizes: &[(u64, u64)], order: &[usize], width: u64) -> (Vec<(u64, u64)>, u64) {
+    // Segments of the skyline from left to right, as their start, how far down they are, and their
+    //  width
+    let mut skyline = vec![(0, 0, width)];
+    let mut positions = vec![(0, 0); sizes.len()];
+    let mut height = 0;
+    for &index in order {
+        let (rect_width, rect_height) = sizes[index];
+        // The rectangle starts at the start of one of the segments, and sits below the lowest of
+        //  the segments it spans
+        let mut best: Option<(u64, u64, usize)> = None;
+        for start in 0..skyline.len() {
+            let x = skyline[start].0;
+            if x + rect_width > width {
+                break;
+            }
+            let y = skyline[start..]
+                .iter()
+                .take_while(|&&(segment_x, ..)| segment_x < x + rect_width)
+                .map(|&(_, segment_y, _)| segment_y)
+                .max()
+                .unwrap_or(0);
+            if best.is_none_or(|(best_y, best_x, _)| (y, x) < (best_y, best_x)) {
+                best = Some((y, x, start));
+            }
+        }
+        let (y, x, start) = best.expect("the strip is too narrow for a rectangle");
+        positions[index] = (x, y);
+        height = height.max(y + rect_height);
+
+        // The rectangle's bottom edge replaces the part of the skyline it covers
+        let end = x + rect_width;
+        let mut replaced = start;
+        while replaced < skyline.len() && skyline[replaced].0 < end {
+            replaced += 1;
+        }
+        let (last_x, last_y, last_width) = skyline[replaced - 1];
+        let rest = (last_x + last_width > end).then(|| (end, last_y, last_x + last_width - end));
+        skyline.splice(
+            start..replaced,
+            std::iter::once((x, y + rect_height, rect_width)).chain(rest),
+        );
+        // Neighboring segments that are just as far down are merged so the skyline stays short
+        skyline.dedup_by(|next, previous| {
+            let merge = next.1 == previous.1;
+            if merge {
+                previous.2 += next.2;
+            }
+            merge
+        });
+    }
+    (positions, height)
+}
+
+/// Formats that [`atlas_coordinates`] can write the places of the images in an atlas in
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AtlasFormat {
+    /// An object with the `image` and its `width` and `height`, and a `frames` list with the
+    /// `name`, `x`, `y`, `width`, and `height` of each image
+    Json,
+    /// A `.sprite-<name>` class for each image that shows it by using the atlas as its background
+    /// image (characters that can't be in a class name are replaced with `-`)
+    Css,
+    /// The "JSON (Hash)" format of TexturePacker, which game engines like Phaser and PixiJS can
+    /// load sprites with
+    TexturePacker,
+}
+
+/// Writes where each image is in an atlas (or any other joined image), for loading the images
+/// back out of it. `sprites` are the name of each image along with where it is, like the targets
+/// of the placements of a [`Layout`](crate::Layout), and `image` is the path or URL of the atlas
+/// that's written into the file
+pub fn atlas_coordinates<S: AsRef<str>>(
+    (width, height): (u32, u32),
+    sprites: impl IntoIterator<Item = (S, Rect)>,
+    image: &str,
+    format: AtlasFormat,
+) -> String {
+    let sprites = sprites.into_iter().collect::<Vec<_>>();
+    let mut out = String::new();
+    // Writing to a string can't fail
+    let _ = match format {
+        AtlasFormat::Json => write_json(&mut out, (width, height), &sprites, image),
+        AtlasFormat::Css => write_css(&mut out, &sprites, image),
+        AtlasFormat::TexturePacker => {
+            write_texture_packer(&mut out, (width, height), &sprites, image)
+        }
+    };
+    out
+}
+
+fn write_json(
+    out: &mut String,
+    (width, height): (u32, u32),
+    sprites: &[(impl AsRef<str>, Rect)],
+    image: &str,
+) -> std::fmt::Result {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"image\": {},", json_string(image))?;
+    writeln!(out, "  \"width\": {},", width)?;
+    writeln!(out, "  \"height\": {},", height)?;
+    writeln!(out, "  \"frames\": [")?;
+    for (index, (name, rect)) in sprites.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"name\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}}}{}",
+            json_string(name.as_ref()),
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            if index + 1 < sprites.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+fn write_css(
+    out: &mut String,
+    sprites: &[(impl AsRef<str>, Rect)],
+    image: &str,
+) -> std::fmt::Result {
+    for (name, rect) in sprites {
+        let class = name
+            .as_ref()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '-',
+            })
+            .collect::<String>();
+        writeln!(out, ".sprite-{} {{", class)?;
+        writeln!(
+            out,
+            "  background: url({}) {}px {}px no-repeat;",
+            json_string(image),
+            -(rect.x as i64),
+            -(rect.y as i64)
+        )?;
+        writeln!(out, "  width: {}px;", rect.width)?;
+        writeln!(out, "  height: {}px;", rect.height)?;
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
+
+fn write_texture_packer(
+    out: &mut String,
+    (width, height): (u32, u32),
+    sprites: &[(impl AsRef<str>, Rect)],
+    image: &str,
+) -> std::fmt::Result {
+    writeln!(out, "{{\"frames\": {{")?;
+    for (index, (name, rect)) in sprites.iter().enumerate() {
+        // Images are never trimmed or turned, so each one is its whole frame
+        writeln!(
+            out,
+            "  {name}: {{\"frame\": {{\"x\": {x}, \"y\": {y}, \"w\": {w}, \"h\": {h}}}, \
+             \"rotated\": false, \"trimmed\": false, \
+             \"spriteSourceSize\": {{\"x\": 0, \"y\": 0, \"w\": {w}, \"h\": {h}}}, \
+             \"sourceSize\": {{\"w\": {w}, \"h\": {h}}}}}{comma}",
+            name = json_string(name.as_ref()),
+            x = rect.x,
+            y = rect.y,
+            w = rect.width,
+            h = rect.height,
+            comma = if index + 1 < sprites.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "}},")?;
+    writeln!(
+        out,
+        "\"meta\": {{\"app\": \"ffphotojoin\", \"version\": \"1.0\", \"image\": {}, \
+         \"format\": \"RGBA8888\", \"size\": {{\"w\": {}, \"h\": {}}}, \"scale\": \"1\"}}",
+        json_string(image),
+        width,
+        height
+    )?;
+    writeln!(out, "}}")
+}
+
+// A string quoted for JSON (which CSS reads the same way)
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,427 @@
+//! Joining photos from async code. The work runs on a thread of its own (one of Tokio's blocking
+//! threads with the `tokio` feature) and is awaited like any other future, so it works with any
+//! async runtime without blocking it
+
+use crate::{
+    join_items_with_progress, join_photos_with_progress, JoinError, JoinEvent, JoinItem,
+    PhotoJoinOptions,
+};
+use futures_core::future::FusedFuture;
+use futures_core::stream::{FusedStream, Stream};
+use image::DynamicImage;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+// What the thread doing the work has given back so far
+struct State<T> {
+    output: Option<thread::Result<T>>,
+    finished: bool,
+    events: VecDeque<JoinEvent>,
+    // The task waiting for the output and the one waiting for the next event
+    waker: Option<Waker>,
+    event_waker: Option<Waker>,
+}
+
+// The state is never left half updated, so it's still usable after a panic while it was locked
+//  (like passing on the work's panic)
+fn lock<T>(state: &Mutex<State<T>>) -> MutexGuard<'_, State<T>> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Work being done on a thread of its own, which resolves to what the work gives back. If the
+/// work panics, awaiting it panics the same way. Once it has resolved, polling it again just
+/// leaves it pending (see [`FusedFuture::is_terminated`])
+pub struct Blocking<T> {
+    state: Arc<Mutex<State<T>>>,
+    done: bool,
+}
+
+// Runs `work` on a thread of its own, like decoding or encoding an image, so it can be awaited
+//  without blocking the async runtime
+fn spawn_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Blocking<T> {
+    spawn_with_events(|_| work())
+}
+
+// Same as `spawn_blocking`, but the work can report progress along the way
+fn spawn_with_events<T: Send + 'static>(
+    work: impl FnOnce(&mut dyn FnMut(JoinEvent)) -> T + Send + 'static,
+) -> Blocking<T> {
+    let state = Arc::new(Mutex::new(State {
+        output: None,
+        finished: false,
+        events: VecDeque::new(),
+        waker: None,
+        event_waker: None,
+    }));
+    let shared = Arc::clone(&state);
+    spawn_thread(move || {
+        let mut progress = |event| {
+            let mut state = lock(&shared);
+            state.events.push_back(event);
+            if let Some(waker) = state.event_waker.take() {
+                waker.wake();
+            }
+        };
+        let output = panic::catch_unwind(AssertUnwindSafe(|| work(&mut progress)));
+        let mut state = lock(&shared);
+        state.output = Some(output);
+        state.finished = true;
+        let wakers = state
+            .waker
+            .take()
+            .into_iter()
+            .chain(state.event_waker.take());
+        wakers.for_each(Waker::wake);
+    });
+    Blocking { state, done: false }
+}
+
+// Starts a job on one of Tokio's blocking threads when there's a Tokio runtime to run it on, and
+//  on a new thread otherwise
+fn spawn_thread(job: impl FnOnce() + Send + 'static) {
+    #[cfg(feature = "tokio")]
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        runtime.spawn_blocking(job);
+        return;
+    }
+    thread::spawn(job);
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if self.done {
+            return Poll::Pending;
+        }
+        let output = {
+            let mut state = lock(&self.state);
+            match state.output.take() {
+                Some(output) => output,
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        };
+        self.done = true;
+        match output {
+            Ok(output) => Poll::Ready(output),
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<T> FusedFuture for Blocking<T> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+/// A join running on a thread of its own, which resolves to the output image. The steps of the
+/// join can be awaited one at a time with [`next_event`](JoinTask::next_event), or taken as a
+/// [`Stream`] with [`events`](JoinTask::events), while it runs
+pub struct JoinTask {
+    work: Blocking<Result<DynamicImage, JoinError>>,
+}
+
+impl JoinTask {
+    /// Waits for the next step of the join to complete (the same events
+    /// [`join_photos_with_progress`] reports), which gives `None` once the join is done and every
+    /// event has been taken. Events aren't lost if they come faster than they're taken
+    pub fn next_event(&mut self) -> NextEvent<'_> {
+        NextEvent { task: self }
+    }
+
+    /// The steps of the join as they complete, as a stream that ends once the join is done and
+    /// every event has been taken. It doesn't borrow the task, so it can be read at the same time
+    /// as the task is awaited. Each event is only given out once, to whichever of these streams
+    /// and [`next_event`](JoinTask::next_event) takes it first
+    pub fn events(&self) -> Events {
+        Events {
+            state: Arc::clone(&self.work.state),
+        }
+    }
+}
+
+impl Future for JoinTask {
+    type Output = Result<DynamicImage, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.work).poll(cx)
+    }
+}
+
+impl FusedFuture for JoinTask {
+    fn is_terminated(&self) -> bool {
+        self.work.is_terminated()
+    }
+}
+
+// Takes the next event of a join, or gives `None` if it's done and there are none left
+fn poll_event<T>(state: &Mutex<State<T>>, cx: &mut Context<'_>) -> Poll<Option<JoinEvent>> {
+    let mut state = lock(state);
+    if let Some(event) = state.events.pop_front() {
+        Poll::Ready(Some(event))
+    } else if state.finished {
+        Poll::Ready(None)
+    } else {
+        state.event_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// The future [`JoinTask::next_event`] returns
+pub struct NextEvent<'a> {
+    task: &'a mut JoinTask,
+}
+
+impl Future for NextEvent<'_> {
+    type Output = Option<JoinEvent>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<JoinEvent>> {
+        poll_event(&self.task.work.state, cx)
+    }
+}
+
+/// The stream [`JoinTask::events`] returns
+pub struct Events {
+    state: Arc<Mutex<State<Result<DynamicImage, JoinError>>>>,
+}
+
+impl Stream for Events {
+    type Item = JoinEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<JoinEvent>> {
+        poll_event(&self.state, cx)
+    }
+}
+
+impl FusedStream for Events {
+    fn is_terminated(&self) -> bool {
+        let state = lock(&self.state);
+        state.finished && state.events.is_empty()
+    }
+}
+
+/// Same as [`join_photos`](crate::join_photos), but the join runs on a thread of its own so async
+/// code can await it (and its progress) without blocking
+pub fn join_photos_async(photos: Vec<DynamicImage>, options: PhotoJoinOptions) -> JoinTask {
+    JoinTask {
+        work: spawn_with_events(move |progress| {
+            join_photos_with_progress(photos, options, progress)
+        }),
+    }
+}
+
+/// Same as [`join_items`](crate::join_items), but the join runs on a thread of its own like
+/// [`join_photos_async`]
+pub fn join_items_async(items: Vec<JoinItem>, options: PhotoJoinOptions) -> JoinTask {
+    JoinTask {
+        work: spawn_with_events(move |progress| join_items_with_progress(items, options, progress)),
+    }
+}
+
+/// Opens an image on a thread of its own. Awaiting several of these at once opens the images in
+/// parallel. Photos are turned upright according to their EXIF orientation with the `exif`
+/// feature (like [`load_oriented_async`]), and HEIC/HEIF photos can be opened with the `heic`
+/// feature
+pub fn load_async(
+    path: impl Into<std::path::PathBuf>,
+) -> Blocking<image::ImageResult<DynamicImage>> {
+    let path = path.into();
+    spawn_blocking(move || {
+        #[cfg(feature = "exif")]
+        let img = crate::load_oriented(&path)?;
+        #[cfg(all(not(feature = "exif"), feature = "heic"))]
+        let img = match crate::heic::is_heic(&path) {
+            true => crate::heic::load_heic(&path)?,
+            false => image::open(&path)?,
+        };
+        #[cfg(not(any(feature = "exif", feature = "heic")))]
+        let img = image::open(&path)?;
+        Ok(img)
+    })
+}
+
+/// Same as [`load_oriented`](crate::load_oriented), but the image is opened on a thread of its
+/// own. Awaiting several of these at once opens the images in parallel
+#[cfg(feature = "exif")]
+pub fn load_oriented_async(
+    path: impl Into<std::path::PathBuf>,
+) -> Blocking<image::ImageResult<DynamicImage>> {
+    let path = path.into();
+    spawn_blocking(move || crate::load_oriented(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{join_photos, Direction};
+    use image::{GenericImageView, Rgb, RgbImage};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+
+    // Wakes the thread that's waiting on a future
+    struct ThreadWaker {
+        thread: thread::Thread,
+        woken: AtomicBool,
+    }
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.woken.store(true, Ordering::SeqCst);
+            self.thread.unpark();
+        }
+    }
+
+    fn waker() -> Arc<ThreadWaker> {
+        Arc::new(ThreadWaker {
+            thread: thread::current(),
+            woken: AtomicBool::new(false),
+        })
+    }
+
+    // Polls a future on this thread until it's ready, sleeping in between until it's woken
+    fn block_on<F: Future + Unpin>(future: &mut F) -> F::Output {
+        let thread_waker = waker();
+        let waker = Waker::from(Arc::clone(&thread_waker));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut *future).poll(&mut cx) {
+                return output;
+            }
+            while !thread_waker.woken.swap(false, Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+    }
+
+    // Takes every event of a stream, the same way as `block_on`
+    fn collect<S: Stream + Unpin>(stream: &mut S) -> Vec<S::Item> {
+        struct Next<'a, S>(&'a mut S);
+        impl<S: Stream + Unpin> Future for Next<'_, S> {
+            type Output = Option<S::Item>;
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                Pin::new(&mut *self.0).poll_next(cx)
+            }
+        }
+        let mut items = Vec::new();
+        while let Some(item) = block_on(&mut Next(stream)) {
+            items.push(item);
+        }
+        items
+    }
+
+    fn photos() -> Vec<DynamicImage> {
+        [(30, 20), (20, 40), (25, 25)]
+            .iter()
+            .map(|&(width, height)| {
+                DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                    Rgb([x as u8 * 8, y as u8 * 6, (width + height) as u8])
+                }))
+            })
+            .collect()
+    }
+
+    fn options() -> PhotoJoinOptions {
+        PhotoJoinOptions {
+            direction: Direction::Horizontal,
+            spacing: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn blocking_work_resolves_to_its_output() {
+        let mut work = spawn_blocking(|| 6 * 7);
+        assert!(!work.is_terminated());
+        assert_eq!(block_on(&mut work), 42);
+        assert!(work.is_terminated());
+    }
+
+    #[test]
+    fn polling_after_completion_stays_pending() {
+        let mut work = spawn_blocking(|| "done");
+        assert_eq!(block_on(&mut work), "done");
+        let waker = Waker::from(waker());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut work).poll(&mut cx).is_pending());
+        assert!(work.is_terminated());
+    }
+
+    #[test]
+    fn panics_are_passed_on_to_whoever_awaits() {
+        let mut work = spawn_blocking(|| -> u32 { panic!("the work failed") });
+        let panic = panic::catch_unwind(AssertUnwindSafe(|| block_on(&mut work))).unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"the work failed"));
+    }
+
+    #[test]
+    fn join_task_resolves_to_the_same_image_as_joining() {
+        let expected = join_photos(photos(), options()).unwrap();
+        let mut task = join_photos_async(photos(), options());
+        let joined = block_on(&mut task).unwrap();
+        assert_eq!(joined.dimensions(), expected.dimensions());
+        assert!(joined.as_bytes() == expected.as_bytes());
+        assert!(task.is_terminated());
+
+        let items = photos().into_iter().map(JoinItem::new).collect();
+        let joined = block_on(&mut join_items_async(items, options())).unwrap();
+        assert!(joined.as_bytes() == expected.as_bytes());
+    }
+
+    #[test]
+    fn events_are_streamed_in_order() {
+        let mut task = join_photos_async(photos(), options());
+        let mut events = task.events();
+        let streamed = collect(&mut events);
+        assert!(events.is_terminated());
+        assert!(matches!(
+            streamed.first(),
+            Some(JoinEvent::LayoutComputed { images: 3, .. })
+        ));
+        let overlaid = streamed
+            .iter()
+            .filter_map(|event| match event {
+                JoinEvent::ImageOverlaid { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(overlaid, [0, 1, 2]);
+
+        // Every event was taken by the stream, so there are none left for the task
+        assert!(block_on(&mut task).is_ok());
+        assert!(block_on(&mut task.next_event()).is_none());
+    }
+
+    #[test]
+    fn next_event_takes_events_one_at_a_time() {
+        let mut task = join_photos_async(photos(), options());
+        let mut count = 0;
+        while let Some(event) = block_on(&mut task.next_event()) {
+            if count == 0 {
+                assert!(matches!(event, JoinEvent::LayoutComputed { .. }));
+            }
+            count += 1;
+        }
+        assert!(count > 3);
+        assert!(block_on(&mut task).is_ok());
+    }
+
+    #[test]
+    fn images_load_without_blocking() {
+        let path =
+            std::env::temp_dir().join(format!("ffphotojoin-load-{}.png", std::process::id()));
+        photos()[0].save(&path).unwrap();
+        let loaded = block_on(&mut load_async(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().dimensions(), (30, 20));
+        assert!(block_on(&mut load_async("missing.png")).is_err());
+    }
+}
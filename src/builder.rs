@@ -0,0 +1,142 @@
+use crate::{
+    compose, focus_point, prepare_item, Direction, FitMode, JoinError, JoinEvent, JoinItem,
+    LayoutItem, PhotoJoinOptions, Sizing,
+};
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+
+/// Joins images that are added one at a time. Only the layout needs to know about every image, so
+/// each image is shrunk as soon as it's clear it will only ever be drawn smaller than it is. That
+/// way images can be decoded and pushed without keeping all of them in memory at full size
+pub struct JoinBuilder {
+    options: PhotoJoinOptions,
+    images: Vec<DynamicImage>,
+    items: Vec<LayoutItem>,
+}
+
+impl JoinBuilder {
+    pub fn new(options: PhotoJoinOptions) -> Self {
+        Self {
+            options,
+            images: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Adds the next image (or [`JoinItem`]) to the join
+    pub fn push(&mut self, item: impl Into<JoinItem>) -> Result<(), JoinError> {
+        let item = item.into();
+        let index = self.items.len();
+        // Keep the original image if it doesn't need to be cropped, adjusted, or rotated
+        let transformed = match prepare_item(index, &item)? {
+            Cow::Owned(img) => Some(img),
+            Cow::Borrowed(_) => None,
+        };
+        let img = transformed.unwrap_or(item.image);
+        let size = img.dimensions();
+        if size.0 == 0 || size.1 == 0 {
+            return Err(JoinError::ZeroSizedImage { index });
+        }
+
+        self.images.push(img);
+        self.items.push(LayoutItem {
+            size,
+            weight: item.weight,
+            align: item.align,
+            border: item.border,
+            caption: None,
+            focus: item.focus.map(|focus| focus_point(focus, size)),
+        });
+        self.shrink_images();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn finish(self) -> Result<DynamicImage, JoinError> {
+        self.finish_with_progress(|_| {})
+    }
+
+    /// Same as [`finish`](Self::finish), but calls `progress` as each step of the join completes
+    pub fn finish_with_progress(
+        self,
+        mut progress: impl FnMut(JoinEvent),
+    ) -> Result<DynamicImage, JoinError> {
+        compose(&self.images, &self.items, &self.options, &mut progress)
+    }
+
+    // Shrinks every stored image that's larger than the largest it could still end up in the
+    //  output. The layout keeps using the original sizes, so this doesn't change the result
+    fn shrink_images(&mut self) {
+        let scales = self
+            .items
+            .iter()
+            .map(|item| self.max_scale(item))
+            .collect::<Vec<_>>();
+        for ((img, item), scale) in self.images.iter_mut().zip(&self.items).zip(scales) {
+            let scale = match scale {
+                Some(scale) if scale < 1.0 => scale,
+                _ => continue,
+            };
+            let width = ((item.size.0 as f32 * scale).ceil() as u32).max(1);
+            let height = ((item.size.1 as f32 * scale).ceil() as u32).max(1);
+            if width < img.width() && height < img.height() {
+                let filter = self.options.filter_for(img.dimensions(), (width, height));
+                *img = img.resize_exact(width, height, filter);
+            }
+        }
+    }
+
+    // The most an image could be scaled by in the output no matter which images are pushed after
+    //  it, if there's a limit at all (images only ever get larger with `Sizing::ToLargest`)
+    fn max_scale(&self, item: &LayoutItem) -> Option<f32> {
+        let (w, h) = (item.size.0 as f32, item.size.1 as f32);
+        let crop = matches!(
+            self.options.fit,
+            FitMode::CropCenter | FitMode::CropAnchor(_)
+        );
+        let bound = |sizes: &mut dyn Iterator<Item = u32>| match self.options.sizing {
+            Sizing::ToSmallest => sizes.min(),
+            Sizing::Exact(size) => Some(size),
+            Sizing::ToLargest => None,
+        };
+        match self.options.direction {
+            Direction::Grid { .. } => {
+                let cell_width = bound(&mut self.items.iter().map(|item| item.size.0))? as f32;
+                let cell_height = bound(&mut self.items.iter().map(|item| item.size.1))? as f32;
+                let (scale_x, scale_y) = (cell_width / w, cell_height / h);
+                Some(if crop {
+                    scale_x.max(scale_y)
+                } else {
+                    scale_x.min(scale_y)
+                })
+            }
+            // Rows are stretched to fill the width, so any image could end up larger
+            Direction::Justified { .. } | Direction::Aspect { .. } => None,
+            // Atlases only ever draw images at their own sizes
+            Direction::Atlas { .. } => Some(1.0),
+            // Which direction it is (and so how large the images end up) isn't known yet
+            Direction::Auto => None,
+            direction => {
+                let perpendicular = |size: (u32, u32)| match direction {
+                    Direction::Vertical => size.0,
+                    _ => size.1,
+                };
+                let size = bound(&mut self.items.iter().map(|item| perpendicular(item.size)))?;
+                let scale = size as f32 / perpendicular(item.size) as f32;
+                // Cropped and scaled images are stretched along with their space when it's
+                //  weighted, padded ones never get larger than they are
+                Some(match self.options.fit {
+                    FitMode::Pad(_) => scale,
+                    _ => scale * item.weight.max(1.0),
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Color profiles, so photos from devices with different color spaces (like Display P3 phones)
+//! match once they're joined. Images are converted into sRGB as they're opened, and the output
+//! is marked as sRGB when it's encoded
+
+use crate::container::{insert_jpeg_segment, insert_png_chunk};
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageError, ImageFormat, ImageResult};
+use qcms::{DataType, Intent, Profile, Transform};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// What starts the APP2 segments of a JPEG that hold its ICC profile
+const JPEG_ICC_MARKER: &[u8] = b"ICC_PROFILE\0";
+
+/// Reads the ICC color profile embedded in a JPEG or PNG file, if it has one
+pub fn read_icc_profile(path: impl AsRef<Path>) -> ImageResult<Option<Vec<u8>>> {
+    let path = path.as_ref();
+    match ImageFormat::from_path(path) {
+        Ok(ImageFormat::Png) => {
+            let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+            let reader = decoder
+                .read_info()
+                .map_err(|err| ImageError::IoError(err.into()))?;
+            Ok(reader.info().icc_profile.as_ref().map(|icc| icc.to_vec()))
+        }
+        Ok(ImageFormat::Jpeg) => {
+            let mut jpeg = Vec::new();
+            File::open(path)?.read_to_end(&mut jpeg)?;
+            Ok(jpeg_icc_profile(&jpeg))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Puts together the ICC profile from the APP2 segments of a JPEG, which can be split across
+//  several segments that each say which part of the profile they are
+fn jpeg_icc_profile(jpeg: &[u8]) -> Option<Vec<u8>> {
+    let mut parts = Vec::new();
+    let mut at = 2;
+    // Walk the segments up to the start of the image data, where the metadata ends
+    while at + 4 <= jpeg.len() && jpeg[at] == 0xFF && jpeg[at + 1] != 0xDA {
+        let marker = jpeg[at + 1];
+        let length = u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        let end = (at + 2 + length).min(jpeg.len());
+        let data = &jpeg[(at + 4).min(end)..end];
+        if marker == 0xE2 && data.starts_with(JPEG_ICC_MARKER) && data.len() > 14 {
+            parts.push((data[12], &data[14..]));
+        }
+        at = end;
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    parts.sort_by_key(|&(sequence, _)| sequence);
+    Some(
+        parts
+            .into_iter()
+            .flat_map(|(_, part)| part)
+            .copied()
+            .collect(),
+    )
+}
+
+/// Converts an image from the color space of an ICC profile into sRGB, so it matches other
+/// images once they're joined. The converted image has 8 bits per channel, since that's all the
+/// color management library handles. Returns `None` if the profile can't be used
+pub fn convert_to_srgb(img: &DynamicImage, icc_profile: &[u8]) -> Option<DynamicImage> {
+    let input = Profile::new_from_slice(icc_profile, false)?;
+    let mut output = Profile::new_sRGB();
+    output.precache_output_transform();
+
+    let (width, height) = img.dimensions();
+    if img.color().has_alpha() {
+        let transform = Transform::new(&input, &output, DataType::RGBA8, Intent::Perceptual)?;
+        let mut pixels = img.to_rgba8().into_raw();
+        transform.apply(&mut pixels);
+        ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        let transform = Transform::new(&input, &output, DataType::RGB8, Intent::Perceptual)?;
+        let mut pixels = img.to_rgb8().into_raw();
+        transform.apply(&mut pixels);
+        ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+    }
+}
+
+/// Marks an encoded JPEG or PNG file as being sRGB, with an ICC profile for JPEGs and an sRGB
+/// chunk for PNGs. Other formats are left as they are
+pub fn embed_srgb_profile(encoded: Vec<u8>, format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Jpeg => {
+            let payload = [JPEG_ICC_MARKER, &[1, 1], &srgb_icc_profile()].concat();
+            insert_jpeg_segment(encoded, 0xE2, &payload)
+        }
+        // The only data is the rendering intent, where 0 is perceptual
+        ImageFormat::Png => insert_png_chunk(encoded, b"sRGB", &[0]),
+        _ => encoded,
+    }
+}
+
+/// A small ICC (version 2) profile of the sRGB color space
+pub fn srgb_icc_profile() -> Vec<u8> {
+    // The sRGB primaries and white point adapted to the D50 white of the ICC connection space
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", text_description("sRGB")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+        (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+        (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+        (b"rTRC", srgb_curve()),
+        (b"gTRC", srgb_curve()),
+        (b"bTRC", srgb_curve()),
+    ];
+
+    // The tag data follows the header and the table of tags, each starting on a multiple of 4
+    let mut data = Vec::new();
+    let mut table = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend_from_slice(&signature[..]);
+        table.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+        data.resize(data.len().div_ceil(4) * 4, 0);
+    }
+    let size = data_start + data.len();
+
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // Preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // Version 2.1
+    profile.extend_from_slice(b"mntr"); // A display profile
+    profile.extend_from_slice(b"RGB ");
+    profile.extend_from_slice(b"XYZ ");
+    profile.extend_from_slice(&[0; 12]); // Creation date
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]); // Platform, flags, device maker, model and attributes
+    profile.extend_from_slice(&[0; 4]); // Perceptual rendering intent
+    profile.extend_from_slice(&xyz([0.9642, 1.0, 0.8249])[8..]); // D50 illuminant
+    profile.extend_from_slice(&[0; 4]); // Creator
+    profile.extend_from_slice(&[0; 44]); // Profile ID and reserved
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn xyz(values: [f64; 3]) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for value in values {
+        tag.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+    }
+    tag
+}
+
+fn text(text: &str) -> Vec<u8> {
+    [b"text\0\0\0\0", text.as_bytes(), b"\0"].concat()
+}
+
+fn text_description(text: &str) -> Vec<u8> {
+    let mut tag = b"desc\0\0\0\0".to_vec();
+    tag.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    // Empty Unicode and ScriptCode descriptions
+    tag.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+    tag
+}
+
+// The sRGB transfer function as a table of 1024 points
+fn srgb_curve() -> Vec<u8> {
+    const POINTS: u32 = 1024;
+    let mut tag = b"curv\0\0\0\0".to_vec();
+    tag.extend_from_slice(&POINTS.to_be_bytes());
+    for i in 0..POINTS {
+        let encoded = i as f64 / (POINTS - 1) as f64;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        tag.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    tag
+}
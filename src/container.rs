@@ -0,0 +1,38 @@
+// Adding extra data (like metadata and color profiles) to already encoded JPEG and PNG files,
+//  which the image crate's encoders can't write themselves
+
+// Adds a segment with the given marker (like APP1) right after the start of the JPEG, and after
+//  its JFIF APP0 segment if it has one
+pub(crate) fn insert_jpeg_segment(jpeg: Vec<u8>, marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut at = 2;
+    if jpeg[at..].starts_with(&[0xFF, 0xE0]) {
+        at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+    }
+    let length = (2 + payload.len()) as u16;
+
+    let mut output = Vec::with_capacity(jpeg.len() + 2 + length as usize);
+    output.extend_from_slice(&jpeg[..at]);
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(payload);
+    output.extend_from_slice(&jpeg[at..]);
+    output
+}
+
+// Adds a chunk of the given kind right after the PNG's header chunk
+pub(crate) fn insert_png_chunk(png: Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    // The 8 byte signature and the IHDR chunk (which always has 13 bytes of data)
+    let at = 8 + 4 + 4 + 13 + 4;
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+
+    let mut output = Vec::with_capacity(png.len() + chunk.len());
+    output.extend_from_slice(&png[..at]);
+    output.extend_from_slice(&chunk);
+    output.extend_from_slice(&png[at..]);
+    output
+}
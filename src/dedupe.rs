@@ -0,0 +1,52 @@
+use image::DynamicImage;
+
+/// How many bits two hashes can differ by for [`unique_indices`] to count the images as the same
+/// by default. Burst shots of the same scene usually differ by only a few, while different photos
+/// differ by around half of the 64
+pub const DEFAULT_DEDUPE_DISTANCE: u32 = 6;
+
+/// A 64 bit fingerprint of how an image looks (a difference hash), which barely changes when it's
+/// resized, recompressed, or slightly brightened, so similar looking images have similar hashes.
+/// Hashing thumbnails is much quicker, as long as the images compared are all thumbnails
+pub fn perceptual_hash(img: &DynamicImage) -> u64 {
+    // Each bit says whether a pixel of a tiny grayscale copy is brighter than the one to its right.
+    //  The copy averages every pixel it covers, so fine detail like stripes can't throw it off
+    let small = img.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = hash << 1 | brighter as u64;
+        }
+    }
+    hash
+}
+
+/// How many bits two perceptual hashes differ by, from 0 for images that look the same to 64
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// The indices of the hashes to keep, leaving out any that's within `max_distance` bits of one
+/// that was kept before it. Images without a hash (like ones that couldn't be opened) are always
+/// kept
+pub fn unique_indices(hashes: &[Option<u64>], max_distance: u32) -> Vec<usize> {
+    let mut kept: Vec<u64> = Vec::new();
+    hashes
+        .iter()
+        .enumerate()
+        .filter(|(_, hash)| match hash {
+            Some(hash) => {
+                let duplicate = kept
+                    .iter()
+                    .any(|&other| hash_distance(*hash, other) <= max_distance);
+                if !duplicate {
+                    kept.push(*hash);
+                }
+                !duplicate
+            }
+            None => true,
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
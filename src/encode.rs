@@ -0,0 +1,297 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{self, PngEncoder};
+use image::codecs::tiff::TiffEncoder;
+use image::error::{EncodingError, ImageError};
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, GenericImageView, ImageEncoder, ImageFormat, ImageResult};
+use std::borrow::Cow;
+use std::io::{Cursor, Write};
+
+/// How hard PNG encoding tries to make the file smaller
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+/// Settings for encoding the output image, formats that don't have a setting ignore it
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EncodeOptions {
+    /// JPEG (and lossy WebP and AVIF) quality from 1 to 100
+    pub quality: u8,
+    pub png_compression: PngCompression,
+    /// Encode WebP images losslessly, which ignores `quality`
+    pub webp_lossless: bool,
+    /// How fast AVIF encoding is from 1 to 10, slower encoding makes smaller files
+    pub avif_speed: u8,
+    /// How small the encoded image has to be, which only `encode_to_size` pays attention to
+    pub target_size: Option<TargetSize>,
+}
+
+/// A limit on the size of an encoded image, for places like upload forms that have one
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TargetSize {
+    /// The most bytes the encoded image can take up
+    pub max_bytes: u64,
+    /// Shrink the image when lowering the quality isn't enough, or when the format is lossless
+    pub downscale: bool,
+}
+
+impl Default for EncodeOptions {
+    // The same settings the image crate uses when saving images
+    fn default() -> Self {
+        Self {
+            quality: 75,
+            png_compression: PngCompression::Fast,
+            webp_lossless: false,
+            avif_speed: 4,
+            target_size: None,
+        }
+    }
+}
+
+/// Encodes an image in the given format. Images with 16 bits per channel keep them in PNG and
+/// TIFF files, and are converted to 8 bits for other formats
+pub fn encode_to(
+    img: &DynamicImage,
+    writer: &mut impl Write,
+    format: ImageFormat,
+    options: &EncodeOptions,
+) -> ImageResult<()> {
+    let img = match (format, img.color()) {
+        (ImageFormat::Png | ImageFormat::Tiff, _) => Cow::Borrowed(img),
+        (_, ColorType::Rgb16) => Cow::Owned(DynamicImage::ImageRgb8(img.to_rgb8())),
+        (_, ColorType::Rgba16) => Cow::Owned(DynamicImage::ImageRgba8(img.to_rgba8())),
+        (_, ColorType::L16) => Cow::Owned(DynamicImage::ImageLuma8(img.to_luma8())),
+        (_, ColorType::La16) => Cow::Owned(DynamicImage::ImageLumaA8(img.to_luma_alpha8())),
+        _ => Cow::Borrowed(img),
+    };
+    let (width, height) = img.dimensions();
+
+    match format {
+        ImageFormat::Png => {
+            let compression = match options.png_compression {
+                PngCompression::Fast => png::CompressionType::Fast,
+                PngCompression::Default => png::CompressionType::Default,
+                PngCompression::Best => png::CompressionType::Best,
+            };
+            PngEncoder::new_with_quality(writer, compression, png::FilterType::Sub).write_image(
+                img.as_bytes(),
+                width,
+                height,
+                img.color(),
+            )
+        }
+        ImageFormat::Jpeg => JpegEncoder::new_with_quality(writer, options.quality).write_image(
+            img.as_bytes(),
+            width,
+            height,
+            img.color(),
+        ),
+        // TIFF files are written out of order, so they're put together in memory first
+        ImageFormat::Tiff => {
+            let mut tiff = Cursor::new(Vec::new());
+            TiffEncoder::new(&mut tiff).write_image(img.as_bytes(), width, height, img.color())?;
+            writer.write_all(tiff.get_ref())?;
+            Ok(())
+        }
+        #[cfg(feature = "webp")]
+        ImageFormat::WebP => {
+            writer.write_all(&encode_webp(&img, options)?)?;
+            Ok(())
+        }
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => {
+            writer.write_all(&encode_avif(&img, options)?)?;
+            Ok(())
+        }
+        _ => img.write_to(writer, format),
+    }
+}
+
+/// Same as [`encode_to`], but gives back the encoded image instead of writing it anywhere, for
+/// sending it over the network or keeping it in memory
+pub fn encode(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+) -> ImageResult<Vec<u8>> {
+    let mut encoded = Vec::new();
+    encode_to(img, &mut encoded, format, options)?;
+    Ok(encoded)
+}
+
+/// An image encoded to fit its target size, with what it took to get there
+pub struct SizedEncoding {
+    pub encoded: Vec<u8>,
+    /// The quality it was encoded with, which is lower than the options' quality when that made
+    /// too large a file
+    pub quality: u8,
+    /// The size the image was shrunk to, if it had to be
+    pub shrunk_to: Option<(u32, u32)>,
+}
+
+// Shrinking doesn't lower the quality any further than this (unless the options start lower),
+//  since a smaller image usually looks better than a blocky one
+const MIN_SHRINK_QUALITY: u8 = 50;
+// How many times the image is shrunk before giving up
+const MAX_SHRINKS: usize = 8;
+
+/// Encodes an image with `encode` (which is given the image and the options to use) and fits it
+/// in the target size of the options by lowering the quality of lossy formats as little as it can.
+/// If that isn't enough and the target allows it, the image is shrunk too, by about as much as the
+/// encoded size says it needs to be. Fails when the image can't be made small enough
+pub fn encode_to_size(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    mut encode: impl FnMut(&DynamicImage, &EncodeOptions) -> ImageResult<Vec<u8>>,
+) -> ImageResult<SizedEncoding> {
+    let target = match options.target_size {
+        Some(target) => target,
+        None => {
+            return Ok(SizedEncoding {
+                encoded: encode(img, options)?,
+                quality: options.quality,
+                shrunk_to: None,
+            })
+        }
+    };
+    let lossy = match format {
+        ImageFormat::Jpeg | ImageFormat::Avif => true,
+        ImageFormat::WebP => !options.webp_lossless,
+        _ => false,
+    };
+    let lowest_quality = match (lossy, target.downscale) {
+        (false, _) => options.quality,
+        (true, false) => 1,
+        (true, true) => options.quality.min(MIN_SHRINK_QUALITY),
+    };
+
+    let mut shrunk: Option<DynamicImage> = None;
+    for _ in 0..=MAX_SHRINKS {
+        let current = shrunk.as_ref().unwrap_or(img);
+        let smallest = match fit_quality(current, options, lowest_quality, target, &mut encode)? {
+            Ok((encoded, quality)) => {
+                return Ok(SizedEncoding {
+                    encoded,
+                    quality,
+                    shrunk_to: shrunk.as_ref().map(|img| img.dimensions()),
+                })
+            }
+            Err(smallest) => smallest,
+        };
+        let (width, height) = current.dimensions();
+        if !target.downscale || (width == 1 && height == 1) {
+            return Err(ImageError::Encoding(EncodingError::new(
+                format.into(),
+                format!(
+                    "the smallest it could be encoded was {} bytes, more than the target of {}",
+                    smallest, target.max_bytes
+                ),
+            )));
+        }
+        // The encoded size goes with the number of pixels, so both sides shrink by the square
+        //  root of how much too large it is, and a little more so it isn't just barely too large
+        //  again
+        let scale = ((target.max_bytes as f64 / smallest as f64).sqrt() * 0.95).min(0.9);
+        let width = ((width as f64 * scale).round() as u32).max(1);
+        let height = ((height as f64 * scale).round() as u32).max(1);
+        // Always shrinking the original keeps it from getting blurrier each time
+        shrunk = Some(img.resize_exact(width, height, FilterType::CatmullRom));
+    }
+    Err(ImageError::Encoding(EncodingError::new(
+        format.into(),
+        format!("couldn't shrink it to {} bytes", target.max_bytes),
+    )))
+}
+
+// Finds the highest quality from `lowest` up to the options' quality that makes a small enough
+//  file, with a binary search since higher qualities make larger files. When none do, this gives
+//  the size of the smallest file it made
+fn fit_quality(
+    img: &DynamicImage,
+    options: &EncodeOptions,
+    lowest: u8,
+    target: TargetSize,
+    encode: &mut impl FnMut(&DynamicImage, &EncodeOptions) -> ImageResult<Vec<u8>>,
+) -> ImageResult<Result<(Vec<u8>, u8), u64>> {
+    let mut smallest = u64::MAX;
+    let mut encode_at = |quality: u8| -> ImageResult<Option<Vec<u8>>> {
+        let encoded = encode(
+            img,
+            &EncodeOptions {
+                quality,
+                ..*options
+            },
+        )?;
+        let size = encoded.len() as u64;
+        smallest = smallest.min(size);
+        Ok((size <= target.max_bytes).then_some(encoded))
+    };
+    if let Some(encoded) = encode_at(options.quality)? {
+        return Ok(Ok((encoded, options.quality)));
+    }
+    let (mut low, mut high) = (lowest, options.quality);
+    let mut best = None;
+    // `high` is always too large, and `best` is the highest that fit so far
+    while low < high {
+        let quality = low + (high - low) / 2;
+        match encode_at(quality)? {
+            Some(encoded) => {
+                best = Some((encoded, quality));
+                low = quality + 1;
+            }
+            None => high = quality,
+        }
+    }
+    Ok(best.ok_or(smallest))
+}
+
+/// Encodes an image as a WebP file, lossy with the quality from the options unless they ask for
+/// lossless
+#[cfg(feature = "webp")]
+pub fn encode_webp(img: &DynamicImage, options: &EncodeOptions) -> ImageResult<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let pixels = if img.color().has_alpha() {
+        img.to_rgba8().into_raw()
+    } else {
+        img.to_rgb8().into_raw()
+    };
+    let encoder = if img.color().has_alpha() {
+        webp::Encoder::from_rgba(&pixels, width, height)
+    } else {
+        webp::Encoder::from_rgb(&pixels, width, height)
+    };
+    let encoded = encoder
+        .encode_simple(options.webp_lossless, options.quality as f32)
+        .map_err(|err| {
+            let err = format!("{:?}", err);
+            ImageError::Encoding(EncodingError::new(ImageFormat::WebP.into(), err))
+        })?;
+    Ok(encoded.to_vec())
+}
+
+/// Encodes an image as an AVIF file with the quality and speed from the options
+#[cfg(feature = "avif")]
+pub fn encode_avif(img: &DynamicImage, options: &EncodeOptions) -> ImageResult<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let pixels = img
+        .to_rgba8()
+        .pixels()
+        .map(|pixel| ravif::RGBA8::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+        .collect::<Vec<_>>();
+    let encoded = ravif::Encoder::new()
+        .with_quality(options.quality as f32)
+        .with_speed(options.avif_speed)
+        .encode_rgba(ravif::Img::new(
+            &pixels[..],
+            width as usize,
+            height as usize,
+        ))
+        .map_err(|err| ImageError::Encoding(EncodingError::new(ImageFormat::Avif.into(), err)))?;
+    Ok(encoded.avif_file)
+}
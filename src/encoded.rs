@@ -0,0 +1,69 @@
+use crate::{join_photos, JoinError, PhotoJoinOptions};
+use image::{DynamicImage, ImageError};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug)]
+pub enum EncodedJoinError {
+    /// The image at the given index couldn't be decoded
+    Decode {
+        index: usize,
+        error: ImageError,
+    },
+    Join(JoinError),
+}
+
+impl Display for EncodedJoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodedJoinError::Decode { index, error } => {
+                write!(f, "failed to open image {}: {}", index, error)
+            }
+            EncodedJoinError::Join(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for EncodedJoinError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EncodedJoinError::Decode { error, .. } => Some(error),
+            EncodedJoinError::Join(err) => Some(err),
+        }
+    }
+}
+
+impl From<JoinError> for EncodedJoinError {
+    fn from(err: JoinError) -> Self {
+        EncodedJoinError::Join(err)
+    }
+}
+
+/// Same as [`join_photos`], but the images are given encoded (like the contents of a JPEG or PNG
+/// file that was downloaded or uploaded), and their formats are worked out from their contents.
+/// With the `exif` feature they're also turned upright by their EXIF orientation. The bytes of
+/// each image are dropped as soon as it has been decoded
+pub fn join_encoded(
+    images: Vec<Cow<'_, [u8]>>,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, EncodedJoinError> {
+    let photos = images
+        .into_iter()
+        .enumerate()
+        .map(|(index, encoded)| {
+            decode(&encoded).map_err(|error| EncodedJoinError::Decode { index, error })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(join_photos(photos, options)?)
+}
+
+#[cfg(feature = "exif")]
+fn decode(encoded: &[u8]) -> image::ImageResult<DynamicImage> {
+    crate::load_oriented_from_memory(encoded)
+}
+
+#[cfg(not(feature = "exif"))]
+fn decode(encoded: &[u8]) -> image::ImageResult<DynamicImage> {
+    image::load_from_memory(encoded)
+}
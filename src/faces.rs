@@ -0,0 +1,206 @@
+//! Finding faces in photos with rustface (a port of the SeetaFace detector), so images that are
+//! cropped to fill their space keep the people in them in frame. The frontal face model that
+//! SeetaFace comes with is built in (it's in `models/`, under SeetaFace's BSD license)
+//!
+//! ```no_run
+//! # use ffphotojoin::{FitMode, JoinItem, PhotoJoinOptions, Sizing};
+//! # let photos = Vec::new();
+//! let mut items = photos.into_iter().map(JoinItem::new).collect::<Vec<_>>();
+//! ffphotojoin::faces::focus_on_faces(&mut items);
+//! let options = PhotoJoinOptions {
+//!     sizing: Sizing::Exact(256),
+//!     fit: FitMode::CropCenter,
+//!     ..Default::default()
+//! };
+//! let sheet = ffphotojoin::join_items(items, options);
+//! ```
+
+use crate::{prepare_item, JoinItem, Rect};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use rustface::{Detector, ImageData};
+use std::borrow::Cow;
+use std::io::Cursor;
+
+static MODEL: &[u8] = include_bytes!("../models/seeta_fd_frontal_v1.0.bin");
+
+// Faces are looked for in a copy of the image at most this many pixels wide and tall, which is
+//  much quicker than searching a whole photo and still finds faces a fiftieth of its size
+const DETECT_SIZE: u32 = 1024;
+
+/// A face detector, which takes a moment to set up so it's kept around to look through batches
+/// of photos with
+pub struct FaceDetector {
+    detector: Box<dyn Detector>,
+}
+
+impl FaceDetector {
+    pub fn new() -> Self {
+        let model = rustface::read_model(Cursor::new(MODEL)).expect("the face model is built in");
+        let mut detector = rustface::create_detector_with_model(model);
+        detector.set_min_face_size(20);
+        detector.set_score_thresh(2.0);
+        detector.set_pyramid_scale_factor(0.8);
+        detector.set_slide_window_step(4, 4);
+        Self { detector }
+    }
+
+    /// Where the faces in the image are
+    pub fn detect(&mut self, img: &DynamicImage) -> Vec<Rect> {
+        let (width, height) = img.dimensions();
+        let small = match width > DETECT_SIZE || height > DETECT_SIZE {
+            true => Cow::Owned(img.resize(DETECT_SIZE, DETECT_SIZE, FilterType::Triangle)),
+            false => Cow::Borrowed(img),
+        };
+        let gray = small.to_luma8();
+        let (small_width, small_height) = gray.dimensions();
+        if small_width == 0 || small_height == 0 {
+            return Vec::new();
+        }
+        let scale_x = width as f64 / small_width as f64;
+        let scale_y = height as f64 / small_height as f64;
+        self.detector
+            .detect(&ImageData::new(&gray, small_width, small_height))
+            .iter()
+            .filter_map(|face| {
+                // Faces can reach past the edges of the image, which are cut off
+                let bbox = face.bbox();
+                let left = bbox.x().max(0) as u32;
+                let top = bbox.y().max(0) as u32;
+                let right = (bbox.x() + bbox.width() as i32).clamp(0, small_width as i32) as u32;
+                let bottom = (bbox.y() + bbox.height() as i32).clamp(0, small_height as i32) as u32;
+                if right <= left || bottom <= top {
+                    return None;
+                }
+                let x = (left as f64 * scale_x) as u32;
+                let y = (top as f64 * scale_y) as u32;
+                Some(Rect {
+                    x,
+                    y,
+                    width: ((right as f64 * scale_x).round() as u32).min(width) - x,
+                    height: ((bottom as f64 * scale_y).round() as u32).min(height) - y,
+                })
+            })
+            .collect()
+    }
+
+    /// The smallest rectangle around every face in the image, or `None` when there aren't any
+    pub fn focus(&mut self, img: &DynamicImage) -> Option<Rect> {
+        enclosing(&self.detect(img))
+    }
+
+    /// Sets the [focus](JoinItem::focus) of every item that doesn't have one yet to the faces in
+    /// its image (once it's cropped and rotated like it's joined). Items without any faces are
+    /// left as they are
+    pub fn focus_items(&mut self, items: &mut [JoinItem]) {
+        for (index, item) in items.iter_mut().enumerate() {
+            if item.focus.is_none() {
+                // A crop that doesn't fit is left for the join to report
+                let focus = prepare_item(index, item)
+                    .ok()
+                    .and_then(|img| self.focus(&img));
+                item.focus = focus;
+            }
+        }
+    }
+}
+
+impl Default for FaceDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Same as [`FaceDetector::focus_items`], with a detector that's set up just for these items
+pub fn focus_on_faces(items: &mut [JoinItem]) {
+    FaceDetector::new().focus_items(items);
+}
+
+// The smallest rectangle that covers all of the rectangles
+fn enclosing(rects: &[Rect]) -> Option<Rect> {
+    let left = rects.iter().map(|rect| rect.x).min()?;
+    let top = rects.iter().map(|rect| rect.y).min()?;
+    let right = rects.iter().map(|rect| rect.x + rect.width).max()?;
+    let bottom = rects.iter().map(|rect| rect.y + rect.height).max()?;
+    Some(Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{join_items, Direction, FitMode, PhotoJoinOptions, Sizing};
+    use image::{GenericImage, Luma};
+
+    // Einstein and two of the people behind him in a group photo, 300x240
+    fn people() -> DynamicImage {
+        image::load_from_memory(include_bytes!("../testdata/solvay-1927.jpg")).unwrap()
+    }
+
+    #[test]
+    fn finds_the_faces_in_a_photo() {
+        let faces = FaceDetector::new().detect(&people());
+        assert_eq!(faces.len(), 3);
+        // Einstein is in the middle of the bottom row of the photo
+        assert!(faces.iter().any(|face| {
+            (face.x..face.x + face.width).contains(&135)
+                && (face.y..face.y + face.height).contains(&110)
+        }));
+        for face in faces {
+            assert!(face.x + face.width <= 300 && face.y + face.height <= 240);
+        }
+    }
+
+    #[test]
+    fn focus_covers_every_face() {
+        let faces = [
+            Rect {
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 30,
+            },
+            Rect {
+                x: 50,
+                y: 5,
+                width: 20,
+                height: 25,
+            },
+        ];
+        let expected = Rect {
+            x: 10,
+            y: 5,
+            width: 60,
+            height: 45,
+        };
+        assert_eq!(enclosing(&faces), Some(expected));
+        assert_eq!(enclosing(&[]), None);
+    }
+
+    #[test]
+    fn cropping_keeps_the_faces_in_frame() {
+        // The people are off to the right of a wide gray photo, where a centered crop would cut
+        //  them out
+        let mut wide =
+            DynamicImage::ImageLuma8(image::GrayImage::from_pixel(1200, 240, Luma([128])));
+        wide.copy_from(&people(), 900, 0).unwrap();
+        let mut items = vec![JoinItem::new(wide)];
+        focus_on_faces(&mut items);
+        let focus = items[0].focus.unwrap();
+        assert!(focus.x >= 900);
+
+        let options = PhotoJoinOptions {
+            direction: Direction::Grid { rows: 1, cols: 1 },
+            sizing: Sizing::Exact(240),
+            fit: FitMode::CropCenter,
+            ..Default::default()
+        };
+        let joined = join_items(items, options).unwrap().to_luma8();
+        assert_eq!(joined.dimensions(), (240, 240));
+        assert!(joined.pixels().filter(|pixel| pixel[0] != 128).count() > 240 * 120);
+    }
+}
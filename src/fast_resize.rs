@@ -0,0 +1,129 @@
+//! Resizing with fast_image_resize, which is several times faster than the `image` crate since it
+//! uses the SIMD instructions of the CPU it runs on
+
+use fast_image_resize::images::Image;
+use fast_image_resize::{FilterType as FastFilter, PixelType, ResizeAlg, ResizeOptions, Resizer};
+use image::imageops::FilterType;
+use image::{ImageBuffer, Primitive, Rgba};
+use std::mem;
+
+// The fast_image_resize algorithm that matches one of the `image` crate's filters
+fn algorithm(filter: FilterType) -> ResizeAlg {
+    match filter {
+        FilterType::Nearest => ResizeAlg::Nearest,
+        FilterType::Triangle => ResizeAlg::Convolution(FastFilter::Bilinear),
+        FilterType::CatmullRom => ResizeAlg::Convolution(FastFilter::CatmullRom),
+        FilterType::Gaussian => ResizeAlg::Convolution(FastFilter::Gaussian),
+        FilterType::Lanczos3 => ResizeAlg::Convolution(FastFilter::Lanczos3),
+    }
+}
+
+// Resizes an image with 8 or 16 bits per channel, gives `None` for anything else (or anything
+//  fast_image_resize fails on) so it can be resized the usual way instead
+pub(crate) fn resize<T: Primitive + 'static>(
+    img: &ImageBuffer<Rgba<T>, Vec<T>>,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    premultiplied: bool,
+) -> Option<ImageBuffer<Rgba<T>, Vec<T>>> {
+    let channels = img.as_raw();
+    let (pixel_type, bytes) = match mem::size_of::<T>() {
+        1 => (
+            PixelType::U8x4,
+            channels
+                .iter()
+                .map(|channel| channel.to_u8())
+                .collect::<Option<Vec<_>>>()?,
+        ),
+        2 => (
+            PixelType::U16x4,
+            channels
+                .iter()
+                .map(|channel| channel.to_u16().map(u16::to_ne_bytes))
+                .collect::<Option<Vec<_>>>()?
+                .concat(),
+        ),
+        _ => return None,
+    };
+    let source = Image::from_vec_u8(img.width(), img.height(), bytes, pixel_type).ok()?;
+    let mut resized = Image::new(width, height, pixel_type);
+    // Without premultiplying, see-through pixels are resized like any other (the same as
+    //  `alpha::resize` does)
+    let options = ResizeOptions::new()
+        .resize_alg(algorithm(filter))
+        .use_alpha(premultiplied);
+    Resizer::new()
+        .resize(&source, &mut resized, &options)
+        .ok()?;
+    let bytes = resized.into_vec();
+    let channels = match pixel_type {
+        PixelType::U8x4 => bytes.into_iter().map(T::from).collect::<Option<Vec<_>>>()?,
+        _ => bytes
+            .chunks_exact(2)
+            .map(|channel| T::from(u16::from_ne_bytes([channel[0], channel[1]])))
+            .collect::<Option<Vec<_>>>()?,
+    };
+    ImageBuffer::from_raw(width, height, channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{join_photos, Direction, PhotoJoinOptions, ResizeBackend, Sizing};
+    use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, RgbImage, Rgba};
+
+    fn photos() -> Vec<DynamicImage> {
+        [(40, 30), (25, 50), (60, 20)]
+            .iter()
+            .map(|&(width, height)| {
+                DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                    Rgb([(x * 4) as u8, (y * 5) as u8, 100])
+                }))
+            })
+            .collect()
+    }
+
+    // Joins the photos with both backends, which have to make the same size of image with the
+    //  same channels
+    fn assert_resizes_like_image(photos: &[DynamicImage], options: PhotoJoinOptions) {
+        let fast = PhotoJoinOptions {
+            resize_backend: ResizeBackend::Fast,
+            ..options
+        };
+        let expected = join_photos(photos, options).unwrap();
+        let joined = join_photos(photos, fast).unwrap();
+        assert_eq!(joined.dimensions(), expected.dimensions());
+        assert_eq!(joined.color(), expected.color());
+    }
+
+    #[test]
+    fn resizes_to_the_same_size_as_image() {
+        assert_resizes_like_image(&photos(), PhotoJoinOptions::default());
+        assert_resizes_like_image(
+            &photos(),
+            PhotoJoinOptions {
+                direction: Direction::Grid { rows: 2, cols: 2 },
+                sizing: Sizing::Exact(48),
+                background: Rgba([0, 0, 0, 0]),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn keeps_16_bits_per_channel() {
+        let mut photos = photos();
+        photos[0] = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            40,
+            30,
+            Rgba([20000, 0, 50000, 65535]),
+        ));
+        assert_resizes_like_image(
+            &photos,
+            PhotoJoinOptions {
+                sizing: Sizing::ToLargest,
+                ..Default::default()
+            },
+        );
+    }
+}
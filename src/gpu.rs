@@ -0,0 +1,779 @@
+//! Joining photos on the GPU with wgpu. The images are uploaded as textures, resized and blended
+//! into the output by the GPU, and the finished output is read back, which is much quicker than
+//! doing it on the CPU for huge outputs (like long panoramic strips) and batches of joins. Joins
+//! the GPU can't draw (see [`Gpu::join_items`]) are done on the CPU instead
+
+use crate::linear::{linear_to_srgb, srgb_to_linear};
+use crate::source::Source;
+use crate::{
+    border_rect, lay_out, prepare_items, render, scale_rect, with_focus, CompositeMode, JoinError,
+    JoinEvent, JoinItem, Layout, LayoutItem, PhotoJoinOptions, Rect,
+};
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::borrow::Cow;
+use std::num::NonZeroU64;
+use std::sync::{mpsc, Mutex, OnceLock, PoisonError};
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendState, Buffer, CommandEncoder, Device, Queue, RenderPipeline,
+    Texture, TextureFormat, TextureView,
+};
+
+// Largest width or height of the parts the output is drawn in, which keeps them within what every
+//  GPU can hold in one texture
+const MAX_TILE: u32 = 4096;
+
+// Size of the uniforms of one draw, see `Draw` in the shader
+const DRAW_SIZE: u64 = 64;
+
+/// A GPU set up to join photos on. Setting one up takes a while, so it's kept around to join
+/// batches of photos with. Joins on the same GPU run one at a time
+pub struct Gpu {
+    device: Device,
+    queue: Queue,
+    draw_layout: BindGroupLayout,
+    image_layout: BindGroupLayout,
+    // Pipelines that blend what's drawn over the output and that replace it, drawing in gamma
+    //  encoded values and in linear light
+    blend: [RenderPipeline; 2],
+    replace: [RenderPipeline; 2],
+    // Only one join at a time can use the error scopes of the device
+    lock: Mutex<()>,
+}
+
+// A part of the output image, which is drawn in its own texture
+struct Tile {
+    rect: Rect,
+    texture: Texture,
+    view: TextureView,
+}
+
+impl Gpu {
+    /// Sets up the GPU wgpu picks (preferring a discrete one), or gives `None` when there isn't
+    /// one to use
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("ffphotojoin"),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+            },
+            None,
+        ))
+        .ok()?;
+
+        let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("draw"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(DRAW_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let image_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("image"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("join"),
+            bind_group_layouts: &[&draw_layout, &image_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("join"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("gpu.wgsl"))),
+        });
+        let pipeline = |format: TextureFormat, blend: BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("join"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+        let formats = [TextureFormat::Rgba8Unorm, TextureFormat::Rgba8UnormSrgb];
+        let blend =
+            formats.map(|format| pipeline(format, BlendState::PREMULTIPLIED_ALPHA_BLENDING));
+        let replace = formats.map(|format| pipeline(format, BlendState::REPLACE));
+        Some(Self {
+            device,
+            queue,
+            draw_layout,
+            image_layout,
+            blend,
+            replace,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Same as [`join_photos`](crate::join_photos), but on the GPU
+    pub fn join_photos(
+        &self,
+        photos: Vec<DynamicImage>,
+        options: PhotoJoinOptions,
+    ) -> Result<DynamicImage, JoinError> {
+        let items = photos.into_iter().map(JoinItem::new).collect();
+        self.join_items(items, options)
+    }
+
+    /// Same as [`join_items`](crate::join_items), but on the GPU. The GPU resizes images with the
+    /// nearest pixels for `FilterType::Nearest` and with a triangle filter for every other filter,
+    /// which is logged as a warning with the `log` crate.
+    /// Joins with shadows, rounded corners, overlapping images, separators, or sharpening, and
+    /// joins of images with 16 bits per channel are done on the CPU like usual, as are joins that
+    /// the GPU fails at (like ones too large for its memory)
+    pub fn join_items(
+        &self,
+        items: Vec<JoinItem>,
+        options: PhotoJoinOptions,
+    ) -> Result<DynamicImage, JoinError> {
+        self.join_items_with_progress(items, options, |_| {})
+    }
+
+    /// Same as [`Gpu::join_items`], but calls `progress` as each step of the join completes. When
+    /// the GPU fails partway through, the join starts over on the CPU and reports every step again
+    pub fn join_items_with_progress(
+        &self,
+        items: Vec<JoinItem>,
+        options: PhotoJoinOptions,
+        mut progress: impl FnMut(JoinEvent),
+    ) -> Result<DynamicImage, JoinError> {
+        let (photos, layout_items) = prepare_items(&items)?;
+        let items = with_focus(&photos, &layout_items, &options);
+        let (layout, options) = lay_out(&items, &options)?;
+        if self.can_draw(&photos, &items, &layout, &options) {
+            if let Some(output) = self.draw(&photos, &items, &layout, &options, &mut progress) {
+                return Ok(output);
+            }
+        }
+        Ok(render(&photos, &items, layout, &options, &mut progress))
+    }
+
+    // Whether the GPU can draw everything the join asks for
+    fn can_draw(
+        &self,
+        photos: &[impl Source],
+        items: &[LayoutItem],
+        layout: &Layout,
+        options: &PhotoJoinOptions,
+    ) -> bool {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let fits =
+            photos
+                .iter()
+                .zip(items)
+                .zip(&layout.placements)
+                .all(|((img, item), placement)| {
+                    let source = scale_rect(placement.source, item.size, img.size());
+                    source.width <= max_size && source.height <= max_size && placement.overlap == 0
+                });
+        fits && options.shadow.is_none()
+            && options.separator.is_none()
+            && options.sharpen.is_none()
+            && options.corner_radius == 0
+            && !photos.iter().any(Source::is_deep)
+    }
+
+    // Draws the images where the layout puts them, or gives `None` when the GPU fails to
+    fn draw(
+        &self,
+        photos: &[impl Source],
+        items: &[LayoutItem],
+        layout: &Layout,
+        options: &PhotoJoinOptions,
+        progress: &mut impl FnMut(JoinEvent),
+    ) -> Option<DynamicImage> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let output = self.draw_tiles(photos, items, layout, options, progress);
+        let failed = (0..2)
+            .filter_map(|_| pollster::block_on(self.device.pop_error_scope()))
+            .count()
+            > 0;
+        match failed {
+            true => None,
+            false => output,
+        }
+    }
+
+    // The drawing itself, which leaves any errors for `draw` to catch
+    fn draw_tiles(
+        &self,
+        photos: &[impl Source],
+        items: &[LayoutItem],
+        layout: &Layout,
+        options: &PhotoJoinOptions,
+        progress: &mut impl FnMut(JoinEvent),
+    ) -> Option<DynamicImage> {
+        let (width, height) = (layout.width, layout.height);
+        progress(JoinEvent::LayoutComputed {
+            images: photos.len(),
+            width,
+            height,
+        });
+        let linear = options.linear_light as usize;
+        let format = [TextureFormat::Rgba8Unorm, TextureFormat::Rgba8UnormSrgb][linear];
+        let color = |color: Rgba<u8>| {
+            let channel = |value: u8| match options.linear_light {
+                true => srgb_to_linear(value as f64 / 255.0),
+                false => value as f64 / 255.0,
+            };
+            [
+                channel(color[0]),
+                channel(color[1]),
+                channel(color[2]),
+                color[3] as f64 / 255.0,
+            ]
+        };
+
+        // The output is cleared to the background one tile at a time
+        let tiles = self.tiles(width, height, format);
+        let mut encoder = self.encoder();
+        let [r, g, b, a] = color(options.background);
+        for tile in &tiles {
+            let clear = wgpu::Color {
+                r: r * a,
+                g: g * a,
+                b: b * a,
+                a,
+            };
+            begin_pass(&mut encoder, &tile.view, wgpu::LoadOp::Clear(clear));
+        }
+        self.queue.submit([encoder.finish()]);
+
+        let pipeline = match options.composite_mode {
+            CompositeMode::Replace => &self.replace[linear],
+            CompositeMode::Over | CompositeMode::Premultiplied => &self.blend[linear],
+        };
+        // Every draw's uniforms start at a multiple of the alignment the GPU needs
+        let alignment = self.device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = DRAW_SIZE.div_ceil(alignment) * alignment;
+        // Filters the GPU can't resize with are only warned about once per join
+        let mut warned = false;
+        for index in options.order.indices(photos.len()) {
+            let (img, item, placement) = (&photos[index], &items[index], &layout.placements[index]);
+            let target = placement.target;
+            let source = scale_rect(placement.source, item.size, img.size());
+            let texture = self.upload(&img.crop_rgba8(source), format);
+            progress(JoinEvent::ImageResized {
+                index,
+                width: target.width,
+                height: target.height,
+            });
+
+            // The border is a rectangle the image then covers the middle of, which replaces
+            //  what's underneath like it does on the CPU
+            let filter =
+                options.filter_for((source.width, source.height), (target.width, target.height));
+            if !draws_filter(filter) && !warned {
+                log::warn!(
+                    "resizing with a triangle filter instead of {:?}, which the GPU can't do",
+                    filter
+                );
+                warned = true;
+            }
+            let mode = match filter {
+                FilterType::Nearest => 1,
+                _ if options.composite_mode == CompositeMode::Premultiplied => 3,
+                _ => 2,
+            };
+            let mut draws = Vec::new();
+            if let Some(border) = placement.border {
+                draws.push((border_rect(target, border), color(border.color), 0, true));
+            }
+            draws.push((target, [0.0; 4], mode, false));
+
+            let mut uniforms = Vec::new();
+            let mut passes = Vec::new();
+            for tile in &tiles {
+                let offsets = draws
+                    .iter()
+                    .filter(|(rect, ..)| intersects(*rect, tile.rect))
+                    .map(|&(rect, color, mode, solid)| {
+                        let offset = uniforms.len() as u64;
+                        uniforms.extend(draw_uniforms(rect, tile.rect, color, source, mode));
+                        uniforms.resize((offset + stride) as usize, 0);
+                        (offset as u32, solid)
+                    })
+                    .collect::<Vec<_>>();
+                if !offsets.is_empty() {
+                    passes.push((tile, offsets));
+                }
+            }
+            if !passes.is_empty() {
+                let buffer = self.uniform_buffer(&uniforms);
+                let draw_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("draw"),
+                    layout: &self.draw_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(DRAW_SIZE),
+                        }),
+                    }],
+                });
+                let image_group = self.image_group(&texture);
+                let mut encoder = self.encoder();
+                for (tile, offsets) in passes {
+                    let mut pass = begin_pass(&mut encoder, &tile.view, wgpu::LoadOp::Load);
+                    pass.set_bind_group(1, &image_group, &[]);
+                    for (offset, solid) in offsets {
+                        pass.set_pipeline(if solid {
+                            &self.replace[linear]
+                        } else {
+                            pipeline
+                        });
+                        pass.set_bind_group(0, &draw_group, &[offset]);
+                        pass.draw(0..4, 0..1);
+                    }
+                }
+                self.queue.submit([encoder.finish()]);
+            }
+            progress(JoinEvent::ImageOverlaid {
+                index,
+                x: target.x,
+                y: target.y,
+            });
+        }
+
+        let mut output = self.read(&tiles, width, height)?;
+        // Colors were drawn premultiplied, so they're divided by their alpha again
+        for pixel in output.pixels_mut() {
+            match pixel[3] {
+                u8::MAX => {}
+                0 => {
+                    *pixel = Rgba([
+                        options.background[0],
+                        options.background[1],
+                        options.background[2],
+                        0,
+                    ])
+                }
+                alpha => {
+                    let alpha = alpha as f64 / 255.0;
+                    for channel in 0..3 {
+                        let value = pixel[channel] as f64 / 255.0;
+                        let value = match options.linear_light {
+                            true => linear_to_srgb((srgb_to_linear(value) / alpha).min(1.0)),
+                            false => (value / alpha).min(1.0),
+                        };
+                        pixel[channel] = (value * 255.0).round() as u8;
+                    }
+                }
+            }
+        }
+        let needs_alpha = options.makes_alpha() || photos.iter().any(Source::has_alpha);
+        Some(match needs_alpha {
+            true => DynamicImage::ImageRgba8(output),
+            false => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(output).into_rgb8()),
+        })
+    }
+
+    fn encoder(&self) -> CommandEncoder {
+        self.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("join"),
+            })
+    }
+
+    // Textures for the parts of the output, going left-to-right, top-to-bottom
+    fn tiles(&self, width: u32, height: u32, format: TextureFormat) -> Vec<Tile> {
+        let size = MAX_TILE.min(self.device.limits().max_texture_dimension_2d);
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(size as usize) {
+            for x in (0..width).step_by(size as usize) {
+                let rect = Rect {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                };
+                let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("tile"),
+                    size: extent(rect.width, rect.height),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                tiles.push(Tile {
+                    rect,
+                    texture,
+                    view,
+                });
+            }
+        }
+        tiles
+    }
+
+    fn upload(&self, img: &RgbaImage, format: TextureFormat) -> Texture {
+        let size = extent(img.width(), img.height());
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("image"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.queue.write_texture(
+            texture.as_image_copy(),
+            img.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * img.width()),
+                rows_per_image: None,
+            },
+            size,
+        );
+        texture
+    }
+
+    fn image_group(&self, texture: &Texture) -> BindGroup {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("image"),
+            layout: &self.image_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        })
+    }
+
+    fn uniform_buffer(&self, contents: &[u8]) -> Buffer {
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("draws"),
+            size: contents.len() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.queue.write_buffer(&buffer, 0, contents);
+        buffer
+    }
+
+    // Copies the tiles back into one image
+    fn read(&self, tiles: &[Tile], width: u32, height: u32) -> Option<RgbaImage> {
+        let mut output = RgbaImage::new(width, height);
+        for tile in tiles {
+            // Rows of a copied texture have to start at a multiple of the row alignment
+            let row = 4 * tile.rect.width as usize;
+            let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+            let padded_row = row.div_ceil(align) * align;
+            let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback"),
+                size: (padded_row * tile.rect.height as usize) as u64,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let mut encoder = self.encoder();
+            encoder.copy_texture_to_buffer(
+                tile.texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_row as u32),
+                        rows_per_image: None,
+                    },
+                },
+                extent(tile.rect.width, tile.rect.height),
+            );
+            self.queue.submit([encoder.finish()]);
+
+            let slice = buffer.slice(..);
+            let (sender, receiver) = mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            receiver.recv().ok()?.ok()?;
+            let pixels = slice.get_mapped_range();
+            let output_row = 4 * width as usize;
+            let raw: &mut [u8] = &mut output;
+            for (y, tile_row) in pixels.chunks(padded_row).enumerate() {
+                let start = (tile.rect.y as usize + y) * output_row + 4 * tile.rect.x as usize;
+                raw[start..start + row].copy_from_slice(&tile_row[..row]);
+            }
+        }
+        Some(output)
+    }
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+// Starts drawing into a tile, after clearing it or keeping what's already in it
+fn begin_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    view: &'a TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("join"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+fn intersects(a: Rect, b: Rect) -> bool {
+    a.width > 0
+        && a.height > 0
+        && a.x < b.x + b.width
+        && b.x < a.x + a.width
+        && a.y < b.y + b.height
+        && b.y < a.y + a.height
+}
+
+// The uniforms of one draw into a tile, laid out like `Draw` in the shader
+fn draw_uniforms(rect: Rect, tile: Rect, color: [f64; 4], source: Rect, mode: u32) -> Vec<u8> {
+    let floats = [
+        rect.x as f32 - tile.x as f32,
+        rect.y as f32 - tile.y as f32,
+        rect.width as f32,
+        rect.height as f32,
+        color[0] as f32,
+        color[1] as f32,
+        color[2] as f32,
+        color[3] as f32,
+        tile.width as f32,
+        tile.height as f32,
+        source.width as f32,
+        source.height as f32,
+    ];
+    let mut uniforms = floats
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .chain(mode.to_ne_bytes())
+        .collect::<Vec<_>>();
+    uniforms.resize(DRAW_SIZE as usize, 0);
+    uniforms
+}
+
+// Whether the shader resizes with `filter` itself, every other filter is replaced by a triangle
+//  filter
+fn draws_filter(filter: FilterType) -> bool {
+    matches!(filter, FilterType::Nearest | FilterType::Triangle)
+}
+
+// The GPU the free functions join on, which is set up the first time it's needed
+fn shared() -> Option<&'static Gpu> {
+    static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+    GPU.get_or_init(Gpu::new).as_ref()
+}
+
+/// Same as [`join_items`](crate::join_items), but on a GPU when there is one (see
+/// [`Gpu::join_items`]). The GPU is set up the first time this is called and kept for later joins
+pub fn join_items(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    join_items_with_progress(items, options, |_| {})
+}
+
+/// Same as [`join_items`], but calls `progress` as each step of the join completes
+pub fn join_items_with_progress(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    join_on(shared(), items, options, progress)
+}
+
+// Joins on `gpu`, or on the CPU when there isn't one
+fn join_on(
+    gpu: Option<&Gpu>,
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    match gpu {
+        Some(gpu) => gpu.join_items_with_progress(items, options, progress),
+        None => crate::join_items_with_progress(items, options, progress),
+    }
+}
+
+/// Same as [`join_photos`](crate::join_photos), but on a GPU when there is one like [`join_items`]
+pub fn join_photos(
+    photos: Vec<DynamicImage>,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    join_items(photos.into_iter().map(JoinItem::new).collect(), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, Separator, Shadow, Sharpen};
+    use image::{GenericImageView, ImageBuffer, Rgb, RgbImage};
+
+    fn photos() -> Vec<DynamicImage> {
+        [(40, 30), (25, 50), (60, 20)]
+            .iter()
+            .map(|&(width, height)| {
+                DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                    Rgb([(x * 4) as u8, (y * 5) as u8, 100])
+                }))
+            })
+            .collect()
+    }
+
+    // Whether `gpu` would draw the join itself rather than leaving it to the CPU
+    fn draws(gpu: &Gpu, photos: Vec<DynamicImage>, options: PhotoJoinOptions) -> bool {
+        let items = photos.into_iter().map(JoinItem::new).collect::<Vec<_>>();
+        let (photos, layout_items) = prepare_items(&items).unwrap();
+        let items = with_focus(&photos, &layout_items, &options);
+        let (layout, options) = lay_out(&items, &options).unwrap();
+        gpu.can_draw(&photos, &items, &layout, &options)
+    }
+
+    #[test]
+    fn draws_plain_joins() {
+        let Some(gpu) = Gpu::new() else { return };
+        assert!(draws(&gpu, photos(), PhotoJoinOptions::default()));
+    }
+
+    #[test]
+    fn leaves_what_it_cant_draw_to_the_cpu() {
+        let Some(gpu) = Gpu::new() else { return };
+        let shadow = Shadow {
+            offset: (2, 2),
+            blur: 3,
+            color: Rgba([0, 0, 0, 255]),
+            opacity: 0.5,
+        };
+        let separator = Separator {
+            width: 2,
+            color: Rgba([255, 255, 255, 255]),
+        };
+        let sharpen = Sharpen {
+            amount: 1.0,
+            radius: 1.0,
+            threshold: 0,
+        };
+        for options in [
+            PhotoJoinOptions {
+                shadow: Some(shadow),
+                ..Default::default()
+            },
+            PhotoJoinOptions {
+                separator: Some(separator),
+                ..Default::default()
+            },
+            PhotoJoinOptions {
+                sharpen: Some(sharpen),
+                ..Default::default()
+            },
+            PhotoJoinOptions {
+                corner_radius: 4,
+                ..Default::default()
+            },
+            PhotoJoinOptions {
+                overlap: 5,
+                ..Default::default()
+            },
+        ] {
+            assert!(!draws(&gpu, photos(), options));
+        }
+        let mut deep = photos();
+        deep[1] = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(25, 50, Rgb([0, 30000, 0])));
+        assert!(!draws(&gpu, deep, PhotoJoinOptions::default()));
+    }
+
+    #[test]
+    fn joins_on_the_cpu_without_a_gpu() {
+        let options = PhotoJoinOptions {
+            direction: Direction::Vertical,
+            spacing: 3,
+            ..Default::default()
+        };
+        let items = |photos: Vec<DynamicImage>| photos.into_iter().map(JoinItem::new).collect();
+        let joined = join_on(None, items(photos()), options, |_| {}).unwrap();
+        let expected = crate::join_items(items(photos()), options).unwrap();
+        assert_eq!(joined.color(), expected.color());
+        assert!(joined.as_bytes() == expected.as_bytes());
+    }
+
+    #[test]
+    fn draws_the_same_size_as_the_cpu() {
+        let Some(gpu) = Gpu::new() else { return };
+        for options in [
+            PhotoJoinOptions {
+                spacing: 4,
+                margin: 2,
+                ..Default::default()
+            },
+            PhotoJoinOptions {
+                direction: Direction::Grid { rows: 2, cols: 2 },
+                background: Rgba([0, 0, 0, 0]),
+                ..Default::default()
+            },
+        ] {
+            let on_gpu = gpu.join_photos(photos(), options).unwrap();
+            let on_cpu = crate::join_photos(photos(), options).unwrap();
+            assert_eq!(on_gpu.dimensions(), on_cpu.dimensions());
+            assert_eq!(on_gpu.color(), on_cpu.color());
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Draws one image (or the solid rectangle of a border) into a tile of the output image. Colors are
+//  written premultiplied, so see-through parts blend the same way the CPU blends them
+
+struct Draw {
+    // Where it's drawn in the tile, as x, y, width, height
+    dest: vec4<f32>,
+    // The color of a solid rectangle
+    color: vec4<f32>,
+    tile: vec2<f32>,
+    // Size of the image
+    source: vec2<f32>,
+    // 0 draws a solid rectangle, 1 an image with the nearest pixels, 2 an image with a triangle
+    //  filter, and 3 the same with premultiplied alpha
+    mode: u32,
+}
+
+@group(0) @binding(0) var<uniform> draw: Draw;
+@group(1) @binding(0) var image: texture_2d<f32>;
+
+@vertex
+fn vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let position = (draw.dest.xy + corner * draw.dest.zw) / draw.tile * 2.0 - 1.0;
+    return vec4<f32>(position.x, -position.y, 0.0, 1.0);
+}
+
+// The image resized like `imageops::resize` with a triangle filter, which widens to cover every
+//  pixel the output pixel spans when the image is shrunk
+fn filtered(center: vec2<f32>, scale: vec2<f32>, premultiply: bool) -> vec4<f32> {
+    let radius = max(scale, vec2<f32>(1.0));
+    let low = max(floor(center - radius), vec2<f32>(0.0));
+    let high = min(ceil(center + radius), draw.source);
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var y = low.y; y < high.y; y += 1.0) {
+        let weight_y = max(0.0, 1.0 - abs(y + 0.5 - center.y) / radius.y);
+        for (var x = low.x; x < high.x; x += 1.0) {
+            let weight = weight_y * max(0.0, 1.0 - abs(x + 0.5 - center.x) / radius.x);
+            var pixel = textureLoad(image, vec2<i32>(i32(x), i32(y)), 0);
+            if premultiply {
+                pixel = vec4<f32>(pixel.rgb * pixel.a, pixel.a);
+            }
+            sum += weight * pixel;
+            total += weight;
+        }
+    }
+    var color = sum / max(total, 1e-6);
+    if premultiply && color.a > 0.0 {
+        color = vec4<f32>(color.rgb / color.a, color.a);
+    }
+    return color;
+}
+
+@fragment
+fn fragment(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    var color = draw.color;
+    if draw.mode != 0u {
+        let scale = draw.source / draw.dest.zw;
+        let center = (position.xy - draw.dest.xy) * scale;
+        if draw.mode == 1u {
+            let pixel = min(vec2<i32>(floor(center)), vec2<i32>(draw.source) - 1);
+            color = textureLoad(image, max(pixel, vec2<i32>(0)), 0);
+        } else {
+            color = filtered(center, scale, draw.mode == 3u);
+        }
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
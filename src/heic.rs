@@ -0,0 +1,74 @@
+//! Opening HEIC/HEIF images (like photos from iPhones) with libheif, which has to be installed
+//! for the `heic` feature to build
+
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageBuffer, ImageError, ImageResult};
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+use std::error::Error;
+use std::path::Path;
+
+/// Whether a file is a HEIC/HEIF image, going by its extension
+pub fn is_heic(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("heic") || ext.eq_ignore_ascii_case("heif"))
+}
+
+/// Opens the main image of a HEIC/HEIF file. The image is already turned the way it should be
+/// shown, HEIF files store that outside of the EXIF metadata
+pub fn load_heic(path: impl AsRef<Path>) -> ImageResult<DynamicImage> {
+    let context = open_context(path.as_ref())?;
+    let handle = context.primary_image_handle().map_err(decoding_error)?;
+    let has_alpha = handle.has_alpha_channel();
+    let chroma = if has_alpha {
+        RgbChroma::Rgba
+    } else {
+        RgbChroma::Rgb
+    };
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), None)
+        .map_err(decoding_error)?;
+
+    // Rows of the plane can have padding at the end, so they're copied over one at a time
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| decoding_error("no interleaved RGB plane"))?;
+    let row_length = plane.width as usize * if has_alpha { 4 } else { 3 };
+    let pixels = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|row| &row[..row_length])
+        .copied()
+        .collect();
+    let (width, height) = (plane.width, plane.height);
+    let img = if has_alpha {
+        ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+    };
+    img.ok_or_else(|| decoding_error("decoded image is too small"))
+}
+
+/// The size of the main image of a HEIC/HEIF file, without decoding it
+pub fn heic_dimensions(path: impl AsRef<Path>) -> ImageResult<(u32, u32)> {
+    let context = open_context(path.as_ref())?;
+    let handle = context.primary_image_handle().map_err(decoding_error)?;
+    Ok((handle.width(), handle.height()))
+}
+
+fn open_context(path: &Path) -> ImageResult<HeifContext<'static>> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| decoding_error("path isn't valid UTF-8"))?;
+    HeifContext::read_from_file(path).map_err(decoding_error)
+}
+
+fn decoding_error(err: impl Into<Box<dyn Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("HEIF".to_string()),
+        err,
+    ))
+}
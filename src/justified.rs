@@ -0,0 +1,205 @@
+use crate::{
+    fit, round_lengths, separator_span, size_by, JoinError, Layout, LayoutItem, PhotoJoinOptions,
+    Rect,
+};
+use std::ops::Range;
+
+// The images of a row and how tall the row is before it's rounded to whole pixels
+pub(crate) struct Row {
+    pub(crate) items: Range<usize>,
+    pub(crate) height: f64,
+    // The images go from right to left, starting at the right edge of the output
+    pub(crate) reversed: bool,
+}
+
+// Lays images out in rows like a photo gallery, keeping every image's aspect ratio. Images are
+//  added to a row at the target row height (picked by the sizing) until it's at least `width`
+//  wide, then the whole row is scaled to be exactly `width` wide. The last row keeps the target
+//  height instead of being stretched to fill the width
+pub(crate) fn layout_justified(
+    items: &[LayoutItem],
+    width: u32,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    if width == 0 {
+        return Err(JoinError::InvalidRowWidth);
+    }
+    let target_height = size_by(options.sizing, items.iter().map(|item| item.size.1)) as f64;
+    let spacing = options.spacing as f64;
+    let aspects = aspects(items);
+
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while start < items.len() {
+        // Fill the row until it's wide enough
+        let mut end = start;
+        let mut aspect = 0.0;
+        let mut row_width = 0.0;
+        while end < items.len() && row_width < width as f64 {
+            aspect += aspects[end];
+            end += 1;
+            row_width = aspect * target_height + spacing * (end - start - 1) as f64;
+        }
+        let gaps = spacing * (end - start - 1) as f64;
+        let height = if row_width >= width as f64 {
+            (width as f64 - gaps) / aspect
+        } else {
+            target_height
+        };
+        rows.push(Row {
+            items: start..end,
+            height,
+            reversed: false,
+        });
+        start = end;
+    }
+    lay_out_rows(items, &aspects, &rows, width, options)
+}
+
+// Lays images out in rows at the height picked by the sizing, like text wrapping onto the next
+//  line: images are added to a row for as long as it stays at most `width` wide (a row always
+//  gets at least one image). Rows aren't stretched, so the output is as wide as its widest row.
+//  With `alternate` every other row goes from right to left, so the images snake back and forth
+pub(crate) fn layout_wrapped(
+    items: &[LayoutItem],
+    width: u32,
+    alternate: bool,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    if width == 0 {
+        return Err(JoinError::InvalidRowWidth);
+    }
+    let target_height = size_by(options.sizing, items.iter().map(|item| item.size.1)) as f64;
+    let spacing = options.spacing as f64;
+    let aspects = aspects(items);
+
+    let mut rows: Vec<Row> = Vec::new();
+    let mut start = 0;
+    while start < items.len() {
+        let mut end = start + 1;
+        let mut row_width = aspects[start] * target_height;
+        while end < items.len() {
+            let next_width = row_width + spacing + aspects[end] * target_height;
+            if next_width > width as f64 {
+                break;
+            }
+            row_width = next_width;
+            end += 1;
+        }
+        rows.push(Row {
+            items: start..end,
+            height: target_height,
+            reversed: alternate && rows.len() % 2 == 1,
+        });
+        start = end;
+    }
+    lay_out_rows(items, &aspects, &rows, 1, options)
+}
+
+// How wide each image is for every pixel of row height (weights make images wider)
+pub(crate) fn aspects(items: &[LayoutItem]) -> Vec<f64> {
+    items
+        .iter()
+        .map(|item| item.size.0 as f64 / item.size.1 as f64 * item.weight as f64)
+        .collect()
+}
+
+// Places the images of each row next to each other at the row's height, with the rows stacked on
+//  top of each other. The output is `width` wide, unless a row ends up wider
+pub(crate) fn lay_out_rows(
+    items: &[LayoutItem],
+    aspects: &[f64],
+    rows: &[Row],
+    width: u32,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    let mut placements = Vec::with_capacity(items.len());
+    let mut separators = Vec::new();
+    // Where the gaps between rows start, the lines in them can only be added once the final width
+    //  is known
+    let mut row_gaps = Vec::new();
+    let mut output_width = width;
+    // The placements and separators of reversed rows, which are flipped once the output width is
+    //  known
+    let mut reversed = Vec::new();
+    let mut y = 0u32;
+    for (row_index, row) in rows.iter().enumerate() {
+        // Leave a gap before every row but the first
+        if row_index > 0 {
+            row_gaps.push(y);
+            y = y
+                .checked_add(options.spacing)
+                .ok_or(JoinError::DimensionOverflow)?;
+        }
+        let height = (row.height.round() as u32).max(1);
+        let lengths = round_lengths(
+            &aspects[row.items.clone()]
+                .iter()
+                .map(|aspect| aspect * row.height)
+                .collect::<Vec<_>>(),
+        );
+        let first = (placements.len(), separators.len());
+        let mut x = 0u32;
+        for (i, (item, length)) in items[row.items.clone()].iter().zip(lengths).enumerate() {
+            if i > 0 {
+                if let Some((line_x, line_width)) = separator_span(x, options) {
+                    separators.push(Rect {
+                        x: line_x,
+                        y,
+                        width: line_width,
+                        height,
+                    });
+                }
+                x = x
+                    .checked_add(options.spacing)
+                    .ok_or(JoinError::DimensionOverflow)?;
+            }
+            let cell = Rect {
+                x,
+                y,
+                width: length,
+                height,
+            };
+            placements.push(fit(item, cell, options.fit));
+            x = x.checked_add(length).ok_or(JoinError::DimensionOverflow)?;
+        }
+
+        if row.reversed {
+            reversed.push((first, (placements.len(), separators.len())));
+        }
+        // Justified rows only end up wider than asked for when the gaps alone are too wide
+        output_width = output_width.max(x);
+        y = y.checked_add(height).ok_or(JoinError::DimensionOverflow)?;
+    }
+
+    let flip = |rect: &mut Rect| rect.x = output_width - rect.x - rect.width;
+    for ((first_placement, first_separator), (end_placement, end_separator)) in reversed {
+        for placement in &mut placements[first_placement..end_placement] {
+            flip(&mut placement.target);
+            if let Some(caption) = placement.caption.as_mut() {
+                flip(caption);
+            }
+        }
+        for separator in &mut separators[first_separator..end_separator] {
+            flip(separator);
+        }
+    }
+
+    for gap in row_gaps {
+        if let Some((line_y, line_height)) = separator_span(gap, options) {
+            separators.push(Rect {
+                x: 0,
+                y: line_y,
+                width: output_width,
+                height: line_height,
+            });
+        }
+    }
+
+    Ok(Layout {
+        width: output_width,
+        height: y,
+        placements,
+        separators,
+    })
+}
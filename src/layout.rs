@@ -0,0 +1,287 @@
+//! Declarative layout specs that describe a whole join (the options and which images go where)
+//! in a JSON or TOML file, so a collage can be made again exactly the same way later.
+//!
+//! A TOML spec looks like:
+//!
+//! ```toml
+//! direction = { grid = { rows = 2, cols = 2 } }
+//! sizing = { exact = 512 }
+//! spacing = 8
+//! background = [255, 255, 255, 255]
+//!
+//! [[images]]
+//! path = "a.jpg"
+//!
+//! [[images]]
+//! path = "b.jpg"
+//! rotation = "rotate90"
+//! ```
+//!
+//! Everything but `images` is optional, the images are joined in a row unless `direction` says
+//! otherwise.
+//!
+//! A batch file describes several independent joins, each with a layout spec and an output file:
+//!
+//! ```toml
+//! [[jobs]]
+//! output = "wide.jpg"
+//! direction = "horizontal"
+//! images = [{ path = "a.jpg" }, { path = "b.jpg" }]
+//!
+//! [[jobs]]
+//! output = "tall.png"
+//! direction = "vertical"
+//! images = [{ path = "c.jpg" }, { path = "d.jpg" }]
+//! ```
+
+use crate::preprocess::Adjustments;
+use crate::{
+    join_items_with_progress, Align, Border, JoinError, JoinEvent, JoinItem, PhotoJoinOptions,
+    Rect, Rotation,
+};
+#[cfg(not(feature = "exif"))]
+use image::io::Reader;
+use image::{DynamicImage, ImageError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutSpec {
+    /// Every option of the join, given right alongside `images` in the spec
+    #[serde(flatten)]
+    pub options: PhotoJoinOptions,
+    /// The images in the order they're joined, unless `order` says otherwise (grid cells are
+    /// filled left-to-right, top-to-bottom)
+    pub images: Vec<ImageSpec>,
+}
+
+/// An image in a layout spec, along with how it's transformed (see [`JoinItem`])
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageSpec {
+    /// Relative paths are relative to the spec file when it's loaded with [`LayoutSpec::load`]
+    pub path: PathBuf,
+    #[serde(default = "default_rotation")]
+    pub rotation: Rotation,
+    #[serde(default)]
+    pub crop: Option<Rect>,
+    #[serde(default)]
+    pub align: Option<Align>,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub border: Option<Border>,
+    /// The part of the image to keep in frame when it's cropped, like `{"x": 120, "y": 40,
+    /// "width": 200, "height": 240}` around a face, see [`JoinItem::focus`]
+    #[serde(default)]
+    pub focus: Option<Rect>,
+    /// Touch-ups made to the image, see [`JoinItem::adjustments`]
+    #[serde(default)]
+    pub adjustments: Adjustments,
+}
+
+/// A batch of independent joins
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchSpec {
+    pub jobs: Vec<JobSpec>,
+}
+
+/// One join in a batch file, which is a layout spec with the file its output is written to
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobSpec {
+    /// Relative paths are relative to the batch file when it's loaded with [`BatchSpec::load`]
+    pub output: PathBuf,
+    #[serde(flatten)]
+    pub layout: LayoutSpec,
+}
+
+// Photos are turned upright according to their EXIF orientation with the `exif` feature,
+//  converted to sRGB with the `color` feature, and HEIC/HEIF photos can be opened with the `heic`
+//  feature
+fn open_image(path: &Path) -> Result<DynamicImage, ImageError> {
+    #[cfg(feature = "exif")]
+    let img = crate::load_oriented(path)?;
+    #[cfg(all(not(feature = "exif"), feature = "heic"))]
+    let img = if crate::heic::is_heic(path) {
+        crate::heic::load_heic(path)?
+    } else {
+        Reader::open(path)?.decode()?
+    };
+    #[cfg(not(any(feature = "exif", feature = "heic")))]
+    let img = Reader::open(path)?.decode()?;
+    #[cfg(feature = "color")]
+    let img = match crate::color::read_icc_profile(path)? {
+        Some(icc) => crate::color::convert_to_srgb(&img, &icc).unwrap_or(img),
+        None => img,
+    };
+    Ok(img)
+}
+
+fn default_rotation() -> Rotation {
+    Rotation::None
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+#[derive(Debug)]
+pub enum SpecError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// The spec file doesn't end in `.json` or `.toml`
+    UnknownFormat,
+    /// One of the images in the spec couldn't be opened
+    Image {
+        path: PathBuf,
+        err: ImageError,
+    },
+    Join(JoinError),
+}
+
+impl Display for SpecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::Io(err) => write!(f, "failed to read layout spec: {}", err),
+            SpecError::Json(err) => write!(f, "invalid layout spec: {}", err),
+            SpecError::Toml(err) => write!(f, "invalid layout spec: {}", err),
+            SpecError::UnknownFormat => {
+                write!(f, "layout specs and batch files must be JSON or TOML files")
+            }
+            SpecError::Image { path, err } => {
+                write!(f, "failed to open image {}: {}", path.display(), err)
+            }
+            SpecError::Join(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for SpecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpecError::Io(err) => Some(err),
+            SpecError::Json(err) => Some(err),
+            SpecError::Toml(err) => Some(err),
+            SpecError::UnknownFormat => None,
+            SpecError::Image { err, .. } => Some(err),
+            SpecError::Join(err) => Some(err),
+        }
+    }
+}
+
+impl From<JoinError> for SpecError {
+    fn from(err: JoinError) -> Self {
+        SpecError::Join(err)
+    }
+}
+
+impl LayoutSpec {
+    pub fn from_json(spec: &str) -> Result<Self, SpecError> {
+        serde_json::from_str(spec).map_err(SpecError::Json)
+    }
+
+    pub fn from_toml(spec: &str) -> Result<Self, SpecError> {
+        toml::from_str(spec).map_err(SpecError::Toml)
+    }
+
+    /// Reads a spec from a `.json` or `.toml` file. Relative image paths are resolved against the
+    /// directory the spec is in, so the spec works from anywhere
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SpecError> {
+        let path = path.as_ref();
+        let mut spec: Self = load_file(path)?;
+        if let Some(dir) = path.parent() {
+            spec.resolve_paths(dir);
+        }
+        Ok(spec)
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        for image in &mut self.images {
+            image.path = dir.join(&image.path);
+        }
+    }
+
+    pub fn options(&self) -> PhotoJoinOptions {
+        self.options
+    }
+
+    /// Opens every image in the spec and joins them
+    pub fn execute(&self) -> Result<DynamicImage, SpecError> {
+        self.execute_with_progress(|_| {})
+    }
+
+    /// Same as [`execute`](Self::execute), but calls `progress` as each step of the join completes
+    pub fn execute_with_progress(
+        &self,
+        progress: impl FnMut(JoinEvent),
+    ) -> Result<DynamicImage, SpecError> {
+        let items = self
+            .images
+            .iter()
+            .map(|spec| {
+                let image = open_image(&spec.path).map_err(|err| SpecError::Image {
+                    path: spec.path.clone(),
+                    err,
+                })?;
+                Ok(JoinItem {
+                    image,
+                    rotation: spec.rotation,
+                    crop: spec.crop,
+                    align: spec.align,
+                    weight: spec.weight,
+                    border: spec.border,
+                    caption: None,
+                    focus: spec.focus,
+                    adjustments: spec.adjustments,
+                })
+            })
+            .collect::<Result<Vec<_>, SpecError>>()?;
+        Ok(join_items_with_progress(items, self.options(), progress)?)
+    }
+}
+
+impl BatchSpec {
+    pub fn from_json(spec: &str) -> Result<Self, SpecError> {
+        serde_json::from_str(spec).map_err(SpecError::Json)
+    }
+
+    pub fn from_toml(spec: &str) -> Result<Self, SpecError> {
+        toml::from_str(spec).map_err(SpecError::Toml)
+    }
+
+    /// Reads a batch from a `.json` or `.toml` file. Relative image and output paths are resolved
+    /// against the directory the batch file is in
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SpecError> {
+        let path = path.as_ref();
+        let mut batch: Self = load_file(path)?;
+        if let Some(dir) = path.parent() {
+            for job in &mut batch.jobs {
+                job.output = dir.join(&job.output);
+                job.layout.resolve_paths(dir);
+            }
+        }
+        Ok(batch)
+    }
+}
+
+// Reads a JSON or TOML file, going by its extension
+fn load_file<T: DeserializeOwned>(path: &Path) -> Result<T, SpecError> {
+    let contents = std::fs::read_to_string(path).map_err(SpecError::Io)?;
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("json") => serde_json::from_str(&contents).map_err(SpecError::Json),
+        Some("toml") => toml::from_str(&contents).map_err(SpecError::Toml),
+        _ => Err(SpecError::UnknownFormat),
+    }
+}
@@ -1,135 +1,2243 @@
+pub use animate::{animation_frames, encode_animation, AnimationFormat, AnimationOptions};
+use aspect::layout_aspect;
+use atlas::layout_atlas;
+pub use atlas::{atlas_coordinates, AtlasFormat};
+#[cfg(all(feature = "async", feature = "exif"))]
+pub use background::load_oriented_async;
+#[cfg(feature = "async")]
+pub use background::{
+    join_items_async, join_photos_async, load_async, Blocking, Events, JoinTask, NextEvent,
+};
+pub use builder::JoinBuilder;
+pub use dedupe::{hash_distance, perceptual_hash, unique_indices, DEFAULT_DEDUPE_DISTANCE};
+#[cfg(feature = "avif")]
+pub use encode::encode_avif;
+#[cfg(feature = "webp")]
+pub use encode::encode_webp;
+pub use encode::{
+    encode, encode_to, encode_to_size, EncodeOptions, PngCompression, SizedEncoding, TargetSize,
+};
+pub use encoded::{join_encoded, EncodedJoinError};
 pub use image;
 use image::imageops::FilterType;
-use image::{imageops, DynamicImage, GenericImageView};
+use image::{
+    imageops, ColorType, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel,
+    Primitive, Rgba, RgbaImage,
+};
+use justified::{layout_justified, layout_wrapped};
+pub use lossless::{join_jpegs_lossless, LosslessError};
+#[cfg(feature = "exif")]
+pub use metadata::{date_taken, encode_with_metadata, Metadata, MetadataField};
+pub use mipmap::{mip_strip, mipmaps, output_scales};
+#[cfg(feature = "exif")]
+pub use orientation::{
+    load_oriented, load_oriented_from_memory, load_oriented_reduced, load_oriented_region,
+    load_oriented_scaled, oriented_dimensions,
+};
+pub use pages::{encode_pages, PageFormat};
+use preprocess::Adjustments;
+pub use raw::{join_raw, PixelLayout, RawImage};
+use saliency::with_focus;
+pub use shadow::Shadow;
+use shadow::ShadowMask;
+pub use sharpen::Sharpen;
+pub use sheet::{contact_sheet, contact_sheet_labeled};
+use source::Source;
+pub use source::{join_views, join_views_with_progress};
+use std::borrow::{Borrow, Cow};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+pub use streaming::{
+    estimate_memory, join_photos_streaming, join_photos_streaming_regions, MemoryEstimate,
+    StreamError, StreamFormat,
+};
+pub use tree::{join_tree, Node};
+pub use zoom::{grid_tiles, zoom_descriptor, zoom_tiles, ZoomFormat, ZoomOptions};
+
+mod alpha;
+mod animate;
+mod aspect;
+mod atlas;
+#[cfg(feature = "async")]
+mod background;
+mod builder;
+#[cfg(feature = "color")]
+pub mod color;
+#[cfg(any(feature = "exif", feature = "color"))]
+mod container;
+mod dedupe;
+mod encode;
+mod encoded;
+#[cfg(feature = "faces")]
+pub mod faces;
+#[cfg(feature = "fast-resize")]
+mod fast_resize;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "heic")]
+pub mod heic;
+mod justified;
+#[cfg(feature = "layout")]
+pub mod layout;
+mod linear;
+mod lossless;
+#[cfg(feature = "exif")]
+mod metadata;
+mod mipmap;
+#[cfg(feature = "exif")]
+mod orientation;
+mod pages;
+pub mod preprocess;
+mod raw;
+mod saliency;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod shadow;
+mod sharpen;
+mod sheet;
+mod source;
+mod streaming;
+#[cfg(feature = "text")]
+pub mod text;
+mod tree;
+mod zoom;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Direction {
     Horizontal,
     Vertical,
+    /// Images fill the grid left-to-right, top-to-bottom. If there are more images than cells,
+    /// rows are added until they all fit (so `rows: 0` just means "as many as needed"), but there
+    /// must be at least one column
+    Grid {
+        rows: u32,
+        cols: u32,
+    },
+    /// Images wrap into rows that are all `width` pixels wide (like a photo gallery), keeping
+    /// their aspect ratios. Rows start out at the height picked by the sizing and are scaled to
+    /// fit the width, except for the last row which may be shorter
+    Justified {
+        width: u32,
+    },
+    /// Images are packed into rows like `Justified`, with however many rows (and whichever images
+    /// in each) get the output closest to the aspect ratio `width:height` (like 16:9). Every row
+    /// is the same width and the rows are the height picked by the sizing on average
+    Aspect {
+        width: u32,
+        height: u32,
+    },
+    /// Images wrap onto a new row when the next one would make the row wider than `width`, like
+    /// words in a paragraph. Rows keep the height picked by the sizing instead of being stretched
+    /// to the width, and with `alternate` every other row goes from right to left
+    Wrap {
+        width: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        alternate: bool,
+    },
+    /// Images are packed at their own sizes (the sizing and weights are ignored) into the
+    /// smallest rectangle they fit in, like a sprite sheet or texture atlas, with the spacing
+    /// between them. With a `size` (like a 1024x1024 texture) they're packed into exactly that
+    /// size instead, and the join fails with `JoinError::TooLarge` if they don't fit
+    Atlas {
+        #[cfg_attr(feature = "serde", serde(default))]
+        size: Option<(u32, u32)>,
+    },
+    /// Whichever of a row, a column, or a grid with any number of columns makes the output
+    /// closest to a square, going with the smallest one when they're just as square. Sets of
+    /// portrait and landscape photos both come out reasonably without picking for each one
+    Auto,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Sizing {
     ToSmallest,
     ToLargest,
+    /// Scale every image so the shared edge is exactly this many pixels (grids use square cells
+    /// of this size)
+    Exact(u32),
+}
+
+/// Which part of an image is kept when cropping it to fill its cell
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    /// Keeps the most detailed part of a cropped image (where its edges are densest), which is
+    /// usually the subject. It needs the image's pixels, so layouts worked out from sizes alone
+    /// (and low memory joins) keep the center instead, and anything else anchored this way (like
+    /// text) is centered
+    Smart,
+}
+
+impl Anchor {
+    // How far along the cropped-away space (horizontally, vertically) the kept region starts
+    pub(crate) fn offset(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+            Anchor::Center | Anchor::Smart => (0.5, 0.5),
+        }
+    }
+}
+
+/// Where an image sits along an edge when it doesn't fill its cell
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+impl Align {
+    // How far along the empty space the image starts
+    fn offset(self) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        }
+    }
+}
+
+/// How an image is fit into its cell of the output image
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FitMode {
+    /// Scale the whole image (keeping the aspect ratio) to fit
+    Scale,
+    /// Scale the image (keeping the aspect ratio) to cover the cell and crop what sticks out
+    /// evenly from both sides. Strips use cells of the same length for every image (the shortest
+    /// one, or the longest one with `Sizing::ToLargest`) so every image ends up the same size
+    CropCenter,
+    /// Same as `CropCenter`, but keeps the part of the image at the given anchor
+    CropAnchor(Anchor),
+    /// Never enlarge the image, instead pad it with the background color. Images that are too
+    /// large are still scaled down to fit
+    Pad(Align),
+}
+
+/// How overlapping images fade into each other across the seam
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Feather {
+    /// Fade evenly from one image to the next
+    Linear,
+    /// Fade slowly at the edges of the seam and quickly in the middle, which hides the edges of
+    /// the seam better
+    Cosine,
+}
+
+impl Feather {
+    // How much of the later image shows at `t` of the way across the seam (from 0 to 1)
+    fn weight(self, t: f32) -> f32 {
+        match self {
+            Feather::Linear => t,
+            Feather::Cosine => (1.0 - (t * std::f32::consts::PI).cos()) / 2.0,
+        }
+    }
+}
+
+/// How the see-through parts of images are combined with what's underneath them
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CompositeMode {
+    /// Blend images onto the background (and anything else underneath them)
+    Over,
+    /// Copy images onto the output as they are, so their see-through parts stay see-through
+    /// instead of showing the background. Rounded corners and overlapping images become
+    /// see-through the same way
+    Replace,
+    /// The same as `Over`, but images are resized with premultiplied alpha so the color of
+    /// see-through pixels doesn't bleed into the edges of the rest of the image
+    Premultiplied,
+}
+
+/// What images are resized with (images resized in linear light always use `Image`)
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResizeBackend {
+    /// The resizing of the `image` crate
+    #[default]
+    Image,
+    /// fast_image_resize, which uses the SIMD instructions of the CPU and is several times
+    /// faster. Needs the `fast-resize` feature, without it images are resized with `Image`
+    Fast,
+}
+
+/// The order images are laid out in. Only where the images end up changes, their placements
+/// (and anything else that goes by the index of an image) stay in the order they were given in
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Order {
+    AsGiven,
+    Reversed,
+    /// A random order that's always the same for the same seed and number of images
+    Shuffled(u64),
+}
+
+impl Order {
+    /// The indices of `count` images in the order they're laid out in
+    pub fn indices(self, count: usize) -> Vec<usize> {
+        let mut indices = (0..count).collect::<Vec<_>>();
+        match self {
+            Order::AsGiven => {}
+            Order::Reversed => indices.reverse(),
+            Order::Shuffled(seed) => {
+                // A Fisher-Yates shuffle driven by SplitMix64, which is plenty random for
+                //  arranging photos and won't change between versions like a library's RNG could
+                let mut state = seed;
+                let mut next = || {
+                    state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                    z ^ (z >> 31)
+                };
+                for i in (1..count).rev() {
+                    indices.swap(i, (next() % (i as u64 + 1)) as usize);
+                }
+            }
+        }
+        indices
+    }
+}
+
+/// Clockwise rotation applied to an image before it's joined
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Rotation {
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Border {
+    pub width: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::rgba"))]
+    pub color: Rgba<u8>,
+}
+
+/// A line drawn in the middle of the gap between adjacent images
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Separator {
+    pub width: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::rgba"))]
+    pub color: Rgba<u8>,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An image to join along with how it should be transformed first
+#[derive(Clone)]
+pub struct JoinItem {
+    pub image: DynamicImage,
+    pub rotation: Rotation,
+    /// Only use this part of the image (before it's rotated)
+    pub crop: Option<Rect>,
+    /// Where the image sits in its space when it doesn't fill it, overriding the alignment of
+    /// `FitMode::Pad` (other images are centered)
+    pub align: Option<Align>,
+    /// How much space the image gets in the join direction relative to the others (2 makes it
+    /// twice as long as it would be). In strips, images that are scaled to fit are scaled along
+    /// with their space (the strip gets wider across to fit the heaviest one), and cropped or
+    /// padded images are fit into the stretched (or squashed) space like they would be normally.
+    /// Used by strips and justified rows
+    pub weight: f32,
+    /// A frame drawn around the image, inside of its space
+    pub border: Option<Border>,
+    /// Text drawn with the image, only when joining with `text::join_items_captioned` (which
+    /// needs the `text` feature)
+    pub caption: Option<String>,
+    /// The part of the image (once it's cropped and rotated) to keep in frame when it's cropped
+    /// to fill its space, like the people in a photo. It's set by hand, or to the faces in the
+    /// image by `faces::focus_on_faces` with the `faces` feature. The kept part is centered on
+    /// it as far as the edges of the image allow, whatever the anchor is
+    pub focus: Option<Rect>,
+    /// Touch-ups made to the image (once it's cropped) before it's drawn
+    pub adjustments: Adjustments,
+}
+
+impl JoinItem {
+    pub fn new(image: DynamicImage) -> Self {
+        Self {
+            image,
+            rotation: Rotation::None,
+            crop: None,
+            align: None,
+            weight: 1.0,
+            border: None,
+            caption: None,
+            focus: None,
+            adjustments: Adjustments::default(),
+        }
+    }
+}
+
+impl From<DynamicImage> for JoinItem {
+    fn from(image: DynamicImage) -> Self {
+        Self::new(image)
+    }
+}
+
+/// Options for a join. Start from [`Default::default`], which joins images in a row, shrunk to
+/// the smallest one, on a black background
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct PhotoJoinOptions {
     pub direction: Direction,
     pub sizing: Sizing,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::filter_type"))]
     pub filter: FilterType,
+    /// Pick the filter for each image from how much it's resized instead of always using
+    /// `filter`: Lanczos3 for images shrunk to half their size or less, CatmullRom for images
+    /// shrunk less than that, Nearest for images enlarged by a whole number of times, and
+    /// Triangle for other enlarged images
+    pub auto_filter: bool,
+    /// The filter used for images that are enlarged, instead of `filter` (or the automatic one)
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::option_filter_type"))]
+    pub filter_up: Option<FilterType>,
+    /// The filter used for images that are shrunk, instead of `filter` (or the automatic one)
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::option_filter_type"))]
+    pub filter_down: Option<FilterType>,
+    pub fit: FitMode,
+    /// Number of pixels left between adjacent images
+    pub spacing: u32,
+    /// Color used to fill any part of the output not covered by an image (gaps, the empty space
+    /// around images in grid cells, etc.)
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::rgba"))]
+    pub background: Rgba<u8>,
+    /// Largest output image (width, height) that may be created, joins that would be any larger
+    /// (after being shrunk to fit `max_output`) fail with `JoinError::TooLarge`
+    pub max_dimensions: Option<(u32, u32)>,
+    /// Largest output image (width, height) to make, joins that would be any larger are shrunk
+    /// (along with the spacing, borders, and everything else) until they fit. The images are
+    /// drawn at the smaller size to begin with, so the full size output is never made
+    pub max_output: Option<(u32, u32)>,
+    /// A frame drawn around every image that doesn't have its own (see [`JoinItem::border`])
+    pub border: Option<Border>,
+    /// Number of pixels of background left around the whole output image
+    pub margin: u32,
+    /// Radius in pixels of the rounded corners of every image (and its border), the background
+    /// shows through the cut off corners. 0 keeps the corners square
+    pub corner_radius: u32,
+    /// A shadow drawn underneath every image
+    pub shadow: Option<Shadow>,
+    /// Number of pixels adjacent images overlap by, where the later image fades in over the
+    /// earlier one. Replaces the spacing when it isn't 0, and is only used by strips
+    pub overlap: u32,
+    /// How overlapping images fade into each other
+    pub feather: Feather,
+    /// A line drawn between adjacent images, which is added to the spacing (half of the spacing
+    /// is left on either side of the line). Not drawn between overlapping images
+    pub separator: Option<Separator>,
+    /// Resize images and blend them onto the output in linear light instead of on the gamma
+    /// encoded values, which keeps shrunk high contrast photos from looking darker and muddier
+    /// (but is slower)
+    pub linear_light: bool,
+    /// How the see-through parts of images are combined with what's underneath them
+    pub composite_mode: CompositeMode,
+    /// The order images are laid out in (placements are still in the order the images were
+    /// given in)
+    pub order: Order,
+    /// Sharpening applied to every image that's resized, after it's resized
+    pub sharpen: Option<Sharpen>,
+    /// Round the width and height of the output up to powers of two (like GPU textures often have
+    /// to be), filling the extra space on the right and bottom with the background
+    pub power_of_two: bool,
+    /// Round the width and height of the output up to a multiple of this (like 16 for video
+    /// encoders), after rounding them up to powers of two. 0 and 1 leave them as they are
+    pub align_multiple: u32,
+    /// What images are resized with
+    pub resize_backend: ResizeBackend,
+}
+
+impl Default for PhotoJoinOptions {
+    fn default() -> Self {
+        Self {
+            direction: Direction::Horizontal,
+            sizing: Sizing::ToSmallest,
+            filter: FilterType::Gaussian,
+            auto_filter: false,
+            filter_up: None,
+            filter_down: None,
+            fit: FitMode::Scale,
+            spacing: 0,
+            background: Rgba([0, 0, 0, 255]),
+            max_dimensions: None,
+            max_output: None,
+            border: None,
+            margin: 0,
+            corner_radius: 0,
+            shadow: None,
+            overlap: 0,
+            feather: Feather::Linear,
+            separator: None,
+            linear_light: false,
+            composite_mode: CompositeMode::Over,
+            order: Order::AsGiven,
+            sharpen: None,
+            power_of_two: false,
+            align_multiple: 0,
+            resize_backend: ResizeBackend::Image,
+        }
+    }
+}
+
+impl PhotoJoinOptions {
+    // Whether the output can have see-through pixels even when every image is solid: the
+    //  background is see-through, or images replace what's underneath them so their cut off
+    //  corners and faded in edges stay see-through
+    pub(crate) fn makes_alpha(&self) -> bool {
+        self.background[3] < u8::MAX
+            || (self.composite_mode == CompositeMode::Replace
+                && (self.corner_radius > 0 || self.overlap > 0))
+    }
+
+    // The filter to resize an image from `from` to `to` with
+    pub(crate) fn filter_for(&self, from: (u32, u32), to: (u32, u32)) -> FilterType {
+        let scale = (to.0 as f64 / from.0 as f64).max(to.1 as f64 / from.1 as f64);
+        let chosen = if scale > 1.0 {
+            self.filter_up
+        } else {
+            self.filter_down
+        };
+        if let Some(filter) = chosen {
+            return filter;
+        }
+        if !self.auto_filter {
+            return self.filter;
+        }
+        if scale <= 0.5 {
+            FilterType::Lanczos3
+        } else if scale < 1.0 {
+            FilterType::CatmullRom
+        } else if to.0.is_multiple_of(from.0) && to.1.is_multiple_of(from.1) {
+            // Every pixel just becomes a block of pixels, which stays as sharp as it was
+            FilterType::Nearest
+        } else {
+            FilterType::Triangle
+        }
+    }
+
+    /// The same options for images that are `scale` times as large, with every size in pixels
+    /// scaled along with them. Sizes that aren't 0 are kept at least a pixel, so thin borders and
+    /// gaps don't disappear
+    pub fn scaled(self, scale: f64) -> Self {
+        let px = |value: u32| match value {
+            0 => 0,
+            value => ((value as f64 * scale).round() as u32).max(1),
+        };
+        let offset = |value: i32| (value as f64 * scale).round() as i32;
+        let direction = match self.direction {
+            Direction::Justified { width } => Direction::Justified { width: px(width) },
+            Direction::Wrap { width, alternate } => Direction::Wrap {
+                width: px(width),
+                alternate,
+            },
+            Direction::Atlas { size } => Direction::Atlas {
+                size: size.map(|(width, height)| (px(width), px(height))),
+            },
+            direction => direction,
+        };
+        let sizing = match self.sizing {
+            Sizing::Exact(size) => Sizing::Exact(px(size)),
+            sizing => sizing,
+        };
+        PhotoJoinOptions {
+            direction,
+            sizing,
+            spacing: px(self.spacing),
+            border: self.border.map(|border| Border {
+                width: px(border.width),
+                ..border
+            }),
+            margin: px(self.margin),
+            corner_radius: px(self.corner_radius),
+            shadow: self.shadow.map(|shadow| Shadow {
+                offset: (offset(shadow.offset.0), offset(shadow.offset.1)),
+                blur: px(shadow.blur),
+                ..shadow
+            }),
+            overlap: px(self.overlap),
+            separator: self.separator.map(|separator| Separator {
+                width: px(separator.width),
+                ..separator
+            }),
+            sharpen: self.sharpen.map(|sharpen| Sharpen {
+                radius: sharpen.radius * scale as f32,
+                ..sharpen
+            }),
+            ..self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum JoinError {
+    NoImagesProvided,
+    /// The image at the given index has a width or height of 0
+    ZeroSizedImage {
+        index: usize,
+    },
+    /// The output image would be larger than `u32::MAX` pixels in some direction, or too large
+    /// to fit in memory at all
+    DimensionOverflow,
+    /// The output image would be larger than `PhotoJoinOptions::max_dimensions`
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_width: u32,
+        max_height: u32,
+    },
+    /// A grid was requested with 0 columns
+    InvalidGrid,
+    /// Justified or wrapped rows were requested with a width of 0
+    InvalidRowWidth,
+    /// An aspect ratio was requested with a width or height of 0
+    InvalidAspectRatio,
+    /// `Sizing::Exact(0)` was requested
+    InvalidSizing,
+    /// The crop of the item at the given index is empty or doesn't fit inside its image
+    InvalidCrop {
+        index: usize,
+    },
+    /// The weight of the item at the given index isn't a positive number
+    InvalidWeight {
+        index: usize,
+    },
+    /// The image at the given index isn't the size it was said to be ahead of time
+    ImageSizeMismatch {
+        index: usize,
+    },
+    /// Pages were requested with room for 0 images each
+    InvalidPageSize,
+    /// The raw pixel buffer of the image at the given index is too short for its size, or its
+    /// stride is shorter than a row
+    InvalidBuffer {
+        index: usize,
+    },
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::NoImagesProvided => write!(f, "no images provided"),
+            JoinError::ZeroSizedImage { index } => {
+                write!(f, "image {} has a width or height of 0", index)
+            }
+            JoinError::DimensionOverflow => write!(f, "output image dimensions are too large"),
+            JoinError::TooLarge {
+                width,
+                height,
+                max_width,
+                max_height,
+            } => write!(
+                f,
+                "output image would be {}x{}, which is larger than the maximum of {}x{}",
+                width, height, max_width, max_height
+            ),
+            JoinError::InvalidGrid => write!(f, "grid must have at least one column"),
+            JoinError::InvalidRowWidth => write!(f, "rows must be wider than 0"),
+            JoinError::InvalidAspectRatio => {
+                write!(f, "aspect ratio must be wider and taller than 0")
+            }
+            JoinError::InvalidSizing => write!(f, "exact size must be larger than 0"),
+            JoinError::InvalidCrop { index } => {
+                write!(f, "crop of image {} doesn't fit inside of it", index)
+            }
+            JoinError::InvalidWeight { index } => {
+                write!(f, "weight of image {} must be larger than 0", index)
+            }
+            JoinError::ImageSizeMismatch { index } => {
+                write!(f, "image {} isn't the expected size", index)
+            }
+            JoinError::InvalidPageSize => write!(f, "pages must have room for at least one image"),
+            JoinError::InvalidBuffer { index } => {
+                write!(
+                    f,
+                    "pixel buffer of image {} doesn't match its size and stride",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+impl JoinError {
+    // The same error for a join of some of the images of a longer list, where `index` gives the
+    //  index in the longer list of each image of the join
+    pub(crate) fn map_index(self, index: impl Fn(usize) -> usize) -> Self {
+        match self {
+            JoinError::ZeroSizedImage { index: i } => JoinError::ZeroSizedImage { index: index(i) },
+            JoinError::InvalidCrop { index: i } => JoinError::InvalidCrop { index: index(i) },
+            JoinError::InvalidWeight { index: i } => JoinError::InvalidWeight { index: index(i) },
+            JoinError::ImageSizeMismatch { index: i } => {
+                JoinError::ImageSizeMismatch { index: index(i) }
+            }
+            JoinError::InvalidBuffer { index: i } => JoinError::InvalidBuffer { index: index(i) },
+            err => err,
+        }
+    }
+}
+
+// The parts of an item that affect the layout
+#[derive(Copy, Clone)]
+pub(crate) struct LayoutItem {
+    pub(crate) size: (u32, u32),
+    pub(crate) weight: f32,
+    pub(crate) align: Option<Align>,
+    pub(crate) border: Option<Border>,
+    pub(crate) caption: Option<CaptionSpace>,
+    // Where a crop keeps the image centered (from the item's focus or a smart crop), as fractions
+    //  of its width and height
+    pub(crate) focus: Option<(f32, f32)>,
+}
+
+impl LayoutItem {
+    pub(crate) fn new(size: (u32, u32)) -> Self {
+        Self {
+            size,
+            weight: 1.0,
+            align: None,
+            border: None,
+            caption: None,
+            focus: None,
+        }
+    }
 }
 
+// A strip of the cell kept free for a caption above or below the image
+#[derive(Copy, Clone)]
+pub(crate) struct CaptionSpace {
+    pub(crate) height: u32,
+    pub(crate) below: bool,
+}
+
+/// Which part of an image is used and where (and how large) it ends up in the output image
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Placement {
+    /// The part of the image that's used, in the image's own coordinates
+    pub source: Rect,
+    /// Where the used part of the image is drawn in the output image
+    pub target: Rect,
+    /// The border drawn around `target`, which might be thinner than requested to fit its space
+    pub border: Option<Border>,
+    /// The strip kept free for a caption above or below the image, if any
+    pub caption: Option<Rect>,
+    /// Number of pixels at the start of the image (in the join direction) that overlap the image
+    /// before it, which the image fades in over
+    pub overlap: u32,
+}
+
+/// The size of the output image and where each image goes inside of it, in the same order as
+/// the images
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    pub placements: Vec<Placement>,
+    /// Where the separator lines between images go
+    pub separators: Vec<Rect>,
+}
+
+/// Progress reported while joining photos
 #[derive(Copy, Clone, Debug)]
-pub struct NoImagesProvided;
+pub enum JoinEvent {
+    /// The size of the output image has been determined
+    LayoutComputed {
+        images: usize,
+        width: u32,
+        height: u32,
+    },
+    /// The image at `index` has been resized and is about to be drawn
+    ImageResized {
+        index: usize,
+        width: u32,
+        height: u32,
+    },
+    /// The image at `index` has been drawn onto the output image
+    ImageOverlaid { index: usize, x: u32, y: u32 },
+}
 
-pub fn join_photos(
-    photos: Vec<DynamicImage>,
+/// Joins the images in order. Anything that can be iterated over works, including borrowed
+/// images (like `&[DynamicImage]`) so callers can keep their images
+pub fn join_photos<I: Borrow<DynamicImage>>(
+    photos: impl IntoIterator<Item = I>,
     options: PhotoJoinOptions,
-) -> Result<DynamicImage, NoImagesProvided> {
-    // Just leave if the images are empty (shouldn't happen basically)
+) -> Result<DynamicImage, JoinError> {
+    join_photos_with_progress(photos, options, |_| {})
+}
+
+/// Same as [`join_photos`], but calls `progress` as each step of the join completes
+pub fn join_photos_with_progress<I: Borrow<DynamicImage>>(
+    photos: impl IntoIterator<Item = I>,
+    options: PhotoJoinOptions,
+    progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    let photos = photos.into_iter().collect::<Vec<_>>();
+    let photos = photos
+        .iter()
+        .map(Borrow::borrow)
+        .collect::<Vec<&DynamicImage>>();
+    join_sources(&photos, options, progress)
+}
+
+// Same as `join_photos_with_progress`, for any kind of image that can be drawn
+pub(crate) fn join_sources(
+    photos: &[impl Source],
+    options: PhotoJoinOptions,
+    mut progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    let layout_items = photos
+        .iter()
+        .map(|img| LayoutItem::new(img.size()))
+        .collect::<Vec<_>>();
+    compose(photos, &layout_items, &options, &mut progress)
+}
+
+/// Same as [`join_photos`], but joins the images in pages of at most `max_per_page` images each
+/// and returns one output image per page, instead of one absurdly long strip. The order of the
+/// options goes across all of the pages (reversing puts the last image on the first page)
+pub fn join_photos_paginated<I: Borrow<DynamicImage>>(
+    photos: impl IntoIterator<Item = I>,
+    max_per_page: usize,
+    options: PhotoJoinOptions,
+) -> Result<Vec<DynamicImage>, JoinError> {
+    if max_per_page == 0 {
+        return Err(JoinError::InvalidPageSize);
+    }
+    let photos = photos.into_iter().collect::<Vec<_>>();
     if photos.is_empty() {
-        return Err(NoImagesProvided);
-    }
-    if photos.len() == 1 {
-        return Ok(photos.into_iter().next().unwrap());
-    }
-    println!("Joining {} photos", photos.len());
-
-    // Determine the sizes for the output image
-    let perpendicular_size = photos.iter().fold(
-        match options.sizing {
-            Sizing::ToSmallest => u32::MAX,
-            Sizing::ToLargest => 0,
-        },
-        |size, img| {
-            let dir_size = match options.direction {
-                Direction::Horizontal => img.width(),
-                Direction::Vertical => img.height(),
-            };
-            match options.sizing {
-                Sizing::ToSmallest => size.min(dir_size),
-                Sizing::ToLargest => size.max(dir_size),
-            }
-        },
-    );
-    let join_size = photos.iter().fold(0u32, |size, img| {
-        let scale = get_scale_factor(perpendicular_size, options.direction, &img);
-        size + (scale
-            * match options.direction {
-                Direction::Horizontal => img.width(),
-                Direction::Vertical => img.height(),
-            } as f32) as u32
+        return Err(JoinError::NoImagesProvided);
+    }
+    let order = options.order.indices(photos.len());
+    let options = PhotoJoinOptions {
+        order: Order::AsGiven,
+        ..options
+    };
+    order
+        .chunks(max_per_page)
+        .map(|page| {
+            join_photos(page.iter().map(|&index| photos[index].borrow()), options)
+                .map_err(|err| err.map_index(|index| page[index]))
+        })
+        .collect()
+}
+
+/// Works out how images of the given sizes would be joined without touching any image data,
+/// which is useful to check or preview a join before doing it
+pub fn compute_layout(
+    sizes: &[(u32, u32)],
+    options: PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    let items = sizes
+        .iter()
+        .map(|&size| LayoutItem::new(size))
+        .collect::<Vec<_>>();
+    lay_out(&items, &options).map(|(layout, _)| layout)
+}
+
+/// Same as [`compute_layout`], but with a weight for each image (see [`JoinItem::weight`]).
+/// Images past the end of `weights` get a weight of 1
+pub fn compute_layout_weighted(
+    sizes: &[(u32, u32)],
+    weights: &[f32],
+    options: PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    let items = sizes
+        .iter()
+        .enumerate()
+        .map(|(index, &size)| LayoutItem {
+            weight: weights.get(index).copied().unwrap_or(1.0),
+            ..LayoutItem::new(size)
+        })
+        .collect::<Vec<_>>();
+    lay_out(&items, &options).map(|(layout, _)| layout)
+}
+
+/// Same as [`join_photos`], but each image can be cropped, rotated, etc. on its own
+pub fn join_items(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    join_items_with_progress(items, options, |_| {})
+}
+
+/// Same as [`join_items`], but calls `progress` as each step of the join completes
+pub fn join_items_with_progress(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    mut progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    let (photos, layout_items) = prepare_items(&items)?;
+    compose(&photos, &layout_items, &options, &mut progress)
+}
+
+// Applies the per-item transforms, which have to happen before anything else since they can
+//  change the image sizes
+pub(crate) fn prepare_items(
+    items: &[JoinItem],
+) -> Result<(Vec<Cow<'_, DynamicImage>>, Vec<LayoutItem>), JoinError> {
+    let photos = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| prepare_item(index, item))
+        .collect::<Result<Vec<_>, _>>()?;
+    let layout_items = items
+        .iter()
+        .zip(&photos)
+        .map(|(item, img)| LayoutItem {
+            size: img.dimensions(),
+            weight: item.weight,
+            align: item.align,
+            border: item.border,
+            caption: None,
+            focus: item.focus.map(|focus| focus_point(focus, img.dimensions())),
+        })
+        .collect();
+    Ok((photos, layout_items))
+}
+
+// Lays out and draws the images. The images don't have to be the size of their layout items
+//  (which may have been shrunk already) as long as they have the same aspect ratio
+pub(crate) fn compose(
+    photos: &[impl Source],
+    items: &[LayoutItem],
+    options: &PhotoJoinOptions,
+    progress: &mut impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    let items = with_focus(photos, items, options);
+    let (layout, options) = lay_out(&items, options)?;
+    Ok(render(photos, &items, layout, &options, progress))
+}
+
+// Draws the images where the layout puts them
+pub(crate) fn render(
+    photos: &[impl Source],
+    items: &[LayoutItem],
+    layout: Layout,
+    options: &PhotoJoinOptions,
+    progress: &mut impl FnMut(JoinEvent),
+) -> DynamicImage {
+    let Layout {
+        width,
+        height,
+        placements,
+        separators,
+    } = layout;
+    progress(JoinEvent::LayoutComputed {
+        images: photos.len(),
+        width,
+        height,
     });
-    println!(
-        "Determined output image size: {}",
-        match options.direction {
-            Direction::Horizontal => format!("{}x{}", perpendicular_size, join_size),
-            Direction::Vertical => format!("{}x{}", join_size, perpendicular_size),
+
+    // The part of each image that's drawn, and whether it's copied into the output as it is
+    let sources = (0..photos.len())
+        .map(|index| {
+            scale_rect(
+                placements[index].source,
+                items[index].size,
+                photos[index].size(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let as_is = (0..photos.len())
+        .map(|index| draws_as_is(&photos[index], sources[index], &placements[index], options))
+        .collect::<Vec<_>>();
+    // When every image is copied as it is and together they cover the whole output (like a sprite
+    //  sheet of images that are all the same size), none of the background would be left showing
+    let covered = options.shadow.is_none()
+        && as_is.iter().all(|&as_is| as_is)
+        && tiles(&placements, width, height);
+
+    // Start from a blank canvas filled with the background color, which only needs an alpha
+    //  channel if the output or any of the images can be transparent
+    let needs_alpha = options.makes_alpha() || photos.iter().any(Source::has_alpha);
+    // Images with 16 bits per channel are composited at 16 bits so they don't lose precision,
+    //  the canvas always has alpha until the end so images can be overlaid right onto it
+    let deep = photos.iter().any(Source::is_deep);
+    let mut output_img = if deep {
+        DynamicImage::ImageRgba16(canvas(width, height, widen(options.background), covered))
+    } else if needs_alpha {
+        DynamicImage::ImageRgba8(canvas(width, height, options.background, covered))
+    } else {
+        DynamicImage::ImageRgb8(canvas(width, height, options.background.to_rgb(), covered))
+    };
+
+    // Shadows go underneath everything else, so they're all drawn first
+    if let Some(shadow) = options.shadow {
+        for placement in &placements {
+            let (rect, radius) = outer_shape(placement, options.corner_radius);
+            ShadowMask::new(&shadow, rect, radius).draw(&mut output_img, 0);
         }
-    );
+    }
+    if let Some(separator) = options.separator {
+        for &rect in &separators {
+            fill_rect(&mut output_img, rect, 0, separator.color, 0);
+        }
+    }
 
-    // Resize the first image to the full size of the output
-    // We should be able to use `photos.first().unwrap()` safely because we know there is at least
-    //  1 image provided
-    let mut output_img = photos.first().unwrap().resize_exact(
-        match options.direction {
-            Direction::Horizontal => join_size,
-            Direction::Vertical => perpendicular_size,
-        },
-        match options.direction {
-            Direction::Horizontal => perpendicular_size,
-            Direction::Vertical => join_size,
-        },
-        FilterType::Nearest,
-    );
+    // Images are drawn in the order they were laid out in, so each one fades in over the one
+    //  before it where they overlap
+    for index in options.order.indices(photos.len()) {
+        let (img, item, placement) = (&photos[index], &items[index], placements[index]);
+        let target = placement.target;
 
-    let _ = photos.into_iter().fold(0u32, |pos, img| {
-        // Get sizing and positioning information
-        let (w, h) = get_size(perpendicular_size, options.direction, &img);
-        let (x, y) = match options.direction {
-            Direction::Horizontal => (pos, 0),
-            Direction::Vertical => (0, pos),
+        // Draw the border as a rectangle that the image then covers the middle of
+        if let Some(border) = placement.border {
+            let radius = border_radius(options.corner_radius, border);
+            fill_rect(
+                &mut output_img,
+                border_rect(target, border),
+                radius,
+                border.color,
+                0,
+            );
+        }
+
+        // Overlay the cropped and resized image on top of the final image
+        let resized_event = JoinEvent::ImageResized {
+            index,
+            width: target.width,
+            height: target.height,
         };
+        if as_is[index] {
+            progress(resized_event);
+            copy_onto(&mut output_img, img, sources[index], target.x, target.y);
+        } else if let DynamicImage::ImageRgba16(canvas) = &mut output_img {
+            let resized = render_placement_deep(img, item.size, &placement, options);
+            progress(resized_event);
+            overlay_deep(canvas, &resized, target.x, target.y, options);
+        } else {
+            let resized = render_placement(img, item.size, &placement, options);
+            progress(resized_event);
+            overlay(&mut output_img, &resized, target.x, target.y, options);
+        }
+        progress(JoinEvent::ImageOverlaid {
+            index,
+            x: target.x,
+            y: target.y,
+        });
+    }
+
+    match output_img {
+        DynamicImage::ImageRgba16(canvas) if !needs_alpha => {
+            DynamicImage::ImageRgb16(DynamicImage::ImageRgba16(canvas).into_rgb16())
+        }
+        output_img => output_img,
+    }
+}
 
-        // Overlay the resized image on top of the final image
-        imageops::overlay(
-            &mut output_img,
-            &imageops::resize(&img, w, h, options.filter),
-            x,
-            y,
-        );
-        println!("Overlayed image at {},{} with size {}x{}", x, y, w, h);
+// A canvas filled with the background color, or left empty when every pixel of it is about to be
+//  drawn over anyway, which is much quicker for large images since the system hands out empty
+//  memory without touching it
+fn canvas<P: Pixel + 'static>(
+    width: u32,
+    height: u32,
+    background: P,
+    covered: bool,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    match covered {
+        true => ImageBuffer::new(width, height),
+        false => ImageBuffer::from_pixel(width, height, background),
+    }
+}
 
-        // Accumulate size in the join direction
-        match options.direction {
-            Direction::Vertical => pos + h,
-            Direction::Horizontal => pos + w,
+// Whether the targets of the placements cover every pixel of the output image, without any of
+//  them overlapping
+fn tiles(placements: &[Placement], width: u32, height: u32) -> bool {
+    let area = |rect: Rect| rect.width as u64 * rect.height as u64;
+    let covered = placements
+        .iter()
+        .map(|placement| area(placement.target))
+        .sum::<u64>();
+    if covered != width as u64 * height as u64 {
+        return false;
+    }
+    // Targets that don't overlap and add up to the area of the output image cover all of it. Going
+    //  from top to bottom, each target only has to be checked against the ones that start above
+    //  its bottom edge
+    let mut targets = placements
+        .iter()
+        .map(|placement| placement.target)
+        .filter(|&target| area(target) > 0)
+        .collect::<Vec<_>>();
+    targets.sort_by_key(|target| (target.y, target.x));
+    targets.iter().enumerate().all(|(index, a)| {
+        targets[index + 1..]
+            .iter()
+            .take_while(|b| b.y < a.y + a.height)
+            .all(|b| b.x >= a.x + a.width || a.x >= b.x + b.width)
+    })
+}
+
+// Whether the part of an image at `source` ends up in the output exactly as it is: it doesn't need
+//  to be resized, its corners aren't cut off, it isn't faded in, and it covers everything
+//  underneath it
+fn draws_as_is(
+    img: &impl Source,
+    source: Rect,
+    placement: &Placement,
+    options: &PhotoJoinOptions,
+) -> bool {
+    let target = placement.target;
+    (source.width, source.height) == (target.width, target.height)
+        && options.corner_radius == 0
+        && placement.overlap == 0
+        && (options.composite_mode == CompositeMode::Replace || img.is_opaque())
+}
+
+// Copies the part of an image at `source` onto the output image a row at a time. Images already
+//  in the format of the output image are copied straight from their own pixels, and others are
+//  converted in one go rather than pixel by pixel
+fn copy_onto(output_img: &mut DynamicImage, img: &impl Source, source: Rect, x: u32, y: u32) {
+    let whole = |(width, height)| Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    match (output_img, img.as_dynamic()) {
+        (DynamicImage::ImageRgb8(canvas), Some(DynamicImage::ImageRgb8(img))) => {
+            copy_rows(canvas, img, source, x, y)
         }
-    });
+        (DynamicImage::ImageRgba8(canvas), Some(DynamicImage::ImageRgba8(img))) => {
+            copy_rows(canvas, img, source, x, y)
+        }
+        (DynamicImage::ImageRgba16(canvas), Some(DynamicImage::ImageRgba16(img))) => {
+            copy_rows(canvas, img, source, x, y)
+        }
+        (DynamicImage::ImageRgb8(canvas), _) => {
+            let img = img.crop_rgb8(source);
+            copy_rows(canvas, &img, whole(img.dimensions()), x, y)
+        }
+        (DynamicImage::ImageRgba8(canvas), _) => {
+            let img = img.crop_rgba8(source);
+            copy_rows(canvas, &img, whole(img.dimensions()), x, y)
+        }
+        (DynamicImage::ImageRgba16(canvas), _) => {
+            let img = img.crop_rgba16(source);
+            copy_rows(canvas, &img, whole(img.dimensions()), x, y)
+        }
+        (canvas, _) => imageops::replace(canvas, &img.crop_rgba8(source), x, y),
+    }
+}
+
+// Copies the part of an image at `source` into a larger one of the same format, where it has to
+//  fit completely
+fn copy_rows<P: Pixel + 'static>(
+    canvas: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    source: Rect,
+    x: u32,
+    y: u32,
+) {
+    let channels = P::CHANNEL_COUNT as usize;
+    let canvas_row = canvas.width() as usize * channels;
+    let img_row = img.width() as usize * channels;
+    let row = source.width as usize * channels;
+    if row == 0 {
+        return;
+    }
+    let rows = img
+        .chunks_exact(img_row)
+        .skip(source.y as usize)
+        .take(source.height as usize);
+    for (index, pixels) in rows.enumerate() {
+        let from = source.x as usize * channels;
+        let start = (y as usize + index) * canvas_row + x as usize * channels;
+        (**canvas)[start..start + row].copy_from_slice(&pixels[from..from + row]);
+    }
+}
+
+/// Black or white, whichever stands out more against `background` (like for text). See-through
+/// backgrounds get black
+pub fn contrasting_color(background: Rgba<u8>) -> Rgba<u8> {
+    let luma =
+        0.299 * background[0] as f32 + 0.587 * background[1] as f32 + 0.114 * background[2] as f32;
+    if luma > 127.0 || background[3] < 128 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+// Crops and resizes an image to how it appears in the output image (including its rounded
+//  corners), where `size` is the size the image was laid out with. Images that don't need to
+//  change size are copied as-is since even "resizing" to the same size blurs them
+pub(crate) fn render_placement(
+    img: &impl Source,
+    size: (u32, u32),
+    placement: &Placement,
+    options: &PhotoJoinOptions,
+) -> RgbaImage {
+    let Placement { target, .. } = *placement;
+    let source = scale_rect(placement.source, size, img.size());
+    let cropped = img.crop_rgba8(source);
+    let mut resized = if (source.width, source.height) == (target.width, target.height) {
+        cropped
+    } else {
+        let (width, height) = (target.width, target.height);
+        let filter = options.filter_for((source.width, source.height), (width, height));
+        let premultiplied = options.composite_mode == CompositeMode::Premultiplied;
+        let mut resized = if options.linear_light {
+            linear::resize_linear(&cropped, width, height, filter, premultiplied)
+        } else {
+            resize(&cropped, width, height, filter, premultiplied, options)
+        };
+        if let Some(options) = &options.sharpen {
+            sharpen::sharpen(&mut resized, options);
+        }
+        resized
+    };
+    finish_placement(&mut resized, placement, options);
+    resized
+}
+
+// The same as `render_placement`, but keeps 16 bits per channel
+fn render_placement_deep(
+    img: &impl Source,
+    size: (u32, u32),
+    placement: &Placement,
+    options: &PhotoJoinOptions,
+) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+    let Placement { target, .. } = *placement;
+    let source = scale_rect(placement.source, size, img.size());
+    let cropped = img.crop_rgba16(source);
+    let mut resized = if (source.width, source.height) == (target.width, target.height) {
+        cropped
+    } else {
+        let (width, height) = (target.width, target.height);
+        let filter = options.filter_for((source.width, source.height), (width, height));
+        let premultiplied = options.composite_mode == CompositeMode::Premultiplied;
+        let mut resized = if options.linear_light {
+            linear::resize_linear_deep(&cropped, width, height, filter, premultiplied)
+        } else {
+            resize(&cropped, width, height, filter, premultiplied, options)
+        };
+        if let Some(options) = &options.sharpen {
+            sharpen::sharpen(&mut resized, options);
+        }
+        resized
+    };
+    finish_placement(&mut resized, placement, options);
+    resized
+}
+
+// Resizes an image with the backend the options ask for, falling back on the `image` crate for
+//  images fast_image_resize can't take
+#[cfg_attr(not(feature = "fast-resize"), allow(unused_variables))]
+fn resize<T: Primitive + 'static>(
+    img: &ImageBuffer<Rgba<T>, Vec<T>>,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    premultiplied: bool,
+    options: &PhotoJoinOptions,
+) -> ImageBuffer<Rgba<T>, Vec<T>> {
+    #[cfg(feature = "fast-resize")]
+    if options.resize_backend == ResizeBackend::Fast {
+        if let Some(resized) = fast_resize::resize(img, width, height, filter, premultiplied) {
+            return resized;
+        }
+    }
+    alpha::resize(img, width, height, filter, premultiplied)
+}
+
+// The middle of a part of an image, as fractions of the image's width and height
+pub(crate) fn focus_point(focus: Rect, (width, height): (u32, u32)) -> (f32, f32) {
+    let middle = |start: u32, length: u32, size: u32| {
+        ((start as f32 + length as f32 / 2.0) / size as f32).clamp(0.0, 1.0)
+    };
+    (
+        middle(focus.x, focus.width, width),
+        middle(focus.y, focus.height, height),
+    )
+}
+
+// Rounds the corners of a resized image and fades it in where it overlaps the previous one
+fn finish_placement<T: Primitive + 'static>(
+    img: &mut ImageBuffer<Rgba<T>, Vec<T>>,
+    placement: &Placement,
+    options: &PhotoJoinOptions,
+) {
+    if options.corner_radius > 0 {
+        round_corners(img, options.corner_radius);
+    }
+    if placement.overlap > 0 {
+        let vertical = options.direction == Direction::Vertical;
+        fade_in(img, placement.overlap, vertical, options.feather);
+    }
+}
+
+// Whether an image has more than 8 bits per channel
+pub(crate) fn is_deep(img: &DynamicImage) -> bool {
+    matches!(
+        img.color(),
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+    )
+}
 
-    Ok(output_img)
+// Stretches an 8 bit color to 16 bits, so 255 becomes 65535
+fn widen(color: Rgba<u8>) -> Rgba<u16> {
+    Rgba(color.0.map(|channel| channel as u16 * 257))
 }
 
-fn get_scale_factor(perpendicular_size: u32, direction: Direction, img: &DynamicImage) -> f32 {
-    perpendicular_size as f32
-        / match direction {
-            Direction::Horizontal => img.height(),
-            Direction::Vertical => img.width(),
-        } as f32
+// Multiplies an alpha value by a number from 0 to 1
+fn scale_alpha<T: Primitive>(alpha: T, factor: f32) -> T {
+    let alpha = alpha.to_f32().unwrap_or_default();
+    T::from((alpha * factor).round()).unwrap_or_else(T::zero)
 }
 
-fn get_size(perpendicular_size: u32, direction: Direction, img: &DynamicImage) -> (u32, u32) {
-    let scale_factor = get_scale_factor(perpendicular_size, direction, img);
-    match direction {
-        Direction::Horizontal => (
-            (scale_factor * img.width() as f32) as u32,
-            perpendicular_size,
+// Makes the first `length` columns (or rows, for vertical strips) of an image more and more
+//  solid, so it blends with the image underneath
+fn fade_in<T: Primitive + 'static>(
+    img: &mut ImageBuffer<Rgba<T>, Vec<T>>,
+    length: u32,
+    vertical: bool,
+    feather: Feather,
+) {
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let pos = if vertical { y } else { x };
+        if pos < length {
+            let weight = feather.weight((pos as f32 + 0.5) / length as f32);
+            pixel[3] = scale_alpha(pixel[3], weight);
+        }
+    }
+}
+
+// Draws an image on top of the output image (or a part of it), replacing what's underneath or
+//  blending it in linear light if the options ask for it
+pub(crate) fn overlay(
+    img: &mut DynamicImage,
+    top: &impl GenericImageView<Pixel = Rgba<u8>>,
+    x: u32,
+    y: u32,
+    options: &PhotoJoinOptions,
+) {
+    if options.composite_mode == CompositeMode::Replace {
+        imageops::replace(img, top, x, y);
+    } else if options.linear_light {
+        linear::overlay_linear(img, top, x, y);
+    } else {
+        imageops::overlay(img, top, x, y);
+    }
+}
+
+// The same as `overlay`, for a 16 bit output image
+fn overlay_deep(
+    img: &mut ImageBuffer<Rgba<u16>, Vec<u16>>,
+    top: &ImageBuffer<Rgba<u16>, Vec<u16>>,
+    x: u32,
+    y: u32,
+    options: &PhotoJoinOptions,
+) {
+    if options.composite_mode == CompositeMode::Replace {
+        imageops::replace(img, top, x, y);
+    } else if options.linear_light {
+        linear::overlay_linear_deep(img, top, x, y);
+    } else {
+        imageops::overlay(img, top, x, y);
+    }
+}
+
+// Makes the corners of an image see-through outside of a circle of the given radius, with the
+//  edge of the circle partially see-through so it doesn't look jagged
+fn round_corners<T: Primitive + 'static>(img: &mut ImageBuffer<Rgba<T>, Vec<T>>, radius: u32) {
+    let (width, height) = img.dimensions();
+    let radius = radius.min(width / 2).min(height / 2);
+    for y in (0..radius).chain(height - radius..height) {
+        for x in (0..radius).chain(width - radius..width) {
+            let coverage = corner_coverage(x, y, width, height, radius);
+            let pixel = img.get_pixel_mut(x, y);
+            pixel[3] = scale_alpha(pixel[3], coverage);
+        }
+    }
+}
+
+// How much of the pixel at `x`,`y` is inside of a `width` by `height` rectangle with corners
+//  rounded to `radius`, from 0 to 1
+pub(crate) fn corner_coverage(x: u32, y: u32, width: u32, height: u32, radius: u32) -> f32 {
+    let radius = radius.min(width / 2).min(height / 2) as f32;
+    // Distance from the middle of the pixel to the center of the corner's circle, only pixels
+    //  in the corners can be outside of the rounded rectangle
+    let distance = |value: u32, length: u32| {
+        let center = value as f32 + 0.5;
+        if center < radius {
+            Some(radius - center)
+        } else if center > length as f32 - radius {
+            Some(center - (length as f32 - radius))
+        } else {
+            None
+        }
+    };
+    match (distance(x, width), distance(y, height)) {
+        (Some(dx), Some(dy)) => (radius - dx.hypot(dy) + 0.5).clamp(0.0, 1.0),
+        _ => 1.0,
+    }
+}
+
+// The area covered by an image including its border, along with the radius of its corners
+pub(crate) fn outer_shape(placement: &Placement, corner_radius: u32) -> (Rect, u32) {
+    match placement.border {
+        Some(border) => (
+            border_rect(placement.target, border),
+            border_radius(corner_radius, border),
         ),
-        Direction::Vertical => (
-            perpendicular_size,
-            (scale_factor * img.height() as f32) as u32,
+        None => (placement.target, corner_radius),
+    }
+}
+
+// The radius of the rounded corners of a border, which is larger than the image's so the border
+//  is the same width all the way around
+pub(crate) fn border_radius(corner_radius: u32, border: Border) -> u32 {
+    if corner_radius == 0 {
+        0
+    } else {
+        corner_radius + border.width
+    }
+}
+
+// Maps a rectangle inside an image of size `from` to the same area of the image at size `to`
+pub(crate) fn scale_rect(rect: Rect, from: (u32, u32), to: (u32, u32)) -> Rect {
+    if from == to {
+        return rect;
+    }
+    let scale =
+        |value: u32, from: u32, to: u32| (value as f64 * to as f64 / from as f64).round() as u32;
+    let x = scale(rect.x, from.0, to.0).min(to.0 - 1);
+    let y = scale(rect.y, from.1, to.1).min(to.1 - 1);
+    Rect {
+        x,
+        y,
+        width: scale(rect.width, from.0, to.0).clamp(1, to.0 - x),
+        height: scale(rect.height, from.1, to.1).clamp(1, to.1 - y),
+    }
+}
+
+// The area covered by the border around an image
+pub(crate) fn border_rect(target: Rect, border: Border) -> Rect {
+    Rect {
+        x: target.x - border.width,
+        y: target.y - border.width,
+        width: target.width + 2 * border.width,
+        height: target.height + 2 * border.width,
+    }
+}
+
+// Crops and rotates the image of an item, only copying it if either is needed
+pub(crate) fn prepare_item(
+    index: usize,
+    item: &JoinItem,
+) -> Result<Cow<'_, DynamicImage>, JoinError> {
+    let mut img = Cow::Borrowed(&item.image);
+    if let Some(crop) = item.crop {
+        let (w, h) = img.dimensions();
+        let fits = crop.width > 0
+            && crop.height > 0
+            && crop
+                .x
+                .checked_add(crop.width)
+                .is_some_and(|right| right <= w)
+            && crop
+                .y
+                .checked_add(crop.height)
+                .is_some_and(|bottom| bottom <= h);
+        if !fits {
+            return Err(JoinError::InvalidCrop { index });
+        }
+        img = Cow::Owned(img.crop_imm(crop.x, crop.y, crop.width, crop.height));
+    }
+    if !item.adjustments.is_none() {
+        img = Cow::Owned(preprocess::adjust(&img, &item.adjustments));
+    }
+    Ok(match item.rotation {
+        Rotation::None => img,
+        Rotation::Rotate90 => Cow::Owned(img.rotate90()),
+        Rotation::Rotate180 => Cow::Owned(img.rotate180()),
+        Rotation::Rotate270 => Cow::Owned(img.rotate270()),
+    })
+}
+
+// Fills a rectangle with its corners rounded to `radius`, where `img` only holds the rows of the
+//  output image from `top` on (anything outside of it is left out)
+pub(crate) fn fill_rect(
+    img: &mut DynamicImage,
+    rect: Rect,
+    radius: u32,
+    color: Rgba<u8>,
+    top: u32,
+) {
+    let start = rect.y.max(top);
+    let end = (rect.y + rect.height).min(top + img.height());
+    for y in start..end {
+        for x in rect.x..rect.x + rect.width {
+            let coverage = corner_coverage(x - rect.x, y - rect.y, rect.width, rect.height, radius);
+            if coverage >= 1.0 {
+                img.put_pixel(x, y - top, color);
+            } else if coverage > 0.0 {
+                let mut partial = color;
+                partial[3] = (color[3] as f32 * coverage).round() as u8;
+                let mut pixel = img.get_pixel(x, y - top);
+                pixel.blend(&partial);
+                img.put_pixel(x, y - top, pixel);
+            }
+        }
+    }
+}
+
+// Determines the size of the output image and where each image goes inside of it, along with the
+//  options to draw the images with (which are scaled down along with everything else when the
+//  output is shrunk to fit `max_output`)
+pub(crate) fn lay_out(
+    items: &[LayoutItem],
+    options: &PhotoJoinOptions,
+) -> Result<(Layout, PhotoJoinOptions), JoinError> {
+    // The images are drawn with the direction that was picked, since overlaps fade in along it
+    let options = &PhotoJoinOptions {
+        direction: pick_direction(items, options),
+        ..*options
+    };
+    let mut layout = arrange(items, options)?;
+    let mut drawn = *options;
+    if let Some((max_width, max_height)) = options.max_output {
+        // The images are laid out again as if they were smaller, and rounding can leave that a
+        //  pixel or two too large, so it's shrunk a little more until it fits
+        let mut scale = 1.0;
+        for _ in 0..4 {
+            let fit = (max_width as f64 / layout.width as f64)
+                .min(max_height as f64 / layout.height as f64);
+            if fit >= 1.0 {
+                break;
+            }
+            scale *= fit;
+            drawn = PhotoJoinOptions {
+                max_output: None,
+                ..options.scaled(scale)
+            };
+            let shrunk = items
+                .iter()
+                .map(|item| LayoutItem {
+                    size: (
+                        ((item.size.0 as f64 * scale).round() as u32).max(1),
+                        ((item.size.1 as f64 * scale).round() as u32).max(1),
+                    ),
+                    ..*item
+                })
+                .collect::<Vec<_>>();
+            layout = arrange(&shrunk, &drawn)?;
+            // Sources are parts of the images at their real sizes
+            for ((placement, item), shrunk) in layout.placements.iter_mut().zip(items).zip(&shrunk)
+            {
+                placement.source = scale_rect(placement.source, shrunk.size, item.size);
+            }
+        }
+    }
+
+    // Outputs that have to be a certain size are padded out on the right and bottom, so nothing
+    //  moves and the places of the images don't change
+    layout.width = round_up_length(layout.width, options)?;
+    layout.height = round_up_length(layout.height, options)?;
+
+    // Catch outputs that are too large here rather than letting the image crate panic (or run
+    //  out of memory) when the output image is allocated
+    let (width, height) = (layout.width, layout.height);
+    if let Some((max_width, max_height)) = options.max_dimensions {
+        if width > max_width || height > max_height {
+            return Err(JoinError::TooLarge {
+                width,
+                height,
+                max_width,
+                max_height,
+            });
+        }
+    }
+    let fits_in_memory = (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .is_some_and(|bytes| bytes <= isize::MAX as u64);
+    if !fits_in_memory {
+        return Err(JoinError::DimensionOverflow);
+    }
+    Ok((layout, drawn))
+}
+
+// A width or height of the output rounded up to the next power of two and multiple of
+//  `align_multiple`, when the options ask for them
+pub(crate) fn round_up_length(length: u32, options: &PhotoJoinOptions) -> Result<u32, JoinError> {
+    let length = match options.power_of_two {
+        true => length.checked_next_power_of_two(),
+        false => Some(length),
+    };
+    match options.align_multiple {
+        0 | 1 => length,
+        multiple => length.and_then(|length| length.checked_next_multiple_of(multiple)),
+    }
+    .ok_or(JoinError::DimensionOverflow)
+}
+
+// The direction the images are laid out in, which is the one in the options unless it's `Auto`.
+//  Every arrangement it could be is laid out to see how square it is, and if none of them work
+//  it's left as a row for `arrange` to report why
+fn pick_direction(items: &[LayoutItem], options: &PhotoJoinOptions) -> Direction {
+    if options.direction != Direction::Auto {
+        return options.direction;
+    }
+    // A grid with one column is a column and one with a column for each image is a row
+    let grids = (2..items.len() as u32).map(|cols| Direction::Grid { rows: 0, cols });
+    let mut best: Option<(f64, u64, Direction)> = None;
+    for direction in vec![Direction::Horizontal, Direction::Vertical]
+        .into_iter()
+        .chain(grids)
+    {
+        let layout = match arrange(
+            items,
+            &PhotoJoinOptions {
+                direction,
+                ..*options
+            },
+        ) {
+            Ok(layout) => layout,
+            Err(_) => continue,
+        };
+        // Comparing on a log scale makes twice too wide as bad as twice too tall
+        let squareness = (layout.width as f64 / layout.height as f64).ln().abs();
+        let area = layout.width as u64 * layout.height as u64;
+        let better = best
+            .as_ref()
+            .is_none_or(|&(best_squareness, best_area, _)| {
+                squareness < best_squareness || (squareness == best_squareness && area < best_area)
+            });
+        if better {
+            best = Some((squareness, area, direction));
+        }
+    }
+    best.map_or(Direction::Horizontal, |(_, _, direction)| direction)
+}
+
+// Lays the images out at the size the options ask for
+fn arrange(items: &[LayoutItem], options: &PhotoJoinOptions) -> Result<Layout, JoinError> {
+    // Just leave if the images are empty (shouldn't happen basically)
+    if items.is_empty() {
+        return Err(JoinError::NoImagesProvided);
+    }
+    if options.sizing == Sizing::Exact(0) {
+        return Err(JoinError::InvalidSizing);
+    }
+    for (index, item) in items.iter().enumerate() {
+        if item.size.0 == 0 || item.size.1 == 0 {
+            return Err(JoinError::ZeroSizedImage { index });
+        }
+        if !(item.weight > 0.0 && item.weight.is_finite()) {
+            return Err(JoinError::InvalidWeight { index });
+        }
+    }
+
+    // Images without their own border get the default one
+    let items = match options.border {
+        Some(border) => Cow::Owned(
+            items
+                .iter()
+                .map(|item| LayoutItem {
+                    border: item.border.or(Some(border)),
+                    ..*item
+                })
+                .collect(),
         ),
+        None => Cow::Borrowed(items),
+    };
+    // Separators are drawn in the middle of the gaps between images, which are widened to fit
+    let options = &PhotoJoinOptions {
+        spacing: options
+            .spacing
+            .checked_add(options.separator.map_or(0, |separator| separator.width))
+            .ok_or(JoinError::DimensionOverflow)?,
+        ..*options
+    };
+    // The images are laid out in the requested order, and then their placements are put back
+    //  in the order the images were given in
+    let order = options.order.indices(items.len());
+    let ordered = order.iter().map(|&index| items[index]).collect::<Vec<_>>();
+    let mut layout = match options.direction {
+        Direction::Grid { rows, cols } => layout_grid(&ordered, rows, cols, options),
+        Direction::Justified { width } => layout_justified(&ordered, width, options),
+        Direction::Aspect { width, height } => layout_aspect(&ordered, (width, height), options),
+        Direction::Wrap { width, alternate } => layout_wrapped(&ordered, width, alternate, options),
+        Direction::Atlas { size } => layout_atlas(&ordered, size, options),
+        direction => layout_strip(&ordered, direction, options),
+    }?;
+    let mut placements = layout.placements.clone();
+    for (&index, &placement) in order.iter().zip(&layout.placements) {
+        placements[index] = placement;
+    }
+    layout.placements = placements;
+
+    // Push everything in from the edges to make room for the margin
+    if options.margin > 0 {
+        let margin = options.margin;
+        let outer = |size: u32| {
+            margin
+                .checked_mul(2)
+                .and_then(|margins| size.checked_add(margins))
+                .ok_or(JoinError::DimensionOverflow)
+        };
+        layout.width = outer(layout.width)?;
+        layout.height = outer(layout.height)?;
+        let shift = |rect: Rect| Rect {
+            x: rect.x + margin,
+            y: rect.y + margin,
+            ..rect
+        };
+        for placement in &mut layout.placements {
+            placement.target = shift(placement.target);
+            placement.caption = placement.caption.map(shift);
+        }
+        for separator in &mut layout.separators {
+            *separator = shift(*separator);
+        }
+    }
+    Ok(layout)
+}
+
+// The part of the gap between two images (which starts at `start`) that the separator line takes
+//  up, as its start and width. `options` has to have the separator's width added to the spacing
+//  already, like `lay_out` does
+pub(crate) fn separator_span(start: u32, options: &PhotoJoinOptions) -> Option<(u32, u32)> {
+    options.separator.map(|separator| {
+        let offset = (options.spacing - separator.width) / 2;
+        (start + offset, separator.width)
+    })
+}
+
+pub(crate) fn size_by(sizing: Sizing, sizes: impl Iterator<Item = u32>) -> u32 {
+    match sizing {
+        Sizing::ToSmallest => sizes.min().unwrap_or(0),
+        Sizing::ToLargest => sizes.max().unwrap_or(0),
+        Sizing::Exact(size) => size,
+    }
+}
+
+// Lays images out in a single row or column, scaling each one (keeping the aspect ratio) so that
+//  the edges perpendicular to the join direction are all the same size, times their weights
+fn layout_strip(
+    items: &[LayoutItem],
+    direction: Direction,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    // Swap widths and heights around for vertical strips so the rest can pretend it's horizontal
+    let oriented = |(a, b): (u32, u32)| match direction {
+        Direction::Vertical => (b, a),
+        _ => (a, b),
+    };
+    let perpendicular_size = size_by(
+        options.sizing,
+        items.iter().map(|item| oriented(item.size).1),
+    );
+
+    // How much of each image's space its caption takes up along and across the join direction
+    let caption_space = |item: &LayoutItem| {
+        let height = item.caption.map_or(0, |caption| caption.height) as f64;
+        match direction {
+            Direction::Vertical => (height, 0.0),
+            _ => (0.0, height),
+        }
+    };
+    // The exact length of each image (and its caption) in the join direction once scaled to the
+    //  perpendicular size
+    let exact_lengths = items
+        .iter()
+        .map(|item| {
+            let (length, perpendicular) = oriented(item.size);
+            let (along, across) = caption_space(item);
+            let image_size = (perpendicular_size as f64 - across).max(1.0);
+            image_size / perpendicular as f64 * length as f64 + along
+        })
+        .collect::<Vec<_>>();
+    // When cropping, every image gets the same (whole) length so they all end up the same size
+    let crop_length = match options.sizing {
+        Sizing::ToLargest => exact_lengths.iter().copied().fold(0.0, f64::max),
+        _ => exact_lengths.iter().copied().fold(f64::INFINITY, f64::min),
+    }
+    .floor()
+    .max(1.0);
+    let lengths = round_lengths(
+        &items
+            .iter()
+            .zip(exact_lengths)
+            .map(|(item, length)| {
+                let length = match options.fit {
+                    FitMode::Scale => length,
+                    FitMode::Pad(_) => {
+                        length.min(oriented(item.size).0 as f64 + caption_space(item).0)
+                    }
+                    FitMode::CropCenter | FitMode::CropAnchor(_) => crop_length,
+                };
+                length * item.weight as f64
+            })
+            .collect::<Vec<_>>(),
+    );
+    // Scaled images grow (or shrink) along with their weighted space, so the strip has to be as
+    //  wide across as the heaviest image needs. Cropped and padded images fill or pad their space
+    //  instead
+    let across_size = match options.fit {
+        FitMode::Scale => {
+            let heaviest = items.iter().map(|item| item.weight).fold(0.0, f32::max);
+            ((perpendicular_size as f64 * heaviest as f64).round() as u32).max(1)
+        }
+        _ => perpendicular_size,
+    };
+
+    let mut pos = 0u32;
+    let mut separators = Vec::new();
+    let mut previous_length = None;
+    // Where the last image actually ends, which is where the overlap of the next one stops
+    let mut previous_end = 0u32;
+    let placements = items
+        .iter()
+        .zip(lengths)
+        .map(|(item, length)| {
+            // Leave a gap before every image but the first, or move it back over the last image
+            //  when overlapping (never by more than either image is long)
+            if let Some(previous_length) = previous_length {
+                pos = if options.overlap > 0 {
+                    pos - options.overlap.min(previous_length).min(length)
+                } else {
+                    if let Some((start, width)) = separator_span(pos, options) {
+                        let (x, y) = oriented((start, 0));
+                        let (width, height) = oriented((width, across_size));
+                        separators.push(Rect {
+                            x,
+                            y,
+                            width,
+                            height,
+                        });
+                    }
+                    pos.checked_add(options.spacing)
+                        .ok_or(JoinError::DimensionOverflow)?
+                };
+            }
+            previous_length = Some(length);
+
+            let (x, y) = oriented((pos, 0));
+            let (width, height) = oriented((length, across_size));
+            let placement = fit(
+                item,
+                Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                options.fit,
+            );
+            let (start, _) = oriented((placement.target.x, placement.target.y));
+            let (target_length, _) = oriented((placement.target.width, placement.target.height));
+            let placement = Placement {
+                overlap: if options.overlap > 0 {
+                    previous_end.saturating_sub(start).min(target_length)
+                } else {
+                    0
+                },
+                ..placement
+            };
+            previous_end = start + target_length;
+
+            // Accumulate size in the join direction
+            pos = pos
+                .checked_add(length)
+                .ok_or(JoinError::DimensionOverflow)?;
+            Ok(placement)
+        })
+        .collect::<Result<_, _>>()?;
+
+    let (width, height) = oriented((pos, across_size));
+    Ok(Layout {
+        width,
+        height,
+        placements,
+        separators,
+    })
+}
+
+// Rounds lengths to whole pixels so that they add up to the rounded total length, giving the
+//  pixels left over from rounding down to the lengths that lost the most (largest remainder, the
+//  earliest length first when they lost the same). Every length is at least a pixel, the pixels
+//  that takes are taken back from the lengths that lost the least
+pub(crate) fn round_lengths(lengths: &[f64]) -> Vec<u32> {
+    let mut rounded = lengths
+        .iter()
+        .map(|length| (length.floor() as u32).max(1))
+        .collect::<Vec<_>>();
+    let total = lengths.iter().sum::<f64>().round();
+    let mut leftover = total - rounded.iter().map(|&length| length as f64).sum::<f64>();
+
+    let remainders = lengths
+        .iter()
+        .zip(&rounded)
+        .map(|(&length, &rounded)| length - rounded as f64)
+        .collect::<Vec<_>>();
+    let mut by_remainder = (0..lengths.len()).collect::<Vec<_>>();
+    by_remainder.sort_by(|&a, &b| remainders[b].total_cmp(&remainders[a]));
+    for &i in &by_remainder {
+        if leftover < 1.0 {
+            break;
+        }
+        rounded[i] = rounded[i].saturating_add(1);
+        leftover -= 1.0;
+    }
+    for &i in by_remainder.iter().rev() {
+        if leftover > -1.0 {
+            break;
+        }
+        if rounded[i] > 1 {
+            rounded[i] -= 1;
+            leftover += 1.0;
+        }
+    }
+    rounded
+}
+
+// Lays images out in equally sized cells, fitting each image inside its cell
+fn layout_grid(
+    items: &[LayoutItem],
+    rows: u32,
+    cols: u32,
+    options: &PhotoJoinOptions,
+) -> Result<Layout, JoinError> {
+    if cols == 0 {
+        return Err(JoinError::InvalidGrid);
+    }
+    let rows = rows.max((items.len() as u32).div_ceil(cols));
+    let cell_width = size_by(options.sizing, items.iter().map(|item| item.size.0));
+    let cell_height = size_by(options.sizing, items.iter().map(|item| item.size.1));
+    let spacing = options.spacing;
+    let width = grid_length(cols, cell_width, spacing).ok_or(JoinError::DimensionOverflow)?;
+    let height = grid_length(rows, cell_height, spacing).ok_or(JoinError::DimensionOverflow)?;
+
+    let placements = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let (col, row) = (i as u32 % cols, i as u32 / cols);
+            let cell = Rect {
+                x: col * (cell_width + spacing),
+                y: row * (cell_height + spacing),
+                width: cell_width,
+                height: cell_height,
+            };
+            fit(item, cell, options.fit)
+        })
+        .collect();
+
+    // Lines go between every column and every row, even where there are empty cells
+    let mut separators = Vec::new();
+    for col in 1..cols {
+        let gap = col * (cell_width + spacing) - spacing;
+        if let Some((x, line_width)) = separator_span(gap, options) {
+            separators.push(Rect {
+                x,
+                y: 0,
+                width: line_width,
+                height,
+            });
+        }
+    }
+    for row in 1..rows {
+        let gap = row * (cell_height + spacing) - spacing;
+        if let Some((y, line_height)) = separator_span(gap, options) {
+            separators.push(Rect {
+                x: 0,
+                y,
+                width,
+                height: line_height,
+            });
+        }
+    }
+
+    Ok(Layout {
+        width,
+        height,
+        placements,
+        separators,
+    })
+}
+
+// The total length of `count` cells of `cell_size` pixels with `spacing` pixels between each
+fn grid_length(count: u32, cell_size: u32, spacing: u32) -> Option<u32> {
+    count
+        .checked_mul(cell_size)?
+        .checked_add((count - 1).checked_mul(spacing)?)
+}
+
+// Determines how an image is placed into a cell of the output image
+pub(crate) fn fit(item: &LayoutItem, cell: Rect, fit: FitMode) -> Placement {
+    let (w, h) = item.size;
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: w,
+        height: h,
+    };
+
+    // Captions next to the image take a strip off of the cell, but always leave at least a pixel
+    //  for the image
+    let (cell, caption) = match item.caption {
+        Some(space) if space.height > 0 && cell.height > 1 => {
+            let height = space.height.min(cell.height - 1);
+            let rest = cell.height - height;
+            if space.below {
+                let strip = Rect {
+                    y: cell.y + rest,
+                    height,
+                    ..cell
+                };
+                (
+                    Rect {
+                        height: rest,
+                        ..cell
+                    },
+                    Some(strip),
+                )
+            } else {
+                let strip = Rect { height, ..cell };
+                let rest = Rect {
+                    y: cell.y + height,
+                    height: rest,
+                    ..cell
+                };
+                (rest, Some(strip))
+            }
+        }
+        _ => (cell, None),
+    };
+
+    // Borders take up space inside of the cell, but always leave at least a pixel for the image
+    let border = item.border.map(|border| Border {
+        width: border
+            .width
+            .min((cell.width - 1) / 2)
+            .min((cell.height - 1) / 2),
+        ..border
+    });
+    let inset = border.map_or(0, |border| border.width);
+    let cell = Rect {
+        x: cell.x + inset,
+        y: cell.y + inset,
+        width: cell.width - 2 * inset,
+        height: cell.height - 2 * inset,
+    };
+
+    let scale_x = cell.width as f32 / w as f32;
+    let scale_y = cell.height as f32 / h as f32;
+    match fit {
+        // Shrink the image to fit inside of the cell and center it there (or align it, when padding)
+        FitMode::Scale | FitMode::Pad(_) => {
+            let (scale, align) = match fit {
+                FitMode::Pad(align) => (scale_x.min(scale_y).min(1.0), align),
+                _ => (scale_x.min(scale_y), Align::Center),
+            };
+            let align = item.align.unwrap_or(align);
+            let width = ((scale * w as f32).round() as u32).clamp(1, cell.width);
+            let height = ((scale * h as f32).round() as u32).clamp(1, cell.height);
+            Placement {
+                source: full,
+                target: Rect {
+                    x: cell.x + ((cell.width - width) as f32 * align.offset()) as u32,
+                    y: cell.y + ((cell.height - height) as f32 * align.offset()) as u32,
+                    width,
+                    height,
+                },
+                border,
+                caption,
+                overlap: 0,
+            }
+        }
+        // Only use the part of the image that covers the cell once scaled
+        FitMode::CropCenter | FitMode::CropAnchor(_) => {
+            let anchor = match fit {
+                FitMode::CropAnchor(anchor) => anchor,
+                _ => Anchor::Center,
+            };
+            let scale = scale_x.max(scale_y);
+            let width = ((cell.width as f32 / scale).round() as u32).clamp(1, w);
+            let height = ((cell.height as f32 / scale).round() as u32).clamp(1, h);
+            let (x, y) = match item.focus {
+                // The kept part is centered on the focus, as far as the edges of the image allow
+                Some((focus_x, focus_y)) => {
+                    let around = |focus: f32, size: u32, kept: u32| {
+                        (focus * size as f32 - kept as f32 / 2.0)
+                            .round()
+                            .clamp(0.0, (size - kept) as f32) as u32
+                    };
+                    (around(focus_x, w, width), around(focus_y, h, height))
+                }
+                None => {
+                    let (offset_x, offset_y) = anchor.offset();
+                    (
+                        ((w - width) as f32 * offset_x) as u32,
+                        ((h - height) as f32 * offset_y) as u32,
+                    )
+                }
+            };
+            Placement {
+                source: Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                target: cell,
+                border,
+                caption,
+                overlap: 0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn rounded_lengths_add_up_to_the_total() {
+        for lengths in [
+            &[1.5, 1.5, 1.5, 1.5][..],
+            &[0.2, 10.4, 5.4],
+            &[33.3, 33.3, 33.4],
+            &[12.75, 0.5, 7.125, 19.625],
+            &[100.0],
+        ] {
+            let total = lengths.iter().sum::<f64>().round() as u32;
+            assert_eq!(round_lengths(lengths).iter().sum::<u32>(), total);
+        }
+    }
+
+    #[test]
+    fn rounded_lengths_are_never_zero() {
+        let rounded = round_lengths(&[0.0, 0.3, 9.7]);
+        assert_eq!(rounded, [1, 1, 8]);
+        assert!(round_lengths(&[0.1; 8]).iter().all(|&length| length >= 1));
+    }
+
+    #[test]
+    fn leftover_pixels_go_to_the_largest_remainders_first() {
+        assert_eq!(round_lengths(&[1.25, 1.75, 1.5]), [1, 2, 2]);
+        // Ties go to the earliest lengths, every time
+        assert_eq!(round_lengths(&[1.5, 1.5, 1.5, 1.5]), [2, 2, 1, 1]);
+        assert_eq!(round_lengths(&[2.5, 1.5]), [3, 1]);
+    }
+
+    // Solid images of different sizes in their own colors, so where each one was drawn can be
+    //  told apart
+    fn solid_photos() -> (Vec<DynamicImage>, Vec<Rgb<u8>>) {
+        let colors = vec![Rgb([200, 0, 0]), Rgb([0, 200, 0]), Rgb([0, 0, 200])];
+        let photos = [(40, 30), (25, 50), (60, 20)]
+            .iter()
+            .zip(&colors)
+            .map(|(&(width, height), &color)| {
+                DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, color))
+            })
+            .collect();
+        (photos, colors)
+    }
+
+    // Every pixel of the join is the color of the image whose placement covers it, or the
+    //  background where none does
+    fn assert_drawn_where_placed(options: PhotoJoinOptions) {
+        let (photos, colors) = solid_photos();
+        let sizes = photos
+            .iter()
+            .map(|img| img.dimensions())
+            .collect::<Vec<_>>();
+        let layout = compute_layout(&sizes, options).unwrap();
+        let joined = join_photos(&photos, options).unwrap().to_rgb8();
+        assert_eq!(joined.dimensions(), (layout.width, layout.height));
+        for (x, y, &pixel) in joined.enumerate_pixels() {
+            let covering = layout.placements.iter().position(|placement| {
+                let target = placement.target;
+                (target.x..target.x + target.width).contains(&x)
+                    && (target.y..target.y + target.height).contains(&y)
+            });
+            let expected = covering.map_or(options.background.to_rgb(), |index| colors[index]);
+            assert_eq!(pixel, expected, "pixel at {}, {}", x, y);
+        }
+    }
+
+    fn placement_options(direction: Direction) -> PhotoJoinOptions {
+        PhotoJoinOptions {
+            direction,
+            filter: FilterType::Nearest,
+            spacing: 3,
+            margin: 2,
+            background: Rgba([255, 255, 255, 255]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn horizontal_layout_matches_the_join() {
+        assert_drawn_where_placed(placement_options(Direction::Horizontal));
+    }
+
+    #[test]
+    fn vertical_layout_matches_the_join() {
+        assert_drawn_where_placed(PhotoJoinOptions {
+            sizing: Sizing::ToLargest,
+            ..placement_options(Direction::Vertical)
+        });
+    }
+
+    #[test]
+    fn grid_layout_matches_the_join() {
+        assert_drawn_where_placed(PhotoJoinOptions {
+            sizing: Sizing::Exact(32),
+            fit: FitMode::Pad(Align::Center),
+            ..placement_options(Direction::Grid { rows: 2, cols: 2 })
+        });
     }
 }
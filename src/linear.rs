@@ -0,0 +1,186 @@
+use crate::alpha;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
+use std::sync::OnceLock;
+
+// Resizing and blending in linear light, where the channel values are proportional to the
+//  amount of light instead of being gamma encoded like sRGB. Averaging gamma encoded values
+//  darkens edges and fine detail, which is most visible when shrinking high contrast photos.
+//  Linear values are kept as 16 bits since 8 bits loses too much of the dark end
+
+// How many entries the table going back from linear to sRGB has, the linear value is shifted down
+//  to index it
+const TO_SRGB_BITS: u32 = 12;
+
+pub(crate) fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(light: f64) -> f64 {
+    if light <= 0.0031308 {
+        light * 12.92
+    } else {
+        1.055 * light.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_linear_table() -> &'static [u16; 256] {
+    static TABLE: OnceLock<[u16; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0; 256];
+        for (value, linear) in table.iter_mut().enumerate() {
+            *linear = (srgb_to_linear(value as f64 / 255.0) * 65535.0).round() as u16;
+        }
+        table
+    })
+}
+
+fn to_srgb_table() -> &'static [u8] {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let size = 1 << TO_SRGB_BITS;
+        (0..size)
+            .map(|i| {
+                // The middle of the range of linear values that end up at this entry
+                let light = (i as f64 + 0.5) / size as f64;
+                (linear_to_srgb(light) * 255.0).round() as u8
+            })
+            .collect()
+    })
+}
+
+// Tables for 16 bit images, which have an entry for every value both ways. Linear values still
+//  fit in 16 bits, only the darkest few sRGB values share a linear value
+fn deep_table(convert: fn(f64) -> f64) -> Vec<u16> {
+    (0..=u16::MAX)
+        .map(|value| (convert(value as f64 / 65535.0) * 65535.0).round() as u16)
+        .collect()
+}
+
+fn to_linear_deep_table() -> &'static [u16] {
+    static TABLE: OnceLock<Vec<u16>> = OnceLock::new();
+    TABLE.get_or_init(|| deep_table(srgb_to_linear))
+}
+
+fn to_srgb_deep_table() -> &'static [u16] {
+    static TABLE: OnceLock<Vec<u16>> = OnceLock::new();
+    TABLE.get_or_init(|| deep_table(linear_to_srgb))
+}
+
+// Runs the color channels of a 16 bit pixel through a table, alpha is never gamma encoded
+fn convert_deep(pixel: Rgba<u16>, table: &[u16]) -> Rgba<u16> {
+    let [r, g, b, a] = pixel.0;
+    Rgba([table[r as usize], table[g as usize], table[b as usize], a])
+}
+
+fn to_linear(pixel: Rgba<u8>) -> Rgba<u16> {
+    let table = to_linear_table();
+    let [r, g, b, a] = pixel.0;
+    // Alpha isn't gamma encoded, it just needs to be stretched to 16 bits
+    Rgba([
+        table[r as usize],
+        table[g as usize],
+        table[b as usize],
+        a as u16 * 257,
+    ])
+}
+
+fn to_srgb(pixel: Rgba<u16>) -> Rgba<u8> {
+    let table = to_srgb_table();
+    let [r, g, b, a] = pixel.0;
+    let shift = 16 - TO_SRGB_BITS;
+    Rgba([
+        table[(r >> shift) as usize],
+        table[(g >> shift) as usize],
+        table[(b >> shift) as usize],
+        (a / 257) as u8,
+    ])
+}
+
+// Same as `imageops::resize`, but averages the pixels in linear light
+pub(crate) fn resize_linear(
+    img: &impl GenericImageView<Pixel = Rgba<u8>>,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    premultiplied: bool,
+) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let linear = ImageBuffer::from_fn(w, h, |x, y| to_linear(img.get_pixel(x, y)));
+    let resized = alpha::resize(&linear, width, height, filter, premultiplied);
+    ImageBuffer::from_fn(width, height, |x, y| to_srgb(*resized.get_pixel(x, y)))
+}
+
+// Same as `imageops::overlay`, but mixes see-through pixels with what's underneath in linear light
+pub(crate) fn overlay_linear(
+    bottom: &mut DynamicImage,
+    top: &impl GenericImageView<Pixel = Rgba<u8>>,
+    x: u32,
+    y: u32,
+) {
+    let (bottom_width, bottom_height) = bottom.dimensions();
+    let width = top.width().min(bottom_width.saturating_sub(x));
+    let height = top.height().min(bottom_height.saturating_sub(y));
+    for ty in 0..height {
+        for tx in 0..width {
+            let pixel = top.get_pixel(tx, ty);
+            match pixel[3] {
+                0 => {}
+                u8::MAX => bottom.put_pixel(x + tx, y + ty, pixel),
+                _ => {
+                    let mut under = to_linear(bottom.get_pixel(x + tx, y + ty));
+                    under.blend(&to_linear(pixel));
+                    bottom.put_pixel(x + tx, y + ty, to_srgb(under));
+                }
+            }
+        }
+    }
+}
+
+// Same as `resize_linear`, for images with 16 bits per channel
+pub(crate) fn resize_linear_deep(
+    img: &impl GenericImageView<Pixel = Rgba<u16>>,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    premultiplied: bool,
+) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+    let (w, h) = img.dimensions();
+    let (to_linear, to_srgb) = (to_linear_deep_table(), to_srgb_deep_table());
+    let linear = ImageBuffer::from_fn(w, h, |x, y| convert_deep(img.get_pixel(x, y), to_linear));
+    let resized = alpha::resize(&linear, width, height, filter, premultiplied);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        convert_deep(*resized.get_pixel(x, y), to_srgb)
+    })
+}
+
+// Same as `overlay_linear`, for images with 16 bits per channel
+pub(crate) fn overlay_linear_deep(
+    bottom: &mut ImageBuffer<Rgba<u16>, Vec<u16>>,
+    top: &ImageBuffer<Rgba<u16>, Vec<u16>>,
+    x: u32,
+    y: u32,
+) {
+    let (to_linear, to_srgb) = (to_linear_deep_table(), to_srgb_deep_table());
+    let width = top.width().min(bottom.width().saturating_sub(x));
+    let height = top.height().min(bottom.height().saturating_sub(y));
+    for ty in 0..height {
+        for tx in 0..width {
+            let pixel = *top.get_pixel(tx, ty);
+            match pixel[3] {
+                0 => {}
+                u16::MAX => bottom.put_pixel(x + tx, y + ty, pixel),
+                _ => {
+                    let under = bottom.get_pixel_mut(x + tx, y + ty);
+                    let mut mixed = convert_deep(*under, to_linear);
+                    mixed.blend(&convert_deep(pixel, to_linear));
+                    *under = convert_deep(mixed, to_srgb);
+                }
+            }
+        }
+    }
+}
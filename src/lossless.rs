@@ -0,0 +1,1220 @@
+use crate::Direction;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LosslessError {
+    NoImagesProvided,
+    /// Only horizontal and vertical joins can be done losslessly
+    UnsupportedDirection,
+    /// The image at the given index isn't a JPEG that can be joined losslessly (like a
+    /// progressive JPEG), or it's damaged
+    Unsupported {
+        index: usize,
+        reason: &'static str,
+    },
+    /// The image at the given index can't be stitched to the others without re-encoding it
+    Incompatible {
+        index: usize,
+        reason: &'static str,
+    },
+    /// The output would be wider or taller than the 65535 pixels a JPEG can be
+    TooLarge,
+}
+
+impl Display for LosslessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LosslessError::NoImagesProvided => write!(f, "no images provided"),
+            LosslessError::UnsupportedDirection => {
+                write!(f, "only horizontal and vertical joins can be lossless")
+            }
+            LosslessError::Unsupported { index, reason } => {
+                write!(f, "image {} can't be joined losslessly: {}", index, reason)
+            }
+            LosslessError::Incompatible { index, reason } => write!(
+                f,
+                "image {} can't be joined losslessly with the others: {}",
+                index, reason
+            ),
+            LosslessError::TooLarge => write!(f, "output image would be too large for a JPEG"),
+        }
+    }
+}
+
+impl Error for LosslessError {}
+
+/// Joins JPEG images in a row or a column by stitching their compressed blocks together (like
+/// jpegtran does) instead of decoding and re-encoding them, so not a single pixel changes. Only
+/// baseline JPEGs can be joined, and they all have to have the same color channels, chroma
+/// subsampling, and quantization tables (which photos from the same camera at the same quality
+/// setting do). Images in a row have to be the same height, and every one but the last has to be
+/// a multiple of 8 or 16 pixels wide (the width of the blocks, which can't be cut apart); columns
+/// work the same way the other way around. Nothing is resized or drawn between the images, EXIF
+/// orientations aren't applied (with the `exif` feature, rotated images are refused), and the
+/// output keeps the JFIF, ICC profile, and Adobe segments of the first image
+pub fn join_jpegs_lossless(
+    jpegs: &[impl AsRef<[u8]>],
+    direction: Direction,
+) -> Result<Vec<u8>, LosslessError> {
+    let horizontal = match direction {
+        Direction::Horizontal => true,
+        Direction::Vertical => false,
+        _ => return Err(LosslessError::UnsupportedDirection),
+    };
+    let images = jpegs
+        .iter()
+        .enumerate()
+        .map(|(index, jpeg)| {
+            check_orientation(jpeg.as_ref())
+                .and_then(|()| Jpeg::parse(jpeg.as_ref()))
+                .map_err(|reason| LosslessError::Unsupported { index, reason })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = images.first().ok_or(LosslessError::NoImagesProvided)?;
+
+    // Each image has to line up with the first one, and end on a block boundary if another image
+    //  comes after it
+    let (mcu_width, mcu_height) = first.mcu_size();
+    for (index, img) in images.iter().enumerate() {
+        let incompatible = |reason| LosslessError::Incompatible { index, reason };
+        let same_sampling = img.components.len() == first.components.len()
+            && img
+                .components
+                .iter()
+                .zip(&first.components)
+                .all(|(a, b)| (a.id, a.h, a.v) == (b.id, b.h, b.v));
+        if !same_sampling {
+            return Err(incompatible(
+                "it has different color channels or chroma subsampling",
+            ));
+        }
+        let same_quantization = img
+            .components
+            .iter()
+            .zip(&first.components)
+            .all(|(a, b)| img.quant[a.tq] == first.quant[b.tq]);
+        if !same_quantization {
+            return Err(incompatible(
+                "it was compressed with different quantization tables (like a different quality)",
+            ));
+        }
+        let last = index + 1 == images.len();
+        if horizontal {
+            if img.height != first.height {
+                return Err(incompatible("it's a different height"));
+            }
+            if !last && img.width % mcu_width != 0 {
+                return Err(incompatible(
+                    "its width isn't a multiple of the block width",
+                ));
+            }
+        } else {
+            if img.width != first.width {
+                return Err(incompatible("it's a different width"));
+            }
+            if !last && img.height % mcu_height != 0 {
+                return Err(incompatible(
+                    "its height isn't a multiple of the block height",
+                ));
+            }
+        }
+    }
+
+    let total = |length: fn(&Jpeg) -> u32| images.iter().map(length).sum::<u32>();
+    let (width, height) = match horizontal {
+        true => (total(|img| img.width), first.height),
+        false => (first.width, total(|img| img.height)),
+    };
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(LosslessError::TooLarge);
+    }
+
+    // Copy the blocks of every image into place in one large grid of blocks for each component
+    let mut joined = Jpeg {
+        width,
+        height,
+        components: first.components.clone(),
+        quant: first.quant,
+        segments: first.segments.clone(),
+        blocks: Vec::new(),
+        block_grids: Vec::new(),
+    };
+    joined.allocate_blocks();
+    let mut offset = 0;
+    for img in &images {
+        for (component, blocks) in joined.blocks.iter_mut().enumerate() {
+            let (grid_width, _) = joined.block_grids[component];
+            let (img_width, img_height) = img.block_grids[component];
+            let (h, v) = (
+                img.components[component].h as usize,
+                img.components[component].v as usize,
+            );
+            let (x, y) = match horizontal {
+                true => (offset * h, 0),
+                false => (0, offset * v),
+            };
+            for row in 0..img_height {
+                let from = &img.blocks[component][row * img_width..][..img_width];
+                blocks[(y + row) * grid_width + x..][..img_width].copy_from_slice(from);
+            }
+        }
+        let (mcus_across, mcus_down) = img.mcu_counts();
+        offset += match horizontal {
+            true => mcus_across,
+            false => mcus_down,
+        };
+    }
+    Ok(joined.encode())
+}
+
+// Refuses images that are meant to be shown turned, since turning them would mean re-encoding
+#[cfg(feature = "exif")]
+fn check_orientation(jpeg: &[u8]) -> Result<(), &'static str> {
+    match crate::orientation::orientation_of(&mut std::io::Cursor::new(jpeg)) {
+        1 => Ok(()),
+        _ => Err("its EXIF orientation turns it, which can't be done without re-encoding it"),
+    }
+}
+
+#[cfg(not(feature = "exif"))]
+fn check_orientation(_: &[u8]) -> Result<(), &'static str> {
+    Ok(())
+}
+
+// The quantized DCT coefficients of an 8x8 block, in zigzag order
+type Block = [i16; 64];
+
+#[derive(Copy, Clone)]
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    // Which quantization table it uses
+    tq: usize,
+}
+
+// A baseline JPEG read down to its blocks of coefficients
+struct Jpeg<'a> {
+    width: u32,
+    height: u32,
+    components: Vec<Component>,
+    // Quantization tables in zigzag order, by their ids
+    quant: [Option<[u16; 64]>; 4],
+    // The whole APP segments (with their markers) that are copied to the output
+    segments: Vec<&'a [u8]>,
+    // The blocks of each component, a row at a time, padded out to whole MCUs
+    blocks: Vec<Vec<Block>>,
+    // How many blocks across and down each component's grid of blocks is
+    block_grids: Vec<(usize, usize)>,
+}
+
+impl<'a> Jpeg<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err("it isn't a JPEG");
+        }
+        let mut jpeg = Jpeg {
+            width: 0,
+            height: 0,
+            components: Vec::new(),
+            quant: [None; 4],
+            segments: Vec::new(),
+            blocks: Vec::new(),
+            block_grids: Vec::new(),
+        };
+        let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+        let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+        let mut restart_interval = 0;
+        let mut scanned = false;
+        let mut pos = 2;
+        loop {
+            // Markers can be padded with any number of 0xFF bytes
+            while data.get(pos) == Some(&0xFF) {
+                pos += 1;
+            }
+            let marker = *data.get(pos).ok_or("it ends too early")?;
+            pos += 1;
+            if data[pos - 2] != 0xFF {
+                return Err("it's damaged");
+            }
+            if marker == 0xD9 {
+                break;
+            }
+            if matches!(marker, 0x01 | 0xD0..=0xD8) {
+                continue;
+            }
+            let length = match data.get(pos..pos + 2) {
+                Some(length) => u16::from_be_bytes([length[0], length[1]]) as usize,
+                None => return Err("it ends too early"),
+            };
+            let segment = data
+                .get(pos + 2..pos + length)
+                .filter(|_| length >= 2)
+                .ok_or("it ends too early")?;
+            match marker {
+                0xC0 | 0xC1 => jpeg.read_frame(segment)?,
+                0xC2 | 0xC6 | 0xCA | 0xCE => return Err("it's a progressive JPEG"),
+                0xC3 | 0xC5 | 0xC7 | 0xCB | 0xCD | 0xCF => {
+                    return Err("it's a lossless or hierarchical JPEG")
+                }
+                0xC9 => return Err("it's compressed with arithmetic coding"),
+                0xC4 => read_huffman_tables(segment, &mut dc_tables, &mut ac_tables)?,
+                0xDB => jpeg.read_quantization_tables(segment)?,
+                0xDD => {
+                    let interval = segment.get(..2).ok_or("it's damaged")?;
+                    restart_interval = u16::from_be_bytes([interval[0], interval[1]]) as usize;
+                }
+                0xDA => {
+                    let mut reader = BitReader::new(data, pos + length);
+                    jpeg.read_scan(
+                        segment,
+                        &dc_tables,
+                        &ac_tables,
+                        restart_interval,
+                        &mut reader,
+                    )?;
+                    scanned = true;
+                    pos = reader.end_of_scan();
+                    continue;
+                }
+                // JFIF, ICC profiles, and Adobe's color transform all say how to read the colors
+                0xE0 | 0xE2 | 0xEE => jpeg.segments.push(&data[pos - 2..pos + length]),
+                _ => {}
+            }
+            pos += length;
+        }
+        if !scanned {
+            return Err("it doesn't have any image data");
+        }
+        Ok(jpeg)
+    }
+
+    fn read_frame(&mut self, segment: &[u8]) -> Result<(), &'static str> {
+        if !self.components.is_empty() {
+            return Err("it has more than one frame");
+        }
+        if segment.len() < 6 {
+            return Err("it's damaged");
+        }
+        if segment[0] != 8 {
+            return Err("it has more than 8 bits per channel");
+        }
+        self.height = u16::from_be_bytes([segment[1], segment[2]]) as u32;
+        self.width = u16::from_be_bytes([segment[3], segment[4]]) as u32;
+        if self.width == 0 || self.height == 0 {
+            return Err("its size is missing");
+        }
+        let count = segment[5] as usize;
+        let components = segment.get(6..6 + count * 3).ok_or("it's damaged")?;
+        for component in components.chunks_exact(3) {
+            let (h, v, tq) = (component[1] >> 4, component[1] & 15, component[2] as usize);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || tq > 3 {
+                return Err("it's damaged");
+            }
+            self.components.push(Component {
+                id: component[0],
+                h,
+                v,
+                tq,
+            });
+        }
+        if self.components.is_empty() {
+            return Err("it doesn't have any color channels");
+        }
+        self.allocate_blocks();
+        Ok(())
+    }
+
+    // Makes an empty grid of blocks for each component, as large as its size calls for
+    fn allocate_blocks(&mut self) {
+        let (mcus_across, mcus_down) = self.mcu_counts();
+        self.block_grids = self
+            .components
+            .iter()
+            .map(|c| (mcus_across * c.h as usize, mcus_down * c.v as usize))
+            .collect();
+        self.blocks = self
+            .block_grids
+            .iter()
+            .map(|&(across, down)| vec![[0; 64]; across * down])
+            .collect();
+    }
+
+    fn read_quantization_tables(&mut self, mut segment: &[u8]) -> Result<(), &'static str> {
+        while let [info, rest @ ..] = segment {
+            let (precision, id) = (info >> 4, (info & 15) as usize);
+            let size = if precision == 0 { 64 } else { 128 };
+            if id > 3 || rest.len() < size {
+                return Err("it's damaged");
+            }
+            let mut table = [0; 64];
+            for (index, value) in table.iter_mut().enumerate() {
+                *value = match precision {
+                    0 => rest[index] as u16,
+                    _ => u16::from_be_bytes([rest[index * 2], rest[index * 2 + 1]]),
+                };
+            }
+            self.quant[id] = Some(table);
+            segment = &rest[size..];
+        }
+        Ok(())
+    }
+
+    fn read_scan(
+        &mut self,
+        segment: &[u8],
+        dc_tables: &[Option<Huffman>; 4],
+        ac_tables: &[Option<Huffman>; 4],
+        restart_interval: usize,
+        reader: &mut BitReader,
+    ) -> Result<(), &'static str> {
+        if self.components.is_empty() {
+            return Err("its image data comes before its size");
+        }
+        let count = *segment.first().ok_or("it's damaged")? as usize;
+        let selectors = segment.get(1..1 + count * 2).ok_or("it's damaged")?;
+        // Which component each part of the scan is, and the Huffman tables it's read with
+        let mut scan = Vec::with_capacity(count);
+        for selector in selectors.chunks_exact(2) {
+            let component = self
+                .components
+                .iter()
+                .position(|c| c.id == selector[0])
+                .ok_or("it's damaged")?;
+            let dc = dc_tables[(selector[1] >> 4) as usize & 3]
+                .as_ref()
+                .ok_or("it's damaged")?;
+            let ac = ac_tables[(selector[1] & 15) as usize & 3]
+                .as_ref()
+                .ok_or("it's damaged")?;
+            scan.push((component, dc, ac));
+        }
+        if scan.is_empty() {
+            return Err("it's damaged");
+        }
+
+        let mut tables = vec![None; self.components.len()];
+        for &(component, dc, ac) in &scan {
+            tables[component] = Some((dc, ac));
+        }
+        let mut predictions = vec![0; self.components.len()];
+        let mut mcus = 0;
+        // Reads one MCU, made of the blocks at the given places of the given components
+        let mut read_mcu =
+            |jpeg: &mut Self, reader: &mut BitReader, blocks: &[(usize, usize, usize)]| {
+                if restart_interval > 0 && mcus > 0 && mcus % restart_interval == 0 {
+                    reader.restart();
+                    predictions
+                        .iter_mut()
+                        .for_each(|prediction| *prediction = 0);
+                }
+                mcus += 1;
+                for &(component, x, y) in blocks {
+                    let (dc, ac) = tables[component].ok_or("it's damaged")?;
+                    let (across, _) = jpeg.block_grids[component];
+                    let block = &mut jpeg.blocks[component][y * across + x];
+                    read_block(reader, dc, ac, &mut predictions[component], block)?;
+                }
+                Ok(())
+            };
+
+        if let [(component, ..)] = scan[..] {
+            // A scan of one component goes a block at a time, over only the blocks the image
+            //  covers rather than whole MCUs
+            let (across, down) = self.covered_blocks(component);
+            for y in 0..down {
+                for x in 0..across {
+                    read_mcu(self, reader, &[(component, x, y)])?;
+                }
+            }
+        } else {
+            let (mcus_across, mcus_down) = self.mcu_counts();
+            let mut blocks = Vec::new();
+            for mcu_y in 0..mcus_down {
+                for mcu_x in 0..mcus_across {
+                    blocks.clear();
+                    for &(component, ..) in &scan {
+                        blocks.extend(self.mcu_blocks(component, mcu_x, mcu_y));
+                    }
+                    read_mcu(self, reader, &blocks)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The blocks of a component in the MCU at the given place, in the order they're stored in
+    fn mcu_blocks(
+        &self,
+        component: usize,
+        mcu_x: usize,
+        mcu_y: usize,
+    ) -> impl Iterator<Item = (usize, usize, usize)> {
+        let (h, v) = (
+            self.components[component].h as usize,
+            self.components[component].v as usize,
+        );
+        (0..v).flat_map(move |y| (0..h).map(move |x| (component, mcu_x * h + x, mcu_y * v + y)))
+    }
+
+    // How many blocks across and down the pixels of a component take up, without the padding out
+    //  to whole MCUs
+    fn covered_blocks(&self, component: usize) -> (usize, usize) {
+        let (max_h, max_v) = self.max_sampling();
+        let c = self.components[component];
+        let width = (self.width * c.h as u32).div_ceil(max_h);
+        let height = (self.height * c.v as u32).div_ceil(max_v);
+        (width.div_ceil(8) as usize, height.div_ceil(8) as usize)
+    }
+
+    fn max_sampling(&self) -> (u32, u32) {
+        let max_h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let max_v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (max_h as u32, max_v as u32)
+    }
+
+    // How many pixels across and down each MCU (the smallest group of blocks of every component)
+    //  covers
+    fn mcu_size(&self) -> (u32, u32) {
+        let (max_h, max_v) = self.max_sampling();
+        (max_h * 8, max_v * 8)
+    }
+
+    // How many MCUs across and down the image is
+    fn mcu_counts(&self) -> (usize, usize) {
+        let (width, height) = self.mcu_size();
+        (
+            self.width.div_ceil(width) as usize,
+            self.height.div_ceil(height) as usize,
+        )
+    }
+}
+
+fn read_huffman_tables(
+    mut segment: &[u8],
+    dc_tables: &mut [Option<Huffman>; 4],
+    ac_tables: &mut [Option<Huffman>; 4],
+) -> Result<(), &'static str> {
+    while let [info, rest @ ..] = segment {
+        let (class, id) = (info >> 4, (info & 15) as usize);
+        let counts = rest.get(..16).ok_or("it's damaged")?;
+        let count = counts.iter().map(|&count| count as usize).sum::<usize>();
+        let values = rest.get(16..16 + count).ok_or("it's damaged")?;
+        if class > 1 || id > 3 {
+            return Err("it's damaged");
+        }
+        let table = Some(Huffman::new(counts, values));
+        match class {
+            0 => dc_tables[id] = table,
+            _ => ac_tables[id] = table,
+        }
+        segment = &rest[16 + count..];
+    }
+    Ok(())
+}
+
+// Codes up to this long are looked up in one go
+const LOOKUP_BITS: u32 = 9;
+
+// A Huffman table for reading the symbols of a scan
+struct Huffman {
+    // The largest code of each length (or -1 for none), and what to add to a code of that length
+    //  to get the index of its value
+    max_codes: [i32; 17],
+    offsets: [i32; 17],
+    values: Vec<u8>,
+    // The length and value of the code each `LOOKUP_BITS` bits start with, when it's that short
+    lookup: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    // A table from how many codes there are of each length from 1 to 16, and their values in order
+    fn new(counts: &[u8], values: &[u8]) -> Self {
+        let mut table = Huffman {
+            max_codes: [-1; 17],
+            offsets: [0; 17],
+            values: values.to_vec(),
+            lookup: vec![(0, 0); 1 << LOOKUP_BITS],
+        };
+        let (mut code, mut index) = (0, 0);
+        for (length, &count) in (1..=16).zip(counts) {
+            table.offsets[length] = index as i32 - code;
+            for _ in 0..count {
+                if length <= LOOKUP_BITS as usize {
+                    let shift = LOOKUP_BITS as usize - length;
+                    let start = (code as usize) << shift;
+                    table.lookup[start..start + (1 << shift)].fill((length as u8, values[index]));
+                }
+                code += 1;
+                index += 1;
+            }
+            if count > 0 {
+                table.max_codes[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, &'static str> {
+        let (length, value) = self.lookup[reader.peek(LOOKUP_BITS) as usize];
+        if length > 0 {
+            reader.consume(length as u32);
+            return Ok(value);
+        }
+        let mut code = 0;
+        for length in 1..=16 {
+            code = code << 1 | reader.bits(1) as i32;
+            if code <= self.max_codes[length] {
+                let index = (code + self.offsets[length]) as usize;
+                return self.values.get(index).copied().ok_or("it's damaged");
+            }
+        }
+        Err("it's damaged")
+    }
+}
+
+// Reads the entropy-coded data of a scan a few bits at a time
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    // The next bits to read, starting from the highest
+    buffer: u64,
+    buffered: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos,
+            buffer: 0,
+            buffered: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.buffered <= 56 {
+            let byte = match self.data.get(self.pos) {
+                // 0xFF bytes are followed by a 0 byte so they aren't mistaken for markers. A real
+                //  marker ends the data, and reading past the end gives zeros
+                Some(0xFF) if self.data.get(self.pos + 1) == Some(&0) => {
+                    self.pos += 2;
+                    0xFF
+                }
+                Some(0xFF) | None => 0,
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                }
+            };
+            self.buffer |= (byte as u64) << (56 - self.buffered);
+            self.buffered += 8;
+        }
+    }
+
+    fn peek(&mut self, count: u32) -> u64 {
+        self.fill();
+        self.buffer >> (64 - count)
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.buffer <<= count;
+        self.buffered -= count;
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let bits = self.peek(count) as u32;
+        self.consume(count);
+        bits
+    }
+
+    // Reads a coefficient of `size` bits, where the ones that start with a 0 are negative
+    fn coefficient(&mut self, size: u8) -> i32 {
+        let bits = self.bits(size as u32) as i32;
+        match size {
+            0 => 0,
+            _ if bits < 1 << (size - 1) => bits - (1 << size) + 1,
+            _ => bits,
+        }
+    }
+
+    // Skips the padding and the restart marker at the end of a restart interval
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.buffered = 0;
+        while self.pos + 1 < self.data.len()
+            && !(self.data[self.pos] == 0xFF && matches!(self.data[self.pos + 1], 0xD0..=0xD7))
+        {
+            self.pos += 1;
+        }
+        self.pos = (self.pos + 2).min(self.data.len());
+    }
+
+    // Where the marker after the scan starts
+    fn end_of_scan(&self) -> usize {
+        (self.pos..self.data.len().saturating_sub(1))
+            .find(|&pos| {
+                self.data[pos] == 0xFF && !matches!(self.data[pos + 1], 0x00 | 0xD0..=0xD7 | 0xFF)
+            })
+            .unwrap_or(self.data.len())
+    }
+}
+
+fn read_block(
+    reader: &mut BitReader,
+    dc: &Huffman,
+    ac: &Huffman,
+    prediction: &mut i32,
+    block: &mut Block,
+) -> Result<(), &'static str> {
+    let size = dc.decode(reader)?;
+    if size > 11 {
+        return Err("it's damaged");
+    }
+    *prediction += reader.coefficient(size);
+    block[0] = *prediction as i16;
+    let mut index = 1;
+    while index < 64 {
+        let symbol = ac.decode(reader)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 15);
+        if size == 0 {
+            // 0xF0 skips 16 zeros, and anything else ends the block
+            if run != 15 {
+                break;
+            }
+            index += 16;
+            continue;
+        }
+        index += run;
+        if index > 63 {
+            return Err("it's damaged");
+        }
+        block[index] = reader.coefficient(size) as i16;
+        index += 1;
+    }
+    Ok(())
+}
+
+impl Jpeg<'_> {
+    // Writes the image out as a baseline JPEG with a single scan and Huffman tables made for its
+    //  blocks (the way `jpegtran -optimize` does)
+    fn encode(&self) -> Vec<u8> {
+        let mut frequencies = [[[0; 257]; 2]; 2];
+        self.encode_blocks(&mut |class, table, symbol, _, _| {
+            frequencies[class][table][symbol as usize] += 1
+        });
+        let tables =
+            frequencies.map(|class| class.map(|frequencies| HuffmanCode::new(&frequencies)));
+
+        let mut output = vec![0xFF, 0xD8];
+        for segment in &self.segments {
+            output.extend_from_slice(segment);
+        }
+        let mut table_ids = self.components.iter().map(|c| c.tq).collect::<Vec<_>>();
+        table_ids.sort_unstable();
+        table_ids.dedup();
+        let mut wide = false;
+        for id in table_ids {
+            let table = self.quant[id].unwrap_or([1; 64]);
+            let mut payload = Vec::new();
+            if table.iter().any(|&value| value > 255) {
+                wide = true;
+                payload.push(0x10 | id as u8);
+                payload.extend(table.iter().flat_map(|value| value.to_be_bytes()));
+            } else {
+                payload.push(id as u8);
+                payload.extend(table.iter().map(|&value| value as u8));
+            }
+            write_segment(&mut output, 0xDB, &payload);
+        }
+
+        // Quantization tables with 16 bit values make it an extended JPEG rather than baseline
+        let mut frame = vec![8];
+        frame.extend((self.height as u16).to_be_bytes());
+        frame.extend((self.width as u16).to_be_bytes());
+        frame.push(self.components.len() as u8);
+        for c in &self.components {
+            frame.extend([c.id, c.h << 4 | c.v, c.tq as u8]);
+        }
+        write_segment(&mut output, if wide { 0xC1 } else { 0xC0 }, &frame);
+
+        for (class, class_tables) in tables.iter().enumerate() {
+            for (id, table) in class_tables.iter().enumerate() {
+                if table.values.is_empty() {
+                    continue;
+                }
+                let mut payload = vec![(class << 4 | id) as u8];
+                payload.extend(table.counts);
+                payload.extend(&table.values);
+                write_segment(&mut output, 0xC4, &payload);
+            }
+        }
+
+        let mut scan = vec![self.components.len() as u8];
+        for (index, c) in self.components.iter().enumerate() {
+            let table = table_for(index) as u8;
+            scan.extend([c.id, table << 4 | table]);
+        }
+        scan.extend([0, 63, 0]);
+        write_segment(&mut output, 0xDA, &scan);
+        let mut writer = BitWriter {
+            output: &mut output,
+            buffer: 0,
+            buffered: 0,
+        };
+        self.encode_blocks(&mut |class, table, symbol, bits, size| {
+            let code = &tables[class][table];
+            let symbol = symbol as usize;
+            writer.write(code.codes[symbol] as u32, code.lengths[symbol]);
+            writer.write(bits, size);
+        });
+        writer.flush();
+        output.extend([0xFF, 0xD9]);
+        output
+    }
+
+    // Goes through the symbols of the scan in order, giving `emit` the class (DC or AC) and id of
+    //  the table each is coded with, the symbol, and the extra bits (and how many) that follow it
+    fn encode_blocks(&self, emit: &mut impl FnMut(usize, usize, u8, u32, u8)) {
+        let mut predictions = vec![0; self.components.len()];
+        let mut encode = |component: usize, x: usize, y: usize| {
+            let (across, _) = self.block_grids[component];
+            let block = &self.blocks[component][y * across + x];
+            let table = table_for(component);
+            let difference = block[0] as i32 - predictions[component];
+            predictions[component] = block[0] as i32;
+            let (size, bits) = magnitude(difference);
+            emit(0, table, size, bits, size);
+            let mut run = 0;
+            for &value in &block[1..] {
+                if value == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    emit(1, table, 0xF0, 0, 0);
+                    run -= 16;
+                }
+                let (size, bits) = magnitude(value as i32);
+                emit(1, table, run << 4 | size, bits, size);
+                run = 0;
+            }
+            if run > 0 {
+                emit(1, table, 0x00, 0, 0);
+            }
+        };
+
+        if self.components.len() == 1 {
+            let (across, down) = self.covered_blocks(0);
+            for y in 0..down {
+                for x in 0..across {
+                    encode(0, x, y);
+                }
+            }
+        } else {
+            let (mcus_across, mcus_down) = self.mcu_counts();
+            for mcu_y in 0..mcus_down {
+                for mcu_x in 0..mcus_across {
+                    for component in 0..self.components.len() {
+                        for (component, x, y) in self.mcu_blocks(component, mcu_x, mcu_y) {
+                            encode(component, x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The first component (the brightness of color images) gets Huffman tables of its own, and the
+//  rest share the others
+fn table_for(component: usize) -> usize {
+    (component > 0) as usize
+}
+
+// How many bits a coefficient takes, and those bits (negative numbers are stored one less, so
+//  they start with a 0)
+fn magnitude(value: i32) -> (u8, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    let bits = match value < 0 {
+        true => (value - 1) as u32 & ((1 << size) - 1),
+        false => value as u32,
+    };
+    (size as u8, bits)
+}
+
+// A Huffman table for writing symbols
+struct HuffmanCode {
+    codes: [u16; 256],
+    lengths: [u8; 256],
+    // How many codes there are of each length from 1 to 16, and their symbols in order
+    counts: [u8; 16],
+    values: Vec<u8>,
+}
+
+impl HuffmanCode {
+    // The shortest codes for symbols that come up this often that are at most 16 bits long,
+    //  worked out the way section K.2 of the JPEG spec describes
+    fn new(frequencies: &[u64; 257]) -> Self {
+        let mut code = HuffmanCode {
+            codes: [0; 256],
+            lengths: [0; 256],
+            counts: [0; 16],
+            values: Vec::new(),
+        };
+        if frequencies.iter().all(|&frequency| frequency == 0) {
+            return code;
+        }
+        // A symbol that's never used takes the code of all 1 bits, which isn't allowed
+        let mut frequencies = *frequencies;
+        frequencies[256] = 1;
+        let mut sizes = [0usize; 257];
+        let mut next = [None; 257];
+        // Join the two least common groups of symbols until there's one left, which makes every
+        //  symbol in them a bit longer
+        let least = |frequencies: &[u64; 257], not: Option<usize>| {
+            (0..257)
+                .filter(|&symbol| frequencies[symbol] > 0 && Some(symbol) != not)
+                .min_by_key(|&symbol| (frequencies[symbol], std::cmp::Reverse(symbol)))
+        };
+        while let Some((a, b)) =
+            least(&frequencies, None).and_then(|a| least(&frequencies, Some(a)).map(|b| (a, b)))
+        {
+            frequencies[a] += frequencies[b];
+            frequencies[b] = 0;
+            let mut symbol = a;
+            sizes[symbol] += 1;
+            while let Some(following) = next[symbol] {
+                symbol = following;
+                sizes[symbol] += 1;
+            }
+            next[symbol] = Some(b);
+            let mut symbol = b;
+            sizes[symbol] += 1;
+            while let Some(following) = next[symbol] {
+                symbol = following;
+                sizes[symbol] += 1;
+            }
+        }
+
+        let mut counts = [0usize; 258];
+        for &size in &sizes {
+            if size > 0 {
+                counts[size] += 1;
+            }
+        }
+        // Codes longer than 16 bits are shortened by pairing them with shorter ones
+        for length in (17..counts.len()).rev() {
+            while counts[length] > 0 {
+                let mut shorter = length - 2;
+                while counts[shorter] == 0 {
+                    shorter -= 1;
+                }
+                counts[length] -= 2;
+                counts[length - 1] += 1;
+                counts[shorter + 1] += 2;
+                counts[shorter] -= 1;
+            }
+        }
+        // Then the unused symbol's code (one of the longest) is taken back out
+        if let Some(longest) = (1..=16).rev().find(|&length| counts[length] > 0) {
+            counts[longest] -= 1;
+        }
+
+        for size in 1..sizes.len() {
+            code.values.extend(
+                (0..256)
+                    .filter(|&symbol| sizes[symbol] == size)
+                    .map(|s| s as u8),
+            );
+        }
+        let (mut next_code, mut index) = (0u16, 0);
+        for (length, &count) in (1..=16).zip(&counts[1..]) {
+            code.counts[length - 1] = count as u8;
+            for _ in 0..count {
+                let symbol = code.values[index] as usize;
+                code.codes[symbol] = next_code;
+                code.lengths[symbol] = length as u8;
+                next_code += 1;
+                index += 1;
+            }
+            next_code <<= 1;
+        }
+        code.values.truncate(index);
+        code
+    }
+}
+
+// Writes entropy-coded data, with a 0 byte after every 0xFF byte
+struct BitWriter<'a> {
+    output: &'a mut Vec<u8>,
+    buffer: u32,
+    buffered: u8,
+}
+
+impl BitWriter<'_> {
+    fn write(&mut self, bits: u32, count: u8) {
+        self.buffer = self.buffer << count | (bits & ((1 << count) - 1));
+        self.buffered += count;
+        while self.buffered >= 8 {
+            self.buffered -= 8;
+            let byte = (self.buffer >> self.buffered) as u8;
+            self.output.push(byte);
+            if byte == 0xFF {
+                self.output.push(0);
+            }
+        }
+        self.buffer &= (1 << self.buffered) - 1;
+    }
+
+    // Pads the last byte with 1 bits
+    fn flush(&mut self) {
+        let padding = (8 - self.buffered % 8) % 8;
+        self.write((1 << padding) - 1, padding);
+    }
+}
+
+fn write_segment(output: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    output.extend([0xFF, marker]);
+    output.extend(((payload.len() + 2) as u16).to_be_bytes());
+    output.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{ColorType, GenericImageView, GrayImage, ImageFormat, Luma, Rgb, RgbImage};
+
+    // A baseline JPEG with no chroma subsampling (the only kind the image crate writes) of a
+    //  pattern that's different for every seed
+    fn jpeg(width: u32, height: u32, seed: u8, quality: u8) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([
+                (x * 7 + y * 3) as u8 ^ seed,
+                (y * 11) as u8 + seed,
+                (x * y) as u8,
+            ])
+        });
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, quality)
+            .encode(&img, width, height, ColorType::Rgb8)
+            .unwrap();
+        out
+    }
+
+    // A baseline JPEG with 4:2:0 chroma subsampling, made straight from blocks that only have a
+    //  DC coefficient
+    fn subsampled_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut jpeg = Jpeg {
+            width,
+            height,
+            components: vec![
+                Component {
+                    id: 1,
+                    h: 2,
+                    v: 2,
+                    tq: 0,
+                },
+                Component {
+                    id: 2,
+                    h: 1,
+                    v: 1,
+                    tq: 1,
+                },
+                Component {
+                    id: 3,
+                    h: 1,
+                    v: 1,
+                    tq: 1,
+                },
+            ],
+            quant: [Some([2; 64]), Some([4; 64]), None, None],
+            segments: Vec::new(),
+            blocks: Vec::new(),
+            block_grids: Vec::new(),
+        };
+        jpeg.allocate_blocks();
+        for (component, blocks) in jpeg.blocks.iter_mut().enumerate() {
+            for (index, block) in blocks.iter_mut().enumerate() {
+                block[0] = (index as i16 * 5 + component as i16 * 20) % 100 - 50;
+            }
+        }
+        jpeg.encode()
+    }
+
+    // A grayscale JPEG two blocks wide with a restart marker between them. Each restart interval
+    //  is coded as if it were the start of the image, so both halves are the scan of a JPEG of
+    //  one block
+    fn restarting_jpeg() -> Vec<u8> {
+        let block = GrayImage::from_fn(8, 8, |x, y| Luma([(x * 30 + y * 2) as u8]));
+        let mut one = Vec::new();
+        JpegEncoder::new(&mut one)
+            .encode(&block, 8, 8, ColorType::L8)
+            .unwrap();
+        let sof = find_marker(&one, 0xC0);
+        let sos = find_marker(&one, 0xDA);
+        let scan_start = sos + 2 + u16::from_be_bytes([one[sos + 2], one[sos + 3]]) as usize;
+        let scan = &one[scan_start..one.len() - 2];
+
+        let mut two = one[..sos].to_vec();
+        two[sof + 7..sof + 9].copy_from_slice(&16u16.to_be_bytes());
+        two.extend([0xFF, 0xDD, 0, 4, 0, 1]);
+        two.extend(&one[sos..scan_start]);
+        two.extend(scan);
+        two.extend([0xFF, 0xD0]);
+        two.extend(scan);
+        two.extend([0xFF, 0xD9]);
+        two
+    }
+
+    fn find_marker(jpeg: &[u8], marker: u8) -> usize {
+        jpeg.windows(2).position(|w| w == [0xFF, marker]).unwrap()
+    }
+
+    fn decode(jpeg: &[u8]) -> RgbImage {
+        image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg)
+            .unwrap()
+            .to_rgb8()
+    }
+
+    fn part(img: &RgbImage, x: u32, y: u32, width: u32, height: u32) -> RgbImage {
+        img.view(x, y, width, height).to_image()
+    }
+
+    #[test]
+    fn one_image_round_trips() {
+        let a = jpeg(20, 13, 0, 80);
+        let joined = join_jpegs_lossless(&[&a], Direction::Horizontal).unwrap();
+        assert_eq!(decode(&joined), decode(&a));
+    }
+
+    #[test]
+    fn joins_horizontally_without_changing_pixels() {
+        let (a, b) = (jpeg(16, 16, 0, 80), jpeg(21, 16, 90, 80));
+        let joined = decode(&join_jpegs_lossless(&[&a, &b], Direction::Horizontal).unwrap());
+        assert_eq!(joined.dimensions(), (37, 16));
+        assert_eq!(part(&joined, 0, 0, 16, 16), decode(&a));
+        assert_eq!(part(&joined, 16, 0, 21, 16), decode(&b));
+    }
+
+    #[test]
+    fn joins_vertically_without_changing_pixels() {
+        let (a, b) = (jpeg(19, 24, 0, 80), jpeg(19, 11, 90, 80));
+        let joined = decode(&join_jpegs_lossless(&[&a, &b], Direction::Vertical).unwrap());
+        assert_eq!(joined.dimensions(), (19, 35));
+        assert_eq!(part(&joined, 0, 0, 19, 24), decode(&a));
+        assert_eq!(part(&joined, 0, 24, 19, 11), decode(&b));
+    }
+
+    #[test]
+    fn reads_restart_intervals() {
+        let two = restarting_jpeg();
+        let img = decode(&two);
+        assert_eq!(part(&img, 0, 0, 8, 8), part(&img, 8, 0, 8, 8));
+
+        let joined = decode(&join_jpegs_lossless(&[&two, &two], Direction::Horizontal).unwrap());
+        assert_eq!(joined.dimensions(), (32, 8));
+        assert_eq!(part(&joined, 0, 0, 16, 8), img);
+        assert_eq!(part(&joined, 16, 0, 16, 8), img);
+    }
+
+    #[test]
+    fn subsampled_images_line_up_on_whole_mcus() {
+        // Without subsampling, blocks are 8 pixels wide
+        let (a, b) = (jpeg(24, 16, 0, 80), jpeg(16, 16, 0, 80));
+        assert!(join_jpegs_lossless(&[&a, &b], Direction::Horizontal).is_ok());
+
+        // With 4:2:0 subsampling they're 16 pixels wide, and the blocks are copied as they are
+        let (a, b) = (subsampled_jpeg(32, 16), subsampled_jpeg(24, 16));
+        let joined = join_jpegs_lossless(&[&a, &b], Direction::Horizontal).unwrap();
+        assert_eq!(decode(&joined).dimensions(), (56, 16));
+        let (a, joined) = (Jpeg::parse(&a).unwrap(), Jpeg::parse(&joined).unwrap());
+        for component in 0..3 {
+            let (across, down) = a.block_grids[component];
+            let (joined_across, _) = joined.block_grids[component];
+            for row in 0..down {
+                assert_eq!(
+                    joined.blocks[component][row * joined_across..][..across],
+                    a.blocks[component][row * across..][..across]
+                );
+            }
+        }
+
+        let (a, b) = (subsampled_jpeg(24, 16), subsampled_jpeg(16, 16));
+        assert_eq!(
+            join_jpegs_lossless(&[&a, &b], Direction::Horizontal),
+            Err(LosslessError::Incompatible {
+                index: 0,
+                reason: "its width isn't a multiple of the block width"
+            })
+        );
+        // The last image can end anywhere
+        assert!(join_jpegs_lossless(&[&b, &a], Direction::Horizontal).is_ok());
+    }
+
+    #[test]
+    fn refuses_other_kinds_of_jpeg() {
+        let a = jpeg(16, 16, 0, 80);
+        let sof = find_marker(&a, 0xC0);
+        for (marker, reason) in [
+            (0xC2, "it's a progressive JPEG"),
+            (0xC9, "it's compressed with arithmetic coding"),
+            (0xC3, "it's a lossless or hierarchical JPEG"),
+        ] {
+            let mut b = a.clone();
+            b[sof + 1] = marker;
+            assert_eq!(
+                join_jpegs_lossless(&[&a, &b], Direction::Horizontal),
+                Err(LosslessError::Unsupported { index: 1, reason })
+            );
+        }
+        assert_eq!(
+            join_jpegs_lossless(&[&a[..], b"not a jpeg"], Direction::Horizontal),
+            Err(LosslessError::Unsupported {
+                index: 1,
+                reason: "it isn't a JPEG"
+            })
+        );
+    }
+
+    #[test]
+    fn refuses_images_that_dont_match() {
+        let a = jpeg(16, 16, 0, 80);
+        assert_eq!(
+            join_jpegs_lossless(&[&a, &jpeg(16, 16, 0, 90)], Direction::Horizontal),
+            Err(LosslessError::Incompatible {
+                index: 1,
+                reason:
+                    "it was compressed with different quantization tables (like a different quality)"
+            })
+        );
+        assert_eq!(
+            join_jpegs_lossless(&[&a, &jpeg(16, 8, 0, 80)], Direction::Horizontal),
+            Err(LosslessError::Incompatible {
+                index: 1,
+                reason: "it's a different height"
+            })
+        );
+        assert_eq!(
+            join_jpegs_lossless(&[&a, &subsampled_jpeg(16, 16)], Direction::Vertical),
+            Err(LosslessError::Incompatible {
+                index: 1,
+                reason: "it has different color channels or chroma subsampling"
+            })
+        );
+        assert_eq!(
+            join_jpegs_lossless(&[&a], Direction::Grid { rows: 1, cols: 1 }),
+            Err(LosslessError::UnsupportedDirection)
+        );
+        assert_eq!(
+            join_jpegs_lossless(&[] as &[&[u8]], Direction::Horizontal),
+            Err(LosslessError::NoImagesProvided)
+        );
+    }
+}
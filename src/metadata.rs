@@ -0,0 +1,121 @@
+use crate::container::{insert_jpeg_segment, insert_png_chunk};
+use crate::{encode, EncodeOptions};
+use exif::experimental::Writer;
+use exif::{Field, In, Tag, Value};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageFormat, ImageResult};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+/// A group of EXIF fields that can be copied from a photo to the output image
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MetadataField {
+    /// When the photo was taken and digitized
+    DateTime,
+    /// The make and model of the camera and lens
+    Camera,
+    /// The copyright notice and artist
+    Copyright,
+}
+
+impl MetadataField {
+    pub const ALL: [MetadataField; 3] = [
+        MetadataField::DateTime,
+        MetadataField::Camera,
+        MetadataField::Copyright,
+    ];
+
+    fn tags(self) -> &'static [Tag] {
+        match self {
+            MetadataField::DateTime => {
+                &[Tag::DateTime, Tag::DateTimeOriginal, Tag::DateTimeDigitized]
+            }
+            MetadataField::Camera => &[Tag::Make, Tag::Model, Tag::LensMake, Tag::LensModel],
+            MetadataField::Copyright => &[Tag::Copyright, Tag::Artist],
+        }
+    }
+}
+
+/// Some of the EXIF metadata of a photo, to be written into another image with
+/// [`encode_with_metadata`]
+#[derive(Clone)]
+pub struct Metadata {
+    fields: Vec<Field>,
+}
+
+impl Metadata {
+    /// Reads the given groups of fields from an image file. Files without EXIF metadata (or
+    /// without any of the fields) give empty metadata
+    pub fn read(path: impl AsRef<Path>, fields: &[MetadataField]) -> ImageResult<Self> {
+        let file = File::open(path)?;
+        let exif = match exif::Reader::new().read_from_container(&mut BufReader::new(file)) {
+            Ok(exif) => exif,
+            Err(exif::Error::Io(err)) => return Err(ImageError::IoError(err)),
+            Err(_) => return Ok(Self { fields: Vec::new() }),
+        };
+        let fields = fields
+            .iter()
+            .flat_map(|field| field.tags())
+            .filter_map(|&tag| exif.get_field(tag, In::PRIMARY).cloned())
+            .collect();
+        Ok(Self { fields })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    // The fields encoded as a TIFF structure, which is how EXIF is stored in every format
+    fn encode(&self) -> Result<Vec<u8>, exif::Error> {
+        let mut writer = Writer::new();
+        for field in &self.fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false)?;
+        Ok(tiff.into_inner())
+    }
+}
+
+/// When a photo was taken according to its EXIF metadata, falling back to when it was digitized
+/// or last changed. The date is formatted like `2021:06:30 14:05:00`, so dates sort in time order
+pub fn date_taken(path: impl AsRef<Path>) -> ImageResult<Option<String>> {
+    let metadata = Metadata::read(path, &[MetadataField::DateTime])?;
+    let field = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .iter()
+        .find_map(|&tag| metadata.fields.iter().find(|field| field.tag == tag));
+    Ok(match field.map(|field| &field.value) {
+        Some(Value::Ascii(values)) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).into_owned()),
+        _ => None,
+    })
+}
+
+/// Encodes an image as a JPEG or PNG file with the metadata written into it. Any other format
+/// fails with an unsupported error
+pub fn encode_with_metadata(
+    img: &DynamicImage,
+    format: ImageFormat,
+    metadata: &Metadata,
+    options: &EncodeOptions,
+) -> ImageResult<Vec<u8>> {
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png) {
+        return Err(ImageError::Unsupported(
+            ImageFormatHint::Exact(format).into(),
+        ));
+    }
+    let encoded = encode(img, format, options)?;
+    if metadata.is_empty() {
+        return Ok(encoded);
+    }
+
+    let tiff = metadata
+        .encode()
+        .map_err(|err| ImageError::Encoding(EncodingError::new(format.into(), err)))?;
+    Ok(match format {
+        ImageFormat::Jpeg => insert_jpeg_segment(encoded, 0xE1, &[b"Exif\0\0", &tiff[..]].concat()),
+        _ => insert_png_chunk(encoded, b"eXIf", &tiff),
+    })
+}
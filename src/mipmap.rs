@@ -0,0 +1,69 @@
+use crate::{is_deep, PhotoJoinOptions};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, ImageBuffer};
+
+/// Every level of the mipmap chain of an image, starting with the image itself. Each level is half
+/// as wide and tall as the one before it (rounded down, but never less than a pixel) down to 1x1,
+/// which is what GPUs expect. Levels are shrunk from the one before them with `filter`, and
+/// `FilterType::Triangle` averages each 2x2 block of pixels when the sizes are even
+pub fn mipmaps(img: &DynamicImage, filter: FilterType) -> Vec<DynamicImage> {
+    let mut levels = vec![img.clone()];
+    loop {
+        let (width, height) = levels[levels.len() - 1].dimensions();
+        if width <= 1 && height <= 1 || width == 0 || height == 0 {
+            return levels;
+        }
+        let next =
+            levels[levels.len() - 1].resize_exact((width / 2).max(1), (height / 2).max(1), filter);
+        levels.push(next);
+    }
+}
+
+/// Same as [`mipmaps`], but with every level stacked below the one before it in one image that's
+/// as wide as the first, lined up on the left. The space to the right of the smaller levels is
+/// left transparent
+pub fn mip_strip(img: &DynamicImage, filter: FilterType) -> DynamicImage {
+    let levels = mipmaps(img, filter);
+    let height = levels.iter().map(|level| level.height()).sum();
+    let mut y = 0;
+    if is_deep(img) {
+        let mut strip = ImageBuffer::new(img.width(), height);
+        for level in &levels {
+            imageops::replace(&mut strip, &level.to_rgba16(), 0, y);
+            y += level.height();
+        }
+        DynamicImage::ImageRgba16(strip)
+    } else {
+        let mut strip = ImageBuffer::new(img.width(), height);
+        for level in &levels {
+            imageops::replace(&mut strip, &level.to_rgba8(), 0, y);
+            y += level.height();
+        }
+        DynamicImage::ImageRgba8(strip)
+    }
+}
+
+/// Copies of a joined image at each of `scales` (like 0.5 for half as wide and tall), all resized
+/// from the image itself with the filter `options` would resize to that size with, so the join
+/// doesn't have to be done again for each size. Sizes are rounded, but never less than a pixel
+pub fn output_scales(
+    img: &DynamicImage,
+    scales: &[f64],
+    options: &PhotoJoinOptions,
+) -> Vec<DynamicImage> {
+    let (width, height) = img.dimensions();
+    scales
+        .iter()
+        .map(|&scale| {
+            let size = (
+                ((width as f64 * scale).round() as u32).max(1),
+                ((height as f64 * scale).round() as u32).max(1),
+            );
+            if size == (width, height) || width == 0 || height == 0 {
+                img.clone()
+            } else {
+                img.resize_exact(size.0, size.1, options.filter_for((width, height), size))
+            }
+        })
+        .collect()
+}
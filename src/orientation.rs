@@ -0,0 +1,235 @@
+use crate::Rect;
+use exif::{In, Tag};
+use image::codecs::jpeg::JpegDecoder;
+use image::error::{DecodingError, ImageFormatHint, ParameterError, ParameterErrorKind};
+use image::io::Reader;
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageError, ImageFormat, ImageResult};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::path::Path;
+
+/// Opens an image and turns (or flips) it the way its EXIF orientation says it should be shown,
+/// like photos straight from a phone. Images without an orientation are left as they are, and
+/// HEIC/HEIF images are opened with libheif (with the `heic` feature), which turns them itself
+pub fn load_oriented(path: impl AsRef<Path>) -> ImageResult<DynamicImage> {
+    let path = path.as_ref();
+    #[cfg(feature = "heic")]
+    if crate::heic::is_heic(path) {
+        return crate::heic::load_heic(path);
+    }
+    let img = Reader::open(path)?.decode()?;
+    Ok(apply_orientation(img, read_orientation(path)))
+}
+
+/// Opens an image like [`load_oriented`], shrunk (keeping its aspect ratio) to fit in a square
+/// `max_size` pixels wide. JPEGs are shrunk while they're decoded, which is much faster than
+/// decoding the whole photo, so this is meant for quick previews and thumbnails
+pub fn load_oriented_scaled(path: impl AsRef<Path>, max_size: u32) -> ImageResult<DynamicImage> {
+    let path = path.as_ref();
+    #[cfg(feature = "heic")]
+    if crate::heic::is_heic(path) {
+        return Ok(shrink(crate::heic::load_heic(path)?, max_size));
+    }
+    let img = decode_reduced(path, max_size)?;
+    Ok(shrink(
+        apply_orientation(img, read_orientation(path)),
+        max_size,
+    ))
+}
+
+/// Opens an image like [`load_oriented`], but JPEGs are decoded at 1/2, 1/4, or 1/8 of their size
+/// when that still leaves their longer side at least `min_size` pixels long. That's much faster
+/// than decoding a whole photo only to shrink it (like for the thumbnails of a contact sheet).
+/// Other images are opened at their full size
+pub fn load_oriented_reduced(path: impl AsRef<Path>, min_size: u32) -> ImageResult<DynamicImage> {
+    let path = path.as_ref();
+    #[cfg(feature = "heic")]
+    if crate::heic::is_heic(path) {
+        return crate::heic::load_heic(path);
+    }
+    let img = decode_reduced(path, min_size)?;
+    Ok(apply_orientation(img, read_orientation(path)))
+}
+
+// Decodes an image, shrinking JPEGs while they're decoded as far as they can be without their
+//  longer side getting shorter than `min_size`
+fn decode_reduced(path: &Path, min_size: u32) -> ImageResult<DynamicImage> {
+    let reader = Reader::open(path)?;
+    if reader.format() != Some(ImageFormat::Jpeg) {
+        return reader.decode();
+    }
+    // The decoder only shrinks by 1/2, 1/4, or 1/8, to at least the requested size
+    let mut decoder = JpegDecoder::new(BufReader::new(File::open(path)?))?;
+    let size = min_size.clamp(1, u16::MAX as u32) as u16;
+    decoder.scale(size, size)?;
+    DynamicImage::from_decoder(decoder)
+}
+
+/// Opens the part of an image inside `region` (in the coordinates of the image once
+/// [`load_oriented`] has turned it). PNGs that aren't interlaced are only decoded down to the
+/// bottom of the region, and only the region's pixels are kept, which saves most of the memory
+/// (and some of the time) that cropping a big image to a small part of it takes. Other images are
+/// decoded whole and cropped
+pub fn load_oriented_region(path: impl AsRef<Path>, region: Rect) -> ImageResult<DynamicImage> {
+    let path = path.as_ref();
+    let orientation = read_orientation(path);
+    #[cfg(feature = "heic")]
+    let heic = crate::heic::is_heic(path);
+    #[cfg(not(feature = "heic"))]
+    let heic = false;
+    // Working out which part of a turned image the region is isn't worth it for the few PNGs
+    //  that have an orientation
+    if orientation == 1 && !heic && Reader::open(path)?.format() == Some(ImageFormat::Png) {
+        if let Some(img) = decode_png_rows(path, region)? {
+            return Ok(img);
+        }
+    }
+    let img = load_oriented(path)?;
+    Ok(img.crop_imm(region.x, region.y, region.width, region.height))
+}
+
+// Decodes the rows of a PNG down to the bottom of `region`, keeping only the pixels inside it.
+//  Interlaced PNGs aren't stored a row at a time, so they give `None`
+fn decode_png_rows(path: &Path, region: Rect) -> ImageResult<Option<DynamicImage>> {
+    // The same transformation the image crate decodes PNGs with, so the pixels come out the same
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let info = reader.info();
+    if info.interlaced {
+        return Ok(None);
+    }
+    if region.x + region.width > info.width || region.y + region.height > info.height {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+    let (color, depth) = reader.output_color_type();
+    let sample_bytes = if depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let pixel_bytes = color.samples() * sample_bytes;
+    let columns = region.x as usize * pixel_bytes..(region.x + region.width) as usize * pixel_bytes;
+
+    let mut data = Vec::with_capacity(columns.len() * region.height as usize);
+    for y in 0..region.y + region.height {
+        let row = reader
+            .next_row()
+            .map_err(png_error)?
+            .ok_or_else(|| png_error("the image ended before all of its rows"))?;
+        if y >= region.y {
+            data.extend_from_slice(&row.data()[columns.clone()]);
+        }
+    }
+
+    let (width, height) = (region.width, region.height);
+    let img = if sample_bytes == 2 {
+        // 16 bit samples are stored big endian
+        let data = data
+            .chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+        match color {
+            png::ColorType::Grayscale => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+            }
+            png::ColorType::GrayscaleAlpha => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+            }
+            png::ColorType::Rgb => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+            }
+            _ => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16),
+        }
+    } else {
+        match color {
+            png::ColorType::Grayscale => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+            }
+            png::ColorType::GrayscaleAlpha => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+            }
+            png::ColorType::Rgb => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+            }
+            _ => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+        }
+    };
+    Ok(img)
+}
+
+fn png_error(err: impl Into<Box<dyn Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        err,
+    ))
+}
+
+fn shrink(img: DynamicImage, max_size: u32) -> DynamicImage {
+    if img.width() > max_size || img.height() > max_size {
+        img.thumbnail(max_size, max_size)
+    } else {
+        img
+    }
+}
+
+/// The size of an image once [`load_oriented`] has turned it, without decoding the whole image
+pub fn oriented_dimensions(path: impl AsRef<Path>) -> ImageResult<(u32, u32)> {
+    let path = path.as_ref();
+    #[cfg(feature = "heic")]
+    if crate::heic::is_heic(path) {
+        return crate::heic::heic_dimensions(path);
+    }
+    let (width, height) = image::image_dimensions(path)?;
+    Ok(match read_orientation(path) {
+        // These orientations are turned a quarter of the way around
+        5..=8 => (height, width),
+        _ => (width, height),
+    })
+}
+
+/// Same as [`load_oriented`], but for an image that's already in memory, like one that was
+/// downloaded or uploaded. HEIC/HEIF images can't be opened this way
+pub fn load_oriented_from_memory(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let img = image::load_from_memory(bytes)?;
+    Ok(apply_orientation(
+        img,
+        orientation_of(&mut Cursor::new(bytes)),
+    ))
+}
+
+// The EXIF orientation tag of the image file (from 1 to 8), or 1 (upright) when it's missing or
+//  can't be read
+fn read_orientation(path: &Path) -> u32 {
+    match File::open(path) {
+        Ok(file) => orientation_of(&mut BufReader::new(file)),
+        Err(_) => 1,
+    }
+}
+
+// Same as `read_orientation`, for an image file that's being read
+pub(crate) fn orientation_of(reader: &mut (impl BufRead + Seek)) -> u32 {
+    let exif = exif::Reader::new().read_from_container(reader).ok();
+    exif.as_ref()
+        .and_then(|exif| exif.get_field(Tag::Orientation, In::PRIMARY))
+        .and_then(|field| field.value.get_uint(0))
+        .unwrap_or(1)
+}
+
+// Undoes what the camera did according to the orientation tag, see the EXIF spec for what each
+//  number means
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
@@ -0,0 +1,163 @@
+use crate::encode::{encode, EncodeOptions};
+use crate::streaming::tiff_error;
+use image::{ColorType, DynamicImage, GenericImageView, ImageFormat, ImageResult};
+use std::io::{Cursor, Seek, Write};
+use tiff::encoder::{colortype, TiffEncoder};
+
+/// Formats that hold several images as pages of one file
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PageFormat {
+    Tiff,
+    /// Each page is a JPEG (with the quality from the encode options) the size of the image at 72
+    /// pixels per inch. See-through parts of images lose their alpha
+    Pdf,
+}
+
+/// Writes every image as its own page of a multi-page file, in order. Pages can be different
+/// sizes
+pub fn encode_pages(
+    pages: &[DynamicImage],
+    writer: &mut impl Write,
+    format: PageFormat,
+    options: &EncodeOptions,
+) -> ImageResult<()> {
+    match format {
+        // TIFF files are written out of order, so they're put together in memory first
+        PageFormat::Tiff => {
+            let mut tiff = Cursor::new(Vec::new());
+            let mut encoder = TiffEncoder::new(&mut tiff).map_err(tiff_error)?;
+            for page in pages {
+                write_tiff_page(&mut encoder, page)?;
+            }
+            writer.write_all(tiff.get_ref())?;
+            Ok(())
+        }
+        PageFormat::Pdf => write_pdf(pages, writer, options),
+    }
+}
+
+fn write_tiff_page<W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    page: &DynamicImage,
+) -> ImageResult<()> {
+    let (width, height) = page.dimensions();
+    match page.color() {
+        ColorType::L8 => encoder.write_image::<colortype::Gray8>(width, height, page.as_bytes()),
+        ColorType::L16 => {
+            encoder.write_image::<colortype::Gray16>(width, height, &page.to_luma16().into_raw())
+        }
+        ColorType::Rgb16 => {
+            encoder.write_image::<colortype::RGB16>(width, height, &page.to_rgb16().into_raw())
+        }
+        ColorType::Rgba16 | ColorType::La16 => {
+            encoder.write_image::<colortype::RGBA16>(width, height, &page.to_rgba16().into_raw())
+        }
+        color if color.has_alpha() => {
+            encoder.write_image::<colortype::RGBA8>(width, height, &page.to_rgba8().into_raw())
+        }
+        _ => encoder.write_image::<colortype::RGB8>(width, height, &page.to_rgb8().into_raw()),
+    }
+    .map_err(tiff_error)
+}
+
+// A bare-bones PDF with one image per page. Every page takes three objects (the page, its image,
+//  and the drawing commands that put the image on the page), after the catalog and page tree
+fn write_pdf(
+    pages: &[DynamicImage],
+    writer: &mut impl Write,
+    options: &EncodeOptions,
+) -> ImageResult<()> {
+    let mut pdf = Vec::new();
+    // Where each object starts, for the cross-reference table at the end
+    let mut offsets = Vec::new();
+    pdf.extend_from_slice(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n");
+
+    let kids = (0..pages.len())
+        .map(|index| format!("{} 0 R", 3 + index * 3))
+        .collect::<Vec<_>>()
+        .join(" ");
+    offsets.push(pdf.len());
+    pdf.extend_from_slice(b"1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n");
+    offsets.push(pdf.len());
+    pdf.extend_from_slice(
+        format!(
+            "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
+            kids,
+            pages.len()
+        )
+        .as_bytes(),
+    );
+
+    for (index, page) in pages.iter().enumerate() {
+        let (width, height) = page.dimensions();
+        let id = 3 + index * 3;
+        let (page_image, color_space) = match page.color() {
+            ColorType::L8 | ColorType::L16 | ColorType::La8 | ColorType::La16 => {
+                (DynamicImage::ImageLuma8(page.to_luma8()), "DeviceGray")
+            }
+            _ => (DynamicImage::ImageRgb8(page.to_rgb8()), "DeviceRGB"),
+        };
+        let jpeg = encode(&page_image, ImageFormat::Jpeg, options)?;
+
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>\nendobj\n",
+                id,
+                width,
+                height,
+                id + 1,
+                id + 2
+            )
+            .as_bytes(),
+        );
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+                 /ColorSpace /{} /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+                id + 1,
+                width,
+                height,
+                color_space,
+                jpeg.len()
+            )
+            .as_bytes(),
+        );
+        pdf.extend_from_slice(&jpeg);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n");
+        // Images are drawn into a 1x1 square, so they're scaled up to cover the page
+        let contents = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", width, height);
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Length {} >>\nstream\n{}\nendstream\nendobj\n",
+                id + 2,
+                contents.len(),
+                contents
+            )
+            .as_bytes(),
+        );
+    }
+
+    let xref = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes(),
+    );
+    for offset in offsets.iter() {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            offsets.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+    writer.write_all(&pdf)?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Cleaning up images before they're joined
+
+use crate::linear::{linear_to_srgb, srgb_to_linear};
+use crate::{is_deep, Rect};
+use image::{DynamicImage, GenericImageView, Rgba};
+
+/// How far from a border's color (in each channel) pixels can be for [`autocrop`] to count them
+/// as part of the border by default, which is enough to cover JPEG noise in a flat color
+pub const DEFAULT_AUTOCROP_TOLERANCE: u8 = 16;
+
+/// Trims borders of one color (like the margins of a scan or the bars of a letterboxed frame) off
+/// the edges of an image. See [`autocrop_bounds`] for what counts as a border
+pub fn autocrop(img: &DynamicImage, tolerance: u8) -> DynamicImage {
+    let bounds = autocrop_bounds(img, tolerance);
+    if bounds.width == img.width() && bounds.height == img.height() {
+        return img.clone();
+    }
+    img.crop_imm(bounds.x, bounds.y, bounds.width, bounds.height)
+}
+
+/// The part of an image left once its borders are trimmed off. Each edge is trimmed one row (or
+/// column) at a time for as long as every pixel in it is within `tolerance` in each channel of
+/// the corner that edge starts at, so the edges can have borders of different colors. An image
+/// that's one color all over is left as it is
+pub fn autocrop_bounds(img: &DynamicImage, tolerance: u8) -> Rect {
+    let (width, height) = img.dimensions();
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    if width == 0 || height == 0 {
+        return full;
+    }
+    let close = |pixel: Rgba<u8>, color: Rgba<u8>| {
+        pixel
+            .0
+            .iter()
+            .zip(color.0.iter())
+            .all(|(&a, &b)| a.max(b) - a.min(b) <= tolerance)
+    };
+    let row_is = |y: u32, columns: (u32, u32), color: Rgba<u8>| {
+        (columns.0..columns.1).all(|x| close(img.get_pixel(x, y), color))
+    };
+    let column_is = |x: u32, rows: (u32, u32), color: Rgba<u8>| {
+        (rows.0..rows.1).all(|y| close(img.get_pixel(x, y), color))
+    };
+
+    let mut top = 0;
+    let color = img.get_pixel(0, 0);
+    while top < height && row_is(top, (0, width), color) {
+        top += 1;
+    }
+    if top == height {
+        return full;
+    }
+    let mut bottom = height;
+    let color = img.get_pixel(0, height - 1);
+    while bottom > top + 1 && row_is(bottom - 1, (0, width), color) {
+        bottom -= 1;
+    }
+    // Only the rows that are left are checked for the sides, so a border along the top doesn't
+    //  stop a different colored one along the side from being trimmed
+    let mut left = 0;
+    let color = img.get_pixel(0, top);
+    while left < width - 1 && column_is(left, (top, bottom), color) {
+        left += 1;
+    }
+    let mut right = width;
+    let color = img.get_pixel(width - 1, top);
+    while right > left + 1 && column_is(right - 1, (top, bottom), color) {
+        right -= 1;
+    }
+    Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+/// The average lightness and color of an image and how much they vary, in the Lab color space
+/// (where the distances between colors are about how different they look)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColorStats {
+    /// The average L, a, and b of the pixels
+    pub mean: [f32; 3],
+    /// The standard deviation of L, a, and b
+    pub deviation: [f32; 3],
+}
+
+// Stats are measured on a copy at most this many pixels wide and tall, which gives about the same
+//  numbers as the whole image
+const STATS_SIZE: u32 = 256;
+// How much `match_colors` can stretch (or squash) the spread of each channel, so the noise in a
+//  nearly flat image isn't blown up to match a busy one
+const MAX_STRETCH: f32 = 2.0;
+
+impl ColorStats {
+    /// Measures an image, leaving out pixels that are completely see-through
+    pub fn of(img: &DynamicImage) -> Self {
+        let small = img.thumbnail(STATS_SIZE, STATS_SIZE).to_rgba8();
+        let labs = small
+            .pixels()
+            .filter(|pixel| pixel[3] > 0)
+            .map(|pixel| to_lab([pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0)))
+            .collect::<Vec<_>>();
+        let count = labs.len().max(1) as f32;
+        let mean =
+            [0, 1, 2].map(|channel| labs.iter().map(|lab| lab[channel]).sum::<f32>() / count);
+        let deviation = [0, 1, 2].map(|channel| {
+            let variance = labs
+                .iter()
+                .map(|lab| (lab[channel] - mean[channel]).powi(2))
+                .sum::<f32>();
+            (variance / count).sqrt()
+        });
+        Self { mean, deviation }
+    }
+}
+
+/// Shifts and stretches the lightness and colors of an image so their average and spread match
+/// `reference` (a color transfer in Lab space), which makes photos taken under different light
+/// look like they belong together. The spread of each channel is stretched or squashed by at most
+/// 2 times
+pub fn match_colors(img: &DynamicImage, reference: &ColorStats) -> DynamicImage {
+    let stats = ColorStats::of(img);
+    let scale = [0, 1, 2].map(|channel| match stats.deviation[channel] {
+        deviation if deviation > f32::EPSILON => {
+            (reference.deviation[channel] / deviation).clamp(1.0 / MAX_STRETCH, MAX_STRETCH)
+        }
+        _ => 1.0,
+    });
+    let transfer = |rgb: [f32; 3]| {
+        let lab = to_lab(rgb);
+        from_lab([0, 1, 2].map(|channel| {
+            (lab[channel] - stats.mean[channel]) * scale[channel] + reference.mean[channel]
+        }))
+    };
+
+    map_colors(img, transfer)
+}
+
+/// Simple touch-ups for an image, which work like the CSS filters of the same names (on the sRGB
+/// values, in this order). The default changes nothing
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Adjustments {
+    /// Multiplies every channel, so 0 is black and 1.5 is half again as bright
+    pub brightness: f32,
+    /// Pushes every channel away from (or toward, below 1) the middle gray
+    pub contrast: f32,
+    /// Pushes colors away from (or toward, below 1) the gray of the same lightness
+    pub saturation: f32,
+    /// Turns the image gray, after the other adjustments
+    pub grayscale: bool,
+    /// Gives the image the brown tint of an old photo, after the other adjustments
+    pub sepia: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self {
+            brightness: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            grayscale: false,
+            sepia: false,
+        }
+    }
+}
+
+impl Adjustments {
+    /// Whether these adjustments leave images as they are
+    pub fn is_none(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Adjusts one sRGB color (with channels from 0 to 1)
+    fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let rgb = rgb
+            .map(|c| (c * self.brightness).clamp(0.0, 1.0))
+            .map(|c| ((c - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0));
+        let saturation = if self.grayscale { 0.0 } else { self.saturation };
+        let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+        let [r, g, b] = rgb.map(|c| luma + (c - luma) * saturation);
+        let rgb = match self.sepia {
+            true => [
+                0.393 * r + 0.769 * g + 0.189 * b,
+                0.349 * r + 0.686 * g + 0.168 * b,
+                0.272 * r + 0.534 * g + 0.131 * b,
+            ],
+            false => [r, g, b],
+        };
+        rgb.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+/// Applies [`Adjustments`] to an image. Transparent parts stay as transparent as they were
+pub fn adjust(img: &DynamicImage, adjustments: &Adjustments) -> DynamicImage {
+    if adjustments.is_none() {
+        return img.clone();
+    }
+    map_colors(img, |rgb| adjustments.apply(rgb))
+}
+
+// Changes the color of every pixel that isn't completely see-through with `transform`, which takes
+//  and gives sRGB colors with channels from 0 to 1. Images with 16 bits per channel keep them
+fn map_colors(img: &DynamicImage, transform: impl Fn([f32; 3]) -> [f32; 3]) -> DynamicImage {
+    if is_deep(img) {
+        let mut buffer = img.to_rgba16();
+        for pixel in buffer.pixels_mut().filter(|pixel| pixel[3] > 0) {
+            let rgb = transform([pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 65535.0));
+            for (channel, value) in pixel.0.iter_mut().zip(&rgb) {
+                *channel = (value * 65535.0).round() as u16;
+            }
+        }
+        DynamicImage::ImageRgba16(buffer)
+    } else {
+        let mut buffer = img.to_rgba8();
+        for pixel in buffer.pixels_mut().filter(|pixel| pixel[3] > 0) {
+            let rgb = transform([pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0));
+            for (channel, value) in pixel.0.iter_mut().zip(&rgb) {
+                *channel = (value * 255.0).round() as u8;
+            }
+        }
+        DynamicImage::ImageRgba8(buffer)
+    }
+}
+
+// The D65 white point that sRGB uses, in XYZ
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+// Converts an sRGB color (with channels from 0 to 1) to Lab
+fn to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|c| srgb_to_linear(c as f64));
+    let xyz = [
+        0.4124 * r + 0.3576 * g + 0.1805 * b,
+        0.2126 * r + 0.7152 * g + 0.0722 * b,
+        0.0193 * r + 0.1192 * g + 0.9505 * b,
+    ];
+    let f = |t: f64| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let [x, y, z] = [0, 1, 2].map(|i| f(xyz[i] / WHITE[i]));
+    [
+        (116.0 * y - 16.0) as f32,
+        (500.0 * (x - y)) as f32,
+        (200.0 * (y - z)) as f32,
+    ]
+}
+
+// Converts a Lab color back to sRGB, with colors that sRGB can't show clipped to its edges
+fn from_lab(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab.map(|c| c as f64);
+    let y = (l + 16.0) / 116.0;
+    let f = [y + a / 500.0, y, y - b / 200.0];
+    let inverse = |t: f64| {
+        if t > 0.206893 {
+            t.powi(3)
+        } else {
+            (t - 16.0 / 116.0) / 7.787
+        }
+    };
+    let [x, y, z] = [0, 1, 2].map(|i| inverse(f[i]) * WHITE[i]);
+    let linear = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ];
+    linear.map(|c| linear_to_srgb(c.clamp(0.0, 1.0)) as f32)
+}
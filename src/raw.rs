@@ -0,0 +1,99 @@
+use crate::{join_photos, JoinError, PhotoJoinOptions};
+use image::{DynamicImage, ImageBuffer};
+
+/// The order of the channels of each pixel of a [`RawImage`], with 8 bits per channel
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PixelLayout {
+    Rgb,
+    Rgba,
+    /// Blue first, like most screen captures on Windows and macOS
+    Bgr,
+    Bgra,
+}
+
+impl PixelLayout {
+    /// How many bytes each pixel takes up
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelLayout::Rgb | PixelLayout::Bgr => 3,
+            PixelLayout::Rgba | PixelLayout::Bgra => 4,
+        }
+    }
+}
+
+/// Pixels borrowed straight from a buffer, like a frame from a screen capture or a camera SDK.
+/// Each row starts `stride` bytes after the one before it, which can leave padding at the end of
+/// each row
+#[derive(Copy, Clone, Debug)]
+pub struct RawImage<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of one row to the start of the next
+    pub stride: usize,
+    pub layout: PixelLayout,
+}
+
+impl<'a> RawImage<'a> {
+    /// Pixels packed tightly, with no padding at the end of each row
+    pub fn packed(data: &'a [u8], width: u32, height: u32, layout: PixelLayout) -> Self {
+        Self {
+            data,
+            width,
+            height,
+            stride: width as usize * layout.bytes_per_pixel(),
+            layout,
+        }
+    }
+
+    /// Copies the pixels into an image, or gives `None` when the stride is shorter than a row or
+    /// the buffer ends before the last row does
+    pub fn to_image(&self) -> Option<DynamicImage> {
+        let row_len = (self.width as usize).checked_mul(self.layout.bytes_per_pixel())?;
+        if self.stride < row_len {
+            return None;
+        }
+        // The last row doesn't need its padding
+        let len = match self.height {
+            0 => 0,
+            height => (height as usize - 1)
+                .checked_mul(self.stride)?
+                .checked_add(row_len)?,
+        };
+        if self.data.len() < len {
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity(row_len * self.height as usize);
+        for row in (0..self.height as usize).map(|y| &self.data[y * self.stride..][..row_len]) {
+            match self.layout {
+                PixelLayout::Rgb | PixelLayout::Rgba => pixels.extend_from_slice(row),
+                PixelLayout::Bgr | PixelLayout::Bgra => {
+                    let channels = self.layout.bytes_per_pixel();
+                    for pixel in row.chunks_exact(channels) {
+                        pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+                        pixels.extend_from_slice(&pixel[3..]);
+                    }
+                }
+            }
+        }
+        match self.layout {
+            PixelLayout::Rgb | PixelLayout::Bgr => {
+                ImageBuffer::from_raw(self.width, self.height, pixels).map(DynamicImage::ImageRgb8)
+            }
+            PixelLayout::Rgba | PixelLayout::Bgra => {
+                ImageBuffer::from_raw(self.width, self.height, pixels).map(DynamicImage::ImageRgba8)
+            }
+        }
+    }
+}
+
+/// Same as [`join_photos`], but joins pixels straight from buffers (see [`RawImage`])
+pub fn join_raw(images: &[RawImage], options: PhotoJoinOptions) -> Result<DynamicImage, JoinError> {
+    let photos = images
+        .iter()
+        .enumerate()
+        .map(|(index, raw)| raw.to_image().ok_or(JoinError::InvalidBuffer { index }))
+        .collect::<Result<Vec<_>, _>>()?;
+    join_photos(photos, options)
+}
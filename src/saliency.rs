@@ -0,0 +1,65 @@
+use crate::source::Source;
+use crate::{Anchor, FitMode, LayoutItem, PhotoJoinOptions};
+use image::DynamicImage;
+use std::borrow::Cow;
+
+// Detail is measured on a copy at most this many pixels wide and tall, which is plenty to tell
+//  where it is
+pub(crate) const FOCUS_SIZE: u32 = 96;
+
+// Where the detail of an image is centered, as fractions of its width and height, going by a
+//  thumbnail of it that fits in `FOCUS_SIZE`. Detail is how sharply the brightness changes at each
+//  pixel (which is high along edges and in texture), and only the pixels with more than the
+//  average count, so a busy subject stands out from an evenly textured background like sky or
+//  grass
+pub(crate) fn focus_of_thumbnail(thumbnail: &DynamicImage) -> (f32, f32) {
+    let small = thumbnail.to_luma8();
+    let (width, height) = small.dimensions();
+    if width < 3 || height < 3 {
+        return (0.5, 0.5);
+    }
+    let luma = |x: u32, y: u32| small.get_pixel(x, y)[0] as f32;
+    let mut edges = Vec::with_capacity((width * height) as usize);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let strength =
+                (luma(x + 1, y) - luma(x - 1, y)).abs() + (luma(x, y + 1) - luma(x, y - 1)).abs();
+            edges.push((x, y, strength));
+        }
+    }
+    let average = edges.iter().map(|&(_, _, strength)| strength).sum::<f32>() / edges.len() as f32;
+
+    let (mut total, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+    for (x, y, strength) in edges {
+        let weight = (strength - average).max(0.0);
+        total += weight;
+        sum_x += weight * (x as f32 + 0.5);
+        sum_y += weight * (y as f32 + 0.5);
+    }
+    if total <= 0.0 {
+        return (0.5, 0.5);
+    }
+    (sum_x / total / width as f32, sum_y / total / height as f32)
+}
+
+// The items with the focus point of each image filled in when the images are cropped to their
+//  most detailed part (which needs their pixels), unless they were given one
+pub(crate) fn with_focus<'a>(
+    photos: &[impl Source],
+    items: &'a [LayoutItem],
+    options: &PhotoJoinOptions,
+) -> Cow<'a, [LayoutItem]> {
+    if options.fit != FitMode::CropAnchor(Anchor::Smart) {
+        return Cow::Borrowed(items);
+    }
+    Cow::Owned(
+        items
+            .iter()
+            .zip(photos)
+            .map(|(item, photo)| LayoutItem {
+                focus: item.focus.or_else(|| Some(photo.focus_point())),
+                ..*item
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,79 @@
+// (De)serialization for the types from the image crate that don't support serde themselves
+
+pub(crate) mod rgba {
+    use image::Rgba;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // Colors are stored as their `[r, g, b, a]` channels
+    pub(crate) fn serialize<S: Serializer>(
+        color: &Rgba<u8>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        color.0.serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Rgba<u8>, D::Error> {
+        <[u8; 4]>::deserialize(deserializer).map(Rgba)
+    }
+}
+
+pub(crate) mod filter_type {
+    use image::imageops::FilterType;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::{Serialize, Serializer};
+
+    const NAMES: &[&str] = &["nearest", "triangle", "catmull_rom", "gaussian", "lanczos3"];
+
+    // Filters are stored by name, the same names the CLI uses
+    pub(crate) fn serialize<S: Serializer>(
+        filter: &FilterType,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match filter {
+            FilterType::Nearest => "nearest",
+            FilterType::Triangle => "triangle",
+            FilterType::CatmullRom => "catmull_rom",
+            FilterType::Gaussian => "gaussian",
+            FilterType::Lanczos3 => "lanczos3",
+        }
+        .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FilterType, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match name.as_str() {
+            "nearest" => Ok(FilterType::Nearest),
+            "triangle" => Ok(FilterType::Triangle),
+            "catmull_rom" => Ok(FilterType::CatmullRom),
+            "gaussian" => Ok(FilterType::Gaussian),
+            "lanczos3" => Ok(FilterType::Lanczos3),
+            _ => Err(de::Error::unknown_variant(&name, NAMES)),
+        }
+    }
+}
+
+pub(crate) mod option_filter_type {
+    use image::imageops::FilterType;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Filter(#[serde(with = "super::filter_type")] FilterType);
+
+    // Filters that can be left out are stored by name too, or as nothing
+    pub(crate) fn serialize<S: Serializer>(
+        filter: &Option<FilterType>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        filter.map(Filter).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<FilterType>, D::Error> {
+        Ok(Option::<Filter>::deserialize(deserializer)?.map(|Filter(filter)| filter))
+    }
+}
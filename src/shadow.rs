@@ -0,0 +1,85 @@
+use crate::{corner_coverage, Rect};
+use image::{imageops, DynamicImage, GenericImage, GenericImageView, GrayImage, Luma, Pixel, Rgba};
+
+/// A blurred shadow drawn behind every image (and its border). Shadows aren't given any room in
+/// the layout, so the parts that fall outside of the output image are cut off (a margin leaves
+/// room for them)
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shadow {
+    /// How far the shadow is moved right and down from the image (negative moves it left and up)
+    pub offset: (i32, i32),
+    /// How many pixels past the edge of the image the shadow fades out over
+    pub blur: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::rgba"))]
+    pub color: Rgba<u8>,
+    /// How see-through the shadow is, from 0 (invisible) to 1 (as solid as `color`)
+    pub opacity: f32,
+}
+
+impl Shadow {
+    // Where the shadow of the image at `rect` goes as (x, y, width, height), which can be partly
+    //  outside of the output image
+    pub(crate) fn area(&self, rect: Rect) -> (i64, i64, u32, u32) {
+        (
+            rect.x as i64 + self.offset.0 as i64 - self.blur as i64,
+            rect.y as i64 + self.offset.1 as i64 - self.blur as i64,
+            rect.width + 2 * self.blur,
+            rect.height + 2 * self.blur,
+        )
+    }
+}
+
+// The shadow of one image, ready to be drawn
+pub(crate) struct ShadowMask {
+    x: i64,
+    y: i64,
+    // How much of the shadow covers each pixel of its area
+    mask: GrayImage,
+    color: Rgba<u8>,
+}
+
+impl ShadowMask {
+    // The shadow of an image (including its border) at `rect` with corners rounded to `radius`
+    pub(crate) fn new(shadow: &Shadow, rect: Rect, radius: u32) -> Self {
+        let (x, y, width, height) = shadow.area(rect);
+        let mut mask = GrayImage::new(width, height);
+        for my in 0..rect.height {
+            for mx in 0..rect.width {
+                let coverage = corner_coverage(mx, my, rect.width, rect.height, radius);
+                mask.put_pixel(
+                    mx + shadow.blur,
+                    my + shadow.blur,
+                    Luma([(coverage * 255.0).round() as u8]),
+                );
+            }
+        }
+        // Almost all of a gaussian blur is within 3 standard deviations, so that fits the blur
+        //  inside of the padding around the shape
+        if shadow.blur > 0 {
+            mask = imageops::blur(&mask, shadow.blur as f32 / 3.0);
+        }
+
+        let mut color = shadow.color;
+        color[3] = (color[3] as f32 * shadow.opacity.clamp(0.0, 1.0)).round() as u8;
+        Self { x, y, mask, color }
+    }
+
+    // Blends the shadow onto `img`, which only holds the rows of the output image from `top` on
+    pub(crate) fn draw(&self, img: &mut DynamicImage, top: u32) {
+        let (width, height) = img.dimensions();
+        for (mx, my, coverage) in self.mask.enumerate_pixels() {
+            let x = self.x + mx as i64;
+            let y = self.y + my as i64 - top as i64;
+            let inside = x >= 0 && y >= 0 && x < width as i64 && y < height as i64;
+            if !inside || coverage[0] == 0 {
+                continue;
+            }
+            let mut color = self.color;
+            color[3] = (color[3] as u32 * coverage[0] as u32 / 255) as u8;
+            let mut pixel = img.get_pixel(x as u32, y as u32);
+            pixel.blend(&color);
+            img.put_pixel(x as u32, y as u32, pixel);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use image::{imageops, ImageBuffer, Primitive, Rgba};
+
+/// An unsharp mask applied to every image after it's resized, which brings back the crispness that
+/// shrinking an image a lot (especially with the smoother filters) blurs away
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sharpen {
+    /// How much the difference between each pixel and its blurred surroundings is added back, so
+    /// 1 doubles it. 0 leaves the images as they are
+    pub amount: f32,
+    /// How far (the standard deviation of the blur, in pixels) the surroundings of each pixel
+    /// reach, which is about how thick the edges that are sharpened are
+    pub radius: f32,
+    /// How different a pixel has to be from its surroundings (from 0 to 255) before it's
+    /// sharpened, which keeps smooth areas and noise from turning grainy
+    pub threshold: u8,
+}
+
+// Sharpens the colors of a resized image, leaving its alpha channel as it is
+pub(crate) fn sharpen<T: Primitive + 'static>(
+    img: &mut ImageBuffer<Rgba<T>, Vec<T>>,
+    sharpen: &Sharpen,
+) {
+    if sharpen.amount <= 0.0 || sharpen.radius <= 0.0 {
+        return;
+    }
+    let blurred = imageops::blur(img, sharpen.radius);
+    let max = T::max_value().to_f32().unwrap_or(1.0);
+    let threshold = sharpen.threshold as f32 / 255.0 * max;
+    for (pixel, blurred) in img.pixels_mut().zip(blurred.pixels()) {
+        for (channel, soft) in pixel.0.iter_mut().zip(&blurred.0).take(3) {
+            let value = channel.to_f32().unwrap_or_default();
+            let difference = value - soft.to_f32().unwrap_or_default();
+            if difference.abs() > threshold {
+                let sharpened = (value + difference * sharpen.amount).clamp(0.0, max);
+                *channel = T::from(sharpened.round()).unwrap_or_else(T::zero);
+            }
+        }
+    }
+}
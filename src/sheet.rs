@@ -0,0 +1,134 @@
+use crate::{contrasting_color, join_photos, Direction, JoinError, PhotoJoinOptions, Sizing};
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{imageops, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Rgba};
+use std::borrow::Borrow;
+
+/// Fits every image into a square `cell_size` cell of a grid that's as close to square as
+/// possible. The direction and sizing of `options` are ignored, everything else applies as usual
+pub fn contact_sheet<I: Borrow<DynamicImage>>(
+    photos: impl IntoIterator<Item = I>,
+    cell_size: u32,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    let photos = photos.into_iter().collect::<Vec<_>>();
+    join_photos(
+        photos.iter().map(|img| img.borrow()),
+        PhotoJoinOptions {
+            direction: sheet_grid(photos.len()),
+            sizing: Sizing::Exact(cell_size),
+            ..options
+        },
+    )
+}
+
+/// Same as [`contact_sheet`], but writes a label (like the image's filename) under each image
+pub fn contact_sheet_labeled<I: Borrow<DynamicImage>, S: AsRef<str>>(
+    photos: impl IntoIterator<Item = (I, S)>,
+    cell_size: u32,
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    // Each image is fit into its own cell with the label underneath, and then the (now equally
+    //  sized) cells are joined without being resized again
+    let scale = (cell_size / 128).max(1);
+    let label_height = 12 * scale;
+    let cells = photos
+        .into_iter()
+        .map(|(img, label)| {
+            let thumbnail = join_photos(
+                [img],
+                PhotoJoinOptions {
+                    direction: Direction::Grid { rows: 1, cols: 1 },
+                    sizing: Sizing::Exact(cell_size),
+                    max_dimensions: None,
+                    margin: 0,
+                    power_of_two: false,
+                    align_multiple: 0,
+                    ..options
+                },
+            )?;
+            let height = cell_size
+                .checked_add(label_height)
+                .ok_or(JoinError::DimensionOverflow)?;
+            let mut cell = if thumbnail.color().has_alpha() {
+                DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+                    cell_size,
+                    height,
+                    options.background,
+                ))
+            } else {
+                DynamicImage::ImageRgb8(ImageBuffer::from_pixel(
+                    cell_size,
+                    height,
+                    options.background.to_rgb(),
+                ))
+            };
+            imageops::overlay(&mut cell, &thumbnail, 0, 0);
+            draw_label(
+                &mut cell,
+                label.as_ref(),
+                cell_size,
+                scale,
+                options.background,
+            );
+            Ok(cell)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    join_photos(
+        cells.iter(),
+        PhotoJoinOptions {
+            direction: sheet_grid(cells.len()),
+            sizing: Sizing::ToSmallest,
+            border: None,
+            corner_radius: 0,
+            shadow: None,
+            ..options
+        },
+    )
+}
+
+// The grid closest to a square that fits `count` cells
+fn sheet_grid(count: usize) -> Direction {
+    let cols = (count as f64).sqrt().ceil().max(1.0) as u32;
+    Direction::Grid { rows: 0, cols }
+}
+
+// Writes a line of text centered in the strip under the image at `top`, cutting it short when
+//  it's too long
+fn draw_label(img: &mut DynamicImage, text: &str, top: u32, scale: u32, background: Rgba<u8>) {
+    let glyph_size = 8 * scale;
+    let max_chars = (img.width() / glyph_size) as usize;
+    let mut chars = text.chars().collect::<Vec<_>>();
+    if chars.len() > max_chars {
+        chars.truncate(max_chars.saturating_sub(3));
+        chars.extend("...".chars().take(max_chars));
+    }
+
+    let color = contrasting_color(background);
+
+    let left = (img.width() - chars.len() as u32 * glyph_size) / 2;
+    let top = top + 2 * scale;
+    for (i, c) in chars.into_iter().enumerate() {
+        let glyph = match BASIC_FONTS.get(c).or_else(|| LATIN_FONTS.get(c)) {
+            Some(glyph) => glyph,
+            None => BASIC_FONTS.get('?').unwrap_or_default(),
+        };
+        let glyph_left = left + i as u32 * glyph_size;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..8 {
+                if bits & (1 << col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        img.put_pixel(
+                            glyph_left + col * scale + dx,
+                            top + row as u32 * scale + dy,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::saliency::{focus_of_thumbnail, FOCUS_SIZE};
+use crate::{join_sources, JoinError, JoinEvent, PhotoJoinOptions, Rect};
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, RgbImage, Rgba, RgbaImage};
+use std::borrow::Cow;
+
+// What drawing an image into the output needs from it, so images that aren't `DynamicImage`s
+//  (like views into larger images) can be joined without copying them into one first
+pub(crate) trait Source {
+    fn size(&self) -> (u32, u32);
+    fn has_alpha(&self) -> bool;
+    // Whether it has more than 8 bits per channel
+    fn is_deep(&self) -> bool;
+    // Whether every pixel is completely solid, which is true of a lot of images that have an alpha
+    //  channel anyway (like screenshots)
+    fn is_opaque(&self) -> bool;
+    // The part of the image at `rect`, converted
+    fn crop_rgb8(&self, rect: Rect) -> RgbImage;
+    fn crop_rgba8(&self, rect: Rect) -> RgbaImage;
+    fn crop_rgba16(&self, rect: Rect) -> ImageBuffer<Rgba<u16>, Vec<u16>>;
+    // Where the detail of the image is centered, see `focus_of_thumbnail`
+    fn focus_point(&self) -> (f32, f32);
+    // The image itself when it's a `DynamicImage`, so its pixels can be copied without converting
+    //  them when they're already in the format of the output image
+    fn as_dynamic(&self) -> Option<&DynamicImage>;
+}
+
+impl Source for DynamicImage {
+    fn size(&self) -> (u32, u32) {
+        self.dimensions()
+    }
+
+    fn has_alpha(&self) -> bool {
+        self.color().has_alpha()
+    }
+
+    fn is_deep(&self) -> bool {
+        crate::is_deep(self)
+    }
+
+    fn is_opaque(&self) -> bool {
+        match self {
+            DynamicImage::ImageLumaA8(buffer) => buffer.pixels().all(|pixel| pixel[1] == u8::MAX),
+            DynamicImage::ImageRgba8(buffer) => buffer.pixels().all(|pixel| pixel[3] == u8::MAX),
+            DynamicImage::ImageLumaA16(buffer) => buffer.pixels().all(|pixel| pixel[1] == u16::MAX),
+            DynamicImage::ImageRgba16(buffer) => buffer.pixels().all(|pixel| pixel[3] == u16::MAX),
+            img => !img.color().has_alpha(),
+        }
+    }
+
+    fn crop_rgb8(&self, rect: Rect) -> RgbImage {
+        crop_dynamic(self, rect, DynamicImage::to_rgb8)
+    }
+
+    fn crop_rgba8(&self, rect: Rect) -> RgbaImage {
+        crop_dynamic(self, rect, DynamicImage::to_rgba8)
+    }
+
+    fn crop_rgba16(&self, rect: Rect) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+        crop_dynamic(self, rect, DynamicImage::to_rgba16)
+    }
+
+    fn focus_point(&self) -> (f32, f32) {
+        focus_of_thumbnail(&self.thumbnail(FOCUS_SIZE, FOCUS_SIZE))
+    }
+
+    fn as_dynamic(&self) -> Option<&DynamicImage> {
+        Some(self)
+    }
+}
+
+// Converts the part of an image that's drawn with `convert`. Only that part is converted, and an
+//  image that's drawn whole is converted in one go, which is just a copy when it's already in the
+//  format it's converted to
+fn crop_dynamic<T>(img: &DynamicImage, rect: Rect, convert: impl FnOnce(&DynamicImage) -> T) -> T {
+    if (rect.x, rect.y) == (0, 0) && (rect.width, rect.height) == img.dimensions() {
+        convert(img)
+    } else {
+        convert(&img.crop_imm(rect.x, rect.y, rect.width, rect.height))
+    }
+}
+
+impl<T: Source + ?Sized> Source for &T {
+    fn size(&self) -> (u32, u32) {
+        (**self).size()
+    }
+
+    fn has_alpha(&self) -> bool {
+        (**self).has_alpha()
+    }
+
+    fn is_deep(&self) -> bool {
+        (**self).is_deep()
+    }
+
+    fn is_opaque(&self) -> bool {
+        (**self).is_opaque()
+    }
+
+    fn crop_rgb8(&self, rect: Rect) -> RgbImage {
+        (**self).crop_rgb8(rect)
+    }
+
+    fn crop_rgba8(&self, rect: Rect) -> RgbaImage {
+        (**self).crop_rgba8(rect)
+    }
+
+    fn crop_rgba16(&self, rect: Rect) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+        (**self).crop_rgba16(rect)
+    }
+
+    fn focus_point(&self) -> (f32, f32) {
+        (**self).focus_point()
+    }
+
+    fn as_dynamic(&self) -> Option<&DynamicImage> {
+        (**self).as_dynamic()
+    }
+}
+
+impl Source for Cow<'_, DynamicImage> {
+    fn size(&self) -> (u32, u32) {
+        (**self).size()
+    }
+
+    fn has_alpha(&self) -> bool {
+        (**self).has_alpha()
+    }
+
+    fn is_deep(&self) -> bool {
+        (**self).is_deep()
+    }
+
+    fn is_opaque(&self) -> bool {
+        (**self).is_opaque()
+    }
+
+    fn crop_rgb8(&self, rect: Rect) -> RgbImage {
+        (**self).crop_rgb8(rect)
+    }
+
+    fn crop_rgba8(&self, rect: Rect) -> RgbaImage {
+        (**self).crop_rgba8(rect)
+    }
+
+    fn crop_rgba16(&self, rect: Rect) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+        (**self).crop_rgba16(rect)
+    }
+
+    fn focus_point(&self) -> (f32, f32) {
+        (**self).focus_point()
+    }
+
+    fn as_dynamic(&self) -> Option<&DynamicImage> {
+        (**self).as_dynamic()
+    }
+}
+
+// An image that's only read a pixel at a time, like a `SubImage` of a larger image
+struct View<'a, V>(&'a V);
+
+impl<V: GenericImageView<Pixel = Rgba<u8>>> Source for View<'_, V> {
+    fn size(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn has_alpha(&self) -> bool {
+        !self.is_opaque()
+    }
+
+    fn is_deep(&self) -> bool {
+        false
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.0.pixels().all(|(_, _, pixel)| pixel[3] == u8::MAX)
+    }
+
+    fn crop_rgb8(&self, rect: Rect) -> RgbImage {
+        DynamicImage::ImageRgba8(self.crop_rgba8(rect)).into_rgb8()
+    }
+
+    fn crop_rgba8(&self, rect: Rect) -> RgbaImage {
+        ImageBuffer::from_fn(rect.width, rect.height, |x, y| {
+            self.0.get_pixel(rect.x + x, rect.y + y)
+        })
+    }
+
+    fn crop_rgba16(&self, rect: Rect) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+        DynamicImage::ImageRgba8(self.crop_rgba8(rect)).into_rgba16()
+    }
+
+    fn focus_point(&self) -> (f32, f32) {
+        // The same size `DynamicImage::thumbnail` shrinks to, with the longer side fitting
+        let (width, height) = self.0.dimensions();
+        let shorter =
+            |short: u32, long: u32| (short as u64 * FOCUS_SIZE as u64 / long as u64).max(1) as u32;
+        let thumbnail = match width >= height {
+            true => imageops::thumbnail(self.0, FOCUS_SIZE, shorter(height, width)),
+            false => imageops::thumbnail(self.0, shorter(width, height), FOCUS_SIZE),
+        };
+        focus_of_thumbnail(&DynamicImage::ImageRgba8(thumbnail))
+    }
+
+    fn as_dynamic(&self) -> Option<&DynamicImage> {
+        None
+    }
+}
+
+/// Same as [`join_photos`](crate::join_photos), but joins anything that can be read as RGBA
+/// pixels, like a [`SubImage`](image::SubImage) of a larger image or an `RgbaImage`, without
+/// copying each of them into a `DynamicImage` first. Only the part of each image that's drawn is
+/// ever copied
+pub fn join_views<V: GenericImageView<Pixel = Rgba<u8>>>(
+    views: &[V],
+    options: PhotoJoinOptions,
+) -> Result<DynamicImage, JoinError> {
+    join_views_with_progress(views, options, |_| {})
+}
+
+/// Same as [`join_views`], but calls `progress` as each step of the join completes
+pub fn join_views_with_progress<V: GenericImageView<Pixel = Rgba<u8>>>(
+    views: &[V],
+    options: PhotoJoinOptions,
+    progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    join_sources(
+        &views.iter().map(View).collect::<Vec<_>>(),
+        options,
+        progress,
+    )
+}
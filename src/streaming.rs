@@ -0,0 +1,458 @@
+use crate::shadow::ShadowMask;
+use crate::{
+    border_radius, border_rect, fill_rect, lay_out, outer_shape, overlay, render_placement,
+    CompositeMode, JoinError, Layout, LayoutItem, PhotoJoinOptions, Placement, Rect,
+};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{imageops, DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageResult, Pixel};
+use image::{GenericImageView, RgbaImage};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Seek, Write};
+
+// How many rows of the output image are put together at once
+const BAND_HEIGHT: u32 = 64;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub enum StreamFormat {
+    Png,
+    Tiff,
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Join(JoinError),
+    /// Loading one of the images or encoding the output failed
+    Image(ImageError),
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Join(err) => err.fmt(f),
+            StreamError::Image(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for StreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StreamError::Join(err) => Some(err),
+            StreamError::Image(err) => Some(err),
+        }
+    }
+}
+
+impl From<JoinError> for StreamError {
+    fn from(err: JoinError) -> Self {
+        StreamError::Join(err)
+    }
+}
+
+impl From<ImageError> for StreamError {
+    fn from(err: ImageError) -> Self {
+        StreamError::Image(err)
+    }
+}
+
+/// Joins photos straight into an encoded PNG or TIFF file a band of rows at a time, so the full
+/// output image never has to be in memory. Only the sizes of the images are needed up front;
+/// `load` is called for each image (by index) once the output reaches it, and the image is
+/// dropped again once it has been completely written. Returns the size of the output image
+pub fn join_photos_streaming<W: Write + Seek>(
+    sizes: &[(u32, u32)],
+    mut load: impl FnMut(usize) -> ImageResult<DynamicImage>,
+    options: PhotoJoinOptions,
+    format: StreamFormat,
+    output: W,
+) -> Result<(u32, u32), StreamError> {
+    let load = |index: usize, region: Rect| {
+        let img = load(index)?;
+        if img.dimensions() != sizes[index] {
+            return Err(JoinError::ImageSizeMismatch { index }.into());
+        }
+        Ok(crop_region(img, region))
+    };
+    stream(sizes, load, options, format, output)
+}
+
+/// The same as [`join_photos_streaming`], but `load` is also given the part of the image (in the
+/// coordinates of its full size) that ends up in the output, and only has to load that part. When
+/// the images are cropped to fit, that saves decoding (and holding on to) the rest of each one,
+/// see `load_oriented_region`
+pub fn join_photos_streaming_regions<W: Write + Seek>(
+    sizes: &[(u32, u32)],
+    mut load: impl FnMut(usize, Rect) -> ImageResult<DynamicImage>,
+    options: PhotoJoinOptions,
+    format: StreamFormat,
+    output: W,
+) -> Result<(u32, u32), StreamError> {
+    let load = |index: usize, region: Rect| {
+        let img = load(index, region)?;
+        if img.dimensions() != (region.width, region.height) {
+            return Err(JoinError::ImageSizeMismatch { index }.into());
+        }
+        Ok(img)
+    };
+    stream(sizes, load, options, format, output)
+}
+
+// Cuts the part of a whole image that's used out of it
+fn crop_region(img: DynamicImage, region: Rect) -> DynamicImage {
+    if (region.x, region.y) == (0, 0) && (region.width, region.height) == img.dimensions() {
+        img
+    } else {
+        img.crop_imm(region.x, region.y, region.width, region.height)
+    }
+}
+
+fn stream<W: Write + Seek>(
+    sizes: &[(u32, u32)],
+    load: impl FnMut(usize, Rect) -> Result<DynamicImage, StreamError>,
+    options: PhotoJoinOptions,
+    format: StreamFormat,
+    output: W,
+) -> Result<(u32, u32), StreamError> {
+    let items = sizes
+        .iter()
+        .map(|&size| LayoutItem::new(size))
+        .collect::<Vec<_>>();
+    let (layout, options) = lay_out(&items, &options)?;
+    let (width, height) = (layout.width, layout.height);
+
+    // The output only needs an alpha channel if the background is see-through or images replace
+    //  what's underneath them, anything drawn over a solid background is solid too. The images
+    //  aren't loaded yet, so any of them replacing the background might be see-through
+    let has_alpha = options.makes_alpha() || options.composite_mode == CompositeMode::Replace;
+    let bands = Bands {
+        layout,
+        options,
+        has_alpha,
+    };
+
+    match format {
+        StreamFormat::Png => {
+            let mut encoder = png::Encoder::new(output, width, height);
+            encoder.set_color(if has_alpha {
+                png::ColorType::Rgba
+            } else {
+                png::ColorType::Rgb
+            });
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut header = encoder.write_header().map_err(png_error)?;
+            let mut writer = header.stream_writer().map_err(png_error)?;
+            bands.write(load, |band| {
+                writer.write_all(band).map_err(|err| png_error(err.into()))
+            })?;
+            writer.finish().map_err(png_error)?;
+            header.finish().map_err(png_error)?;
+        }
+        StreamFormat::Tiff => {
+            let mut encoder = tiff::encoder::TiffEncoder::new(output).map_err(tiff_error)?;
+            if has_alpha {
+                let mut image = encoder
+                    .new_image::<tiff::encoder::colortype::RGBA8>(width, height)
+                    .map_err(tiff_error)?;
+                image.rows_per_strip(BAND_HEIGHT).map_err(tiff_error)?;
+                bands.write(load, |band| image.write_strip(band).map_err(tiff_error))?;
+                image.finish().map_err(tiff_error)?;
+            } else {
+                let mut image = encoder
+                    .new_image::<tiff::encoder::colortype::RGB8>(width, height)
+                    .map_err(tiff_error)?;
+                image.rows_per_strip(BAND_HEIGHT).map_err(tiff_error)?;
+                bands.write(load, |band| image.write_strip(band).map_err(tiff_error))?;
+                image.finish().map_err(tiff_error)?;
+            }
+        }
+    }
+
+    Ok((width, height))
+}
+
+/// Roughly how much memory (in bytes) a join takes at its peak, see [`estimate_memory`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MemoryEstimate {
+    /// Joining with every image and the whole output image in memory at once, like
+    /// [`join_photos`](crate::join_photos) does
+    pub in_memory: u64,
+    /// Joining with [`join_photos_streaming_regions`], which only keeps the images that are being
+    /// written (and a band of the output) in memory
+    pub streaming: u64,
+}
+
+/// Works out about how much memory joining images of the given sizes takes, without touching any
+/// image data, so a join that's too big for the machine can be written while joining instead (or
+/// not started at all). Every image is counted at 4 bytes per pixel, so images with 16 bits per
+/// channel take about twice as much
+pub fn estimate_memory(
+    sizes: &[(u32, u32)],
+    options: PhotoJoinOptions,
+) -> Result<MemoryEstimate, JoinError> {
+    let items = sizes
+        .iter()
+        .map(|&size| LayoutItem::new(size))
+        .collect::<Vec<_>>();
+    let (layout, options) = lay_out(&items, &options)?;
+    let bytes = |width: u32, height: u32| width as u64 * height as u64 * 4;
+    // Drawing an image takes a copy of the part that's used along with the resized image
+    let drawing = |placement: &Placement| {
+        let (source, target) = (placement.source, placement.target);
+        bytes(source.width, source.height) + bytes(target.width, target.height)
+    };
+    let largest_drawing = layout.placements.iter().map(drawing).max().unwrap_or(0);
+
+    let in_memory = sizes
+        .iter()
+        .map(|&(width, height)| bytes(width, height))
+        .sum::<u64>()
+        + bytes(layout.width, layout.height)
+        + largest_drawing;
+
+    // Images are kept (resized) from the band they start in until the one they end in, and the
+    //  image being loaded is only the part that's used
+    let mut most_active = 0;
+    let mut band_y = 0;
+    while band_y < layout.height {
+        let band_end = (band_y + BAND_HEIGHT).min(layout.height);
+        let active = layout
+            .placements
+            .iter()
+            .filter(|placement| {
+                let (top, bottom) = covered_rows(placement, &options);
+                top < band_end as i64 && bottom > band_y as i64
+            })
+            .map(|placement| bytes(placement.target.width, placement.target.height))
+            .sum::<u64>();
+        most_active = most_active.max(active);
+        band_y = band_end;
+    }
+    let streaming = bytes(layout.width, BAND_HEIGHT) + most_active + largest_drawing;
+
+    Ok(MemoryEstimate {
+        in_memory,
+        streaming,
+    })
+}
+
+// Puts the output image together one band of rows at a time
+struct Bands {
+    layout: Layout,
+    options: PhotoJoinOptions,
+    has_alpha: bool,
+}
+
+impl Bands {
+    fn write(
+        self,
+        mut load: impl FnMut(usize, Rect) -> Result<DynamicImage, StreamError>,
+        mut write_band: impl FnMut(&[u8]) -> ImageResult<()>,
+    ) -> Result<(), StreamError> {
+        let Layout {
+            width,
+            height,
+            placements,
+            separators,
+        } = self.layout;
+        let options = self.options;
+
+        // Images that have been loaded and resized (along with their shadows) but not completely
+        //  written yet
+        let mut active: Vec<(usize, RgbaImage, Option<ShadowMask>)> = Vec::new();
+        // Images are loaded from the top down, and ones that start on the same row are loaded
+        //  (and drawn) in the order they were laid out in
+        let mut by_top = options.order.indices(placements.len());
+        by_top.sort_by_key(|&index| covered_rows(&placements[index], &options).0);
+        let mut next = 0;
+        let mut band_y = 0;
+        while band_y < height {
+            let band_height = BAND_HEIGHT.min(height - band_y);
+            let band_end = band_y + band_height;
+
+            // Load every image that starts in this band
+            while next < by_top.len()
+                && covered_rows(&placements[by_top[next]], &options).0 < band_end as i64
+            {
+                // Only the part of the image that's used is loaded, so it's drawn as if that
+                //  part were the whole image
+                let index = by_top[next];
+                let source = placements[index].source;
+                let img = load(index, source)?;
+                let placement = Placement {
+                    source: Rect {
+                        x: 0,
+                        y: 0,
+                        ..source
+                    },
+                    ..placements[index]
+                };
+                let size = (source.width, source.height);
+                let resized = render_placement(&img, size, &placement, &options);
+                let shadow = options.shadow.map(|shadow| {
+                    let (rect, radius) = outer_shape(&placements[index], options.corner_radius);
+                    ShadowMask::new(&shadow, rect, radius)
+                });
+                active.push((index, resized, shadow));
+                next += 1;
+            }
+
+            let background = options.background;
+            let mut band = if self.has_alpha {
+                DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, band_height, background))
+            } else {
+                DynamicImage::ImageRgb8(ImageBuffer::from_pixel(
+                    width,
+                    band_height,
+                    background.to_rgb(),
+                ))
+            };
+            for shadow in active.iter().filter_map(|(_, _, shadow)| shadow.as_ref()) {
+                shadow.draw(&mut band, band_y);
+            }
+            if let Some(separator) = options.separator {
+                for &rect in &separators {
+                    fill_rect(&mut band, rect, 0, separator.color, band_y);
+                }
+            }
+            for (index, resized, _) in &active {
+                let placement = &placements[*index];
+                if let Some(border) = placement.border {
+                    let radius = border_radius(options.corner_radius, border);
+                    let rect = border_rect(placement.target, border);
+                    fill_rect(&mut band, rect, radius, border.color, band_y);
+                }
+                if let Some(rect) = band_rows(placement.target, band_y, band_end) {
+                    let rows = imageops::crop_imm(
+                        resized,
+                        0,
+                        rect.y + band_y - placement.target.y,
+                        rect.width,
+                        rect.height,
+                    );
+                    overlay(&mut band, &rows, rect.x, rect.y, &options);
+                }
+            }
+            write_band(band.as_bytes())?;
+
+            // Forget about images that have been completely written
+            active.retain(|(index, _, _)| {
+                covered_rows(&placements[*index], &options).1 > band_end as i64
+            });
+            band_y = band_end;
+        }
+
+        Ok(())
+    }
+}
+
+// The first row an image (including its border and shadow) covers and the row after its last
+fn covered_rows(placement: &Placement, options: &PhotoJoinOptions) -> (i64, i64) {
+    let (rect, _) = outer_shape(placement, options.corner_radius);
+    let (mut top, mut bottom) = (rect.y as i64, rect.y as i64 + rect.height as i64);
+    if let Some(shadow) = options.shadow {
+        let (_, y, _, height) = shadow.area(rect);
+        top = top.min(y);
+        bottom = bottom.max(y + height as i64);
+    }
+    (top, bottom)
+}
+
+// The part of `rect` between rows `start` and `end`, relative to `start`
+fn band_rows(rect: Rect, start: u32, end: u32) -> Option<Rect> {
+    let top = rect.y.max(start);
+    let bottom = (rect.y + rect.height).min(end);
+    if top >= bottom {
+        return None;
+    }
+    Some(Rect {
+        x: rect.x,
+        y: top - start,
+        width: rect.width,
+        height: bottom - top,
+    })
+}
+
+pub(crate) fn png_error(err: png::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        err,
+    ))
+}
+
+pub(crate) fn tiff_error(err: tiff::TiffError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Tiff),
+        err,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{join_photos, Direction};
+    use image::{Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn photos() -> Vec<DynamicImage> {
+        [(40, 30, 0), (30, 50, 100)]
+            .iter()
+            .map(|&(width, height, seed)| {
+                DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                    Rgb([(x * 5) as u8 + seed, (y * 4) as u8, seed])
+                }))
+            })
+            .collect()
+    }
+
+    // Streams the photos into a PNG and decodes it again
+    fn streamed(photos: &[DynamicImage], options: PhotoJoinOptions) -> DynamicImage {
+        let sizes = photos
+            .iter()
+            .map(|img| img.dimensions())
+            .collect::<Vec<_>>();
+        let mut png = Cursor::new(Vec::new());
+        join_photos_streaming(
+            &sizes,
+            |index| Ok(photos[index].clone()),
+            options,
+            StreamFormat::Png,
+            &mut png,
+        )
+        .unwrap();
+        image::load_from_memory_with_format(png.get_ref(), ImageFormat::Png).unwrap()
+    }
+
+    fn assert_streams_like_join(options: PhotoJoinOptions) {
+        let photos = photos();
+        let joined = join_photos(&photos, options).unwrap();
+        let streamed = streamed(&photos, options);
+        assert_eq!(streamed.color(), joined.color());
+        assert_eq!(streamed.dimensions(), joined.dimensions());
+        assert!(streamed.as_bytes() == joined.as_bytes());
+    }
+
+    #[test]
+    fn rounded_corners_over_solid_background_stay_solid() {
+        assert_streams_like_join(PhotoJoinOptions {
+            direction: Direction::Horizontal,
+            spacing: 4,
+            corner_radius: 8,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn replaced_rounded_corners_stay_see_through() {
+        let options = PhotoJoinOptions {
+            direction: Direction::Vertical,
+            spacing: 4,
+            corner_radius: 8,
+            composite_mode: CompositeMode::Replace,
+            ..Default::default()
+        };
+        assert_streams_like_join(options);
+        let corner = streamed(&photos(), options).to_rgba8()[(0, 0)];
+        assert_eq!(corner[3], 0);
+    }
+}
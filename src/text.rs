@@ -0,0 +1,249 @@
+//! Captions and titles drawn with TrueType/OpenType fonts
+
+use crate::{
+    is_deep, lay_out, prepare_items, render, widen, with_focus, Anchor, CaptionSpace, JoinError,
+    JoinEvent, JoinItem, PhotoJoinOptions, Rect,
+};
+use ab_glyph::{point, Font, PxScale, ScaleFont};
+pub use ab_glyph::{FontArc, InvalidFont};
+use image::{imageops, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Rgba};
+
+/// Where captions are drawn relative to their image
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub enum CaptionPosition {
+    /// In a strip under the image, which takes space away from the image
+    Below,
+    /// In a strip above the image, which takes space away from the image
+    Above,
+    /// On top of the image itself, at the given spot
+    Over(Anchor),
+}
+
+/// How captions look
+#[derive(Clone)]
+pub struct CaptionStyle {
+    pub font: FontArc,
+    /// Height of the text in pixels
+    pub size: f32,
+    pub color: Rgba<u8>,
+    pub position: CaptionPosition,
+}
+
+impl CaptionStyle {
+    /// Black text below the images
+    pub fn new(font: FontArc, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: Rgba([0, 0, 0, 255]),
+            position: CaptionPosition::Below,
+        }
+    }
+
+    // How tall a strip of one line of text is, including some padding around it
+    fn line_height(&self) -> u32 {
+        let scaled = self.font.as_scaled(PxScale::from(self.size));
+        (scaled.height() + 2.0 * self.padding()).ceil() as u32
+    }
+
+    fn padding(&self) -> f32 {
+        (self.size / 4.0).round()
+    }
+}
+
+/// Same as [`join_items`](crate::join_items), but also draws the caption of every item that has
+/// one
+pub fn join_items_captioned(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    style: &CaptionStyle,
+) -> Result<DynamicImage, JoinError> {
+    join_items_captioned_with_progress(items, options, style, |_| {})
+}
+
+/// Same as [`join_items_captioned`], but calls `progress` as each step of the join completes
+pub fn join_items_captioned_with_progress(
+    items: Vec<JoinItem>,
+    options: PhotoJoinOptions,
+    style: &CaptionStyle,
+    mut progress: impl FnMut(JoinEvent),
+) -> Result<DynamicImage, JoinError> {
+    let (photos, mut layout_items) = prepare_items(&items)?;
+
+    // Captions next to their images need room in the layout
+    let below = match style.position {
+        CaptionPosition::Below => Some(true),
+        CaptionPosition::Above => Some(false),
+        CaptionPosition::Over(_) => None,
+    };
+    if let Some(below) = below {
+        let height = style.line_height();
+        for (layout_item, item) in layout_items.iter_mut().zip(&items) {
+            if item.caption.is_some() {
+                layout_item.caption = Some(CaptionSpace { height, below });
+            }
+        }
+    }
+
+    let layout_items = with_focus(&photos, &layout_items, &options);
+    let (layout, options) = lay_out(&layout_items, &options)?;
+    let placements = layout.placements.clone();
+    let mut output_img = render(&photos, &layout_items, layout, &options, &mut progress);
+    for (item, placement) in items.iter().zip(placements) {
+        let text = match &item.caption {
+            Some(text) => text,
+            None => continue,
+        };
+        match style.position {
+            CaptionPosition::Over(anchor) => {
+                draw_text(&mut output_img, text, style, placement.target, anchor)
+            }
+            _ => {
+                if let Some(strip) = placement.caption {
+                    draw_text(&mut output_img, text, style, strip, Anchor::Center)
+                }
+            }
+        }
+    }
+    Ok(output_img)
+}
+
+// Draws a line of text inside of `rect` at the spot given by `anchor` (keeping some padding from
+//  the edges), cutting off anything that doesn't fit
+fn draw_text(img: &mut DynamicImage, text: &str, style: &CaptionStyle, rect: Rect, anchor: Anchor) {
+    let scaled = style.font.as_scaled(PxScale::from(style.size));
+
+    // Lay the glyphs out in a row starting at 0,0
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(scaled.scale(), point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    let padding = style.padding();
+    let (offset_x, offset_y) = anchor.offset();
+    let left =
+        rect.x as f32 + padding + (rect.width as f32 - 2.0 * padding - caret).max(0.0) * offset_x;
+    let top = rect.y as f32
+        + padding
+        + (rect.height as f32 - 2.0 * padding - scaled.height()).max(0.0) * offset_y;
+
+    for glyph in glyphs {
+        let outline = match scaled.outline_glyph(glyph) {
+            Some(outline) => outline,
+            None => continue,
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let x = (left + bounds.min.x).round() as i64 + x as i64;
+            let y = (top + bounds.min.y).round() as i64 + y as i64;
+            let inside = x >= rect.x as i64
+                && y >= rect.y as i64
+                && x < (rect.x + rect.width) as i64
+                && y < (rect.y + rect.height) as i64;
+            if !inside {
+                return;
+            }
+            let mut color = style.color;
+            color[3] = (color[3] as f32 * coverage.min(1.0)).round() as u8;
+            let mut pixel = img.get_pixel(x as u32, y as u32);
+            pixel.blend(&color);
+            img.put_pixel(x as u32, y as u32, pixel);
+        });
+    }
+}
+
+/// A line of text in a banner above or below the whole joined image (or over it)
+#[derive(Clone)]
+pub struct TitleOptions {
+    pub text: String,
+    /// How the text looks and where the banner goes, the text is always centered
+    pub style: CaptionStyle,
+    /// Color of the banner behind the text
+    pub background: Rgba<u8>,
+}
+
+/// Adds a title banner to an image, usually the output of a join. The banner is as wide as the
+/// image and one line of text tall. Images with 16 bits per channel keep them
+pub fn add_title(img: &DynamicImage, title: &TitleOptions) -> Result<DynamicImage, JoinError> {
+    let (width, height) = img.dimensions();
+    let banner_height = title.style.line_height();
+    let (img_y, banner_y) = match title.style.position {
+        CaptionPosition::Above => (banner_height, 0),
+        CaptionPosition::Below => (0, height),
+        CaptionPosition::Over(anchor) => {
+            let mut output_img = img.clone();
+            let rect = Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            };
+            draw_text(&mut output_img, &title.text, &title.style, rect, anchor);
+            return Ok(output_img);
+        }
+    };
+
+    let total_height = height
+        .checked_add(banner_height)
+        .ok_or(JoinError::DimensionOverflow)?;
+    let alpha = img.color().has_alpha() || title.background[3] < u8::MAX;
+    let background = title.background;
+    let mut output_img = match (is_deep(img), alpha) {
+        (true, true) => DynamicImage::ImageRgba16(on_canvas(
+            &img.to_rgba16(),
+            widen(background),
+            total_height,
+            img_y,
+        )),
+        (true, false) => DynamicImage::ImageRgb16(on_canvas(
+            &img.to_rgb16(),
+            widen(background).to_rgb(),
+            total_height,
+            img_y,
+        )),
+        (false, true) => {
+            DynamicImage::ImageRgba8(on_canvas(&img.to_rgba8(), background, total_height, img_y))
+        }
+        (false, false) => DynamicImage::ImageRgb8(on_canvas(
+            &img.to_rgb8(),
+            background.to_rgb(),
+            total_height,
+            img_y,
+        )),
+    };
+    let banner = Rect {
+        x: 0,
+        y: banner_y,
+        width,
+        height: banner_height,
+    };
+    draw_text(
+        &mut output_img,
+        &title.text,
+        &title.style,
+        banner,
+        Anchor::Center,
+    );
+    Ok(output_img)
+}
+
+// The image on a canvas `height` pixels tall filled with `background`, `y` pixels down from the
+//  top
+fn on_canvas<P: Pixel + 'static>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    background: P,
+    height: u32,
+    y: u32,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::from_pixel(img.width(), height, background);
+    imageops::overlay(&mut canvas, img, 0, y);
+    canvas
+}
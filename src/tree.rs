@@ -0,0 +1,59 @@
+use crate::{join_photos, Direction, JoinError, PhotoJoinOptions};
+use image::DynamicImage;
+use std::borrow::Cow;
+
+/// A nested layout, like one large image beside a stacked pair:
+/// `Node::Horizontal(vec![Node::Image(a), Node::Vertical(vec![Node::Image(b), Node::Image(c)])])`
+#[derive(Clone)]
+pub enum Node {
+    Image(DynamicImage),
+    Horizontal(Vec<Node>),
+    Vertical(Vec<Node>),
+    Grid {
+        rows: u32,
+        cols: u32,
+        nodes: Vec<Node>,
+    },
+}
+
+/// Joins a tree of nodes from the inside out. Every join uses `options` (apart from the
+/// direction, which comes from the nodes), and each joined group is then scaled like any other
+/// image when it's joined with its siblings
+pub fn join_tree(root: &Node, options: PhotoJoinOptions) -> Result<DynamicImage, JoinError> {
+    join_node(root, &options).map(Cow::into_owned)
+}
+
+// Joins a node with `options`. The groups inside of it are joined the same way, except that only
+//  the whole output is rounded up to a power of two or multiple
+fn join_node<'a>(
+    node: &'a Node,
+    options: &PhotoJoinOptions,
+) -> Result<Cow<'a, DynamicImage>, JoinError> {
+    let (direction, nodes) = match node {
+        // Images on their own are used as-is, they're scaled along with the rest of their group
+        Node::Image(img) => return Ok(Cow::Borrowed(img)),
+        Node::Horizontal(nodes) => (Direction::Horizontal, nodes),
+        Node::Vertical(nodes) => (Direction::Vertical, nodes),
+        Node::Grid { rows, cols, nodes } => (
+            Direction::Grid {
+                rows: *rows,
+                cols: *cols,
+            },
+            nodes,
+        ),
+    };
+    let inner = PhotoJoinOptions {
+        power_of_two: false,
+        align_multiple: 0,
+        ..*options
+    };
+    let images = nodes
+        .iter()
+        .map(|node| join_node(node, &inner))
+        .collect::<Result<Vec<_>, _>>()?;
+    let options = PhotoJoinOptions {
+        direction,
+        ..*options
+    };
+    join_photos(images, options).map(Cow::Owned)
+}
@@ -0,0 +1,248 @@
+use crate::atlas::json_string;
+use crate::Rect;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use std::fmt::Write;
+
+/// How the tiles of a zoomable image are laid out, for viewers like OpenSeadragon that only load
+/// the tiles of an enormous image that are on screen
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ZoomFormat {
+    /// Deep Zoom, described by a `.dzi` XML file with the tiles of each level in a folder next to
+    /// it, like `joined_files/12/3_4.jpg`
+    DeepZoom,
+    /// Static tiles of the IIIF Image API 3.0 (level 0), described by an `info.json` file with
+    /// the tiles in the same folder, like `1024,0,512,512/512,512/0/default.jpg`
+    Iiif,
+}
+
+/// How the tiles of a zoomable image are cut
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZoomOptions {
+    pub format: ZoomFormat,
+    /// The width and height of each tile, besides the overlap (tiles on the right and bottom edges
+    /// of each level can be smaller)
+    pub tile_size: u32,
+    /// How many pixels each Deep Zoom tile overlaps the tiles next to it by, which hides the seams
+    /// between them. IIIF tiles never overlap
+    pub overlap: u32,
+}
+
+impl ZoomOptions {
+    /// The tile sizes viewers expect by default, 254 pixels overlapping by one for Deep Zoom (so
+    /// tiles in the middle are 256 pixels) and 512 pixels for IIIF
+    pub fn new(format: ZoomFormat) -> Self {
+        match format {
+            ZoomFormat::DeepZoom => Self {
+                format,
+                tile_size: 254,
+                overlap: 1,
+            },
+            ZoomFormat::Iiif => Self {
+                format,
+                tile_size: 512,
+                overlap: 0,
+            },
+        }
+    }
+}
+
+/// Cuts an image into the tiles of every level of a zoomable image, from the image itself down to
+/// a level that fits in one tile (or 1x1 for Deep Zoom). Each level is half as wide and tall as
+/// the one before it, rounded up. `save` is given each tile along with its path relative to the
+/// tiles folder (the folder of the IIIF `info.json`, or the `_files` folder next to the `.dzi`),
+/// and `extension` is the file extension of the tiles, like `jpg`
+pub fn zoom_tiles<E>(
+    img: &DynamicImage,
+    options: &ZoomOptions,
+    extension: &str,
+    mut save: impl FnMut(&str, &DynamicImage) -> Result<(), E>,
+) -> Result<(), E> {
+    let tile_size = options.tile_size.max(1);
+    let full = img.dimensions();
+    let levels = zoom_levels(full, options);
+    let mut shrunk: Option<DynamicImage> = None;
+    for halvings in 0..levels {
+        if halvings > 0 {
+            let last = shrunk.as_ref().unwrap_or(img);
+            let (width, height) = last.dimensions();
+            shrunk = Some(last.resize_exact(
+                width.div_ceil(2).max(1),
+                height.div_ceil(2).max(1),
+                FilterType::Triangle,
+            ));
+        }
+        let level = shrunk.as_ref().unwrap_or(img);
+        let (width, height) = level.dimensions();
+        for row in 0..height.div_ceil(tile_size) {
+            for col in 0..width.div_ceil(tile_size) {
+                let (x, y) = (col * tile_size, row * tile_size);
+                let (path, tile) = match options.format {
+                    ZoomFormat::DeepZoom => {
+                        let left = x.saturating_sub(options.overlap);
+                        let top = y.saturating_sub(options.overlap);
+                        let right = (x + tile_size).saturating_add(options.overlap).min(width);
+                        let bottom = (y + tile_size).saturating_add(options.overlap).min(height);
+                        (
+                            format!("{}/{}_{}.{}", levels - 1 - halvings, col, row, extension),
+                            level.crop_imm(left, top, right - left, bottom - top),
+                        )
+                    }
+                    ZoomFormat::Iiif => {
+                        let size = (tile_size.min(width - x), tile_size.min(height - y));
+                        (
+                            iiif_path(full, halvings, (x, y), size, extension),
+                            level.crop_imm(x, y, size.0, size.1),
+                        )
+                    }
+                };
+                save(&path, &tile)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The file that describes a zoomable image of this size to viewers, the `.dzi` XML of Deep Zoom
+/// or the `info.json` of IIIF. `id` is the URL of the IIIF folder (ignored for Deep Zoom, which
+/// finds its tiles next to the `.dzi`)
+pub fn zoom_descriptor(
+    (width, height): (u32, u32),
+    options: &ZoomOptions,
+    extension: &str,
+    id: &str,
+) -> String {
+    let mut out = String::new();
+    // Writing to a string can't fail
+    let _ = match options.format {
+        ZoomFormat::DeepZoom => write_dzi(&mut out, (width, height), options, extension),
+        ZoomFormat::Iiif => write_iiif_info(&mut out, (width, height), options, extension, id),
+    };
+    out
+}
+
+// How many levels a zoomable image has. Deep Zoom goes all the way down to 1x1, and IIIF stops at
+//  the first level that fits in one tile
+fn zoom_levels((width, height): (u32, u32), options: &ZoomOptions) -> u32 {
+    let stop = match options.format {
+        ZoomFormat::DeepZoom => 1,
+        ZoomFormat::Iiif => options.tile_size.max(1),
+    };
+    let mut longest = width.max(height);
+    let mut levels = 1;
+    while longest > stop {
+        longest = longest.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+// The path of a IIIF tile in the canonical form viewers ask for it in, with its region in pixels
+//  of the full image and its size in pixels of its level
+fn iiif_path(
+    (width, height): (u32, u32),
+    halvings: u32,
+    (x, y): (u32, u32),
+    size: (u32, u32),
+    extension: &str,
+) -> String {
+    let scale = 1u64 << halvings;
+    let region_x = x as u64 * scale;
+    let region_y = y as u64 * scale;
+    let region_width = (size.0 as u64 * scale).min(width as u64 - region_x);
+    let region_height = (size.1 as u64 * scale).min(height as u64 - region_y);
+    let region = if (region_x, region_y, region_width, region_height)
+        == (0, 0, width as u64, height as u64)
+    {
+        "full".to_string()
+    } else {
+        format!(
+            "{},{},{},{}",
+            region_x, region_y, region_width, region_height
+        )
+    };
+    let size = if halvings == 0 {
+        "max".to_string()
+    } else {
+        format!("{},{}", size.0, size.1)
+    };
+    format!("{}/{}/0/default.{}", region, size, extension)
+}
+
+fn write_dzi(
+    out: &mut String,
+    (width, height): (u32, u32),
+    options: &ZoomOptions,
+    extension: &str,
+) -> std::fmt::Result {
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        out,
+        "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" Overlap=\"{}\" TileSize=\"{}\">",
+        extension, options.overlap, options.tile_size
+    )?;
+    writeln!(out, "  <Size Width=\"{}\" Height=\"{}\"/>", width, height)?;
+    writeln!(out, "</Image>")
+}
+
+fn write_iiif_info(
+    out: &mut String,
+    (width, height): (u32, u32),
+    options: &ZoomOptions,
+    extension: &str,
+    id: &str,
+) -> std::fmt::Result {
+    let scale_factors = (0..zoom_levels((width, height), options))
+        .map(|halvings| (1u64 << halvings).to_string())
+        .collect::<Vec<_>>();
+    writeln!(out, "{{")?;
+    writeln!(
+        out,
+        "  \"@context\": \"http://iiif.io/api/image/3/context.json\","
+    )?;
+    writeln!(out, "  \"id\": {},", json_string(id))?;
+    writeln!(out, "  \"type\": \"ImageService3\",")?;
+    writeln!(out, "  \"protocol\": \"http://iiif.io/api/image\",")?;
+    writeln!(out, "  \"profile\": \"level0\",")?;
+    writeln!(out, "  \"width\": {},", width)?;
+    writeln!(out, "  \"height\": {},", height)?;
+    // Level 0 images are JPEGs unless they say otherwise
+    if extension != "jpg" {
+        writeln!(out, "  \"preferredFormats\": [{}],", json_string(extension))?;
+        writeln!(out, "  \"extraFormats\": [{}],", json_string(extension))?;
+    }
+    writeln!(
+        out,
+        "  \"tiles\": [{{\"width\": {}, \"height\": {}, \"scaleFactors\": [{}]}}]",
+        options.tile_size,
+        options.tile_size,
+        scale_factors.join(", ")
+    )?;
+    writeln!(out, "}}")
+}
+
+/// Where each tile is when an image of this size is cut into a grid of tiles of at most
+/// `tile_size`, row by row from the top left. Tiles on the right and bottom edges are smaller when
+/// the image isn't a multiple of the tile size
+pub fn grid_tiles((width, height): (u32, u32), tile_size: (u32, u32)) -> Vec<Rect> {
+    let (tile_width, tile_height) = (tile_size.0.max(1), tile_size.1.max(1));
+    let mut tiles = Vec::new();
+    for row in 0..height.div_ceil(tile_height) {
+        for col in 0..width.div_ceil(tile_width) {
+            let (x, y) = (col * tile_width, row * tile_height);
+            tiles.push(Rect {
+                x,
+                y,
+                width: tile_width.min(width - x),
+                height: tile_height.min(height - y),
+            });
+        }
+    }
+    tiles
+}